@@ -0,0 +1,114 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// How long ago an item was last modified, in the steps the summary uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AgeBucket {
+    UnderOneWeek,
+    OneToFourWeeks,
+    OneToSixMonths,
+    Older,
+    /// The modification time could not be read
+    Unknown,
+}
+
+impl AgeBucket {
+    pub const ALL: [AgeBucket; 5] = [
+        AgeBucket::UnderOneWeek,
+        AgeBucket::OneToFourWeeks,
+        AgeBucket::OneToSixMonths,
+        AgeBucket::Older,
+        AgeBucket::Unknown,
+    ];
+
+    pub fn of(age: Option<Duration>) -> Self {
+        match age.map(|age| age.as_secs() / DAY) {
+            None => AgeBucket::Unknown,
+            Some(0..7) => AgeBucket::UnderOneWeek,
+            Some(7..28) => AgeBucket::OneToFourWeeks,
+            Some(28..183) => AgeBucket::OneToSixMonths,
+            Some(_) => AgeBucket::Older,
+        }
+    }
+
+    /// Bucket of something last modified at `modified`; times in the future
+    /// count as new
+    pub fn of_time(modified: Option<SystemTime>) -> Self {
+        Self::of(modified.map(|m| m.elapsed().unwrap_or_default()))
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AgeBucket::UnderOneWeek => "under 1 week",
+            AgeBucket::OneToFourWeeks => "1-4 weeks",
+            AgeBucket::OneToSixMonths => "1-6 months",
+            AgeBucket::Older => "older",
+            AgeBucket::Unknown => "unknown age",
+        }
+    }
+}
+
+/// Reclaimable space last modified within one age bucket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct AgeUsage {
+    pub age: AgeBucket,
+    pub items: usize,
+    pub bytes: u64,
+}
+
+/// Sum sizes per age bucket, youngest first. The four age ranges are always
+/// listed so stale and recent space compare at a glance; unknown ages only
+/// when there are any.
+pub fn usage_by_age(sizes: impl IntoIterator<Item = (AgeBucket, u64)>) -> Vec<AgeUsage> {
+    let mut usage: Vec<AgeUsage> = AgeBucket::ALL
+        .iter()
+        .map(|&age| AgeUsage {
+            age,
+            items: 0,
+            bytes: 0,
+        })
+        .collect();
+    for (age, bytes) in sizes {
+        let entry = &mut usage[age as usize];
+        entry.items += 1;
+        entry.bytes += bytes;
+    }
+    usage.retain(|u| u.age != AgeBucket::Unknown || u.items > 0);
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_by_age() {
+        let days = |n: u64| Some(Duration::from_secs(n * DAY));
+        assert_eq!(AgeBucket::of(days(0)), AgeBucket::UnderOneWeek);
+        assert_eq!(AgeBucket::of(days(7)), AgeBucket::OneToFourWeeks);
+        assert_eq!(AgeBucket::of(days(60)), AgeBucket::OneToSixMonths);
+        assert_eq!(AgeBucket::of(days(400)), AgeBucket::Older);
+        assert_eq!(AgeBucket::of_time(None), AgeBucket::Unknown);
+
+        let usage = usage_by_age([
+            (AgeBucket::Older, 500),
+            (AgeBucket::UnderOneWeek, 10),
+            (AgeBucket::Older, 20),
+        ]);
+        let bytes: Vec<(AgeBucket, u64)> = usage.iter().map(|u| (u.age, u.bytes)).collect();
+        assert_eq!(
+            bytes,
+            [
+                (AgeBucket::UnderOneWeek, 10),
+                (AgeBucket::OneToFourWeeks, 0),
+                (AgeBucket::OneToSixMonths, 0),
+                (AgeBucket::Older, 520),
+            ]
+        );
+        assert_eq!(usage[3].items, 2);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::cache_detector::{CacheItem, SizedFile};
+use crate::error::CleanerError;
+use crate::scan_report::ScanReport;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path components that identify the application owning everything below them
+const KNOWN_APPS: &[(&str, &str)] = &[
+    ("google-chrome", "Chrome"),
+    ("chromium", "Chromium"),
+    ("BraveSoftware", "Brave"),
+    ("mozilla", "Firefox"),
+    ("spotify", "Spotify"),
+    ("discord", "Discord"),
+    ("Slack", "Slack"),
+    ("Code", "VS Code"),
+    ("JetBrains", "JetBrains IDEs"),
+    (".cargo", "cargo"),
+    (".rustup", "rustup"),
+    ("pip", "pip"),
+    ("pypoetry", "Poetry"),
+    ("__pycache__", "Python"),
+    (".npm", "npm"),
+    ("node_modules", "npm"),
+    ("yarn", "Yarn"),
+    ("pnpm", "pnpm"),
+    ("go-build", "Go"),
+    (".gradle", "Gradle"),
+    (".m2", "Maven"),
+    ("thumbnails", "Thumbnails"),
+    ("mesa_shader_cache", "Mesa"),
+    ("apt", "apt"),
+    ("dnf", "dnf"),
+    ("pacman", "pacman"),
+    ("journal", "systemd-journald"),
+];
+
+/// Directories whose immediate children are usually named after their application
+const APP_PARENTS: &[&str] = &[".cache", "cache", "Caches", "log", "logs"];
+
+/// Name used for items no heuristic could attribute
+pub const UNKNOWN_APP: &str = "Other";
+
+/// Reclaimable space attributed to one application
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct AppUsage {
+    pub app: String,
+    pub items: usize,
+    pub bytes: u64,
+}
+
+/// Guess the application owning `path`: the deepest known component wins,
+/// then the directory directly below a cache or log root
+pub fn app_for(path: &Path) -> String {
+    let components: Vec<&str> = path.iter().filter_map(|c| c.to_str()).collect();
+
+    for component in components.iter().rev() {
+        if let Some((_, app)) = KNOWN_APPS.iter().find(|(name, _)| name == component) {
+            return app.to_string();
+        }
+    }
+
+    components
+        .windows(2)
+        .rev()
+        .find(|pair| APP_PARENTS.contains(&pair[0]))
+        .map(|pair| pair[1].trim_start_matches('.').to_string())
+        .unwrap_or_else(|| UNKNOWN_APP.to_string())
+}
+
+/// Split generic cache roots such as `~/.cache` into their children so each
+/// application below them is attributed separately. The children are sized
+/// from the files the sizing pass already walked instead of a second walk.
+pub fn split_app_roots(cache_items: &[CacheItem], files: &[SizedFile]) -> ScanReport<CacheItem> {
+    let mut report = ScanReport::new();
+    let mut children = Vec::new();
+
+    for item in cache_items {
+        let is_root = item
+            .path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| APP_PARENTS.contains(&name));
+        let entries = match fs::read_dir(item.target_path()) {
+            Ok(entries) if is_root && app_for(&item.path) == UNKNOWN_APP => entries,
+            Ok(_) => {
+                report.items.push(item.clone());
+                continue;
+            }
+            Err(e) => {
+                report
+                    .warnings
+                    .push(CleanerError::from_io(item.target_path(), &e));
+                report.items.push(item.clone());
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            children.push(CacheItem {
+                path: item.path.join(&name),
+                last_accessed: None,
+                temperature: None,
+                size_estimated: false,
+                resolved_path: item.resolved_path.as_ref().map(|p| p.join(&name)),
+                size_bytes: None,
+                file_count: None,
+                ..item.clone()
+            });
+        }
+    }
+
+    let roots: HashSet<&Path> = children.iter().filter_map(|c| c.path.parent()).collect();
+    let mut totals: HashMap<PathBuf, (u64, usize)> = HashMap::new();
+    for file in files {
+        let child = file
+            .path
+            .ancestors()
+            .find(|a| a.parent().is_some_and(|parent| roots.contains(parent)));
+        if let Some(child) = child {
+            let total = totals.entry(child.to_path_buf()).or_default();
+            total.0 += file.apparent_size;
+            total.1 += 1;
+        }
+    }
+
+    for mut child in children {
+        let (bytes, file_count) = totals.get(&child.path).copied().unwrap_or_default();
+        child.size_bytes = Some(bytes);
+        child.file_count = Some(file_count);
+        report.items.push(child);
+    }
+    report
+}
+
+/// Aggregate item sizes per application, largest first
+pub fn usage_by_app<'a>(sizes: impl IntoIterator<Item = (&'a Path, u64)>) -> Vec<AppUsage> {
+    let mut usage: HashMap<String, (usize, u64)> = HashMap::new();
+    for (path, bytes) in sizes {
+        let entry = usage.entry(app_for(path)).or_default();
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+
+    let mut usage: Vec<AppUsage> = usage
+        .into_iter()
+        .map(|(app, (items, bytes))| AppUsage { app, items, bytes })
+        .collect();
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.app.cmp(&b.app)));
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_split_app_roots_reuses_sized_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join(".cache");
+        fs::create_dir_all(root.join("spotify")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        let item = CacheItem {
+            size_bytes: Some(30),
+            file_count: Some(2),
+            ..CacheItem::new(root.clone(), CacheType::UserCache, "")
+        };
+        let file = |path: PathBuf, size| SizedFile {
+            path,
+            apparent_size: size,
+            disk_size: size,
+        };
+        let files = [
+            file(root.join("spotify/data/a"), 10),
+            file(root.join("spotify/b"), 20),
+        ];
+
+        let mut split = split_app_roots(&[item], &files).items;
+        split.sort_by(|a, b| a.path.cmp(&b.path));
+        let sizes: Vec<_> = split.iter().map(|c| (c.size_bytes, c.file_count)).collect();
+        assert_eq!(sizes, vec![(Some(0), Some(0)), (Some(30), Some(2))]);
+    }
+
+    #[test]
+    fn test_app_attribution() {
+        assert_eq!(
+            app_for(Path::new("/home/alice/.cache/google-chrome/Default/Cache")),
+            "Chrome"
+        );
+        assert_eq!(
+            app_for(Path::new("/home/alice/.cargo/registry/cache")),
+            "cargo"
+        );
+        assert_eq!(app_for(Path::new("/home/alice/.cache/spotify")), "Spotify");
+        assert_eq!(app_for(Path::new("/var/log/nginx/error.log.1")), "nginx");
+        assert_eq!(app_for(Path::new("/srv/scratch")), UNKNOWN_APP);
+
+        let usage = usage_by_app([
+            (Path::new("/home/a/.cache/spotify"), 10),
+            (Path::new("/home/a/.cache/google-chrome"), 30),
+            (Path::new("/home/b/.cache/google-chrome"), 20),
+        ]);
+        assert_eq!(
+            usage,
+            vec![
+                AppUsage {
+                    app: "Chrome".to_string(),
+                    items: 2,
+                    bytes: 50,
+                },
+                AppUsage {
+                    app: "Spotify".to_string(),
+                    items: 1,
+                    bytes: 10,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::cache_detector::CacheItem;
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::log_cleaner::LogFile;
+use crate::state;
+use chrono::{DateTime, Utc};
+use jwalk::WalkDir;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Read size used while hashing
+const HASH_CHUNK: usize = 1024 * 1024;
+
+/// One file recorded in the audit manifest before it is deleted
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AuditRecord {
+    /// Cache item or log file the file belongs to
+    pub item: PathBuf,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// BLAKE3 hash of the contents, absent when hashing was skipped or failed
+    pub blake3: Option<String>,
+    /// Why `blake3` is absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Writes the checksum manifest of everything a run is about to delete
+pub struct AuditManifest {
+    max_hash_bytes: u64,
+    hash_rate_bytes_per_sec: u64,
+}
+
+impl AuditManifest {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            max_hash_bytes: config.safety.manifest_max_hash_size,
+            hash_rate_bytes_per_sec: config.safety.manifest_hash_rate,
+        }
+    }
+
+    /// Record every file below the items as one JSON line each, returning the
+    /// manifest path
+    pub fn write(
+        &self,
+        cache_items: &[CacheItem],
+        log_files: &[LogFile],
+    ) -> Result<PathBuf, CleanerError> {
+        let manifest_path = Self::manifest_path();
+        if let Some(parent) = manifest_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| CleanerError::from_io(parent, &e))?;
+        }
+        // Written under a temporary name, so a manifest that exists is complete
+        let partial = state::temp_path(&manifest_path);
+        let file = File::create(&partial).map_err(|e| CleanerError::from_io(&partial, &e))?;
+        let mut out = BufWriter::new(file);
+
+        let items = cache_items
+            .iter()
+            .map(|i| i.target_path())
+            .chain(log_files.iter().map(|l| l.path.as_path()));
+        for item in items {
+            for path in Self::files_below(item)? {
+                let record = self.record(item, &path);
+                serde_json::to_writer(&mut out, &record)
+                    .map_err(|e| CleanerError::Io {
+                        path: manifest_path.clone(),
+                        kind: io::ErrorKind::Other,
+                        message: e.to_string(),
+                    })
+                    .and_then(|()| {
+                        writeln!(out).map_err(|e| CleanerError::from_io(&manifest_path, &e))
+                    })?;
+            }
+        }
+
+        out.into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.sync_all())
+            .map_err(|e| CleanerError::from_io(&partial, &e))?;
+        fs::rename(&partial, &manifest_path)
+            .map_err(|e| CleanerError::from_io(&manifest_path, &e))?;
+        Ok(manifest_path)
+    }
+
+    /// Every file below `item`. The scan's limits on depth, directory sizes
+    /// and time don't apply: the manifest must cover everything about to be
+    /// deleted, so a directory that can't be read fails it instead.
+    fn files_below(item: &Path) -> Result<Vec<PathBuf>, CleanerError> {
+        if !item.is_dir() {
+            return Ok(vec![item.to_path_buf()]);
+        }
+        let mut files = Vec::new();
+        for entry in WalkDir::new(item).skip_hidden(false).follow_links(false) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                files.push(entry.path());
+            }
+        }
+        Ok(files)
+    }
+
+    fn record(&self, item: &Path, path: &Path) -> AuditRecord {
+        let metadata = fs::symlink_metadata(path).ok();
+        let size_bytes = metadata.as_ref().map_or(0, |m| m.len());
+
+        let (blake3, note) = if size_bytes > self.max_hash_bytes {
+            (None, Some("larger than manifest_max_hash_size".to_string()))
+        } else {
+            match self.hash_file(path) {
+                Ok(hash) => (Some(hash), None),
+                Err(e) => (None, Some(e.to_string())),
+            }
+        };
+
+        AuditRecord {
+            item: item.to_path_buf(),
+            path: path.to_path_buf(),
+            size_bytes,
+            modified: metadata
+                .as_ref()
+                .and_then(|m| m.modified().ok())
+                .map(DateTime::<Utc>::from),
+            mode: metadata.as_ref().map_or(0, |m| m.mode()),
+            uid: metadata.as_ref().map_or(0, |m| m.uid()),
+            gid: metadata.as_ref().map_or(0, |m| m.gid()),
+            blake3,
+            note,
+        }
+    }
+
+    /// Hash a file, sleeping as needed to stay under the configured read rate
+    fn hash_file(&self, path: &Path) -> io::Result<String> {
+        let mut file = File::open(path)?;
+        let mut hasher = blake3::Hasher::new();
+        let mut buffer = vec![0; HASH_CHUNK];
+        let started = Instant::now();
+        let mut total = 0u64;
+
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            total += read as u64;
+
+            if self.hash_rate_bytes_per_sec > 0 {
+                let due =
+                    Duration::from_secs_f64(total as f64 / self.hash_rate_bytes_per_sec as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+        }
+
+        Ok(hasher.finalize().to_hex().to_string())
+    }
+
+    fn manifest_path() -> PathBuf {
+        let timestamp = Utc::now().format("%Y%m%d_%H%M%S%3f");
+        state::dir()
+            .join("audit")
+            .join(format!("manifest_{}.ndjson", timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_records_hash_and_skips_large_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let small = temp_dir.path().join("small");
+        let large = temp_dir.path().join("large");
+        fs::write(&small, "hello").unwrap();
+        fs::write(&large, vec![0u8; 64]).unwrap();
+
+        let mut config = Config::default();
+        config.safety.manifest_max_hash_size = 32;
+        let manifest = AuditManifest::new(&config);
+
+        let record = manifest.record(temp_dir.path(), &small);
+        assert_eq!(record.size_bytes, 5);
+        assert_eq!(
+            record.blake3.as_deref(),
+            Some(blake3::hash(b"hello").to_hex().as_str())
+        );
+
+        let record = manifest.record(temp_dir.path(), &large);
+        assert!(record.blake3.is_none());
+        assert!(record.note.is_some());
+    }
+
+    #[test]
+    fn test_lists_every_file_below() {
+        let temp_dir = TempDir::new().unwrap();
+        let deep = temp_dir.path().join("a/b/c/d/e/f/g/h/.hidden");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("file"), "x").unwrap();
+        fs::write(temp_dir.path().join(".top"), "x").unwrap();
+
+        let mut files = AuditManifest::files_below(temp_dir.path()).unwrap();
+        files.sort();
+        assert_eq!(files, vec![temp_dir.path().join(".top"), deep.join("file")]);
+    }
+}
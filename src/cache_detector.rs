@@ -1,7 +1,22 @@
+use crate::ScanOptions;
 use crate::config::Config;
+use crate::detector::{self, Detector, Stage};
+use crate::error::CleanerError;
+use crate::estimate;
+use crate::file_operations::is_deleting_name;
+use crate::log_cleaner::LogFile;
+use crate::owners::Owner;
+use crate::scan_report::ScanReport;
+use crate::temperature::Temperature;
+use crate::throttle;
+use crate::vcs;
+use crate::walker::{ScanWalker, WalkEntry};
 use glob::glob;
-use jwalk::WalkDir;
 use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
@@ -13,10 +28,62 @@ pub struct CacheItem {
     pub size_bytes: Option<u64>,
     pub file_count: Option<usize>,
     pub last_modified: Option<SystemTime>,
+    /// Latest access time of the files below the item, read while sizing
+    pub last_accessed: Option<SystemTime>,
+    /// How actively the item is used, rated after sizing
+    pub temperature: Option<Temperature>,
+    /// Whether `size_bytes` and `file_count` were extrapolated from a sample
+    /// of the tree rather than counted (`--estimate`)
+    pub size_estimated: bool,
+    /// Canonical target when `path` is a symlink whose target will be cleaned
+    pub resolved_path: Option<PathBuf>,
+    /// Owner of the cleaned path, when it could be read
+    pub owner: Option<Owner>,
+    /// Why the item was picked up, e.g. the pattern that matched
+    pub reason: String,
+}
+
+impl CacheItem {
+    /// An item at `path` found for `reason`, not yet dated, sized or resolved
+    pub fn new(path: impl Into<PathBuf>, cache_type: CacheType, reason: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            cache_type,
+            size_bytes: None,
+            file_count: None,
+            last_modified: None,
+            last_accessed: None,
+            temperature: None,
+            size_estimated: false,
+            resolved_path: None,
+            owner: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// Dated and owned as `metadata` of its path says, when it could be read
+    pub fn with_metadata(mut self, metadata: Option<&Metadata>) -> Self {
+        self.last_modified = metadata.and_then(|m| m.modified().ok());
+        self.owner = metadata.map(Owner::of);
+        self
+    }
+
+    /// Dated and owned as the metadata of its path, without following a
+    /// symlink there, says
+    pub fn dated(self) -> Self {
+        let metadata = fs::symlink_metadata(&self.path).ok();
+        self.with_metadata(metadata.as_ref())
+    }
+
+    /// The path whose contents are actually measured and cleaned
+    pub fn target_path(&self) -> &Path {
+        self.resolved_path.as_deref().unwrap_or(&self.path)
+    }
 }
 
 /// Types of cache items
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum CacheType {
     UserCache,
     SystemCache,
@@ -26,6 +93,22 @@ pub enum CacheType {
     DevelopmentCache,
     BuildArtifact,
     TemporaryFile,
+    /// Caches such as font and icon caches that are rebuilt automatically
+    RegeneratingCache,
+    /// Unreferenced flatpak objects and stale DKMS builds, root only
+    SystemReclaim,
+    /// Trash directories at the top of mounted volumes
+    VolumeTrash,
+    /// Deferred MTA queue files long past any delivery attempt
+    DeadMailQueue,
+    /// CUPS print jobs whose files sat untouched past the retention
+    PrintJob,
+    /// Browser profiles nobody opened in a long time, cleaned only on request
+    UnusedBrowserProfile,
+    /// Container logs and emptyDir volumes of deleted Kubernetes pods
+    OrphanedPodData,
+    /// Archived systemd journal files past the vacuum limits
+    Journal,
 }
 
 impl CacheType {
@@ -39,18 +122,121 @@ impl CacheType {
             CacheType::DevelopmentCache => "Development tool cache",
             CacheType::BuildArtifact => "Build artifact",
             CacheType::TemporaryFile => "Temporary file/directory",
+            CacheType::RegeneratingCache => "Auto-regenerating cache",
+            CacheType::SystemReclaim => "Advanced system reclaim",
+            CacheType::VolumeTrash => "Trash on mounted volume",
+            CacheType::DeadMailQueue => "Dead mail queue file",
+            CacheType::PrintJob => "Leftover print job",
+            CacheType::UnusedBrowserProfile => "Unused browser profile",
+            CacheType::OrphanedPodData => "Orphaned Kubernetes pod data",
+            CacheType::Journal => "Systemd journal",
+        }
+    }
+
+    /// Whether the cache is rebuilt on its own, so clearing it needs no
+    /// confirmation
+    pub fn is_regenerating(&self) -> bool {
+        matches!(self, CacheType::RegeneratingCache)
+    }
+
+    /// Short name used to pick categories on the command line
+    pub fn category(&self) -> &'static str {
+        match self {
+            CacheType::UserCache => "user",
+            CacheType::SystemCache => "system",
+            CacheType::PackageManagerCache => "package",
+            CacheType::ApplicationCache => "app",
+            CacheType::BrowserCache => "browser",
+            CacheType::DevelopmentCache => "dev",
+            CacheType::BuildArtifact => "build",
+            CacheType::TemporaryFile => "temp",
+            CacheType::RegeneratingCache => "regenerating",
+            CacheType::SystemReclaim => "reclaim",
+            CacheType::VolumeTrash => "volume-trash",
+            CacheType::DeadMailQueue => "mail-queue",
+            CacheType::PrintJob => "print-job",
+            CacheType::UnusedBrowserProfile => "browser-profile",
+            CacheType::OrphanedPodData => "pod-data",
+            CacheType::Journal => "journal",
+        }
+    }
+}
+
+/// Category name of old log files, next to the cache categories
+pub const LOG_CATEGORY: &str = "logs";
+
+/// Every category name accepted on the command line
+pub const CATEGORIES: &[&str] = &[
+    "user",
+    "system",
+    "package",
+    "app",
+    "browser",
+    "dev",
+    "build",
+    "temp",
+    "regenerating",
+    "reclaim",
+    "volume-trash",
+    "mail-queue",
+    "print-job",
+    "browser-profile",
+    "pod-data",
+    "journal",
+    LOG_CATEGORY,
+];
+
+/// Found items and bytes of one category
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct CategoryUsage {
+    pub category: String,
+    pub items: usize,
+    pub bytes: u64,
+}
+
+/// Sum sizes per category, in the order of [`CATEGORIES`], leaving out
+/// categories nothing was found in
+pub fn usage_by_category(
+    sizes: impl IntoIterator<Item = (&'static str, u64)>,
+) -> Vec<CategoryUsage> {
+    let mut usage: Vec<CategoryUsage> = CATEGORIES
+        .iter()
+        .map(|category| CategoryUsage {
+            category: category.to_string(),
+            items: 0,
+            bytes: 0,
+        })
+        .collect();
+    for (category, bytes) in sizes {
+        if let Some(entry) = usage.iter_mut().find(|u| u.category == category) {
+            entry.items += 1;
+            entry.bytes += bytes;
         }
     }
+    usage.retain(|u| u.items > 0);
+    usage
 }
 
 /// Cache detection engine
 pub struct CacheDetector {
     config: Config,
+    detectors: Vec<Box<dyn Detector>>,
 }
 
 impl CacheDetector {
+    /// A detector running the built-in detection sources
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self::with_detectors(config, detector::builtin())
+    }
+
+    /// A detector running only `detectors`, in order
+    pub fn with_detectors(config: Config, detectors: Vec<Box<dyn Detector>>) -> Self {
+        Self { config, detectors }
+    }
+
+    /// Run `detector` too, after those registered before it
+    pub fn register(&mut self, detector: Box<dyn Detector>) {
+        self.detectors.push(detector);
     }
 
     /// Check if a directory contains any code files
@@ -82,90 +268,140 @@ impl CacheDetector {
         false
     }
 
-    /// Detect all cache items under the given root path
+    /// Detect the cache items of the patterns stage under the given root path
     pub fn detect_cache_items<P: AsRef<Path>>(
         &self,
         root: P,
-    ) -> Result<Vec<CacheItem>, Box<dyn std::error::Error>> {
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
         let root_path = root.as_ref();
-        let mut cache_items = Vec::new();
-
-        // Detect cache directories
-        cache_items.extend(self.detect_cache_directories(root_path)?);
-
-        // Detect build artifacts
-        cache_items.extend(self.detect_build_artifacts(root_path)?);
-
-        // Detect temporary files
-        cache_items.extend(self.detect_temporary_files(root_path)?);
+        let mut report = ScanReport::new();
+
+        let options = ScanOptions::new(self.config.clone());
+        let patterns = self
+            .detectors
+            .iter()
+            .filter(|d| d.stage() == Stage::Patterns);
+        for detector in patterns {
+            report.merge(detector.scan(root_path, &options)?);
+        }
 
         // RETROACTIVELY REMOVE ANY ITEMS WITH CODE EXTENSIONS OR CONTAINING CODE FILES
         // This ensures that no matter which detection method found them,
         // code files and directories containing code files are excluded from the final results
-        cache_items.retain(|item| {
+        report.items.retain(|item| {
             !self.is_code_file(&item.path) && !self.directory_contains_code_files(&item.path)
         });
 
+        // Version control internals match names like `tmp` but are never caches
+        report.items.retain(|item| !vcs::is_in_metadata(&item.path));
+
         // Remove duplicates and sort by type
-        self.deduplicate_and_sort(cache_items)
+        report.items = self.deduplicate_and_sort(report.items)?;
+
+        if self.config.safety.resolve_symlink_targets {
+            self.resolve_symlink_targets(root_path, &mut report);
+        }
+
+        report.dedup_warnings();
+        Ok(report)
     }
 
     /// Detect cache directories using various patterns
-    fn detect_cache_directories(
+    pub(crate) fn detect_cache_directories(
         &self,
         root: &Path,
-    ) -> Result<Vec<CacheItem>, Box<dyn std::error::Error>> {
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        let mut report = ScanReport::new();
+
         // Check if this is a user home directory scan
         let is_user_scan = self.is_user_directory(root);
 
-        // Configure parallel walking with jwalk
-        let max_threads = self
-            .config
-            .performance
-            .max_threads
-            .unwrap_or(rayon::current_num_threads());
-        let parallelism = if max_threads == 1 {
-            jwalk::Parallelism::Serial
-        } else {
-            jwalk::Parallelism::RayonNewPool(max_threads)
-        };
-
-        // Use parallel directory traversal with jwalk
-        let entries: Result<Vec<_>, _> = WalkDir::new(root)
-            .parallelism(parallelism)
-            .max_depth(self.config.performance.max_depth.unwrap_or(10))
-            .follow_links(!self.config.performance.skip_symlinks)
-            .into_iter()
-            .filter_map(|entry_result| match entry_result {
-                Ok(entry) => {
-                    if entry.file_type().is_dir() {
-                        Some(Ok(entry))
-                    } else {
-                        None
-                    }
-                }
-                Err(e) => Some(Err(e)),
+        // Keep directories only; unreadable ones are recorded as warnings
+        let entries: Vec<_> = ScanWalker::new(&self.config)
+            .walk(root)
+            .entries(&mut report.warnings)
+            .filter(|entry| {
+                entry.file_type().is_dir()
+                    || (entry.file_type().is_file()
+                        && self
+                            .is_regenerating_cache(&entry.path().to_string_lossy().to_lowercase()))
+                    || (self.config.safety.resolve_symlink_targets
+                        && entry.path_is_symlink()
+                        && entry.path().is_dir())
             })
             .collect();
 
-        let entries = entries?;
-
         // Use rayon for parallel processing of directory classification
-        let items: Result<Vec<_>, _> = entries
+        report.items = entries
             .into_par_iter()
-            .filter_map(
-                |entry| match self.classify_directory_entry(&entry, is_user_scan) {
-                    Ok(Some(cache_item)) => Some(Ok(cache_item)),
-                    Ok(None) => None,
-                    Err(e) => Some(Err(format!("Classification error: {}", e))),
-                },
+            .filter_map(|entry| {
+                self.classify_directory_entry(&entry, is_user_scan)
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(report)
+    }
+
+    /// Point symlinked items at their canonical target, dropping any whose
+    /// target is not a directory, is excluded, or leaves the allowed roots
+    fn resolve_symlink_targets(&self, root: &Path, report: &mut ScanReport<CacheItem>) {
+        let allowed_roots: Vec<PathBuf> = std::iter::once(root.to_path_buf())
+            .chain(
+                self.config
+                    .safety
+                    .symlink_target_roots
+                    .iter()
+                    .map(|r| PathBuf::from(Config::expand_home(r))),
             )
+            .filter_map(|r| std::fs::canonicalize(r).ok())
             .collect();
 
-        match items {
-            Ok(cache_items) => Ok(cache_items),
-            Err(e) => Err(e.into()),
-        }
+        let mut warnings = Vec::new();
+        report.items.retain_mut(|item| {
+            if !item.path.is_symlink() {
+                return true;
+            }
+
+            let target = match std::fs::canonicalize(&item.path) {
+                Ok(target) => target,
+                Err(e) => {
+                    warnings.push(CleanerError::from_io(&item.path, &e));
+                    return false;
+                }
+            };
+
+            // A target equal to an allowed root would empty the whole root
+            let reason = if !target.is_dir() {
+                Some("is not a directory")
+            } else if self.config.is_excluded_path(&target) {
+                Some("is excluded")
+            } else if !allowed_roots
+                .iter()
+                .any(|allowed| target.starts_with(allowed) && target != *allowed)
+            {
+                Some("is outside the allowed roots")
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => {
+                    warnings.push(CleanerError::Skipped {
+                        path: item.path.clone(),
+                        reason: format!("symlink target {} {}", target.display(), reason),
+                    });
+                    false
+                }
+                None => {
+                    item.owner = Owner::of_path(&target);
+                    item.resolved_path = Some(target);
+                    true
+                }
+            }
+        });
+
+        report.warnings.extend(warnings);
     }
 
     /// Check if a file should be excluded based on its extension
@@ -188,9 +424,9 @@ impl CacheDetector {
     /// Classify a directory entry as a cache item
     fn classify_directory_entry(
         &self,
-        entry: &jwalk::DirEntry<((), ())>,
+        entry: &WalkEntry,
         is_user_scan: bool,
-    ) -> Result<Option<CacheItem>, String> {
+    ) -> Result<Option<CacheItem>, CleanerError> {
         let path = entry.path();
         let path_str = path.to_string_lossy().to_lowercase();
 
@@ -204,154 +440,213 @@ impl CacheDetector {
             return Ok(None);
         }
 
-        // Determine cache type based on patterns
-        let cache_type = if is_user_scan {
+        // Determine cache type based on patterns; what an interrupted run
+        // renamed aside for deletion is finished off whatever it was
+        let classified = if is_interrupted_deletion(&path) {
+            Some((
+                CacheType::TemporaryFile,
+                "left behind by an interrupted deletion".to_string(),
+            ))
+        } else if is_user_scan {
             self.classify_user_cache(&path_str)
         } else {
             self.classify_system_cache(&path_str)
         };
 
-        if let Some(cache_type) = cache_type {
-            let last_modified = std::fs::metadata(&path)
-                .ok()
-                .and_then(|m| m.modified().ok());
-
-            let cache_item = CacheItem {
-                path: path.to_path_buf(),
-                cache_type,
-                size_bytes: None, // Will be calculated later if needed
-                file_count: None,
-                last_modified,
-            };
+        if let Some((cache_type, reason)) = classified {
+            // Sizes are calculated later if needed
+            let metadata = std::fs::metadata(&path).ok();
+            let cache_item =
+                CacheItem::new(path, cache_type, reason).with_metadata(metadata.as_ref());
             Ok(Some(cache_item))
         } else {
             Ok(None)
         }
     }
 
-    /// Classify user-level cache directories
-    fn classify_user_cache(&self, path_str: &str) -> Option<CacheType> {
-        // Browser caches
-        for pattern in &self.config.cache_patterns.browser_caches {
-            if self.matches_pattern(path_str, pattern) {
-                return Some(CacheType::BrowserCache);
-            }
-        }
-
-        // Development tool caches
-        for pattern in &self.config.cache_patterns.dev_tool_caches {
-            if self.matches_pattern(path_str, pattern) {
-                return Some(CacheType::DevelopmentCache);
-            }
-        }
+    /// Whether the path is a font or icon cache that regenerates itself
+    fn is_regenerating_cache(&self, path_str: &str) -> bool {
+        self.config
+            .cache_patterns
+            .regenerating_caches
+            .iter()
+            .any(|pattern| self.matches_pattern(path_str, pattern))
+    }
 
+    /// Classify user-level cache directories, with the pattern that matched
+    fn classify_user_cache(&self, path_str: &str) -> Option<(CacheType, String)> {
+        let patterns = &self.config.cache_patterns;
         // Package manager caches (user-level)
-        for pattern in &self.config.cache_patterns.package_manager_caches {
-            if pattern.starts_with('~') && self.matches_pattern(path_str, &pattern[2..]) {
-                return Some(CacheType::PackageManagerCache);
-            }
-        }
-
-        // User cache directories
-        for pattern in &self.config.cache_patterns.user_cache_dirs {
-            if self.matches_pattern(path_str, pattern) {
-                return Some(CacheType::UserCache);
-            }
-        }
-
-        // Application cache patterns
-        for pattern in &self.config.cache_patterns.app_cache_patterns {
-            if self.matches_pattern(path_str, pattern) {
-                return Some(CacheType::ApplicationCache);
-            }
-        }
+        let user_package_caches: Vec<String> = patterns
+            .package_manager_caches
+            .iter()
+            .filter_map(|pattern| pattern.strip_prefix("~/").map(str::to_string))
+            .collect();
 
-        None
+        [
+            (
+                "regenerating_caches",
+                &patterns.regenerating_caches,
+                CacheType::RegeneratingCache,
+            ),
+            (
+                "browser_caches",
+                &patterns.browser_caches,
+                CacheType::BrowserCache,
+            ),
+            (
+                "dev_tool_caches",
+                &patterns.dev_tool_caches,
+                CacheType::DevelopmentCache,
+            ),
+            (
+                "package_manager_caches",
+                &user_package_caches,
+                CacheType::PackageManagerCache,
+            ),
+            (
+                "user_cache_dirs",
+                &patterns.user_cache_dirs,
+                CacheType::UserCache,
+            ),
+            (
+                "app_cache_patterns",
+                &patterns.app_cache_patterns,
+                CacheType::ApplicationCache,
+            ),
+        ]
+        .into_iter()
+        .find_map(|(list, patterns, cache_type)| {
+            self.classify_by(path_str, list, patterns, cache_type)
+        })
     }
 
-    /// Classify system-level cache directories
-    fn classify_system_cache(&self, path_str: &str) -> Option<CacheType> {
-        // System cache directories
-        for pattern in &self.config.cache_patterns.system_cache_dirs {
-            if self.matches_pattern(path_str, pattern) {
-                return Some(CacheType::SystemCache);
-            }
-        }
-
+    /// Classify system-level cache directories, with the pattern that matched
+    fn classify_system_cache(&self, path_str: &str) -> Option<(CacheType, String)> {
+        let patterns = &self.config.cache_patterns;
         // Package manager caches (system-level)
-        for pattern in &self.config.cache_patterns.package_manager_caches {
-            if !pattern.starts_with('~') && self.matches_pattern(path_str, pattern) {
-                return Some(CacheType::PackageManagerCache);
-            }
-        }
+        let system_package_caches: Vec<String> = patterns
+            .package_manager_caches
+            .iter()
+            .filter(|pattern| !pattern.starts_with('~'))
+            .cloned()
+            .collect();
+
+        let classified = [
+            (
+                "regenerating_caches",
+                &patterns.regenerating_caches,
+                CacheType::RegeneratingCache,
+            ),
+            (
+                "system_cache_dirs",
+                &patterns.system_cache_dirs,
+                CacheType::SystemCache,
+            ),
+            (
+                "package_manager_caches",
+                &system_package_caches,
+                CacheType::PackageManagerCache,
+            ),
+        ]
+        .into_iter()
+        .find_map(|(list, patterns, cache_type)| {
+            self.classify_by(path_str, list, patterns, cache_type)
+        });
 
         // Check if it's a user cache under system scan
-        if path_str.contains("/home/") {
-            return self.classify_user_cache(path_str);
+        match classified {
+            None if path_str.contains("/home/") => self.classify_user_cache(path_str),
+            classified => classified,
         }
+    }
 
-        None
+    /// `cache_type` with the reason, when one of `patterns` from the config
+    /// list named `list` matches
+    fn classify_by(
+        &self,
+        path_str: &str,
+        list: &str,
+        patterns: &[String],
+        cache_type: CacheType,
+    ) -> Option<(CacheType, String)> {
+        patterns
+            .iter()
+            .find(|pattern| self.matches_pattern(path_str, pattern))
+            .map(|pattern| {
+                (
+                    cache_type,
+                    format!("matches {} pattern '{}'", list, pattern),
+                )
+            })
     }
 
     /// Detect build artifacts and temporary files
-    fn detect_build_artifacts(
+    pub(crate) fn detect_build_artifacts(
         &self,
         root: &Path,
-    ) -> Result<Vec<CacheItem>, Box<dyn std::error::Error>> {
-        let mut items = Vec::new();
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        let mut report = ScanReport::new();
 
         for pattern in &self.config.cache_patterns.build_artifacts {
-            if let Ok(paths) = glob(&format!("{}/{}", root.display(), pattern)) {
-                for path in paths.flatten() {
-                    if path.exists()
-                        && !self.config.is_excluded_path(&path)
-                        && !self.is_code_file(&path)
-                    {
-                        items.push(CacheItem {
-                            path,
-                            cache_type: CacheType::BuildArtifact,
-                            size_bytes: None,
-                            file_count: None,
-                            last_modified: None,
-                        });
+            let full_pattern = format!("{}/{}", root.display(), pattern);
+            let paths = match glob(&full_pattern) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    report.warnings.push(CleanerError::Pattern {
+                        pattern: full_pattern,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            for entry in paths {
+                let path = match entry {
+                    Ok(path) => path,
+                    Err(e) => {
+                        report
+                            .warnings
+                            .push(CleanerError::from_io(e.path(), e.error()));
+                        continue;
                     }
+                };
+
+                if self.config.performance.skip_symlinks && path.is_symlink() {
+                    continue;
+                }
+
+                if path.exists()
+                    && !self.config.is_excluded_path(&path)
+                    && !self.is_code_file(&path)
+                {
+                    report.items.push(CacheItem {
+                        owner: Owner::of_path(&path),
+                        ..CacheItem::new(
+                            path,
+                            CacheType::BuildArtifact,
+                            format!("matches build_artifacts pattern '{}'", pattern),
+                        )
+                    });
                 }
             }
         }
 
-        Ok(items)
+        Ok(report)
     }
 
     /// Detect temporary files and directories
-    fn detect_temporary_files(
+    pub(crate) fn detect_temporary_files(
         &self,
         root: &Path,
-    ) -> Result<Vec<CacheItem>, Box<dyn std::error::Error>> {
-        // Configure parallel walking with jwalk
-        let max_threads = self
-            .config
-            .performance
-            .max_threads
-            .unwrap_or(rayon::current_num_threads());
-        let parallelism = if max_threads == 1 {
-            jwalk::Parallelism::Serial
-        } else {
-            jwalk::Parallelism::RayonNewPool(max_threads)
-        };
-
-        // Use parallel directory traversal with jwalk
-        let entries: Result<Vec<_>, _> = WalkDir::new(root)
-            .parallelism(parallelism)
-            .max_depth(self.config.performance.max_depth.unwrap_or(10))
-            .follow_links(!self.config.performance.skip_symlinks)
-            .into_iter()
-            .collect();
-
-        let entries = entries?;
-
-        // Use rayon for parallel processing of files
-        let items: Result<Vec<_>, _> = entries
-            .into_par_iter()
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        let mut report = ScanReport::new();
+
+        // Use rayon for parallel processing of files as the walk reads them
+        report.items = ScanWalker::new(&self.config)
+            .walk(root)
+            .entries(&mut report.warnings)
+            .par_bridge()
             .filter_map(|entry| {
                 let path = entry.path();
                 let path_str = path.to_string_lossy().to_lowercase();
@@ -387,27 +682,23 @@ impl CacheDetector {
                     };
 
                     if matches {
-                        let last_modified = std::fs::metadata(&path)
-                            .ok()
-                            .and_then(|m| m.modified().ok());
-
-                        return Some(Ok::<CacheItem, String>(CacheItem {
-                            path: path.to_path_buf(),
-                            cache_type: CacheType::TemporaryFile,
-                            size_bytes: None,
-                            file_count: None,
-                            last_modified,
-                        }));
+                        let metadata = std::fs::metadata(&path).ok();
+
+                        return Some(
+                            CacheItem::new(
+                                path.to_path_buf(),
+                                CacheType::TemporaryFile,
+                                format!("matches temp_patterns pattern '{}'", pattern),
+                            )
+                            .with_metadata(metadata.as_ref()),
+                        );
                     }
                 }
                 None
             })
             .collect();
 
-        match items {
-            Ok(cache_items) => Ok(cache_items),
-            Err(e) => Err(e.into()),
-        }
+        Ok(report)
     }
 
     /// Check if a path string matches a pattern (with simple wildcard support)
@@ -468,26 +759,10 @@ impl CacheDetector {
     }
 
     /// Remove duplicates and sort cache items
-    fn deduplicate_and_sort(
-        &self,
-        mut items: Vec<CacheItem>,
-    ) -> Result<Vec<CacheItem>, Box<dyn std::error::Error>> {
-        // Remove duplicates by path
-        items.sort_by(|a, b| a.path.cmp(&b.path));
-        items.dedup_by(|a, b| a.path == b.path);
-
-        // Remove nested items (keep only top-level cache directories)
-        let mut filtered_items = Vec::new();
-
-        for item in items {
-            let is_nested = filtered_items.iter().any(|existing: &CacheItem| {
-                item.path.starts_with(&existing.path) && item.path != existing.path
-            });
-
-            if !is_nested {
-                filtered_items.push(item);
-            }
-        }
+    fn deduplicate_and_sort(&self, items: Vec<CacheItem>) -> Result<Vec<CacheItem>, CleanerError> {
+        // Remove duplicates and nested items (keep only top-level cache
+        // directories)
+        let mut filtered_items = top_level(items);
 
         // Sort by cache type and then by path
         filtered_items.sort_by(|a, b| {
@@ -501,41 +776,219 @@ impl CacheDetector {
     }
 }
 
+/// `items` without those inside another item or at the same place, in their
+/// order. Places are compared with symlinks resolved, so an item reached
+/// through a symlinked directory or cleaned through its symlink target
+/// isn't deleted twice or while the item holding it is being deleted.
+pub fn top_level(items: Vec<CacheItem>) -> Vec<CacheItem> {
+    let places: Vec<PathBuf> = items.par_iter().map(place).collect();
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by(|&a, &b| places[a].cmp(&places[b]).then(a.cmp(&b)));
+
+    // Sorted by place, everything inside an item directly follows it
+    let mut keep = vec![false; items.len()];
+    let mut container: Option<&Path> = None;
+    for index in order {
+        if container.is_some_and(|c| places[index].starts_with(c)) {
+            continue;
+        }
+        keep[index] = true;
+        container = Some(&places[index]);
+    }
+
+    items
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(item, keep)| keep.then_some(item))
+        .collect()
+}
+
+/// `log_files` without those inside one of `items`, compared where they
+/// really are as [`top_level`] does
+pub fn logs_outside(mut log_files: Vec<LogFile>, items: &[CacheItem]) -> Vec<LogFile> {
+    let places: Vec<PathBuf> = items.iter().map(place).collect();
+    log_files.retain(|log| {
+        let place = resolve_parent(&log.path);
+        !places.iter().any(|p| place.starts_with(p))
+    });
+    log_files
+}
+
+/// Where `item` is cleaned, with symlinks resolved: its target if it is
+/// cleaned through one, else the item itself in its resolved directory
+pub fn place(item: &CacheItem) -> PathBuf {
+    match &item.resolved_path {
+        Some(target) => target.clone(),
+        None => resolve_parent(&item.path),
+    }
+}
+
+/// `path` with symlinks resolved in its directory but not in its own name
+pub fn resolve_parent(path: &Path) -> PathBuf {
+    let parent = path
+        .parent()
+        .and_then(|parent| std::fs::canonicalize(parent).ok());
+    match (parent, path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Whether `path` is a directory an earlier run renamed aside to delete
+fn is_interrupted_deletion(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(is_deleting_name)
+}
+
+/// A file met while sizing, kept for exports that need the whole tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizedFile {
+    /// Path below the item as listed; files of symlinked items are rebased
+    /// onto the link
+    pub path: PathBuf,
+    pub apparent_size: u64,
+    pub disk_size: u64,
+}
+
 /// Calculate size for cache items using parallel processing
 pub fn calculate_sizes(
     items: Vec<CacheItem>,
-    _max_threads: usize, // Parameter kept for API compatibility
-) -> Result<Vec<CacheItem>, Box<dyn std::error::Error>> {
-    let updated_items: Vec<CacheItem> = items
+    config: &Config,
+) -> Result<ScanReport<CacheItem>, CleanerError> {
+    Ok(calculate_sizes_with_files(items, config, false)?.0)
+}
+
+/// Like [`calculate_sizes`], additionally returning every file walked when
+/// `keep_files` is set, so exports need no second traversal
+pub fn calculate_sizes_with_files(
+    items: Vec<CacheItem>,
+    config: &Config,
+    keep_files: bool,
+) -> Result<(ScanReport<CacheItem>, Vec<SizedFile>), CleanerError> {
+    // Items are sized in parallel, so each walk runs serially. Sizing ignores
+    // max_depth: an item is deleted as a whole and must be counted as one.
+    let walker = ScanWalker::new(config).unbounded().serial();
+
+    let sized: Vec<(CacheItem, DirectorySize)> = items
         .into_par_iter()
         .map(|mut item| {
-            let (size, count) = calculate_directory_size(&item.path);
-            item.size_bytes = Some(size);
-            item.file_count = Some(count);
+            let size = {
+                let _permit = throttle::permit();
+                calculate_directory_size(&walker, &item, keep_files)
+            };
+            item.size_bytes = Some(size.bytes);
+            item.file_count = Some(size.file_count);
+            item.last_accessed = size.last_accessed;
+            (item, size)
+        })
+        .collect();
+
+    let mut report = ScanReport::new();
+    let mut files = Vec::new();
+    for (item, size) in sized {
+        report.warnings.extend(size.warnings);
+        // Its size would be undercounted, so nothing can vouch for cleaning it
+        if !size.complete {
+            report.warnings.push(CleanerError::Skipped {
+                path: item.path,
+                reason: "size unknown as the scan hit --timeout; left alone".to_string(),
+            });
+            continue;
+        }
+        report.items.push(item);
+        files.extend(size.files);
+    }
+
+    Ok((report, files))
+}
+
+/// Like [`calculate_sizes`], but extrapolating the size of large trees from a
+/// sample of their directories (`--estimate`). Trees small enough to be read
+/// whole and single files are still sized exactly; access times are left out.
+pub fn estimate_sizes(items: Vec<CacheItem>, config: &Config) -> ScanReport<CacheItem> {
+    let mut report = ScanReport::new();
+    report.items = items
+        .into_par_iter()
+        .map(|mut item| {
+            let _permit = throttle::permit();
+            let target = item.target_path();
+            if target.is_dir() {
+                let size = estimate::estimate_size(target, config);
+                item.size_bytes = Some(size.bytes);
+                item.file_count = Some(size.files as usize);
+                item.size_estimated = !size.exact;
+            } else {
+                let bytes = std::fs::metadata(target).map_or(0, |m| m.len());
+                item.size_bytes = Some(bytes);
+                item.file_count = Some(1);
+            }
             item
         })
         .collect();
+    report
+}
 
-    Ok(updated_items)
+/// Total size of everything below one item
+struct DirectorySize {
+    bytes: u64,
+    file_count: usize,
+    /// Directory access times are left out: walking them updates them
+    last_accessed: Option<SystemTime>,
+    /// Whether the walk got through before the scan deadline
+    complete: bool,
+    warnings: Vec<CleanerError>,
+    files: Vec<SizedFile>,
 }
 
 /// Calculate the total size and file count of a directory
-fn calculate_directory_size(path: &Path) -> (u64, usize) {
-    let mut total_size = 0u64;
-    let mut file_count = 0usize;
+fn calculate_directory_size(
+    walker: &ScanWalker,
+    item: &CacheItem,
+    keep_files: bool,
+) -> DirectorySize {
+    let target = item.target_path();
+    let mut size = DirectorySize {
+        bytes: 0,
+        file_count: 0,
+        last_accessed: None,
+        complete: true,
+        warnings: Vec::new(),
+        files: Vec::new(),
+    };
+
+    for entry in walker.walk(target) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(CleanerError::TimedOut(_)) => {
+                size.complete = false;
+                continue;
+            }
+            Err(warning) => {
+                size.warnings.push(warning);
+                continue;
+            }
+        };
+        if entry.file_type().is_file()
+            && let Ok(metadata) = entry.metadata()
+        {
+            size.bytes += metadata.len();
+            size.file_count += 1;
+            size.last_accessed = size.last_accessed.max(metadata.accessed().ok());
 
-    for entry in WalkDir::new(path)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.file_type().is_file())
-    {
-        if let Ok(metadata) = entry.metadata() {
-            total_size += metadata.len();
-            file_count += 1;
+            if keep_files {
+                let path = entry.path();
+                let relative = path.strip_prefix(target).unwrap_or(Path::new(""));
+                size.files.push(SizedFile {
+                    path: item.path.join(relative),
+                    apparent_size: metadata.len(),
+                    disk_size: metadata.blocks() * 512,
+                });
+            }
         }
     }
 
-    (total_size, file_count)
+    size
 }
 
 #[cfg(test)]
@@ -568,7 +1021,136 @@ mod tests {
         let config = Config::default();
         let detector = CacheDetector::new(config);
 
-        let items = detector.detect_cache_items(temp_dir.path()).unwrap();
-        assert!(!items.is_empty());
+        let report = detector.detect_cache_items(temp_dir.path()).unwrap();
+        assert!(!report.items.is_empty());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_regenerating_caches() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR")).join("target");
+        std::fs::create_dir_all(&base).unwrap();
+        let temp_dir = TempDir::new_in(&base).unwrap();
+        let theme = temp_dir.path().join(".local/share/icons/hicolor");
+        std::fs::create_dir_all(&theme).unwrap();
+        std::fs::write(theme.join("icon-theme.cache"), b"cache").unwrap();
+
+        let detector = CacheDetector::new(Config::default());
+        let report = detector.detect_cache_items(temp_dir.path()).unwrap();
+        let item = report
+            .items
+            .iter()
+            .find(|i| i.path == theme.join("icon-theme.cache"))
+            .unwrap();
+        assert_eq!(item.cache_type, CacheType::RegeneratingCache);
+        assert!(item.cache_type.is_regenerating());
+        assert_eq!(
+            item.reason,
+            "matches regenerating_caches pattern '*/icons/*/icon-theme.cache'"
+        );
+    }
+
+    #[test]
+    fn test_sizing_records_latest_file_access() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        std::fs::create_dir_all(cache.join("nested")).unwrap();
+        std::fs::write(cache.join("old"), b"1").unwrap();
+        std::fs::write(cache.join("nested/new"), b"22").unwrap();
+        let newest = ["old", "nested/new"]
+            .iter()
+            .map(|f| {
+                std::fs::metadata(cache.join(f))
+                    .unwrap()
+                    .accessed()
+                    .unwrap()
+            })
+            .max();
+
+        let item = CacheItem::new(cache, CacheType::UserCache, "");
+        let (report, _) =
+            calculate_sizes_with_files(vec![item], &Config::default(), false).unwrap();
+        assert_eq!(report.items[0].size_bytes, Some(3));
+        assert_eq!(report.items[0].last_accessed, newest);
+    }
+
+    #[test]
+    fn test_symlink_targets_confined_to_roots() {
+        // Anything under /tmp is itself classified as temporary, so stay out of it
+        let base = Path::new(env!("CARGO_MANIFEST_DIR")).join("target");
+        std::fs::create_dir_all(&base).unwrap();
+        let temp_dir = TempDir::new_in(&base).unwrap();
+        let root = temp_dir.path().join("home");
+        let inside = root.join("disk").join("cache-data");
+        let outside = temp_dir.path().join("elsewhere");
+        std::fs::create_dir_all(&inside).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&inside, root.join(".cache")).unwrap();
+        std::fs::create_dir(root.join("app")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("app").join("cache")).unwrap();
+
+        let mut config = Config::default();
+        config.safety.resolve_symlink_targets = true;
+        let detector = CacheDetector::new(config);
+        let report = detector.detect_cache_items(&root).unwrap();
+
+        let cache = report
+            .items
+            .iter()
+            .find(|item| item.path == root.join(".cache"))
+            .expect("symlinked .cache should be detected");
+        assert_eq!(
+            cache.resolved_path,
+            Some(std::fs::canonicalize(&inside).unwrap())
+        );
+        assert!(
+            !report
+                .items
+                .iter()
+                .any(|item| item.path == root.join("app").join("cache"))
+        );
+        assert!(report.warnings.iter().any(|w| matches!(
+            w,
+            CleanerError::Skipped { path, .. } if *path == root.join("app").join("cache")
+        )));
+    }
+
+    #[test]
+    fn test_usage_by_category() {
+        let usage = usage_by_category([
+            (LOG_CATEGORY, 5),
+            ("build", 100),
+            ("user", 10),
+            ("build", 50),
+            ("unknown", 1),
+        ]);
+        let totals: Vec<(&str, usize, u64)> = usage
+            .iter()
+            .map(|u| (u.category.as_str(), u.items, u.bytes))
+            .collect();
+        assert_eq!(
+            totals,
+            [("user", 1, 10), ("build", 2, 150), (LOG_CATEGORY, 1, 5)]
+        );
+    }
+
+    #[test]
+    fn test_top_level_across_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = temp_dir.path().join("data");
+        std::fs::create_dir_all(data.join("cache/pip")).unwrap();
+        std::os::unix::fs::symlink(&data, temp_dir.path().join("link")).unwrap();
+        let item = |path: PathBuf| CacheItem::new(path, CacheType::UserCache, "");
+
+        // The same cache through a symlink, and one inside it from another
+        // source, go with the first item holding them
+        let items = vec![
+            item(temp_dir.path().join("link/cache/pip")),
+            item(data.join("cache")),
+            item(temp_dir.path().join("link/cache")),
+            item(temp_dir.path().join("other")),
+        ];
+        let kept: Vec<PathBuf> = top_level(items).into_iter().map(|i| i.path).collect();
+        assert_eq!(kept, [data.join("cache"), temp_dir.path().join("other")]);
     }
 }
@@ -0,0 +1,243 @@
+use crate::error::CleanerError;
+use crate::units;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Mount point of the unified cgroup v2 hierarchy
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Period `cpu.max` quotas are expressed in, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+/// Prefix of the cgroups the cleaner creates for itself
+const CGROUP_PREFIX: &str = "cleaner-";
+
+/// The cgroup this process moved itself into, once confined
+static CONFINED: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// One resource limit of `--cgroup-limits`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgroupLimit {
+    /// Share of one CPU, in percent (above 100 allows several CPUs)
+    Cpu(f64),
+    /// Read and write bandwidth on the scanned device, in bytes per second
+    Io(u64),
+    /// Memory the process may use, in bytes
+    Memory(u64),
+}
+
+/// Parse one `--cgroup-limits` value: `cpu=50%`, `io=50M` or `memory=1G`
+pub fn parse_limit(value: &str) -> Result<CgroupLimit, String> {
+    let (name, amount) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", value))?;
+    match name {
+        "cpu" => {
+            let percent: f64 = amount
+                .strip_suffix('%')
+                .ok_or_else(|| format!("cpu takes a percentage like 50%, got '{}'", amount))?
+                .parse()
+                .map_err(|_| format!("invalid cpu percentage '{}'", amount))?;
+            if percent.is_nan() || percent <= 0.0 {
+                return Err("cpu percentage must be above zero".to_string());
+            }
+            Ok(CgroupLimit::Cpu(percent))
+        }
+        "io" => Ok(CgroupLimit::Io(nonzero_size(amount)?)),
+        "memory" => Ok(CgroupLimit::Memory(nonzero_size(amount)?)),
+        _ => Err(format!(
+            "unknown limit '{}' (supported: cpu, io, memory)",
+            name
+        )),
+    }
+}
+
+fn nonzero_size(amount: &str) -> Result<u64, String> {
+    match units::parse_size(amount)? {
+        0 => Err("limit must be above zero".to_string()),
+        bytes => Ok(bytes),
+    }
+}
+
+/// Move this process into a new cgroup v2 group with `limits`, next to the
+/// one it runs in, the way `systemd-run --scope` would. Later calls only add
+/// the I/O limit for the device of another `scan_root`.
+pub fn confine(limits: &[CgroupLimit], scan_root: &Path) -> Result<PathBuf, CleanerError> {
+    let mut confined = CONFINED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(group) = confined.as_ref() {
+        write_io_limits(group, limits, scan_root)?;
+        return Ok(group.clone());
+    }
+
+    let root = Path::new(CGROUP_ROOT);
+    if !root.join("cgroup.controllers").is_file() {
+        return Err(CleanerError::Cgroup(format!(
+            "no cgroup v2 hierarchy mounted at {}",
+            CGROUP_ROOT
+        )));
+    }
+    let current = fs::read_to_string("/proc/self/cgroup")
+        .map_err(|e| CleanerError::from_io(Path::new("/proc/self/cgroup"), &e))?;
+    let current = current
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| CleanerError::Cgroup("process is not in a cgroup v2 group".to_string()))?;
+
+    let group = create_group(root, current, limits)?;
+    write_io_limits(&group, limits, scan_root)?;
+    let procs = group.join("cgroup.procs");
+    fs::write(&procs, std::process::id().to_string())
+        .map_err(|e| CleanerError::from_io(&procs, &e))?;
+    *confined = Some(group.clone());
+    Ok(group)
+}
+
+/// Create the cleaner's group next to `current` below `root`, with the CPU
+/// and memory limits set, clearing out empty groups of earlier runs
+fn create_group(
+    root: &Path,
+    current: &str,
+    limits: &[CgroupLimit],
+) -> Result<PathBuf, CleanerError> {
+    let current = root.join(current.trim().trim_start_matches('/'));
+    // A group holding processes can't delegate controllers, so the new group
+    // goes next to the current one; the root group is exempt
+    let parent = if current == root {
+        root
+    } else {
+        current.parent().unwrap_or(root)
+    };
+    remove_stale_groups(parent);
+
+    let controllers: String = limits
+        .iter()
+        .map(|limit| match limit {
+            CgroupLimit::Cpu(_) => " +cpu",
+            CgroupLimit::Io(_) => " +io",
+            CgroupLimit::Memory(_) => " +memory",
+        })
+        .collect();
+    let subtree = parent.join("cgroup.subtree_control");
+    fs::write(&subtree, controllers.trim()).map_err(|e| CleanerError::from_io(&subtree, &e))?;
+
+    let group = parent.join(format!("{}{}", CGROUP_PREFIX, std::process::id()));
+    fs::create_dir(&group).map_err(|e| CleanerError::from_io(&group, &e))?;
+    for limit in limits {
+        let (file, value) = match limit {
+            CgroupLimit::Cpu(percent) => (
+                "cpu.max",
+                format!(
+                    "{} {}",
+                    (percent / 100.0 * CPU_PERIOD as f64).round().max(1000.0) as u64,
+                    CPU_PERIOD
+                ),
+            ),
+            CgroupLimit::Memory(bytes) => ("memory.max", bytes.to_string()),
+            CgroupLimit::Io(_) => continue,
+        };
+        let path = group.join(file);
+        fs::write(&path, value).map_err(|e| CleanerError::from_io(&path, &e))?;
+    }
+    Ok(group)
+}
+
+/// Limit reads and writes on the disk holding `scan_root`
+fn write_io_limits(
+    group: &Path,
+    limits: &[CgroupLimit],
+    scan_root: &Path,
+) -> Result<(), CleanerError> {
+    let Some(bytes) = limits.iter().find_map(|limit| match limit {
+        CgroupLimit::Io(bytes) => Some(*bytes),
+        _ => None,
+    }) else {
+        return Ok(());
+    };
+    let metadata = fs::metadata(scan_root).map_err(|e| CleanerError::from_io(scan_root, &e))?;
+    let device = disk_of(metadata.dev()).ok_or_else(|| {
+        CleanerError::Cgroup(format!(
+            "{} is not on a block device, so its I/O can't be limited",
+            scan_root.display()
+        ))
+    })?;
+    let path = group.join("io.max");
+    fs::write(&path, format!("{} rbps={} wbps={}", device, bytes, bytes))
+        .map_err(|e| CleanerError::from_io(&path, &e))
+}
+
+/// `MAJOR:MINOR` of the whole disk behind device `dev`, as `io.max` takes no
+/// partitions
+fn disk_of(dev: u64) -> Option<String> {
+    let id = format!("{}:{}", libc::major(dev), libc::minor(dev));
+    let sys = PathBuf::from("/sys/dev/block").join(&id);
+    if !sys.exists() {
+        return None;
+    }
+    if sys.join("partition").exists() {
+        return fs::read_to_string(sys.join("..").join("dev"))
+            .ok()
+            .map(|dev| dev.trim().to_string());
+    }
+    Some(id)
+}
+
+/// Remove the empty groups earlier runs left behind; a group can't be
+/// removed by the process inside it
+fn remove_stale_groups(parent: &Path) {
+    let Ok(entries) = fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_ours = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(CGROUP_PREFIX));
+        let is_empty = fs::read_to_string(entry.path().join("cgroup.procs"))
+            .is_ok_and(|procs| procs.trim().is_empty());
+        if is_ours && is_empty {
+            fs::remove_dir(entry.path()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cgroup_limits() {
+        assert_eq!(parse_limit("cpu=50%"), Ok(CgroupLimit::Cpu(50.0)));
+        assert_eq!(parse_limit("io=50M"), Ok(CgroupLimit::Io(50 << 20)));
+        assert_eq!(parse_limit("memory=1GiB"), Ok(CgroupLimit::Memory(1 << 30)));
+        assert!(parse_limit("cpu=50").is_err());
+        assert!(parse_limit("io=0").is_err());
+        assert!(parse_limit("disk=5M").is_err());
+
+        // The group goes next to the current one, which holds processes
+        let temp_dir = TempDir::new().unwrap();
+        let slice = temp_dir.path().join("user.slice");
+        fs::create_dir_all(slice.join("session.scope")).unwrap();
+        let group = create_group(
+            temp_dir.path(),
+            "/user.slice/session.scope\n",
+            &[CgroupLimit::Cpu(50.0), CgroupLimit::Memory(1 << 30)],
+        )
+        .unwrap();
+        assert_eq!(group.parent(), Some(slice.as_path()));
+        assert_eq!(
+            fs::read_to_string(slice.join("cgroup.subtree_control")).unwrap(),
+            "+cpu +memory"
+        );
+        assert_eq!(
+            fs::read_to_string(group.join("cpu.max")).unwrap(),
+            "50000 100000"
+        );
+        assert_eq!(
+            fs::read_to_string(group.join("memory.max")).unwrap(),
+            "1073741824"
+        );
+    }
+}
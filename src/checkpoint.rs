@@ -0,0 +1,258 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::error::CleanerError;
+use crate::file_operations::DELETING_PREFIX;
+use crate::log_cleaner::LogFile;
+use crate::owners::Owner;
+use crate::plan::{Plan, PlanEntry};
+use crate::state;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Directory below the state directory holding the checkpoint
+const CHECKPOINT_DIR: &str = "checkpoint";
+
+/// The items of the clean, written once when it starts
+const PLAN_FILE: &str = "plan.json";
+
+/// One line per item the clean started and finished
+const JOURNAL_FILE: &str = "journal";
+
+/// Journal of the clean in progress, once one began
+static JOURNAL: Mutex<Option<File>> = Mutex::new(None);
+
+/// What a clean set out to delete, and which process did the deleting
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    pid: u32,
+    plan: Plan,
+}
+
+/// Progress on one item, appended to the journal as it happens
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalEntry {
+    Started(PathBuf),
+    Done(PathBuf),
+}
+
+/// What an interrupted clean left to do
+#[derive(Debug)]
+pub struct Resume {
+    /// Items not started yet, still to be verified like a plan
+    pub plan: Plan,
+    /// Items being deleted when the clean stopped, which have changed by
+    /// design and are deleted without verification
+    pub partial: Vec<PlanEntry>,
+    /// Process that deleted them, whose renamed-aside directories are left
+    pid: u32,
+}
+
+impl Resume {
+    /// The partial items, plus whatever their deletion had renamed aside
+    pub fn partial_items(&self) -> (Vec<CacheItem>, Vec<LogFile>) {
+        let mut cache_items: Vec<CacheItem> = self
+            .partial
+            .iter()
+            .filter(|e| fs::symlink_metadata(&e.path).is_ok())
+            .filter_map(|e| e.to_cache_item())
+            .collect();
+        let log_files = self
+            .partial
+            .iter()
+            .filter(|e| fs::symlink_metadata(&e.path).is_ok())
+            .filter_map(|e| e.to_log_file())
+            .collect();
+
+        let prefix = format!("{}{}-", DELETING_PREFIX, self.pid);
+        let parents: HashSet<&Path> = self
+            .partial
+            .iter()
+            .filter_map(|e| e.path.parent())
+            .collect();
+        for parent in parents {
+            let Ok(entries) = fs::read_dir(parent) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                    cache_items.push(CacheItem {
+                        owner: Owner::of_path(&entry.path()),
+                        ..CacheItem::new(
+                            entry.path(),
+                            CacheType::TemporaryFile,
+                            "left behind by an interrupted deletion",
+                        )
+                    });
+                }
+            }
+        }
+        (cache_items, log_files)
+    }
+}
+
+fn dir() -> PathBuf {
+    state::dir().join(CHECKPOINT_DIR)
+}
+
+/// Whether an interrupted clean left a checkpoint
+pub fn exists() -> bool {
+    dir().join(PLAN_FILE).is_file()
+}
+
+/// Record the items a clean is about to delete, replacing any earlier
+/// checkpoint, and journal their progress until `finish`
+pub fn begin(
+    scan_root: &Path,
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+) -> Result<(), CleanerError> {
+    let journal = begin_in(&dir(), scan_root, cache_items, log_files)?;
+    *lock() = Some(journal);
+    Ok(())
+}
+
+fn begin_in(
+    dir: &Path,
+    scan_root: &Path,
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+) -> Result<File, CleanerError> {
+    let header = Header {
+        pid: std::process::id(),
+        plan: Plan::from_items(scan_root, cache_items, log_files),
+    };
+    let content = serde_json::to_vec(&header).map_err(|e| CleanerError::Plan(e.to_string()))?;
+    // The journal is emptied first, so it never belongs to another plan
+    let journal_path = dir.join(JOURNAL_FILE);
+    fs::create_dir_all(dir).map_err(|e| CleanerError::from_io(dir, &e))?;
+    File::create(&journal_path).map_err(|e| CleanerError::from_io(&journal_path, &e))?;
+    state::write_atomic(&dir.join(PLAN_FILE), &content)?;
+    OpenOptions::new()
+        .append(true)
+        .open(&journal_path)
+        .map_err(|e| CleanerError::from_io(&journal_path, &e))
+}
+
+/// Note that deleting the item at `path` began
+pub fn started(path: &Path) {
+    append(JournalEntry::Started(path.to_path_buf()));
+}
+
+/// Note that the item at `path` needs no more work, deleted or not
+pub fn done(path: &Path) {
+    append(JournalEntry::Done(path.to_path_buf()));
+}
+
+/// Each entry is a single write to a file opened for appending, so a killed
+/// run loses at most the line being written
+fn append(entry: JournalEntry) {
+    let mut journal = lock();
+    let Some(file) = journal.as_mut() else {
+        return;
+    };
+    if let Ok(mut line) = serde_json::to_vec(&entry) {
+        line.push(b'\n');
+        file.write_all(&line).ok();
+    }
+}
+
+/// Drop the checkpoint once the clean ran to its end
+pub fn finish() {
+    if lock().take().is_some() {
+        fs::remove_dir_all(dir()).ok();
+    }
+}
+
+/// What the checkpoint of an interrupted clean left to do, if there is one
+pub fn load() -> Result<Option<Resume>, CleanerError> {
+    load_from(&dir())
+}
+
+fn load_from(dir: &Path) -> Result<Option<Resume>, CleanerError> {
+    let plan_path = dir.join(PLAN_FILE);
+    let content = match fs::read_to_string(&plan_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(CleanerError::from_io(&plan_path, &e)),
+    };
+    let Header { pid, mut plan } = serde_json::from_str(&content)
+        .map_err(|e| CleanerError::Plan(format!("{}: {}", plan_path.display(), e)))?;
+
+    let mut started = HashSet::new();
+    let mut done = HashSet::new();
+    // A torn last line is an entry that never made it
+    let journal = fs::read_to_string(dir.join(JOURNAL_FILE)).unwrap_or_default();
+    for entry in journal.lines().filter_map(|l| serde_json::from_str(l).ok()) {
+        match entry {
+            JournalEntry::Started(path) => started.insert(path),
+            JournalEntry::Done(path) => done.insert(path),
+        };
+    }
+
+    plan.entries.retain(|e| !done.contains(&e.path));
+    let (partial, untouched) = plan
+        .entries
+        .into_iter()
+        .partition(|e| started.contains(&e.path));
+    plan.entries = untouched;
+    Ok(Some(Resume { plan, partial, pid }))
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<File>> {
+    JOURNAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache_item(path: PathBuf) -> CacheItem {
+        CacheItem::new(path, CacheType::UserCache, "")
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("checkpoint");
+        let [done, partial, untouched] = ["done", "partial", "untouched"].map(|name| {
+            let path = temp_dir.path().join(name);
+            fs::create_dir(&path).unwrap();
+            path
+        });
+        let items = [&done, &partial, &untouched].map(|p| cache_item(p.clone()));
+        let mut journal = begin_in(&dir, temp_dir.path(), &items, &[]).unwrap();
+        for entry in [
+            JournalEntry::Started(done.clone()),
+            JournalEntry::Done(done.clone()),
+            JournalEntry::Started(partial.clone()),
+        ] {
+            writeln!(journal, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+        }
+        write!(journal, "{{\"done\":\"/tor").unwrap();
+
+        // The partial item was renamed aside when the run was killed
+        fs::remove_dir(&done).unwrap();
+        let aside = temp_dir
+            .path()
+            .join(format!("{}{}-0", DELETING_PREFIX, std::process::id()));
+        fs::rename(&partial, &aside).unwrap();
+
+        let resume = load_from(&dir).unwrap().unwrap();
+        let paths = |entries: &[PlanEntry]| -> Vec<PathBuf> {
+            entries.iter().map(|e| e.path.clone()).collect()
+        };
+        assert_eq!(paths(&resume.plan.entries), [untouched]);
+        assert_eq!(paths(&resume.partial), [partial]);
+        let (cache_items, log_files) = resume.partial_items();
+        assert_eq!(cache_items.len(), 1);
+        assert_eq!(cache_items[0].path, aside);
+        assert!(log_files.is_empty());
+
+        assert!(load_from(&temp_dir.path().join("none")).unwrap().is_none());
+    }
+}
@@ -1,13 +1,56 @@
-use clap::{Arg, ArgAction, Command};
-use std::path::PathBuf;
+use crate::cache_detector::CATEGORIES;
+use crate::cgroup::{self, CgroupLimit};
+use crate::config::LogAction;
+use crate::events::EventFormat;
+use crate::log_cleaner::LogType;
+use crate::mounts::FreeThreshold;
+use crate::output::{OutputFormat, SCHEMA_NAMES};
+use crate::owners;
+use crate::selection::Selection;
+use crate::temperature::Temperature;
+use crate::units;
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Command line interface configuration
 #[derive(Debug, Clone)]
 pub struct CliArgs {
     /// Root path to scan for cache directories
     pub path: PathBuf,
+    /// No path was given, so the detected default roots are scanned
+    /// instead of `path`
+    pub default_roots: bool,
+    /// Other roots of the same run inside `path`, which their own scans
+    /// cover and this one leaves out
+    pub nested_roots: Vec<PathBuf>,
     /// Actually delete the found cache and log files
     pub clean: bool,
+    /// Choose the items to clean in a fuzzy finder
+    pub pick: bool,
+    /// Drill into the detected items and clean at any depth
+    pub explore: bool,
+    /// Ask about every item before cleaning it
+    pub ask: bool,
+    /// Move cleaned items to the trash instead of deleting them
+    pub trash: bool,
+    /// Overwrite files before deleting them
+    pub shred: bool,
+    /// Clean only these numbers of the listing; `Some(None)` asks for them
+    pub select: Option<Option<Selection>>,
+    /// Only these categories are actually cleaned; the rest are reported
+    pub clean_types: Option<Vec<String>>,
+    /// Categories that are only reported, as in a dry run
+    pub dry_run_types: Vec<String>,
+    /// Also report unreferenced flatpak objects and stale DKMS builds
+    pub system_reclaim: bool,
+    /// Clean unused browser profiles instead of only reporting them
+    pub clean_unused_profiles: bool,
+    /// Also find what deleted Kubernetes pods left on this node
+    pub kubernetes_node: bool,
+    /// Work out how much of the projected savings hard links and reflinks keep allocated
+    pub actual_savings: bool,
     /// Show what would be deleted without actually deleting
     pub dry_run: bool,
     /// Enable verbose output
@@ -17,32 +60,378 @@ pub struct CliArgs {
     /// Enable log cleanup
     pub clean_logs: bool,
     /// Override log age threshold (in days)
-    pub log_age_days: Option<u64>,
+    pub log_age: Option<Duration>,
+    /// Override of how old logs are cleaned
+    pub log_action: Option<LogAction>,
+    /// Only consider log files of these types
+    pub log_types: Option<Vec<LogType>>,
+    /// Leave out log files of these types
+    pub exclude_log_types: Vec<LogType>,
+    /// Only consider items last modified longer ago than this
+    pub older_than: Option<Duration>,
+    /// Only consider items whose files weren't read for this long
+    pub not_accessed_for: Option<Duration>,
+    /// Only consider caches rated this hot or cold
+    pub only: Option<Temperature>,
+    /// Clean only while the scan root's filesystem has less space available
+    pub only_if_free_below: Option<FreeThreshold>,
+    /// Only consider items owned by this UID
+    pub owner: Option<u32>,
     /// Force cleanup without confirmation
     pub force: bool,
+    /// Skip a scan root that doesn't exist instead of failing
+    pub ignore_missing: bool,
+    /// Wait while the machine is on battery or busy, as `[schedule]` says
+    pub scheduled: bool,
+    /// Resource limits of the cgroup the process moves itself into
+    pub cgroup_limits: Vec<CgroupLimit>,
     /// Show detailed size information
     pub show_sizes: bool,
+    /// Extrapolate the sizes of large items from a sample of their trees
+    pub estimate: bool,
     /// Only show summary without listing individual items
     pub summary_only: bool,
+    /// Write a deletion script to this path instead of deleting
+    pub emit_script: Option<PathBuf>,
+    /// Print findings and results as JSON on stdout instead of text
+    pub output_format: OutputFormat,
+    /// Stream the steps of the run as they happen (`--events`)
+    pub events: Option<EventFormat>,
+    /// Export the findings in this format to this path
+    pub export: Option<(ExportFormat, PathBuf)>,
+    /// Hide user and project names in exported reports
+    pub redact_paths: bool,
+    /// Override the number of worker threads
+    pub threads: Option<usize>,
+    /// Stop scanning after this long and work with what was found
+    pub timeout: Option<Duration>,
+    /// Don't clean anything when the scan hit the timeout
+    pub abort_on_timeout: bool,
+    /// Write the final outcome as JSON to this path, however the run ends
+    pub result_file: Option<PathBuf>,
+    /// Report in the result file whether anything was actually deleted
+    pub changed_when_freed: bool,
+    /// Plan/apply workflow subcommand, if one was given
+    pub command: Option<CliCommand>,
+}
+
+impl CliArgs {
+    /// Whether log files of `log_type` pass `--log-types` and
+    /// `--exclude-log-types`
+    pub fn includes_log_type(&self, log_type: &LogType) -> bool {
+        self.log_types
+            .as_ref()
+            .is_none_or(|types| types.contains(log_type))
+            && !self.exclude_log_types.contains(log_type)
+    }
+
+    /// Whether some categories are cleaned while others are only reported
+    pub fn mixes_modes(&self) -> bool {
+        self.clean_types.is_some() || !self.dry_run_types.is_empty()
+    }
+
+    /// Whether items of `category` are actually cleaned, rather than only
+    /// reported, in a mixed run
+    pub fn cleans_category(&self, category: &str) -> bool {
+        let chosen = match &self.clean_types {
+            Some(types) => types.iter().any(|t| t == category),
+            None => self.clean,
+        };
+        chosen && !self.dry_run_types.iter().any(|t| t == category)
+    }
+}
+
+/// Subcommands of the plan/apply workflow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Scan and record the deletion set in a plan file
+    Plan { output: PathBuf },
+    /// Execute a previously written plan file
+    Apply { plan: PathBuf },
+    /// Look for old log files only, leaving caches out of the scan
+    Logs,
+    /// Summarize the recorded scan history without scanning
+    Stats,
+    /// Continue a clean that was killed, from its checkpoint
+    Resume,
+    /// Move the items of a backup manifest back from the trash
+    Restore { backup: PathBuf },
+    /// Replace this binary with the latest signed release
+    SelfUpdate { check_only: bool },
+    /// Print the JSON Schema of one or all output formats
+    Schema { name: Option<String> },
+    /// Compare a fresh scan with the previous one of the same root
+    Diff { output: Option<PathBuf> },
+    /// Check the configuration for dangerous, dead and redundant entries
+    ConfigLint,
+    /// Clean caches of users over the per-user quota
+    Quota { report_only: bool },
+    /// Scan and break the findings down without cleaning
+    Report {
+        by_app: bool,
+        output: Option<PathBuf>,
+    },
+    /// Scan, record and alert for scheduled runs, never deleting anything
+    Monitor { metrics: Option<PathBuf> },
+    /// Find the build output of the projects in a directory
+    Project {
+        /// Clean only the output of stale projects
+        sweep: bool,
+        /// Overrides `projects.stale_after`
+        stale_after: Option<Duration>,
+    },
 }
 
 impl Default for CliArgs {
     fn default() -> Self {
         Self {
             path: PathBuf::from("/"),
+            default_roots: false,
+            nested_roots: Vec::new(),
             clean: false,
+            pick: false,
+            explore: false,
+            ask: false,
+            trash: false,
+            shred: false,
+            select: None,
+            clean_types: None,
+            dry_run_types: Vec::new(),
+            system_reclaim: false,
+            clean_unused_profiles: false,
+            kubernetes_node: false,
+            actual_savings: false,
             dry_run: false,
             verbose: false,
             config: None,
             clean_logs: false,
-            log_age_days: None,
+            log_age: None,
+            log_action: None,
+            log_types: None,
+            exclude_log_types: Vec::new(),
+            older_than: None,
+            not_accessed_for: None,
+            only: None,
+            only_if_free_below: None,
+            owner: None,
             force: false,
+            ignore_missing: false,
+            scheduled: false,
+            cgroup_limits: Vec::new(),
             show_sizes: true,
+            estimate: false,
             summary_only: false,
+            emit_script: None,
+            output_format: OutputFormat::Text,
+            events: None,
+            export: None,
+            redact_paths: false,
+            threads: None,
+            timeout: None,
+            abort_on_timeout: false,
+            result_file: None,
+            changed_when_freed: false,
+            command: None,
         }
     }
 }
 
+/// File format of `--export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// ncdu's JSON export of the scanned tree
+    Ncdu,
+    /// One spreadsheet row per found item
+    Csv,
+}
+
+/// Positional scan root, shared by the top-level command and the subcommands
+/// that scan
+fn path_arg() -> Arg {
+    Arg::new("path")
+        .help("Root path to scan for cache directories and log files")
+        .long_help(
+            "The root directory to scan for cache directories and log files. \
+             Use '/' for system-wide scanning or specify a user directory like '/home/user'. \
+             System-wide scanning requires root privileges for full access. Without a path \
+             your home directory is scanned, plus /var and /tmp when running as root; \
+             --all scans '/' instead.",
+        )
+        .index(1)
+}
+
+/// `--clean`, which deletes what the scan found
+fn clean_arg() -> Arg {
+    Arg::new("clean")
+        .long("clean")
+        .short('c')
+        .help("Actually delete the found cache directories and files")
+        .long_help(
+            "Enable deletion mode. Without this flag, the tool will only scan and report \
+             what would be deleted. This is the recommended way to first understand \
+             what the tool would clean before actually running the cleanup.",
+        )
+        .action(ArgAction::SetTrue)
+}
+
+/// Ways to choose among the found items and to remove them
+fn selection_args() -> Vec<Arg> {
+    vec![
+        Arg::new("pick")
+            .long("pick")
+            .short('p')
+            .help("Pick the items to clean in a fuzzy finder")
+            .long_help(
+                "Open the detected items in a fuzzy finder. Type fragments of a path to \
+                 narrow the list, toggle items with space and confirm with enter; only \
+                 the selected items are then cleaned. Combine with --dry-run to preview.",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("explore")
+            .long("explore")
+            .short('e')
+            .help("Browse into the detected items and pick what to clean at any depth")
+            .long_help(
+                "Open an ncdu-like explorer over the detected items. Enter an item to see \
+                 the size of every directory and file inside it, largest first, and keep \
+                 drilling down; mark whole items, single subdirectories or files, then \
+                 choose 'Done' to clean only what was marked. Escape goes back up. \
+                 Combine with --dry-run to preview.",
+            )
+            .action(ArgAction::SetTrue)
+            .conflicts_with("pick"),
+        Arg::new("select")
+            .long("select")
+            .help("Clean only the listed items with these numbers, e.g. --select 1,3,7-12")
+            .long_help(
+                "Clean only the items with these numbers in the listing, given as numbers \
+                 and ranges separated by commas, e.g. 1,3,7-12. Cache items are numbered \
+                 first and old log files after them. Without a LIST the numbers are asked \
+                 for once the listing is shown. Combine with --dry-run to preview.",
+            )
+            .value_name("LIST")
+            .num_args(0..=1)
+            .value_parser(Selection::parse),
+        Arg::new("ask")
+            .long("ask")
+            .help("Ask before cleaning each item")
+            .long_help(
+                "Ask about every item before cleaning it: y cleans it, n keeps it, a cleans \
+                 it and all the rest, q keeps it and all the rest, and s keeps it and every \
+                 remaining item of its type, so one category can be turned down at once. \
+                 Answering each item replaces the confirmation of large cleans. Combine \
+                 with --dry-run to preview.",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("trash")
+            .long("trash")
+            .help("Move cleaned items to the trash instead of deleting them")
+            .long_help(
+                "Move cleaned items to the trash instead of deleting them, so a file manager \
+                 can restore an accidental clean. Items go to the home trash \
+                 ($XDG_DATA_HOME/Trash) with the usual .trashinfo record, or to the .Trash \
+                 directory at the top of their volume when they are on another filesystem; \
+                 nothing is copied between filesystems. No space is freed until the trash \
+                 is emptied. Trash directories and live logs that are truncated are still \
+                 cleaned in place.",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("shred")
+            .long("shred")
+            .help("Overwrite files with random data before deleting them")
+            .long_help(
+                "Overwrite every file with random data before deleting it, so browser \
+                 caches, cookies and session files of a shared machine can't be read back \
+                 from the disk. The number of passes is set by safety.shred_passes \
+                 (default 3). Files with other hard links are only unlinked, and live logs \
+                 that are truncated are not overwritten. SSDs, copy-on-write filesystems \
+                 and journals may keep old copies regardless; combine with --clean-types \
+                 to shred only some categories, as overwriting is far slower than deleting.",
+            )
+            .conflicts_with("trash")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// Categories cleaned and only reported in the same run
+fn category_args() -> [Arg; 2] {
+    [
+        Arg::new("clean-types")
+            .long("clean-types")
+            .help("Actually clean only these categories and report the rest")
+            .long_help(
+                "Comma-separated categories to actually clean, e.g. browser,temp. Everything \
+                 else found is only reported, as in a dry run, in the same invocation. \
+                 Implies --clean for the listed categories. Categories: user, system, \
+                 package, app, browser, dev, build, temp, regenerating, reclaim, \
+                 volume-trash, mail-queue, print-job, browser-profile, pod-data, journal and logs.",
+            )
+            .value_name("TYPES")
+            .value_delimiter(',')
+            .value_parser(CATEGORIES.to_vec()),
+        Arg::new("dry-run-types")
+            .long("dry-run-types")
+            .help("Only report these categories, as in a dry run")
+            .long_help(
+                "Comma-separated categories that are only reported, as in a dry run, while \
+                 the others are cleaned by --clean or --clean-types, e.g. \
+                 --clean --dry-run-types system,package. Takes precedence over \
+                 --clean-types. The categories are those of --clean-types.",
+            )
+            .value_name("TYPES")
+            .value_delimiter(',')
+            .value_parser(CATEGORIES.to_vec()),
+    ]
+}
+
+/// `--emit-script`, which writes the deletions out instead of running them
+fn emit_script_arg() -> Arg {
+    Arg::new("emit-script")
+        .long("emit-script")
+        .help("Write a shell script of the deletions instead of deleting")
+        .long_help(
+            "Write a reviewable shell script containing the exact commands the cleaner \
+             would run, with the size of each item as a comment, instead of deleting \
+             anything. Protected items are listed as comments only.",
+        )
+        .value_name("FILE")
+}
+
+/// `--output`, the format findings and results are printed in
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .long("output")
+        .help("Output format: text or json")
+        .long_help(
+            "Print the findings and results as JSON instead of text, to pipe into jq \
+             or dashboards. Every scan prints one line with a scan-report document and \
+             every clean or dry run one with a clean-report document; see \
+             `cleaner schema` for both. Everything else, prompts included, goes to \
+             stderr.",
+        )
+        .value_name("FORMAT")
+        .value_parser(OutputFormat::parse)
+        .default_value("text")
+}
+
+/// `--events`, a stream of progress events on stdout
+fn events_arg() -> Arg {
+    Arg::new("events")
+        .long("events")
+        .help("Stream progress events, e.g. --events ndjson")
+        .long_help(
+            "Print the steps of the run on stdout as they happen, one JSON object per \
+             line, for wrappers that show live progress: scan-started, item-found \
+             for every item once measured, item-deleted or item-failed as each \
+             deletion finishes, and a closing summary. The only FORMAT is ndjson; \
+             see `cleaner schema event`. Everything else, prompts included, goes to \
+             stderr.",
+        )
+        .value_name("FORMAT")
+        .value_parser(EventFormat::parse)
+        .conflicts_with("output")
+}
+
 /// Build command line interface
 pub fn build_cli() -> Command {
     Command::new(env!("CARGO_PKG_NAME"))
@@ -54,28 +443,297 @@ pub fn build_cli() -> Command {
              processing for fast cleanup of cache directories, temporary files, and old log files."
         )
         .author("Brean-dev")
-        .arg(
-            Arg::new("path")
-                .help("Root path to scan for cache directories and log files")
-                .long_help(
-                    "The root directory to scan for cache directories and log files. \
-                     Use '/' for system-wide scanning or specify a user directory like '/home/user'. \
-                     System-wide scanning requires root privileges for full access."
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("scan")
+                .about("Scan and list what would be cleaned (the default)")
+                .long_about(
+                    "Scan for caches and, with --logs, old log files, and list what a clean \
+                     would remove. Nothing is deleted. Running cleaner without a subcommand \
+                     does the same."
+                )
+                .arg(path_arg())
+                .arg(emit_script_arg())
+                .arg(output_arg())
+                .arg(events_arg()),
+        )
+        .subcommand(
+            Command::new("clean")
+                .about("Scan and clean what was found (same as --clean)")
+                .long_about(
+                    "Scan like 'scan' and delete what was found, after the usual \
+                     confirmations. The items can be narrowed down with --pick, --explore, \
+                     --select or --ask, and categories left to a dry run with \
+                     --dry-run-types. Same as running cleaner with --clean."
+                )
+                .arg(path_arg())
+                .args(selection_args())
+                .args(category_args())
+                .arg(output_arg().conflicts_with_all(["pick", "explore"]))
+                .arg(events_arg().conflicts_with_all(["pick", "explore"])),
+        )
+        .subcommand(
+            Command::new("logs")
+                .about("Find and clean old log files only")
+                .long_about(
+                    "Look for log files older than the configured threshold, leaving caches \
+                     out of the scan. Without --clean they are only listed. The log options \
+                     such as --log-age, --log-types and --log-action apply as usual."
+                )
+                .arg(path_arg())
+                .arg(clean_arg().help("Delete the old log files found"))
+                .args(
+                    selection_args()
+                        .into_iter()
+                        .filter(|arg| arg.get_id() != "explore"),
+                )
+                .arg(output_arg().conflicts_with("pick"))
+                .arg(events_arg().conflicts_with("pick")),
+        )
+        .subcommand(
+            Command::new("plan")
+                .about("Scan and write the deletion set to a plan file")
+                .long_about(
+                    "Scan like a normal run and record every item that would be deleted, with \
+                     its size and modification time, in a JSON plan file. Nothing is deleted. \
+                     Execute the plan later with 'apply'."
+                )
+                .arg(path_arg())
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Where to write the plan file")
+                        .value_name("FILE")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Delete exactly the items recorded in a plan file")
+                .long_about(
+                    "Delete the items recorded by 'plan'. Items whose size or modification time \
+                     changed since planning, or that no longer exist, are refused and reported."
+                )
+                .arg(
+                    Arg::new("plan")
+                        .help("Plan file written by 'plan'")
+                        .value_name("FILE")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("resume")
+                .about("Continue a clean that was killed, from its checkpoint")
+                .long_about(
+                    "Every clean records what it is about to delete and journals each item as \
+                     it starts and finishes, in the state directory. When the process is \
+                     killed, 'resume' deletes what is left without scanning again: items that \
+                     were not started are checked like those of 'apply', and items deleted \
+                     halfway are finished off."
+                ),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Move the items of an earlier clean back from the trash")
+                .long_about(
+                    "Every clean with create_backup_list writes a JSON manifest of its items \
+                     next to the backup list. 'restore' moves the items a --trash clean put \
+                     into the trash back where they were and reports every entry that can't \
+                     be recovered: items deleted outright, gone from the trash, or whose place \
+                     is taken again."
+                )
+                .arg(
+                    Arg::new("backup")
+                        .help("Backup manifest (.json) or backup list (.txt) of the clean")
+                        .value_name("FILE")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("self-update")
+                .about("Replace this binary with the latest signed release")
+                .long_about(
+                    "Look up the latest release of the project and, when it is newer, download \
+                     the static binary for this machine, check its minisign signature against \
+                     the key built into this binary and that the signed comment names that \
+                     binary and a newer version, make sure it runs, and rename it over the \
+                     running binary in one step. Runs already in progress keep the old binary."
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("Only report whether a newer release is available")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show what changed since the previous scan")
+                .long_about(
+                    "Scan and compare the result with the previous recorded scan of the same \
+                     path: new items, items that grew the most, and items that disappeared. \
+                     Every scan is recorded in the history unless [history] enabled is false."
+                )
+                .arg(path_arg())
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Also write the differences as JSON to this file")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Summarize the recorded scan history")
+                .long_about(
+                    "Summarize the scan history without scanning: how many scans and cleans \
+                     were recorded, what the cleans removed, what the latest scan found and \
+                     which items keep coming back to be cleaned. Without a path every \
+                     recorded scan root is summarized."
                 )
-                .default_value("/")
-                .index(1),
+                .arg(path_arg())
+                .arg(output_arg()),
         )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("lint")
+                        .about("Check the configuration for problematic patterns")
+                        .long_about(
+                            "Analyze the configuration for dangerous patterns (such as a bare \
+                             '/' or '*'), patterns shadowed by exclusions, duplicate or fully \
+                             overlapping patterns, and exclusions that match nothing. Exits \
+                             with status 1 when anything is found."
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("quota")
+                .about("Enforce the per-user cache quota")
+                .long_about(
+                    "Total every user's cache footprint and, for users over the [quota] \
+                     per_user limit, clean their caches (oldest or largest first, see \
+                     [quota] order) until they are back under it."
+                )
+                .arg(path_arg())
+                .arg(
+                    Arg::new("report-only")
+                        .long("report-only")
+                        .help("Only list users over quota without cleaning")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Scan and summarize reclaimable space without cleaning")
+                .long_about(
+                    "Scan like a normal run and summarize the findings. With --by-app, items \
+                     are attributed to the application that owns them (Chrome, cargo, \
+                     Spotify, ...) from their paths. Nothing is deleted."
+                )
+                .arg(path_arg())
+                .arg(
+                    Arg::new("by-app")
+                        .long("by-app")
+                        .help("Aggregate reclaimable space by owning application")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Also write the report as JSON to this file")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("monitor")
+                .about("Scan, record history and alert without ever cleaning")
+                .long_about(
+                    "Scan for scheduled monitoring, e.g. from a root timer: the scan is \
+                     recorded in the history, metrics are written to [monitor] metrics_file \
+                     and the notify command and webhook are called when an alert threshold \
+                     is crossed. Nothing is ever deleted, whatever the configuration says. \
+                     Without a path the whole system is scanned."
+                )
+                .arg(path_arg())
+                .arg(
+                    Arg::new("metrics")
+                        .long("metrics")
+                        .help("Write Prometheus metrics to this file")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("project")
+                .about("Report and clean the build output of the projects in a directory")
+                .long_about(
+                    "Find projects below the path (the current directory by default) by their \
+                     manifest files and report their build output: cargo target directories, \
+                     node_modules, Python virtualenvs, .gradle and build directories of Gradle \
+                     projects and configured CMake build trees. Generic cache patterns aren't \
+                     used. With --clean the output is removed, cargo's with 'cargo clean'. \
+                     Projects are ranked by the size of their output and when they were last \
+                     worked on; --sweep cleans the output of the stale ones only."
+                )
+                .arg(path_arg())
+                .arg(
+                    Arg::new("clean")
+                        .long("clean")
+                        .short('c')
+                        .help("Delete the build output found")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("sweep")
+                        .long("sweep")
+                        .help("Delete the build output of stale projects only")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("stale-after")
+                        .long("stale-after")
+                        .help("Projects untouched this long are stale, e.g. 90d")
+                        .value_name("AGE")
+                        .value_parser(units::parse_age_arg),
+                ),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print JSON Schemas of the machine-readable outputs")
+                .long_about(
+                    "Print versioned JSON Schema documents describing the scan report, clean \
+                     report, plan file and audit manifest record formats. Without a name, all schemas are printed as \
+                     one object keyed by name."
+                )
+                .arg(
+                    Arg::new("name")
+                        .help("Only print the schema with this name")
+                        .value_parser(SCHEMA_NAMES.to_vec())
+                        .index(1),
+                ),
+        )
+        .arg(path_arg())
+        .arg(clean_arg())
+        .args(selection_args())
+        .args(category_args())
         .arg(
-            Arg::new("clean")
-                .long("clean")
-                .short('c')
-                .help("Actually delete the found cache directories and files")
+            Arg::new("all")
+                .long("all")
+                .help("Scan the whole system from / when no path is given")
                 .long_help(
-                    "Enable deletion mode. Without this flag, the tool will only scan and report \
-                     what would be deleted. This is the recommended way to first understand \
-                     what the tool would clean before actually running the cleanup."
+                    "Scan from '/' when no path is given, instead of your home directory \
+                     (plus /var and /tmp when running as root). Unprivileged system-wide \
+                     scans mostly run into directories they may not read."
                 )
-                .action(ArgAction::SetTrue),
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("dry-run")
@@ -87,7 +745,8 @@ pub fn build_cli() -> Command {
                      delete anything. This overrides the --clean flag and is useful for testing \
                      configuration changes."
                 )
-                .action(ArgAction::SetTrue),
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("verbose")
@@ -98,7 +757,8 @@ pub fn build_cli() -> Command {
                     "Enable verbose output showing detailed information about the scanning process, \
                      thread usage, permission issues, and individual file operations."
                 )
-                .action(ArgAction::SetTrue),
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("config")
@@ -110,7 +770,8 @@ pub fn build_cli() -> Command {
                      look for config.toml in the XDG config directory (~/.config/cleaner/config.toml). \
                      If no config file exists, a default one will be created."
                 )
-                .value_name("FILE"),
+                .value_name("FILE")
+                .global(true),
         )
         .arg(
             Arg::new("clean-logs")
@@ -122,19 +783,195 @@ pub fn build_cli() -> Command {
                      This will search for log files in standard locations like /var/log and user \
                      application log directories."
                 )
-                .action(ArgAction::SetTrue),
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("log-age")
                 .long("log-age")
-                .help("Override log age threshold in days (default: 7)")
+                .help("Override log age threshold, e.g. 36h or 2w (default: 7d)")
+                .long_help(
+                    "Override the maximum age for log files. Accepts durations like 36h, 7d or \
+                     2w; a bare number counts days. Log files older than this threshold will be \
+                     considered for deletion. This overrides the setting in the configuration file."
+                )
+                .value_name("AGE")
+                .value_parser(units::parse_age_arg)
+                .global(true),
+        )
+        .arg(
+            Arg::new("log-action")
+                .long("log-action")
+                .help("How old logs are cleaned: delete or truncate")
+                .long_help(
+                    "How old logs are cleaned. 'delete' removes them; 'truncate' empties them \
+                     in place with ftruncate, keeping the file, its owner and its mode, for \
+                     daemons that hold their logs open where /proc doesn't show it. Logs a \
+                     running process is seen writing to follow log_cleanup.live_logs instead. \
+                     This overrides log_cleanup.log_action in the configuration file."
+                )
+                .value_name("ACTION")
+                .value_parser(LogAction::parse)
+                .global(true),
+        )
+        .arg(
+            Arg::new("log-types")
+                .long("log-types")
+                .help("Only consider log files of these types, e.g. system,developer")
+                .long_help(
+                    "Comma-separated log types to consider; log files of other types are left \
+                     out of the report and the cleanup. Types: system, application, user, \
+                     debug, error, access, security and developer, as shown next to each \
+                     log file. Implies --logs."
+                )
+                .value_name("TYPES")
+                .value_delimiter(',')
+                .value_parser(LogType::parse)
+                .global(true),
+        )
+        .arg(
+            Arg::new("exclude-log-types")
+                .long("exclude-log-types")
+                .help("Leave out log files of these types, e.g. security")
+                .long_help(
+                    "Comma-separated log types to leave out of the report and the cleanup, \
+                     even when --log-types lists them. The types are those of --log-types."
+                )
+                .value_name("TYPES")
+                .value_delimiter(',')
+                .value_parser(LogType::parse)
+                .global(true),
+        )
+        .arg(
+            Arg::new("owner")
+                .long("owner")
+                .help("Only consider items owned by this user")
+                .long_help(
+                    "Restrict detection and cleaning to cache items and log files owned by \
+                     the given user name or numeric UID. Useful on shared machines where only \
+                     some users' data may be cleaned."
+                )
+                .value_name("USER")
+                .value_parser(owners::resolve_user)
+                .global(true),
+        )
+        .arg(
+            Arg::new("older-than")
+                .long("older-than")
+                .help("Only consider items not modified for this long, e.g. 2w")
+                .long_help(
+                    "Only consider cache items and log files whose last modification is older \
+                     than this. Accepts durations like 36h, 7d or 2w; a bare number counts days. \
+                     Items whose modification time is unknown are left out."
+                )
+                .value_name("AGE")
+                .value_parser(units::parse_age_arg)
+                .global(true),
+        )
+        .arg(
+            Arg::new("not-accessed-for")
+                .long("not-accessed-for")
+                .help("Only consider items none of whose files were read for this long, e.g. 30d")
+                .long_help(
+                    "Only consider cache items none of whose files were accessed for this long, \
+                     and log files not accessed for this long. Unlike --older-than this \
+                     keeps caches that were written once but are still read. Access times \
+                     are exact to within a day under the default relatime mount option; on \
+                     filesystems mounted noatime they never change, so items there are left \
+                     out with a warning. Accepts durations like 36h, 7d or 2w; a bare number \
+                     counts days."
+                )
+                .value_name("AGE")
+                .value_parser(units::parse_age_arg)
+                .global(true),
+        )
+        .arg(
+            Arg::new("only")
+                .long("only")
+                .help("Only consider caches of this temperature: hot, warm or cold")
+                .long_help(
+                    "Only consider cache items of this temperature. Items are hot when their \
+                     files were written or read within two days or they changed size since \
+                     the previous scan of the path, cold when nothing was written or read for \
+                     30 days and they didn't change, and warm otherwise. Without access times, \
+                     e.g. on filesystems mounted noatime, nothing is rated cold. Log files \
+                     aren't rated and are left out."
+                )
+                .value_name("TEMPERATURE")
+                .value_parser(Temperature::parse)
+                .global(true),
+        )
+        .arg(
+            Arg::new("only-if-free-below")
+                .long("only-if-free-below")
+                .help("Only clean when less than this is available, e.g. 10% or 5GiB")
+                .long_help(
+                    "Only clean when the filesystem holding the scan path has less space \
+                     available than this, given as a size (5GiB) or a percentage of the \
+                     filesystem (10%). Available means usable by unprivileged users: blocks \
+                     reserved for root are not counted, even when running as root. Otherwise \
+                     the findings are only reported."
+                )
+                .value_name("THRESHOLD")
+                .value_parser(FreeThreshold::parse)
+                .global(true),
+        )
+        .arg(
+            Arg::new("system-reclaim")
+                .long("system-reclaim")
+                .help("Also find unreferenced flatpak objects and stale DKMS builds (root only)")
+                .long_help(
+                    "Also look for space no standard tool reports: ostree objects in the system \
+                     flatpak repository that no ref reaches, and DKMS driver builds for kernels \
+                     that are no longer installed or for superseded driver versions. These \
+                     are listed as advanced system reclaim. Initramfs images in /boot and module \
+                     trees in /lib/modules of kernels that are neither running nor installed \
+                     are reported too, but never cleaned. Requires root."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("kubernetes-node")
+                .long("kubernetes-node")
+                .help("Also find container logs and emptyDir volumes of deleted pods (root only)")
+                .long_help(
+                    "Help a Kubernetes node out of disk pressure before the kubelet's own \
+                     garbage collection catches up: container logs in /var/log/pods and \
+                     emptyDir volumes in /var/lib/kubelet/pods of pods the container runtime no \
+                     longer has a sandbox for are listed for cleaning. The runtime is asked \
+                     through crictl at kubernetes.cri_endpoint; when that fails, nothing is \
+                     listed. Requires root."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("clean-unused-profiles")
+                .long("clean-unused-profiles")
+                .help("Also clean browser profiles unused for browser_profiles.unused_after")
+                .long_help(
+                    "Firefox and Chromium-based browser profiles nobody opened for \
+                     browser_profiles.unused_after (180 days by default) are always reported \
+                     with their full size. With this flag they are listed for cleaning too. \
+                     A profile holds bookmarks, saved passwords and history, so only use it \
+                     when those profiles are really abandoned."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("actual-savings")
+                .long("actual-savings")
+                .help("Also estimate the space deleting would really free")
                 .long_help(
-                    "Override the maximum age for log files in days. Log files older than this \
-                     threshold will be considered for deletion. This overrides the setting in \
-                     the configuration file."
+                    "Besides the apparent size of what would be deleted, report how much space \
+                     deleting it actually frees. Files hard-linked from outside the candidates and \
+                     extents shared through reflinks or snapshots (btrfs, XFS) stay allocated \
+                     and are left out. This walks the candidates once more."
                 )
-                .value_name("DAYS")
-                .value_parser(clap::value_parser!(u64)),
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("force")
@@ -143,9 +980,54 @@ pub fn build_cli() -> Command {
                 .help("Force cleanup without confirmation prompts")
                 .long_help(
                     "Skip confirmation prompts and force cleanup. Use with caution as this \
-                     bypasses safety checks that ask for user confirmation before large deletions."
+                     bypasses safety checks that ask for user confirmation before large deletions. \
+                     Mount points, the scan root, / and $HOME are never deleted, and items directly \
+                     inside a home directory still require typing their name."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("ignore-missing")
+                .long("ignore-missing")
+                .help("Skip a scan root that doesn't exist instead of failing")
+                .long_help(
+                    "When the path to scan doesn't exist or isn't a directory, note it and exit \
+                     successfully instead of failing with an error. Meant for fleet runs where \
+                     some hosts lack the path."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("scheduled")
+                .long("scheduled")
+                .help("Run as a scheduled job, waiting while on battery or busy")
+                .long_help(
+                    "Mark the run as started by a timer: before scanning, wait while the \
+                     conditions of the [schedule] config section hold (running on battery, \
+                     high load or CPU/IO pressure), checking again periodically, and skip the \
+                     run if they last too long. `cleaner monitor` always behaves like this."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("cgroup-limits")
+                .long("cgroup-limits")
+                .help("Confine the run to a cgroup, e.g. --cgroup-limits io=50M cpu=50%")
+                .long_help(
+                    "Move the cleaner into a transient cgroup v2 group with these limits before \
+                     scanning, like systemd-run --scope: cpu=PERCENT of one CPU, io=SIZE per \
+                     second of reads and writes on the scanned disk, memory=SIZE. Needs root \
+                     and a cgroup v2 hierarchy; the run fails if the limits can't be applied. \
+                     Takes several values, so give the path before it."
                 )
-                .action(ArgAction::SetTrue),
+                .value_name("LIMIT")
+                .num_args(1..)
+                .value_delimiter(',')
+                .value_parser(cgroup::parse_limit)
+                .global(true),
         )
         .arg(
             Arg::new("no-sizes")
@@ -155,7 +1037,26 @@ pub fn build_cli() -> Command {
                     "Skip size calculation for found files and directories. This makes the scan \
                      faster but you won't see how much space would be freed."
                 )
-                .action(ArgAction::SetTrue),
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("estimate")
+                .long("estimate")
+                .help("Estimate the sizes of large items from a sample (much faster)")
+                .long_help(
+                    "Size items by reading a bounded sample of their directories instead of \
+                     walking every file: each tree is read breadth first up to \
+                     performance.estimate_sample directories, and what lies below is \
+                     extrapolated from random descents into it. On caches of millions of \
+                     files this takes seconds instead of minutes. Estimated sizes are marked \
+                     with ~ in the listing and size_estimated in JSON output; small trees are \
+                     still sized exactly. Exports and access times need every file, so they \
+                     can't be combined with it, and 'report --by-app' sizes exactly regardless."
+                )
+                .conflicts_with_all(["no-sizes", "export", "not-accessed-for"])
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("summary-only")
@@ -164,9 +1065,47 @@ pub fn build_cli() -> Command {
                 .help("Show only summary without listing individual items")
                 .long_help(
                     "Show only a summary of found cache directories and log files without \
-                     listing each individual item. Useful for quick overview or scripting."
+                     listing each individual item. Useful for quick overview or scripting. \
+                     With --output json, scans print only their totals per type, filesystem \
+                     and age, and cleans leave out the result of each item."
                 )
-                .action(ArgAction::SetTrue),
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(emit_script_arg())
+        .arg(output_arg().conflicts_with_all(["pick", "explore"]))
+        .arg(events_arg().conflicts_with_all(["pick", "explore"]))
+        .arg(
+            Arg::new("export")
+                .long("export")
+                .help("Export the findings, e.g. --export ncdu out.json or --export csv out.csv")
+                .long_help(
+                    "Write the findings to FILE so they can be used in another tool. FORMAT \
+                     ncdu writes the scanned tree as an ncdu JSON export, to be browsed with \
+                     `ncdu -f FILE`; csv writes one row per cache item and log file with its \
+                     kind, path, type, size in bytes, file count and last-modified time, for \
+                     spreadsheets. The sizes are taken from the scan itself, so nothing is \
+                     walked twice."
+                )
+                .num_args(2)
+                .value_names(["FORMAT", "FILE"])
+                .global(true),
+        )
+        .arg(
+            Arg::new("redact-paths")
+                .long("redact-paths")
+                .help("Hide user and project names in exported reports")
+                .long_help(
+                    "Redact paths in --export files, --output json documents and the report \
+                     of 'report --output', so they can be attached to public bug trackers. \
+                     Home directories become <user>; names that neither a cache pattern nor \
+                     a system directory accounts for, and everything inside a cache item, \
+                     become short hashes, e.g. /home/<user>/.cache/<3fa2b1c0>/<9e01d2aa>. \
+                     User names are hashed too. Hashes differ from run to run. The text \
+                     shown on the terminal is not redacted."
+                )
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
         .arg(
             Arg::new("threads")
@@ -175,11 +1114,68 @@ pub fn build_cli() -> Command {
                 .help("Number of threads to use for parallel processing")
                 .long_help(
                     "Override the number of threads used for parallel processing. By default, \
-                     the tool uses the number of CPU cores available, capped at 8 threads. \
-                     Use this to limit resource usage on busy systems."
+                     the count follows the storage holding the scan root: 2 threads on spinning \
+                     disks, up to 16 on SSD/NVMe and up to 8 when it can't be detected, never more \
+                     than the CPU cores available. Use this to limit resource usage on busy systems."
                 )
                 .value_name("COUNT")
-                .value_parser(clap::value_parser!(usize)),
+                .value_parser(clap::value_parser!(usize))
+                .global(true),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .help("Stop scanning after this long, e.g. 10m")
+                .long_help(
+                    "Bound the wall-clock time of the scan. Once it has run this long, scanning \
+                     stops, the partial results are reported and marked incomplete, and cleaning \
+                     only touches what was found. Accepts durations like 90s, 10m or 1h; a bare \
+                     number counts seconds."
+                )
+                .value_name("DURATION")
+                .value_parser(units::parse_timeout_arg)
+                .global(true),
+        )
+        .arg(
+            Arg::new("abort-on-timeout")
+                .long("abort-on-timeout")
+                .help("Don't clean anything if the scan hit --timeout")
+                .long_help(
+                    "When the scan was stopped by --timeout, report the partial results and exit \
+                     with an error instead of cleaning what was found."
+                )
+                .action(ArgAction::SetTrue)
+                .requires("timeout")
+                .global(true),
+        )
+        .arg(
+            Arg::new("result-file")
+                .long("result-file")
+                .help("Write the outcome of the run as JSON to FILE")
+                .long_help(
+                    "Write the final outcome of the run (status, exit code, items found, items \
+                     cleaned and bytes freed) as JSON to FILE, independent of the console \
+                     output. The file is written however the run ends: on success, on errors \
+                     and when interrupted by SIGINT, SIGTERM or SIGHUP. See `cleaner schema \
+                     result` for its format."
+                )
+                .value_name("FILE")
+                .global(true),
+        )
+        .arg(
+            Arg::new("changed-when-freed")
+                .long("changed-when-freed")
+                .help("Mark the result file changed only when something was deleted")
+                .long_help(
+                    "Add a `changed` field to the result file for configuration management \
+                     wrappers such as Ansible or Salt: false when nothing was deleted (including \
+                     dry runs and runs with nothing to clean), true when at least one item was, \
+                     alongside the item and byte counts. Either way the exit code is 0 unless \
+                     the run itself failed."
+                )
+                .action(ArgAction::SetTrue)
+                .requires("result-file")
+                .global(true),
         )
         .arg(
             Arg::new("max-depth")
@@ -191,28 +1187,288 @@ pub fn build_cli() -> Command {
                      Default is 10 levels deep."
                 )
                 .value_name("DEPTH")
-                .value_parser(clap::value_parser!(usize)),
+                .value_parser(clap::value_parser!(usize))
+                .global(true),
         )
 }
 
+/// Roots scanned when no path is given: the user's home, plus `/var` and
+/// `/tmp` for root, whose caches and leftovers live there too. A root that
+/// is another one under a symlink, such as a home in `/var/home`, is only
+/// scanned once.
+pub fn default_roots(home: Option<PathBuf>, is_root: bool) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = home.into_iter().filter(|h| h.is_absolute()).collect();
+    if is_root {
+        roots.extend(["/var", "/tmp"].map(PathBuf::from));
+    }
+    if roots.is_empty() {
+        roots.push(PathBuf::from("/"));
+    }
+    let mut seen = Vec::new();
+    roots.retain(|root| {
+        let canonical = canonical(root);
+        let first = !seen.contains(&canonical);
+        seen.push(canonical);
+        first
+    });
+    roots
+}
+
+/// The `roots` inside `root`, as paths below it for its scan to leave out:
+/// they get scans of their own, with the rules of a home directory if they
+/// are one
+pub fn nested_roots(root: &Path, roots: &[PathBuf]) -> Vec<PathBuf> {
+    let outer = canonical(root);
+    roots
+        .iter()
+        .filter_map(|other| {
+            let inner = canonical(other);
+            let below = inner.strip_prefix(&outer).ok()?;
+            (!below.as_os_str().is_empty()).then(|| root.join(below))
+        })
+        .collect()
+}
+
+/// `path` with symlinks resolved, or as given if it can't be
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
 /// Parse command line arguments into CliArgs struct
 pub fn parse_args() -> CliArgs {
     let matches = build_cli().get_matches();
+    // `scan`, `clean` and `logs` take the options of a plain run that aren't
+    // global, each those that make sense for it
+    let run = match matches.subcommand() {
+        Some(("scan" | "clean" | "logs" | "stats", sub)) => sub,
+        _ => &matches,
+    };
 
+    // The picker and explorer are left out of builds without `tui`
+    if let Some(flag) = ["pick", "explore"]
+        .into_iter()
+        .find(|id| !cfg!(feature = "tui") && flag(run, id))
+    {
+        build_cli()
+            .error(
+                ErrorKind::UnknownArgument,
+                format!("--{} needs cleaner built with the `tui` feature", flag),
+            )
+            .exit();
+    }
+    if !cfg!(feature = "self-update") && matches.subcommand_name() == Some("self-update") {
+        build_cli()
+            .error(
+                ErrorKind::InvalidSubcommand,
+                "self-update needs cleaner built with the `self-update` feature",
+            )
+            .exit();
+    }
+
+    let (path, command) = match matches.subcommand() {
+        Some(("scan" | "clean", sub)) => (sub.get_one::<String>("path"), None),
+        Some(("logs", sub)) => (sub.get_one::<String>("path"), Some(CliCommand::Logs)),
+        Some(("stats", sub)) => (sub.get_one::<String>("path"), Some(CliCommand::Stats)),
+        Some(("plan", sub)) => (
+            sub.get_one::<String>("path"),
+            Some(CliCommand::Plan {
+                output: PathBuf::from(sub.get_one::<String>("output").unwrap()),
+            }),
+        ),
+        Some(("diff", sub)) => (
+            sub.get_one::<String>("path"),
+            Some(CliCommand::Diff {
+                output: sub.get_one::<String>("output").map(PathBuf::from),
+            }),
+        ),
+        Some(("config", _)) => (
+            matches.get_one::<String>("path"),
+            Some(CliCommand::ConfigLint),
+        ),
+        Some(("quota", sub)) => (
+            sub.get_one::<String>("path"),
+            Some(CliCommand::Quota {
+                report_only: sub.get_flag("report-only"),
+            }),
+        ),
+        Some(("report", sub)) => (
+            sub.get_one::<String>("path"),
+            Some(CliCommand::Report {
+                by_app: sub.get_flag("by-app"),
+                output: sub.get_one::<String>("output").map(PathBuf::from),
+            }),
+        ),
+        Some(("monitor", sub)) => (
+            sub.get_one::<String>("path"),
+            Some(CliCommand::Monitor {
+                metrics: sub.get_one::<String>("metrics").map(PathBuf::from),
+            }),
+        ),
+        Some(("project", sub)) => (
+            sub.get_one::<String>("path"),
+            Some(CliCommand::Project {
+                sweep: sub.get_flag("sweep"),
+                stale_after: sub.get_one::<Duration>("stale-after").copied(),
+            }),
+        ),
+        Some(("schema", sub)) => (
+            matches.get_one::<String>("path"),
+            Some(CliCommand::Schema {
+                name: sub.get_one::<String>("name").cloned(),
+            }),
+        ),
+        Some(("apply", sub)) => (
+            matches.get_one::<String>("path"),
+            Some(CliCommand::Apply {
+                plan: PathBuf::from(sub.get_one::<String>("plan").unwrap()),
+            }),
+        ),
+        Some(("resume", _)) => (matches.get_one::<String>("path"), Some(CliCommand::Resume)),
+        Some(("restore", sub)) => (
+            matches.get_one::<String>("path"),
+            Some(CliCommand::Restore {
+                backup: PathBuf::from(sub.get_one::<String>("backup").unwrap()),
+            }),
+        ),
+        Some(("self-update", sub)) => (
+            matches.get_one::<String>("path"),
+            Some(CliCommand::SelfUpdate {
+                check_only: sub.get_flag("check"),
+            }),
+        ),
+        _ => (matches.get_one::<String>("path"), None),
+    };
+
+    // `project` works on the current directory when no path is given, and
+    // `monitor` on the whole machine
+    let is_project = matches!(command, Some(CliCommand::Project { .. }));
+    let is_monitor = matches!(command, Some(CliCommand::Monitor { .. }));
     CliArgs {
-        path: PathBuf::from(matches.get_one::<String>("path").unwrap()),
-        clean: matches.get_flag("clean") && !matches.get_flag("dry-run"),
+        path: path.map_or_else(
+            || PathBuf::from(if is_project { "." } else { "/" }),
+            PathBuf::from,
+        ),
+        default_roots: path.is_none() && !is_project && !is_monitor && !matches.get_flag("all"),
+        nested_roots: Vec::new(),
+        clean: (flag(run, "clean")
+            || matches.subcommand_name() == Some("clean")
+            || matches
+                .subcommand_matches("project")
+                .is_some_and(|sub| sub.get_flag("clean")))
+            && !matches.get_flag("dry-run"),
+        pick: flag(run, "pick"),
+        explore: flag(run, "explore"),
+        ask: flag(run, "ask"),
+        trash: flag(run, "trash"),
+        shred: flag(run, "shred"),
+        select: run
+            .try_contains_id("select")
+            .unwrap_or(false)
+            .then(|| value::<Selection>(run, "select").cloned()),
+        clean_types: values::<String>(run, "clean-types").map(|types| types.cloned().collect()),
+        dry_run_types: values::<String>(run, "dry-run-types")
+            .map(|types| types.cloned().collect())
+            .unwrap_or_default(),
+        system_reclaim: matches.get_flag("system-reclaim"),
+        clean_unused_profiles: matches.get_flag("clean-unused-profiles"),
+        kubernetes_node: matches.get_flag("kubernetes-node"),
+        actual_savings: matches.get_flag("actual-savings"),
         dry_run: matches.get_flag("dry-run"),
         verbose: matches.get_flag("verbose"),
         config: matches.get_one::<String>("config").map(PathBuf::from),
         clean_logs: matches.get_flag("clean-logs"),
-        log_age_days: matches.get_one::<u64>("log-age").copied(),
+        log_age: matches.get_one::<Duration>("log-age").copied(),
+        log_action: matches.get_one::<LogAction>("log-action").copied(),
+        log_types: matches
+            .get_many::<LogType>("log-types")
+            .map(|types| types.cloned().collect()),
+        exclude_log_types: matches
+            .get_many::<LogType>("exclude-log-types")
+            .map(|types| types.cloned().collect())
+            .unwrap_or_default(),
+        older_than: matches.get_one::<Duration>("older-than").copied(),
+        not_accessed_for: matches.get_one::<Duration>("not-accessed-for").copied(),
+        only: matches.get_one::<Temperature>("only").copied(),
+        only_if_free_below: matches
+            .get_one::<FreeThreshold>("only-if-free-below")
+            .copied(),
+        owner: matches.get_one::<u32>("owner").copied(),
         force: matches.get_flag("force"),
-        show_sizes: !matches.get_flag("no-sizes"),
+        ignore_missing: matches.get_flag("ignore-missing"),
+        scheduled: matches.get_flag("scheduled") || is_monitor,
+        cgroup_limits: matches
+            .get_many::<CgroupLimit>("cgroup-limits")
+            .map(|limits| limits.copied().collect())
+            .unwrap_or_default(),
+        estimate: matches.get_flag("estimate"),
+        // Quotas, diffs, exports, app breakdowns and access times come from
+        // sizing, so it always runs for them
+        show_sizes: !matches.get_flag("no-sizes")
+            || matches.contains_id("export")
+            || matches.contains_id("not-accessed-for")
+            || matches.contains_id("only")
+            || flag(run, "explore")
+            || matches!(
+                command,
+                Some(
+                    CliCommand::Quota { .. }
+                        | CliCommand::Diff { .. }
+                        | CliCommand::Report { by_app: true, .. }
+                )
+            ),
         summary_only: matches.get_flag("summary-only"),
+        emit_script: value::<String>(run, "emit-script").map(PathBuf::from),
+        output_format: value::<OutputFormat>(run, "output")
+            .copied()
+            .unwrap_or_default(),
+        events: value::<EventFormat>(run, "events").copied(),
+        export: export_path(&matches),
+        redact_paths: matches.get_flag("redact-paths"),
+        threads: matches.get_one::<usize>("threads").copied(),
+        timeout: matches.get_one::<Duration>("timeout").copied(),
+        abort_on_timeout: matches.get_flag("abort-on-timeout"),
+        result_file: matches.get_one::<String>("result-file").map(PathBuf::from),
+        changed_when_freed: matches.get_flag("changed-when-freed"),
+        command,
     }
 }
 
+/// Whether the flag `id` was given to a command that may not take it
+fn flag(matches: &ArgMatches, id: &str) -> bool {
+    value::<bool>(matches, id).copied().unwrap_or(false)
+}
+
+/// The value of `id`, if the command takes it and it was given
+fn value<'a, T: Clone + Send + Sync + 'static>(matches: &'a ArgMatches, id: &str) -> Option<&'a T> {
+    matches.try_get_one::<T>(id).ok().flatten()
+}
+
+/// The values of `id`, if the command takes it and they were given
+fn values<'a, T: Clone + Send + Sync + 'static>(
+    matches: &'a ArgMatches,
+    id: &str,
+) -> Option<clap::parser::ValuesRef<'a, T>> {
+    matches.try_get_many::<T>(id).ok().flatten()
+}
+
+/// Format and output file of `--export`, exiting with a usage error on
+/// unknown formats
+fn export_path(matches: &ArgMatches) -> Option<(ExportFormat, PathBuf)> {
+    let mut values = matches.get_many::<String>("export")?;
+    let (format, file) = (values.next()?, values.next()?);
+    let format = match format.as_str() {
+        "ncdu" => ExportFormat::Ncdu,
+        "csv" => ExportFormat::Csv,
+        _ => build_cli()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("unknown export format '{}' (supported: ncdu, csv)", format),
+            )
+            .exit(),
+    };
+    Some((format, PathBuf::from(file)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -223,6 +1479,174 @@ mod tests {
         assert_eq!(cmd.get_name(), env!("CARGO_PKG_NAME"));
     }
 
+    #[test]
+    fn test_plan_and_apply_subcommands() {
+        let matches = build_cli()
+            .try_get_matches_from(["cleaner", "plan", "-o", "plan.json", "/home", "--logs"])
+            .unwrap();
+        let (name, sub) = matches.subcommand().unwrap();
+        assert_eq!(name, "plan");
+        assert_eq!(sub.get_one::<String>("path").unwrap(), "/home");
+        assert!(sub.get_flag("clean-logs"));
+
+        let matches = build_cli()
+            .try_get_matches_from(["cleaner", "apply", "plan.json"])
+            .unwrap();
+        let (name, sub) = matches.subcommand().unwrap();
+        assert_eq!(name, "apply");
+        assert_eq!(sub.get_one::<String>("plan").unwrap(), "plan.json");
+
+        let matches = build_cli()
+            .try_get_matches_from(["cleaner", "report", "--by-app", "/home"])
+            .unwrap();
+        let (name, sub) = matches.subcommand().unwrap();
+        assert_eq!(name, "report");
+        assert!(sub.get_flag("by-app"));
+
+        // Monitoring has no way to ask for cleaning
+        assert!(
+            build_cli()
+                .try_get_matches_from(["cleaner", "monitor", "--clean"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_run_subcommands() {
+        let matches = build_cli()
+            .try_get_matches_from(["cleaner", "clean", "--pick", "/home", "--logs"])
+            .unwrap();
+        let (name, sub) = matches.subcommand().unwrap();
+        assert_eq!(name, "clean");
+        assert!(flag(sub, "pick") && matches.get_flag("clean-logs"));
+
+        // Options a subcommand doesn't take read as not given
+        let matches = build_cli()
+            .try_get_matches_from(["cleaner", "scan", "--output", "json"])
+            .unwrap();
+        let (_, sub) = matches.subcommand().unwrap();
+        assert!(!flag(sub, "pick") && !flag(sub, "clean"));
+        assert_eq!(value(sub, "output"), Some(&OutputFormat::Json));
+        assert!(values::<String>(sub, "clean-types").is_none());
+
+        for args in [
+            &["cleaner", "scan", "--clean"][..],
+            &["cleaner", "logs", "--explore"],
+            &["cleaner", "clean", "--clean"],
+        ] {
+            assert!(
+                build_cli().try_get_matches_from(args).is_err(),
+                "{:?}",
+                args
+            );
+        }
+        assert!(
+            build_cli()
+                .try_get_matches_from(["cleaner", "logs", "--clean", "--ask", "/var/log"])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_default_roots() {
+        let matches = build_cli().try_get_matches_from(["cleaner"]).unwrap();
+        assert!(matches.get_one::<String>("path").is_none());
+        assert!(!matches.get_flag("all"));
+
+        let home = Some(PathBuf::from("/home/u"));
+        assert_eq!(
+            default_roots(home.clone(), false),
+            vec![PathBuf::from("/home/u")]
+        );
+        assert_eq!(
+            default_roots(home, true),
+            ["/home/u", "/var", "/tmp"].map(PathBuf::from)
+        );
+        assert_eq!(default_roots(None, false), vec![PathBuf::from("/")]);
+
+        // A home below another root is scanned on its own, not twice
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let var = temp_dir.path().join("var");
+        std::fs::create_dir_all(var.join("home/u")).unwrap();
+        std::os::unix::fs::symlink(var.join("home"), temp_dir.path().join("home")).unwrap();
+        let home = temp_dir.path().join("home/u");
+        let roots = [home.clone(), var.clone()];
+        assert_eq!(nested_roots(&var, &roots), [var.join("home/u")]);
+        assert!(nested_roots(&home, &roots).is_empty());
+        assert_eq!(
+            default_roots(Some(PathBuf::from("/tmp")), true),
+            ["/tmp", "/var"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_mixed_clean_and_dry_run_types() {
+        let matches = build_cli()
+            .try_get_matches_from(["cleaner", "--clean-types", "browser,temp,logs"])
+            .unwrap();
+        let types: Vec<&String> = matches.get_many("clean-types").unwrap().collect();
+        assert_eq!(types, ["browser", "temp", "logs"]);
+        assert!(
+            build_cli()
+                .try_get_matches_from(["cleaner", "--dry-run-types", "nonsense"])
+                .is_err()
+        );
+
+        let args = CliArgs {
+            clean_types: Some(vec!["browser".to_string(), "system".to_string()]),
+            dry_run_types: vec!["system".to_string()],
+            ..CliArgs::default()
+        };
+        assert!(args.mixes_modes());
+        assert!(args.cleans_category("browser"));
+        assert!(!args.cleans_category("system"));
+        assert!(!args.cleans_category("temp"));
+
+        // Without --clean-types, --clean decides for everything not excepted
+        let args = CliArgs {
+            clean: true,
+            dry_run_types: vec!["system".to_string()],
+            ..CliArgs::default()
+        };
+        assert!(args.cleans_category("temp"));
+        assert!(!args.cleans_category("system"));
+        assert!(!CliArgs::default().mixes_modes());
+    }
+
+    #[test]
+    fn test_log_type_filters() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "cleaner",
+                "--log-types",
+                "system,developer,security",
+                "--exclude-log-types",
+                "security",
+            ])
+            .unwrap();
+        let args = CliArgs {
+            log_types: matches
+                .get_many::<LogType>("log-types")
+                .map(|types| types.cloned().collect()),
+            exclude_log_types: matches
+                .get_many::<LogType>("exclude-log-types")
+                .unwrap()
+                .cloned()
+                .collect(),
+            ..CliArgs::default()
+        };
+        assert!(args.includes_log_type(&LogType::System));
+        assert!(args.includes_log_type(&LogType::Developer));
+        assert!(!args.includes_log_type(&LogType::Security));
+        assert!(!args.includes_log_type(&LogType::Access));
+        assert!(CliArgs::default().includes_log_type(&LogType::Access));
+        assert!(
+            build_cli()
+                .try_get_matches_from(["cleaner", "--log-types", "kernel"])
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_default_args() {
         let args = CliArgs::default();
@@ -1,4 +1,12 @@
+use crate::cache_detector::CacheType;
+use crate::error::CleanerError;
+use crate::log_cleaner::{Confidence, LogType};
+use crate::mounts::FreeThreshold;
+use crate::owners;
+use crate::storage::StorageKind;
+use crate::units;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -14,6 +22,51 @@ pub struct Config {
     pub safety: SafetyConfig,
     /// Performance settings
     pub performance: PerformanceConfig,
+    /// Per-user cache quota enforced by `cleaner quota`
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    /// Scan history used by `cleaner diff`
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Rebuilding of font and icon caches after they were cleaned
+    #[serde(default)]
+    pub regeneration: RegenerationConfig,
+    /// Mail spool and MTA queue reporting
+    #[serde(default)]
+    pub mail: MailConfig,
+    /// Leftover print jobs in the CUPS spool
+    #[serde(default)]
+    pub printing: PrintingConfig,
+    /// Emptying of the trash on mounted volumes
+    #[serde(default)]
+    pub volume_trash: VolumeTrashConfig,
+    /// Reporting of browser profiles nobody uses anymore
+    #[serde(default)]
+    pub browser_profiles: BrowserProfilesConfig,
+    /// Regenerable caches of VS Code and JetBrains IDEs
+    #[serde(default)]
+    pub ide_caches: IdeCachesConfig,
+    /// Leftovers of deleted pods, cleaned with --kubernetes-node
+    #[serde(default)]
+    pub kubernetes: KubernetesConfig,
+    /// How far the systemd journal is vacuumed
+    #[serde(default)]
+    pub journal: JournalConfig,
+    /// Sweeps of stale projects with `cleaner project --sweep`
+    #[serde(default)]
+    pub projects: ProjectsConfig,
+    /// Metrics and alerts of `cleaner monitor`
+    #[serde(default)]
+    pub monitor: MonitorConfig,
+    /// When scheduled runs put off their work
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    /// What cleaning well-known caches costs later, shown before confirming
+    #[serde(default)]
+    pub rebuild_costs: RebuildCostsConfig,
+    /// Commands run before and after cleaning matching cache items
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<CleanHook>,
 }
 
 /// Comprehensive cache detection patterns
@@ -35,6 +88,26 @@ pub struct CachePatterns {
     pub temp_patterns: Vec<String>,
     /// Build artifact patterns
     pub build_artifacts: Vec<String>,
+    /// Font and icon caches that are rebuilt automatically
+    #[serde(default = "default_regenerating_caches")]
+    pub regenerating_caches: Vec<String>,
+    /// Traversal options overriding `PerformanceConfig` below specific locations
+    #[serde(default)]
+    pub pattern_options: Vec<PatternOptions>,
+}
+
+/// Traversal options applied below the location matched by one pattern
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct PatternOptions {
+    /// Location the options apply to (`~` is expanded, `*` matches within one
+    /// path component, relative patterns match at any depth)
+    pub pattern: String,
+    /// Maximum depth below the matched location
+    pub max_depth: Option<usize>,
+    /// Follow symbolic links below the matched location
+    pub follow_symlinks: Option<bool>,
+    /// Don't descend into other filesystems below the matched location
+    pub one_file_system: Option<bool>,
 }
 
 /// Log file cleanup configuration
@@ -42,42 +115,535 @@ pub struct CachePatterns {
 pub struct LogCleanupConfig {
     /// Enable log cleanup
     pub enabled: bool,
-    /// Maximum age for log files (in days)
-    pub max_age_days: u64,
+    /// Maximum age for log files (bare numbers are days)
+    #[serde(alias = "max_age_days", with = "units::days")]
+    pub max_age: Duration,
     /// Log directory patterns to search
     pub log_patterns: Vec<String>,
     /// Log file extensions to consider
     pub log_extensions: Vec<String>,
-    /// Minimum size threshold for log files (in bytes)
-    pub min_size_bytes: u64,
+    /// Minimum size threshold for log files (bare numbers are bytes)
+    #[serde(alias = "min_size_bytes", with = "units::size")]
+    pub min_size: u64,
+    /// Logs classified with less confidence than this are kept and reported
+    #[serde(default)]
+    pub min_confidence: Confidence,
+    /// What happens to logs a running process holds open for writing
+    #[serde(default)]
+    pub live_logs: LiveLogAction,
+    /// What happens to every other old log
+    #[serde(default)]
+    pub log_action: LogAction,
+    /// Minimum retention per log type (bare numbers are days), a floor that
+    /// `max_age` and `--log-age` cannot go below
+    #[serde(default, alias = "min_retention_days", with = "units::days_map")]
+    pub min_retention: BTreeMap<LogType, Duration>,
 }
 
 /// Safety configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SafetyConfig {
     /// Directories to always exclude from cleaning
     pub exclude_paths: Vec<String>,
-    /// Require confirmation for large deletions (in bytes)
-    pub confirm_threshold_bytes: u64,
+    /// Require confirmation for large deletions (bare numbers are bytes)
+    #[serde(alias = "confirm_threshold_bytes", with = "units::size")]
+    pub confirm_threshold: u64,
     /// Maximum number of files to delete in one operation
     pub max_files_per_operation: usize,
     /// Dry run mode (show what would be deleted without deleting)
     pub dry_run: bool,
     /// Create backup list before deletion
     pub create_backup_list: bool,
+    /// Clean the target of symlinked cache items instead of skipping them
+    pub resolve_symlink_targets: bool,
+    /// Extra roots (besides the scan root) that symlink targets may resolve into
+    pub symlink_target_roots: Vec<String>,
+    /// Record a BLAKE3 hash and metadata of every deleted file in an audit manifest
+    pub checksum_manifest: bool,
+    /// Files larger than this are recorded in the manifest without a hash
+    #[serde(alias = "manifest_max_hash_bytes", with = "units::size")]
+    pub manifest_max_hash_size: u64,
+    /// Read rate limit per second while hashing (0 disables)
+    #[serde(alias = "manifest_hash_rate_bytes_per_sec", with = "units::size")]
+    pub manifest_hash_rate: u64,
+    /// Command asked about every planned deletion, with the item as JSON on
+    /// stdin; a non-zero exit skips the item
+    pub policy_command: Option<String>,
+    /// Refuse to delete anything inside `.git`, `.hg` or `.svn` directories
+    /// or bare git repositories, whatever asked for it
+    pub protect_vcs_metadata: bool,
+    /// Look at the first bytes of every log file before deleting it and skip
+    /// binary files such as databases or images that merely end in `.log`
+    pub verify_log_content: bool,
+    /// How many times `--shred` overwrites a file before deleting it
+    pub shred_passes: u32,
 }
 
 /// Performance configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PerformanceConfig {
     /// Maximum number of threads to use
     pub max_threads: Option<usize>,
-    /// Timeout for directory access (in seconds)
-    pub access_timeout_secs: u64,
+    /// Timeout for listing the directory a walk starts from (bare numbers are
+    /// seconds, 0 disables)
+    #[serde(alias = "access_timeout_secs", with = "units::secs")]
+    pub access_timeout: Duration,
+    /// Directories with more entries than this are skipped
+    pub max_entries_per_dir: Option<usize>,
     /// Skip symbolic links
     pub skip_symlinks: bool,
     /// Maximum depth for directory traversal
     pub max_depth: Option<usize>,
+    /// Don't descend into directories on other filesystems
+    pub one_file_system: bool,
+    /// Reuse sizes of items whose directory is unchanged in runs that don't clean
+    pub size_cache: bool,
+    /// Directories read up front to estimate the scan's progress (0 disables)
+    pub estimate_sample: usize,
+    /// Interactive runs ask before scans estimated to take longer than this
+    /// (bare numbers are seconds, 0 disables)
+    #[serde(with = "units::secs")]
+    pub confirm_scans_over: Duration,
+    /// Which hidden directories the scan descends into
+    pub scan_hidden: ScanHidden,
+    /// Run fewer workers at once while the system is stalled on I/O
+    pub adaptive_throttle: bool,
+    /// Share of time (percent) tasks may stall on I/O before workers are
+    /// halved; they come back one at a time below half of it
+    pub throttle_pressure: f64,
+    /// Items with at least this many files have their directories' entries
+    /// removed across all workers (0 disables)
+    pub parallel_delete_files: usize,
+}
+
+/// Which hidden directories (dotfile trees) a scan descends into. Hidden
+/// directories are still found and matched by name either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanHidden {
+    /// Only those named in a cache pattern, e.g. `.cache` or `.mozilla`
+    Conservative,
+    /// Every hidden directory
+    #[default]
+    All,
+    /// No hidden directory at all
+    None,
+}
+
+/// Per-user cache quota configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// Cache footprint allowed per user (bare numbers are bytes, 0 disables)
+    #[serde(with = "units::size")]
+    pub per_user: u64,
+    /// Which caches are cleaned first when a user is over quota
+    pub order: QuotaOrder,
+}
+
+/// What happens to a log a running process still writes to. Deleting it
+/// frees nothing until the process closes it, usually on restart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveLogAction {
+    /// Empty it in place, freeing the space right away
+    #[default]
+    Truncate,
+    /// Leave it alone and report it
+    Skip,
+    /// Delete it like any other log
+    Delete,
+}
+
+/// How old logs are cleaned. Truncating keeps every file, for daemons that
+/// hold their logs open in ways /proc doesn't show, such as from another
+/// container.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogAction {
+    /// Delete the file
+    #[default]
+    Delete,
+    /// Empty it in place with ftruncate
+    Truncate,
+}
+
+impl LogAction {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "delete" => Ok(LogAction::Delete),
+            "truncate" => Ok(LogAction::Truncate),
+            _ => Err(format!("unknown log action '{}'", name)),
+        }
+    }
+}
+
+/// Order in which an over-quota user's caches are cleaned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaOrder {
+    /// Least recently modified first
+    #[default]
+    Oldest,
+    /// Biggest first, freeing the space with the fewest deletions
+    Largest,
+}
+
+/// Scan history configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Record a snapshot of every scan
+    pub enabled: bool,
+    /// Number of snapshots to keep (0 keeps all)
+    pub keep: usize,
+    /// Suggest excluding items each of this many cleaning runs found but the
+    /// user never chose to clean (0 never suggests)
+    pub suggest_exclusions_after: usize,
+}
+
+/// Regeneration of auto-regenerating caches after cleaning
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegenerationConfig {
+    /// Rebuild font and icon caches right after cleaning them, so the next
+    /// login doesn't stall while they are rebuilt
+    pub enabled: bool,
+}
+
+/// Mail spool and MTA queue reporting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MailConfig {
+    /// Report mail spools larger than this (bare numbers are bytes, 0 disables)
+    #[serde(with = "units::size")]
+    pub spool_warn_size: u64,
+    /// Deferred queue files untouched this long are dead (bare numbers are days)
+    #[serde(with = "units::days")]
+    pub dead_queue_age: Duration,
+    /// Clean dead files from the MTA's deferred queue; spools are never cleaned
+    pub clean_dead_queue: bool,
+}
+
+/// Cleanup of leftover print jobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintingConfig {
+    /// Jobs in the CUPS spool untouched this long are purged with `cancel -x`
+    /// (bare numbers are days, 0 disables)
+    #[serde(with = "units::days")]
+    pub job_retention: Duration,
+}
+
+/// Emptying of the `.Trash-$UID` directories on mounted volumes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeTrashConfig {
+    /// Items trashed this long ago are cleaned (bare numbers are days, 0
+    /// cleans everything in the trash)
+    #[serde(with = "units::days")]
+    pub retention: Duration,
+}
+
+/// Reporting of unused browser profiles, and the browser files no cleaning
+/// may touch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserProfilesConfig {
+    /// Profiles not opened for this long are reported (bare numbers are
+    /// days, 0 disables); they are only cleaned with --clean-unused-profiles
+    #[serde(with = "units::days")]
+    pub unused_after: Duration,
+    /// File names added to the built-in protection list, by browser (firefox,
+    /// chrome, chromium, brave, edge, vivaldi)
+    pub protected_files: BTreeMap<String, Vec<String>>,
+}
+
+/// Cleaning of the regenerable parts of IDE data directories; settings and
+/// local history are never cleaned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdeCachesConfig {
+    /// Clean VS Code's extension downloads, code caches and old workspace storage
+    pub vscode: bool,
+    /// Clean the caches and indexes of JetBrains IDEs
+    pub jetbrains: bool,
+    /// VS Code workspace storage untouched this long is cleaned (bare numbers
+    /// are days, 0 disables)
+    #[serde(with = "units::days")]
+    pub workspace_storage_age: Duration,
+}
+
+/// Cleanup of what deleted pods leave on a Kubernetes node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KubernetesConfig {
+    /// CRI endpoint `crictl` asks for the pods that still exist
+    pub cri_endpoint: String,
+    /// Pod directories changed more recently than this are left alone (bare
+    /// numbers are seconds)
+    #[serde(with = "units::secs")]
+    pub orphan_grace: Duration,
+}
+
+/// Vacuuming of the systemd journal, which is never deleted whole
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    /// Archived journal files older than this are removed (bare numbers are
+    /// days, 0 keeps them whatever their age)
+    #[serde(with = "units::days")]
+    pub vacuum_time: Duration,
+    /// Archived journal files are removed oldest first until the journal
+    /// takes no more than this (0 keeps them whatever the size)
+    #[serde(with = "units::size")]
+    pub vacuum_size: u64,
+}
+
+/// Ranking and sweeping of the projects in a code workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectsConfig {
+    /// Projects with no activity for this long are stale and swept (bare
+    /// numbers are days)
+    #[serde(with = "units::days")]
+    pub stale_after: Duration,
+}
+
+/// Metrics and alerts written by scheduled `cleaner monitor` runs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorConfig {
+    /// Prometheus textfile the metrics are written to after every run
+    pub metrics_file: Option<PathBuf>,
+    /// Alert when more than this is reclaimable (bare numbers are bytes, 0 disables)
+    #[serde(with = "units::size")]
+    pub alert_reclaimable_above: u64,
+    /// Alert when a filesystem with findings has less than this available,
+    /// e.g. "10%" or "5GiB"
+    pub alert_free_below: Option<FreeThreshold>,
+    /// Command run with the alerts as JSON on stdin
+    pub notify_command: Option<String>,
+    /// URL the alerts are POSTed to as JSON
+    pub webhook_url: Option<String>,
+}
+
+/// Conditions that make `monitor` and `--scheduled` runs wait before scanning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// Wait while the machine runs on battery
+    pub defer_on_battery: bool,
+    /// Wait while the one-minute load average per CPU is above this
+    pub max_load_per_cpu: Option<f64>,
+    /// Wait while CPU or IO pressure (PSI `some avg10`, in percent) is above this
+    pub max_pressure: Option<f64>,
+    /// How often to check again while waiting (bare numbers are seconds)
+    #[serde(with = "units::secs")]
+    pub retry_every: Duration,
+    /// Skip the run when it had to wait this long (bare numbers are seconds)
+    #[serde(with = "units::secs")]
+    pub give_up_after: Duration,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            defer_on_battery: false,
+            max_load_per_cpu: None,
+            max_pressure: None,
+            retry_every: Duration::from_secs(5 * 60),
+            give_up_after: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Rebuild cost hints shown before confirming a clean
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RebuildCostsConfig {
+    /// Show the built-in hints for well-known caches
+    pub builtin: bool,
+    /// Hints for matching cache items, taking precedence over the built-in ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<RebuildHint>,
+}
+
+/// What cleaning the cache items a hint matches costs later
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RebuildHint {
+    /// Cache types the hint applies to (empty matches every type)
+    pub cache_types: Vec<CacheType>,
+    /// Glob the item path must match (`~` is expanded, absent matches every path)
+    pub path: Option<String>,
+    /// Shown before confirming, with `{size}` replaced by the size of the
+    /// matching items; empty hides the matching items' built-in hint
+    pub hint: String,
+}
+
+impl Default for RebuildCostsConfig {
+    fn default() -> Self {
+        Self {
+            builtin: true,
+            hints: Vec::new(),
+        }
+    }
+}
+
+/// Commands run around the cleaning of the cache items a hook matches
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanHook {
+    /// Cache types the hook applies to (empty matches every type)
+    pub cache_types: Vec<CacheType>,
+    /// Glob the item path must match (`~` is expanded, absent matches every path)
+    pub path: Option<String>,
+    /// Run before deleting an item; a non-zero exit keeps the item
+    pub pre: Option<String>,
+    /// Run after an item was deleted
+    pub post: Option<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            keep: 50,
+            suggest_exclusions_after: 5,
+        }
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            spool_warn_size: 1024 * 1024 * 1024,                    // 1GB
+            dead_queue_age: Duration::from_secs(30 * 24 * 60 * 60), // 30 days
+            clean_dead_queue: false,
+        }
+    }
+}
+
+impl Default for PrintingConfig {
+    fn default() -> Self {
+        Self {
+            job_retention: Duration::from_secs(7 * 24 * 60 * 60), // 1 week
+        }
+    }
+}
+
+impl Default for VolumeTrashConfig {
+    fn default() -> Self {
+        Self {
+            retention: Duration::from_secs(30 * 24 * 60 * 60), // 30 days
+        }
+    }
+}
+
+impl Default for BrowserProfilesConfig {
+    fn default() -> Self {
+        Self {
+            unused_after: Duration::from_secs(180 * 24 * 60 * 60), // ~6 months
+            protected_files: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for IdeCachesConfig {
+    fn default() -> Self {
+        Self {
+            vscode: true,
+            jetbrains: true,
+            workspace_storage_age: Duration::from_secs(90 * 24 * 60 * 60), // ~3 months
+        }
+    }
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> Self {
+        Self {
+            cri_endpoint: "unix:///run/containerd/containerd.sock".to_string(),
+            orphan_grace: Duration::from_secs(60 * 60), // 1 hour
+        }
+    }
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            vacuum_time: Duration::from_secs(30 * 24 * 60 * 60), // 30 days
+            vacuum_size: 1 << 30,                                // 1 GiB
+        }
+    }
+}
+
+impl Default for ProjectsConfig {
+    fn default() -> Self {
+        Self {
+            stale_after: Duration::from_secs(90 * 24 * 60 * 60), // ~3 months
+        }
+    }
+}
+
+fn default_regenerating_caches() -> Vec<String> {
+    vec![
+        "/var/cache/fontconfig".to_string(),
+        ".cache/fontconfig".to_string(),
+        "*/icons/*/icon-theme.cache".to_string(),
+        ".cache/icon-cache.kcache".to_string(),
+    ]
+}
+
+impl CachePatterns {
+    /// Resolve the patterns aimed at other users' homes: `~user/...` becomes
+    /// that user's home, and `/home/*/...` additionally covers every home in
+    /// `homes` that lives outside /home, so system-wide patterns reach
+    /// per-user caches without naming the users
+    pub fn expand_user_homes(&mut self, homes: &[PathBuf]) {
+        for list in [
+            &mut self.user_cache_dirs,
+            &mut self.system_cache_dirs,
+            &mut self.app_cache_patterns,
+            &mut self.package_manager_caches,
+            &mut self.dev_tool_caches,
+            &mut self.browser_caches,
+            &mut self.temp_patterns,
+            &mut self.regenerating_caches,
+        ] {
+            *list = expand_user_patterns(list, homes);
+        }
+        for options in &mut self.pattern_options {
+            if options.pattern.starts_with('~') && !options.pattern.starts_with("~/") {
+                options.pattern = Config::expand_home(&options.pattern);
+            }
+        }
+    }
+}
+
+fn expand_user_patterns(patterns: &[String], homes: &[PathBuf]) -> Vec<String> {
+    let mut expanded: Vec<String> = Vec::new();
+    for pattern in patterns {
+        let mut push = |pattern: String| {
+            if !expanded.contains(&pattern) {
+                expanded.push(pattern);
+            }
+        };
+        if let Some(rest) = pattern.strip_prefix("/home/*/") {
+            push(pattern.clone());
+            for home in homes.iter().filter(|home| !home.starts_with("/home")) {
+                push(format!("{}/{}", home.display(), rest));
+            }
+        } else if pattern.starts_with('~') && !pattern.starts_with("~/") {
+            // `~/...` stays relative: it matches in whichever home is scanned
+            push(Config::expand_home(pattern));
+        } else {
+            push(pattern.clone());
+        }
+    }
+    expanded
 }
 
 impl Default for CachePatterns {
@@ -102,8 +668,8 @@ impl Default for CachePatterns {
                 "/tmp".to_string(),
                 "/var/lib/apt/lists".to_string(),
                 "/var/cache/apt".to_string(),
-                "/var/cache/fontconfig".to_string(),
                 "/var/cache/man".to_string(),
+                "/var/cache/cups".to_string(),
             ],
 
             // Application-specific patterns
@@ -170,6 +736,11 @@ impl Default for CachePatterns {
                 "*.class".to_string(),
                 "*.dSYM".to_string(),
             ],
+
+            regenerating_caches: default_regenerating_caches(),
+
+            // No per-location overrides by default
+            pattern_options: Vec::new(),
         }
     }
 }
@@ -178,7 +749,7 @@ impl Default for LogCleanupConfig {
     fn default() -> Self {
         Self {
             enabled: true,
-            max_age_days: 7, // 1 week as requested
+            max_age: Duration::from_secs(7 * 24 * 60 * 60), // 1 week as requested
             log_patterns: vec![
                 "/var/log".to_string(),
                 "~/.local/share/*/logs".to_string(),
@@ -195,7 +766,11 @@ impl Default for LogCleanupConfig {
                 "debug".to_string(),
                 "trace".to_string(),
             ],
-            min_size_bytes: 1024, // Only clean logs > 1KB
+            min_size: 1024, // Only clean logs > 1KB
+            min_confidence: Confidence::Low,
+            live_logs: LiveLogAction::Truncate,
+            log_action: LogAction::Delete,
+            min_retention: BTreeMap::new(),
         }
     }
 }
@@ -219,10 +794,19 @@ impl Default for SafetyConfig {
                 "/bin".to_string(),
                 "/sbin".to_string(),
             ],
-            confirm_threshold_bytes: 100 * 1024 * 1024, // 100MB
+            confirm_threshold: 100 * 1024 * 1024, // 100MB
             max_files_per_operation: 10000,
             dry_run: false,
             create_backup_list: true,
+            resolve_symlink_targets: false,
+            symlink_target_roots: Vec::new(),
+            checksum_manifest: false,
+            manifest_max_hash_size: 1024 * 1024 * 1024, // 1GB
+            manifest_hash_rate: 0,
+            policy_command: None,
+            protect_vcs_metadata: true,
+            verify_log_content: true,
+            shred_passes: 3,
         }
     }
 }
@@ -231,16 +815,68 @@ impl Default for PerformanceConfig {
     fn default() -> Self {
         Self {
             max_threads: None, // Use system default
-            access_timeout_secs: 5,
+            access_timeout: Duration::from_secs(5),
+            max_entries_per_dir: Some(1_000_000),
             skip_symlinks: true,
             max_depth: Some(10), // Reasonable depth limit
+            one_file_system: false,
+            size_cache: false,
+            estimate_sample: 2000,
+            confirm_scans_over: Duration::from_secs(5 * 60),
+            scan_hidden: ScanHidden::All,
+            adaptive_throttle: true,
+            throttle_pressure: 30.0,
+            parallel_delete_files: 10_000,
+        }
+    }
+}
+
+/// Marks an `exclude_paths` entry matched as a path prefix instead of a
+/// substring
+pub const ANCHOR: char = '^';
+
+/// The anchored `exclude_paths` entry for `path`
+pub fn anchored(path: &Path) -> String {
+    format!("{}{}", ANCHOR, path.to_string_lossy())
+}
+
+/// Append `entry` to a TOML array, one per line like the entries before it
+/// when the array spans lines
+fn push_entry(array: &mut toml_edit::Array, entry: &str) {
+    let raw = |raw: Option<&toml_edit::RawString>| {
+        raw.and_then(|raw| raw.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let first_prefix = raw(array.get(0).and_then(|value| value.decor().prefix()));
+    // The comment after the last entry belongs to the array's trailing text,
+    // or to the entry itself when it has no trailing comma
+    let mut trailing = raw(Some(array.trailing()));
+    if !array.trailing_comma() {
+        trailing = raw(array.iter().last().and_then(|value| value.decor().suffix())) + &trailing;
+    }
+    match first_prefix.rsplit_once('\n') {
+        Some((_, indent)) if trailing.contains('\n') => {
+            if let Some(last) = array.iter_mut().last() {
+                last.decor_mut().set_suffix("");
+            }
+            let mut value = toml_edit::Value::from(entry);
+            value.decor_mut().set_prefix(format!(
+                "{}{}",
+                trailing.trim_end_matches([' ', '\t']),
+                indent
+            ));
+            array.push_formatted(value);
+            array.set_trailing("\n");
+            array.set_trailing_comma(true);
         }
+        _ => array.push(entry),
     }
 }
 
 impl Config {
     /// Load configuration from file, falling back to default if not found
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, CleanerError> {
         let path = path.as_ref();
 
         if !path.exists() {
@@ -250,22 +886,24 @@ impl Config {
             return Ok(default_config);
         }
 
-        let content = fs::read_to_string(path)?;
-        let config: Self = toml::from_str(&content)?;
+        let content = fs::read_to_string(path).map_err(|e| CleanerError::from_io(path, &e))?;
+        let config: Self =
+            toml::from_str(&content).map_err(|e| CleanerError::Config(e.to_string()))?;
         Ok(config)
     }
 
     /// Save configuration to file
-    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), CleanerError> {
         let path = path.as_ref();
 
         // Create parent directories if they don't exist
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+            fs::create_dir_all(parent).map_err(|e| CleanerError::from_io(parent, &e))?;
         }
 
-        let content = toml::to_string_pretty(self)?;
-        fs::write(path, content)?;
+        let content =
+            toml::to_string_pretty(self).map_err(|e| CleanerError::Config(e.to_string()))?;
+        fs::write(path, content).map_err(|e| CleanerError::from_io(path, &e))?;
         Ok(())
     }
 
@@ -283,7 +921,7 @@ impl Config {
 
     /// Validate configuration
     pub fn validate(&self) -> Result<(), String> {
-        if self.log_cleanup.max_age_days == 0 {
+        if self.log_cleanup.max_age.is_zero() {
             return Err("Log max age cannot be zero".to_string());
         }
 
@@ -303,20 +941,95 @@ impl Config {
             return Err("Max depth cannot be zero".to_string());
         }
 
+        for options in &self.cache_patterns.pattern_options {
+            if options.pattern.is_empty() {
+                return Err("Pattern options require a pattern".to_string());
+            }
+            if options.max_depth == Some(0) {
+                return Err(format!(
+                    "Max depth for pattern '{}' cannot be zero",
+                    options.pattern
+                ));
+            }
+        }
+
+        for hook in &self.hooks {
+            if hook.pre.is_none() && hook.post.is_none() {
+                return Err("Hooks require a pre or post command".to_string());
+            }
+            if let Some(path) = &hook.path
+                && let Err(e) = glob::Pattern::new(&Self::expand_home(path))
+            {
+                return Err(format!("Invalid hook path '{}': {}", path, e));
+            }
+        }
+
+        if self.performance.throttle_pressure.is_nan() || self.performance.throttle_pressure <= 0.0
+        {
+            return Err("Throttle pressure must be above zero".to_string());
+        }
+
+        if self.schedule.retry_every.is_zero() {
+            return Err("Schedule retry_every cannot be zero".to_string());
+        }
+
+        for hint in &self.rebuild_costs.hints {
+            if let Some(path) = &hint.path
+                && let Err(e) = glob::Pattern::new(&Self::expand_home(path))
+            {
+                return Err(format!("Invalid rebuild hint path '{}': {}", path, e));
+            }
+        }
+
+        if let Some(max_entries) = self.performance.max_entries_per_dir
+            && max_entries == 0
+        {
+            return Err("Max entries per directory cannot be zero".to_string());
+        }
+
         Ok(())
     }
 
     /// Get log file age threshold as Duration
     pub fn log_age_threshold(&self) -> Duration {
-        Duration::from_secs(self.log_cleanup.max_age_days * 24 * 60 * 60)
+        self.log_cleanup.max_age
+    }
+
+    /// Minimum age a log of this type must reach before it may be deleted.
+    /// A low-confidence type may be wrong, so such logs get the longest floor.
+    pub fn log_retention_floor(
+        &self,
+        log_type: &LogType,
+        confidence: Confidence,
+    ) -> Option<Duration> {
+        if confidence == Confidence::Low {
+            return self.log_cleanup.min_retention.values().max().copied();
+        }
+        self.log_cleanup.min_retention.get(log_type).copied()
     }
 
-    /// Check if a path should be excluded from cleaning
+    /// Log types whose minimum retention is above the configured age threshold
+    pub fn retention_overrides(&self) -> Vec<(LogType, Duration)> {
+        self.log_cleanup
+            .min_retention
+            .iter()
+            .filter(|(_, floor)| **floor > self.log_cleanup.max_age)
+            .map(|(log_type, floor)| (log_type.clone(), *floor))
+            .collect()
+    }
+
+    /// Check if a path should be excluded from cleaning. Patterns match
+    /// anywhere in the path, except anchored ones starting with `^`, which
+    /// match that path and everything below it.
     pub fn is_excluded_path(&self, path: &Path) -> bool {
         let path_str = path.to_string_lossy();
 
         for exclude_pattern in &self.safety.exclude_paths {
-            if path_str.contains(exclude_pattern) {
+            let excluded = match exclude_pattern.strip_prefix(ANCHOR) {
+                Some(anchored) => path.starts_with(Self::expand_home(anchored)),
+                None => path_str.contains(exclude_pattern),
+            };
+            if excluded {
                 return true;
             }
         }
@@ -324,13 +1037,88 @@ impl Config {
         false
     }
 
-    /// Get effective thread count
-    pub fn effective_thread_count(&self) -> usize {
+    /// Exclude `paths` from future runs as anchored patterns, skipping those
+    /// already excluded. Returns how many were added.
+    pub fn add_exclusions(&mut self, paths: &[PathBuf]) -> usize {
+        let mut added = 0;
+        for path in paths {
+            if !self.is_excluded_path(path) {
+                self.safety.exclude_paths.push(anchored(path));
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Write `exclude_paths` into the config file at `path`, adding the
+    /// entries it lacks and leaving everything else, comments included, as
+    /// it is
+    pub fn save_exclusions<P: AsRef<Path>>(&self, path: P) -> Result<(), CleanerError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return self.save_to_file(path);
+        }
+        let content = fs::read_to_string(path).map_err(|e| CleanerError::from_io(path, &e))?;
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .map_err(|e: toml_edit::TomlError| CleanerError::Config(e.to_string()))?;
+
+        let safety = document
+            .entry("safety")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| CleanerError::Config("safety is not a table".to_string()))?;
+        match safety
+            .get_mut("exclude_paths")
+            .and_then(|item| item.as_array_mut())
+        {
+            Some(array) => {
+                let listed: Vec<String> = array
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect();
+                for exclude in &self.safety.exclude_paths {
+                    if !listed.contains(exclude) {
+                        push_entry(array, exclude);
+                    }
+                }
+            }
+            // Without the key the defaults apply, so they are written out
+            None => {
+                safety.insert(
+                    "exclude_paths",
+                    toml_edit::value(toml_edit::Array::from_iter(&self.safety.exclude_paths)),
+                );
+            }
+        }
+        fs::write(path, document.to_string()).map_err(|e| CleanerError::from_io(path, &e))
+    }
+
+    /// Expand a leading `~` to the user's home directory, and `~user` to the
+    /// home of that user (left as is for unknown users)
+    pub fn expand_home(pattern: &str) -> String {
+        let Some(rest) = pattern.strip_prefix('~') else {
+            return pattern.to_string();
+        };
+        let (user, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if user.is_empty() {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            return format!("{}{}", home, path);
+        }
+        match owners::home_dir(user) {
+            Some(home) => format!("{}{}", home.display(), path),
+            None => pattern.to_string(),
+        }
+    }
+
+    /// Get effective thread count, adapted to the storage being scanned
+    /// unless `max_threads` is set
+    pub fn effective_thread_count(&self, storage: StorageKind) -> usize {
         self.performance.max_threads.unwrap_or_else(|| {
-            std::thread::available_parallelism()
+            let cpus = std::thread::available_parallelism()
                 .map(|n| n.get())
-                .unwrap_or(4)
-                .min(8) // Cap at 8 threads to avoid overwhelming the system
+                .unwrap_or(4);
+            storage.default_threads(cpus)
         })
     }
 }
@@ -345,7 +1133,10 @@ mod tests {
         assert!(config.validate().is_ok());
         assert!(!config.cache_patterns.user_cache_dirs.is_empty());
         assert!(config.log_cleanup.enabled);
-        assert_eq!(config.log_cleanup.max_age_days, 7);
+        assert_eq!(
+            config.log_cleanup.max_age,
+            Duration::from_secs(7 * 24 * 60 * 60)
+        );
     }
 
     #[test]
@@ -353,9 +1144,160 @@ mod tests {
         let config = Config::default();
         let toml_str = toml::to_string(&config).unwrap();
         let deserialized: Config = toml::from_str(&toml_str).unwrap();
+        assert_eq!(config.log_cleanup.max_age, deserialized.log_cleanup.max_age);
+    }
+
+    #[test]
+    fn test_pattern_options_round_trip() {
+        let mut config = Config::default();
+        config.cache_patterns.pattern_options.push(PatternOptions {
+            pattern: "~/.cache".to_string(),
+            max_depth: Some(20),
+            follow_symlinks: None,
+            one_file_system: Some(true),
+        });
+
+        let toml_str = toml::to_string(&config).unwrap();
+        let deserialized: Config = toml::from_str(&toml_str).unwrap();
+        assert_eq!(
+            deserialized.cache_patterns.pattern_options,
+            config.cache_patterns.pattern_options
+        );
+        assert!(deserialized.validate().is_ok());
+    }
+
+    #[test]
+    fn test_expand_user_homes() {
+        let mut patterns = CachePatterns {
+            package_manager_caches: vec![
+                "/home/*/.cache/yarn".to_string(),
+                "~root/.npm".to_string(),
+                "~/.cache/pip".to_string(),
+                "~no-such-user-here/.cache".to_string(),
+            ],
+            ..CachePatterns::default()
+        };
+        patterns.expand_user_homes(&[
+            PathBuf::from("/home/alice"),
+            PathBuf::from("/var/lib/postgresql"),
+        ]);
+
+        assert_eq!(
+            patterns.package_manager_caches,
+            [
+                "/home/*/.cache/yarn",
+                "/var/lib/postgresql/.cache/yarn",
+                "/root/.npm",
+                "~/.cache/pip",
+                "~no-such-user-here/.cache",
+            ]
+        );
+        assert_eq!(Config::expand_home("~root"), "/root");
+    }
+
+    #[test]
+    fn test_log_retention_floor() {
+        let ninety_days = Duration::from_secs(90 * 24 * 60 * 60);
+        let mut config = Config::default();
+        config.log_cleanup.max_age = Duration::from_secs(3 * 24 * 60 * 60);
+        config.log_cleanup.min_retention = BTreeMap::from([
+            (LogType::Access, ninety_days),
+            (LogType::Security, Duration::from_secs(31_557_600)),
+        ]);
+
+        assert_eq!(
+            config.log_retention_floor(&LogType::Access, Confidence::Medium),
+            Some(ninety_days)
+        );
+        assert_eq!(
+            config.log_retention_floor(&LogType::System, Confidence::High),
+            None
+        );
+        assert_eq!(
+            config.log_retention_floor(&LogType::System, Confidence::Low),
+            config.log_retention_floor(&LogType::Security, Confidence::High)
+        );
+        assert_eq!(
+            config.retention_overrides(),
+            vec![
+                (LogType::Access, ninety_days),
+                (LogType::Security, Duration::from_secs(31_557_600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_human_and_legacy_values() {
+        let config: Config = toml::from_str(
+            &toml::to_string(&Config::default())
+                .unwrap()
+                .replace("max_age = \"7days\"", "max_age_days = 3")
+                .replace("min_size = \"1KiB\"", "min_size = \"250MiB\"")
+                .replace(
+                    "confirm_threshold = \"100MiB\"",
+                    "confirm_threshold_bytes = 2048",
+                )
+                .replace("access_timeout = \"5s\"", "access_timeout = \"1m\"")
+                .replace("min_confidence = \"low\"\n", "")
+                .replace("live_logs = \"truncate\"\n", ""),
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.log_cleanup.max_age,
+            Duration::from_secs(3 * 24 * 60 * 60)
+        );
+        assert_eq!(config.log_cleanup.min_size, 250 * 1024 * 1024);
+        assert_eq!(config.safety.confirm_threshold, 2048);
+        assert_eq!(config.performance.access_timeout, Duration::from_secs(60));
+        assert_eq!(config.log_cleanup.min_confidence, Confidence::Low);
+        assert_eq!(config.log_cleanup.live_logs, LiveLogAction::Truncate);
+
+        let invalid = toml::to_string(&Config::default())
+            .unwrap()
+            .replace("min_size = \"1KiB\"", "min_size = \"12 parsecs\"");
+        let err = toml::from_str::<Config>(&invalid).unwrap_err().to_string();
+        assert!(err.contains("invalid size '12 parsecs'"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_performance_fields_use_defaults() {
+        let toml_str = toml::to_string(&Config::default())
+            .unwrap()
+            .replace("max_entries_per_dir = 1000000\n", "");
+        let config: Config = toml::from_str(&toml_str).unwrap();
+        assert_eq!(config.performance.max_entries_per_dir, Some(1_000_000));
+    }
+
+    #[test]
+    fn test_add_exclusions() {
+        let mut config = Config::default();
+        let kept = [
+            PathBuf::from("/home/u/project/build"),
+            PathBuf::from("/home/u/project/build/out"),
+            PathBuf::from("/home/u/.cache/huggingface"),
+        ];
+
+        assert_eq!(config.add_exclusions(&kept), 2);
+        assert!(config.is_excluded_path(Path::new("/home/u/project/build/out/a.o")));
+        assert!(!config.is_excluded_path(Path::new("/home/u/project/build2")));
+        assert_eq!(config.add_exclusions(&kept), 0);
+
+        // Saved into the file without losing its comments
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "# mine\n[safety]\nexclude_paths = [\n    \"/.git\", # vcs\n]\n",
+        )
+        .unwrap();
+        config.safety.exclude_paths = vec!["/.git".to_string()];
+        config.add_exclusions(&kept);
+        config.save_exclusions(&path).unwrap();
         assert_eq!(
-            config.log_cleanup.max_age_days,
-            deserialized.log_cleanup.max_age_days
+            fs::read_to_string(&path).unwrap(),
+            "# mine\n[safety]\nexclude_paths = [\n    \"/.git\", # vcs\n    \
+             \"^/home/u/project/build\",\n    \"^/home/u/.cache/huggingface\",\n]\n"
         );
     }
 }
@@ -364,8 +1306,5 @@ fn test_config_serialization() {
     let config = Config::default();
     let toml_str = toml::to_string(&config).unwrap();
     let deserialized: Config = toml::from_str(&toml_str).unwrap();
-    assert_eq!(
-        config.log_cleanup.max_age_days,
-        deserialized.log_cleanup.max_age_days
-    );
+    assert_eq!(config.log_cleanup.max_age, deserialized.log_cleanup.max_age);
 }
@@ -0,0 +1,125 @@
+use crate::cache_detector::CacheItem;
+use crate::error::CleanerError;
+use crate::log_cleaner::LogFile;
+use crate::redact;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+const HEADER: &str = "kind,path,type,size_bytes,file_count,last_modified";
+
+/// Write one row per cache item and log file to `path`, for spreadsheets
+pub fn export(
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+    path: &Path,
+) -> Result<(), CleanerError> {
+    fs::write(path, build_export(cache_items, log_files))
+        .map_err(|e| CleanerError::from_io(path, &e))
+}
+
+fn build_export(cache_items: &[CacheItem], log_files: &[LogFile]) -> String {
+    let mut content = format!("{}\n", HEADER);
+    for item in cache_items {
+        push_row(
+            &mut content,
+            [
+                "cache",
+                &redact::path(&item.path).to_string_lossy(),
+                item.cache_type.description(),
+                &item.size_bytes.map(|s| s.to_string()).unwrap_or_default(),
+                &item.file_count.map(|c| c.to_string()).unwrap_or_default(),
+                &timestamp(item.last_modified),
+            ],
+        );
+    }
+    for log in log_files {
+        push_row(
+            &mut content,
+            [
+                "log",
+                &redact::path(&log.path).to_string_lossy(),
+                log.log_type.description(),
+                &log.size_bytes.to_string(),
+                "1",
+                &timestamp(Some(log.last_modified)),
+            ],
+        );
+    }
+    content
+}
+
+/// RFC 3339 in UTC, which spreadsheets parse as a date; empty when unknown
+fn timestamp(time: Option<SystemTime>) -> String {
+    time.map(|t| DateTime::<Utc>::from(t).to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Append a line of fields, quoting those with separators, quotes or line
+/// breaks as RFC 4180 does
+fn push_row<const N: usize>(content: &mut String, fields: [&str; N]) {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    content.push_str(&quoted.join(","));
+    content.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::log_cleaner::{Confidence, LogType};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn test_csv_export() {
+        let item = CacheItem {
+            size_bytes: Some(4096),
+            file_count: Some(3),
+            last_modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(86400)),
+            ..CacheItem::new("/home/u/.cache/a \"b\",c", CacheType::UserCache, "")
+        };
+        let log = LogFile {
+            path: PathBuf::from("/var/log/old.log"),
+            size_bytes: 10,
+            last_modified: SystemTime::UNIX_EPOCH,
+            age: Duration::ZERO,
+            log_type: LogType::System,
+            confidence: Confidence::High,
+            owner: None,
+            writer: None,
+            file_id: None,
+            reason: String::new(),
+        };
+        let unmeasured = CacheItem {
+            size_bytes: None,
+            file_count: None,
+            last_modified: None,
+            ..item.clone()
+        };
+
+        let lines: Vec<String> = build_export(&[item, unmeasured], &[log])
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                HEADER,
+                "cache,\"/home/u/.cache/a \"\"b\"\",c\",User cache directory,4096,3,1970-01-02T00:00:00Z",
+                "cache,\"/home/u/.cache/a \"\"b\"\",c\",User cache directory,,,",
+                "log,/var/log/old.log,System log,10,1,1970-01-01T00:00:00Z",
+            ]
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use crate::cache_detector::{CacheDetector, CacheItem};
+use crate::error::CleanerError;
+use crate::log_cleaner::{LogCleaner, LogFile};
+use crate::mounts::MountTable;
+use crate::scan_report::ScanReport;
+use crate::{ScanOptions, ide, journal, kubelet, mail, printing, reclaim, trash, walker};
+use std::path::Path;
+
+/// When a scan runs a detector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// While walking with the cache patterns, its items put through their
+    /// safety filters, deduplication and symlink resolution
+    Patterns,
+    /// After the patterns, its items measured along with theirs
+    Unsized,
+    /// After measuring, its items sized by the detector itself
+    Sized,
+}
+
+/// A source of cache items. [`CacheDetector`] runs the registered detectors
+/// of the [`Stage::Patterns`] stage and puts their items through the same
+/// safety filters, deduplication and symlink resolution, so a new source
+/// needs no change to it; [`crate::scan`] runs the others around measuring
+/// the items.
+pub trait Detector: Send + Sync {
+    /// Short name for messages, such as `cache-directories`
+    fn name(&self) -> &str;
+
+    /// When the scan runs this detector
+    fn stage(&self) -> Stage {
+        Stage::Patterns
+    }
+
+    /// The items found under `root`, with what couldn't be read as warnings
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError>;
+
+    /// Add what [`scan`](Detector::scan) finds to the items `found` before
+    /// it. Items inside one another are settled when the scan ends, keeping
+    /// the outer one; a source that must keep paths out of the others'
+    /// items merges them on its own.
+    fn merge(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+        found: &mut ScanReport<CacheItem>,
+    ) -> Result<(), CleanerError> {
+        found.merge(self.scan(root, options)?);
+        Ok(())
+    }
+
+    /// Old log files found under `root` by a detector outside the patterns
+    /// stage; most find none
+    fn scan_logs(
+        &self,
+        _root: &Path,
+        _options: &ScanOptions,
+    ) -> Result<ScanReport<LogFile>, CleanerError> {
+        Ok(ScanReport::new())
+    }
+}
+
+/// Directories and regenerating files matching the configured cache patterns
+pub struct CacheDirectories;
+
+impl Detector for CacheDirectories {
+    fn name(&self) -> &str {
+        "cache-directories"
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        CacheDetector::new(options.config.clone()).detect_cache_directories(root)
+    }
+}
+
+/// Paths matching the `build_artifacts` globs
+pub struct BuildArtifacts;
+
+impl Detector for BuildArtifacts {
+    fn name(&self) -> &str {
+        "build-artifacts"
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        CacheDetector::new(options.config.clone()).detect_build_artifacts(root)
+    }
+}
+
+/// Files and directories matching the `temp_patterns`
+pub struct TemporaryFiles;
+
+impl Detector for TemporaryFiles {
+    fn name(&self) -> &str {
+        "temporary-files"
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        CacheDetector::new(options.config.clone()).detect_temporary_files(root)
+    }
+}
+
+/// Trash at the top of other volumes, out of reach of the patterns. Root
+/// only empties its own trash, or that of the `trash_owner`.
+pub struct VolumeTrash;
+
+impl Detector for VolumeTrash {
+    fn name(&self) -> &str {
+        "volume-trash"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Unsized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        let uid = options
+            .trash_owner
+            .unwrap_or_else(|| unsafe { libc::geteuid() });
+        Ok(ScanReport {
+            items: trash::detect(root, &options.config, &MountTable::load(), uid),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+/// Leftover print jobs, single files inside a spool that stays
+pub struct PrintJobs;
+
+impl Detector for PrintJobs {
+    fn name(&self) -> &str {
+        "print-jobs"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Unsized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        Ok(ScanReport {
+            items: printing::detect(root, &options.config),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+/// IDE caches, cleaned piecewise so settings and local history stay out of
+/// reach of the broader patterns
+pub struct IdeCaches;
+
+impl Detector for IdeCaches {
+    fn name(&self) -> &str {
+        "ide-caches"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Unsized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        Ok(ScanReport {
+            items: ide::detect(root, &options.config).items,
+            warnings: Vec::new(),
+        })
+    }
+
+    fn merge(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+        found: &mut ScanReport<CacheItem>,
+    ) -> Result<(), CleanerError> {
+        ide::detect(root, &options.config).merge_into(&mut found.items);
+        Ok(())
+    }
+}
+
+/// Root-only reclaim locations, when `system_reclaim` is set
+pub struct SystemReclaim;
+
+impl Detector for SystemReclaim {
+    fn name(&self) -> &str {
+        "system-reclaim"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Sized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        if !options.system_reclaim || walker::scan_timed_out() {
+            return Ok(ScanReport::new());
+        }
+        Ok(reclaim::detect(root, &options.config))
+    }
+}
+
+/// Leftovers of deleted pods, when `kubernetes_node` is set
+pub struct PodLeftovers;
+
+impl Detector for PodLeftovers {
+    fn name(&self) -> &str {
+        "pod-leftovers"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Sized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        if !options.kubernetes_node || walker::scan_timed_out() {
+            return Ok(ScanReport::new());
+        }
+        Ok(kubelet::detect(root, &options.config, &MountTable::load()))
+    }
+}
+
+/// Journal directories, vacuumed rather than deleted, so nothing else found
+/// inside them is cleaned
+pub struct Journals;
+
+impl Detector for Journals {
+    fn name(&self) -> &str {
+        "journals"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Sized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        if walker::scan_timed_out() {
+            return Ok(ScanReport::new());
+        }
+        Ok(ScanReport {
+            items: journal::detect(root, &options.config),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+/// Deferred mail nobody will deliver, when `mail.clean_dead_queue` is set
+pub struct DeadMailQueue;
+
+impl Detector for DeadMailQueue {
+    fn name(&self) -> &str {
+        "dead-mail-queue"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Sized
+    }
+
+    fn scan(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        if !options.config.mail.clean_dead_queue {
+            return Ok(ScanReport::new());
+        }
+        Ok(ScanReport {
+            items: mail::detect(root, &options.config).dead_queue_items(),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+/// Log files past their retention, when `log_cleanup` is enabled. A failed
+/// search is only a warning, leaving the cache items to be cleaned.
+pub struct OldLogs;
+
+impl Detector for OldLogs {
+    fn name(&self) -> &str {
+        "old-logs"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Sized
+    }
+
+    fn scan(&self, _: &Path, _: &ScanOptions) -> Result<ScanReport<CacheItem>, CleanerError> {
+        Ok(ScanReport::new())
+    }
+
+    fn scan_logs(
+        &self,
+        root: &Path,
+        options: &ScanOptions,
+    ) -> Result<ScanReport<LogFile>, CleanerError> {
+        if !options.config.log_cleanup.enabled {
+            return Ok(ScanReport::new());
+        }
+        let mut report = ScanReport::new();
+        match LogCleaner::new(options.config.clone()).find_old_log_files(root) {
+            Ok(found) => report.merge(found),
+            Err(e) => report.warnings.push(e),
+        }
+        Ok(report)
+    }
+}
+
+/// The detectors a scan runs, in order
+pub fn builtin() -> Vec<Box<dyn Detector>> {
+    vec![
+        Box::new(CacheDirectories),
+        Box::new(BuildArtifacts),
+        Box::new(TemporaryFiles),
+        Box::new(VolumeTrash),
+        Box::new(PrintJobs),
+        Box::new(IdeCaches),
+        Box::new(SystemReclaim),
+        Box::new(PodLeftovers),
+        Box::new(Journals),
+        Box::new(DeadMailQueue),
+        Box::new(OldLogs),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::config::Config;
+    use crate::owners::Owner;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Finds every `*.blob` file directly below the root
+    struct Blobs;
+
+    impl Detector for Blobs {
+        fn name(&self) -> &str {
+            "blobs"
+        }
+
+        fn scan(
+            &self,
+            root: &Path,
+            _: &ScanOptions,
+        ) -> Result<ScanReport<CacheItem>, CleanerError> {
+            let mut report = ScanReport::new();
+            for entry in fs::read_dir(root).map_err(|e| CleanerError::from_io(root, &e))? {
+                let path = entry.map_err(|e| CleanerError::from_io(root, &e))?.path();
+                if path.extension().is_some_and(|ext| ext == "blob") {
+                    report.items.push(CacheItem {
+                        owner: Owner::of_path(&path),
+                        ..CacheItem::new(path, CacheType::TemporaryFile, "is a blob")
+                    });
+                }
+            }
+            Ok(report)
+        }
+    }
+
+    #[test]
+    fn test_custom_detector() {
+        let temp_dir = TempDir::new().unwrap();
+        let blob = temp_dir.path().join("data.blob");
+        let cache = temp_dir.path().join(".cache");
+        fs::write(&blob, "x").unwrap();
+        fs::create_dir(&cache).unwrap();
+        // Items of custom detectors go through the same safety filters
+        let sources = temp_dir.path().join("sources.blob");
+        fs::create_dir(&sources).unwrap();
+        fs::write(sources.join("main.rs"), "fn main() {}").unwrap();
+
+        let names: Vec<String> = builtin().iter().map(|d| d.name().to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "cache-directories",
+                "build-artifacts",
+                "temporary-files",
+                "volume-trash",
+                "print-jobs",
+                "ide-caches",
+                "system-reclaim",
+                "pod-leftovers",
+                "journals",
+                "dead-mail-queue",
+                "old-logs"
+            ]
+        );
+
+        let mut detector = CacheDetector::new(Config::default());
+        detector.register(Box::new(Blobs));
+        let report = detector.detect_cache_items(temp_dir.path()).unwrap();
+        let paths: Vec<_> = report.items.iter().map(|i| &i.path).collect();
+        assert!(paths.contains(&&blob));
+        assert!(paths.contains(&&cache));
+        assert!(!paths.contains(&&sources));
+
+        let only_blobs = CacheDetector::with_detectors(Config::default(), vec![Box::new(Blobs)]);
+        let report = only_blobs.detect_cache_items(temp_dir.path()).unwrap();
+        assert_eq!(report.items.len(), 1);
+        assert_eq!(report.items[0].path, blob);
+    }
+}
@@ -1,9 +1,43 @@
-use crate::cache_detector::{CacheItem, CacheType};
-use crate::file_operations::{OperationResult, OperationSummary, format_bytes, format_duration};
-use crate::log_cleaner::{LogFile, LogType};
+use crate::ages::AgeUsage;
+use crate::apps::AppUsage;
+use crate::cache_detector::{CacheItem, CacheType, LOG_CATEGORY};
+use crate::error::CleanerError;
+use crate::estimate::{Estimate, rough_duration};
+use crate::file_operations::{
+    OperationResult, OperationSummary, PlannedOperation, format_bytes, format_duration,
+};
+use crate::growth::GrowthReport;
+use crate::history::{RootStats, ScanDiff, SnapshotItem};
+use crate::kernels::KernelArtifact;
+use crate::lint::{LintIssue, Severity};
+use crate::log_cleaner::{Confidence, LogFile, LogType};
+use crate::mail::MailReport;
+use crate::monitor::Alert;
+use crate::mounts::MountUsage;
+use crate::owners::{self, OwnerUsage};
+use crate::profiles::UnusedProfile;
+use crate::project::ProjectUsage;
+use crate::quota::QuotaViolation;
+use crate::rebuild::RebuildCost;
+use crate::savings::Savings;
+use crate::storage::StorageKind;
+use crate::temperature::Temperature;
+use crate::units::{self, group_digits};
+use crate::writers::HeldOpenFile;
 use colored::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Number of scan warnings listed when not in verbose mode
+const MAX_WARNINGS_SHOWN: usize = 10;
+
+/// Number of items listed per section of a diff when not in verbose mode
+const MAX_DIFF_ROWS: usize = 10;
+
+/// Number of fastest growing items listed in the summary
+const MAX_GROWERS_SHOWN: usize = 5;
 
 /// Display utilities for formatting output
 pub struct Display {
@@ -48,26 +82,50 @@ impl Display {
     }
 
     /// Display scanning information
-    pub fn show_scan_info(&self, root: &str, thread_count: usize, enable_logs: bool) {
+    pub fn show_scan_info(
+        &self,
+        root: &str,
+        thread_count: usize,
+        storage: StorageKind,
+        enable_caches: bool,
+        enable_logs: bool,
+    ) {
         println!(
             "Scanning: {} {}",
             root.white().bold(),
-            if enable_logs {
-                "(cache + logs)".dimmed()
-            } else {
-                "(cache only)".dimmed()
+            match (enable_caches, enable_logs) {
+                (true, true) => "(cache + logs)".dimmed(),
+                (true, false) => "(cache only)".dimmed(),
+                (false, _) => "(logs only)".dimmed(),
             }
         );
 
         if self.verbose {
             println!(
-                "Using {} threads for parallel processing",
-                thread_count.to_string().cyan()
+                "Using {} threads for parallel processing ({})",
+                thread_count.to_string().cyan(),
+                storage.description()
             );
         }
         println!();
     }
 
+    /// Point out log types whose minimum retention overrides the age threshold
+    pub fn show_retention_floors(&self, floors: &[(LogType, Duration)], max_age: Duration) {
+        for (log_type, floor) in floors {
+            println!(
+                "{} {} are kept for at least {} (minimum retention), above the {} age threshold",
+                "NOTE".bold().yellow(),
+                log_type.description(),
+                units::format_duration(*floor).cyan(),
+                units::format_duration(max_age)
+            );
+        }
+        if !floors.is_empty() {
+            println!();
+        }
+    }
+
     /// Display cache items found
     pub fn show_cache_items(&self, items: &[CacheItem]) {
         if items.is_empty() {
@@ -78,7 +136,7 @@ impl Display {
         println!(
             "{} {}",
             "FOUND".blue().bold(),
-            format!("{} cache items:", items.len()).bold()
+            format!("{} cache items:", group_digits(items.len() as u64)).bold()
         );
         println!();
 
@@ -103,7 +161,7 @@ impl Display {
             println!(
                 "  {} {} items, {}",
                 cache_type.description().cyan(),
-                count.to_string().yellow().bold(),
+                group_digits(count as u64).yellow().bold(),
                 format_bytes(total_size).red()
             );
         }
@@ -128,24 +186,42 @@ impl Display {
             }
 
             let size_info = if let Some(size) = item.size_bytes {
-                format!(" ({})", format_bytes(size)).red()
+                let approximate = if item.size_estimated { "~" } else { "" };
+                format!(" ({}{})", approximate, format_bytes(size)).red()
             } else {
                 " (calculating...)".dimmed()
             };
 
+            let temperature = match item.temperature {
+                Some(t @ Temperature::Hot) => format!(" {}", t.name()).red(),
+                Some(t @ Temperature::Warm) => format!(" {}", t.name()).yellow(),
+                Some(t @ Temperature::Cold) => format!(" {}", t.name()).blue(),
+                None => "".normal(),
+            };
+
             println!(
-                "    {} {}{}",
-                "→".dimmed(),
+                "  {} {}{}{}",
+                format!("{:>3}.", i + 1).dimmed(),
                 item.path.display().to_string().white(),
-                size_info
+                size_info,
+                temperature
             );
 
+            if let Some(target) = &item.resolved_path {
+                println!(
+                    "      {} symlink, cleaning target {}",
+                    "•".dimmed(),
+                    target.display().to_string().cyan()
+                );
+            }
+
             if self.verbose {
+                println!("      {} {}", "•".dimmed(), item.reason.dimmed());
                 if let Some(count) = item.file_count {
                     println!(
                         "      {} {} files",
                         "•".dimmed(),
-                        count.to_string().dimmed()
+                        group_digits(count as u64).dimmed()
                     );
                 }
                 if let Some(modified) = item.last_modified
@@ -161,8 +237,9 @@ impl Display {
         }
     }
 
-    /// Display log files found
-    pub fn show_log_files(&self, logs: &[LogFile]) {
+    /// Display log files found, numbered on from `first_number` so the
+    /// numbers continue those of the cache items
+    pub fn show_log_files(&self, logs: &[LogFile], first_number: usize) {
         if logs.is_empty() {
             println!("{}", "No old log files found.".green());
             return;
@@ -171,14 +248,14 @@ impl Display {
         println!(
             "{} {}",
             "LOG FILES".blue().bold(),
-            format!("{} old log files:", logs.len()).bold()
+            format!("{} old log files:", group_digits(logs.len() as u64)).bold()
         );
         println!();
 
         if self.summary_only {
             self.show_log_summary_details(logs);
         } else {
-            self.show_log_details(logs);
+            self.show_log_details(logs, first_number);
         }
     }
 
@@ -196,14 +273,14 @@ impl Display {
             println!(
                 "  {} {} files, {}",
                 log_type.description().cyan(),
-                count.to_string().yellow().bold(),
+                group_digits(count as u64).yellow().bold(),
                 format_bytes(total_size).red()
             );
         }
     }
 
     /// Display detailed log files
-    fn show_log_details(&self, logs: &[LogFile]) {
+    fn show_log_details(&self, logs: &[LogFile], first_number: usize) {
         let mut current_type = None;
 
         for (i, log) in logs.iter().enumerate() {
@@ -221,14 +298,25 @@ impl Display {
             }
 
             println!(
-                "    {} {} {} ({})",
-                "→".dimmed(),
+                "  {} {} {} ({})",
+                format!("{:>3}.", first_number + i).dimmed(),
                 log.path.display().to_string().white(),
                 format_bytes(log.size_bytes).red(),
                 format_duration(log.age).yellow()
             );
+            if log.confidence == Confidence::Low {
+                println!("      {} {}", "•".dimmed(), "type uncertain".dimmed());
+            }
+            if let Some(writer) = &log.writer {
+                println!(
+                    "      {} {}",
+                    "•".dimmed(),
+                    format!("open for writing by {}", writer).yellow()
+                );
+            }
 
             if self.verbose {
+                println!("      {} {}", "•".dimmed(), log.reason.dimmed());
                 println!(
                     "      {} Modified: {}",
                     "•".dimmed(),
@@ -241,8 +329,74 @@ impl Display {
         }
     }
 
+    /// Display non-fatal issues encountered while scanning
+    pub fn show_scan_warnings(&self, warnings: &[CleanerError]) {
+        if warnings.is_empty() {
+            return;
+        }
+
+        println!();
+        println!(
+            "{} {}",
+            "WARNINGS".yellow().bold(),
+            format!(
+                "{} issues during scan:",
+                group_digits(warnings.len() as u64)
+            )
+            .bold()
+        );
+
+        if self.summary_only {
+            return;
+        }
+
+        let shown = if self.verbose {
+            warnings.len()
+        } else {
+            warnings.len().min(MAX_WARNINGS_SHOWN)
+        };
+
+        for warning in &warnings[..shown] {
+            println!("    {} {}", "→".dimmed(), warning.to_string().yellow());
+        }
+
+        if shown < warnings.len() {
+            println!(
+                "    {} {}",
+                "…".dimmed(),
+                format!(
+                    "{} more (use --verbose to list all)",
+                    group_digits((warnings.len() - shown) as u64)
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// Warn that the scan was stopped by the time limit before it finished
+    pub fn show_incomplete_scan(&self, timeout: Duration) {
+        println!();
+        println!(
+            "{} {}",
+            "INCOMPLETE".red().bold(),
+            format!(
+                "Scan stopped after {}; items and sizes cover only part of the tree.",
+                units::format_duration(timeout)
+            )
+            .bold()
+        );
+    }
+
     /// Display total summary
-    pub fn show_total_summary(&self, cache_items: &[CacheItem], log_files: &[LogFile], root: &str) {
+    pub fn show_total_summary(
+        &self,
+        cache_items: &[CacheItem],
+        log_files: &[LogFile],
+        by_mount: &[MountUsage],
+        by_owner: &[OwnerUsage],
+        by_age: &[AgeUsage],
+        root: &str,
+    ) {
         let cache_size: u64 = cache_items.iter().map(|i| i.size_bytes.unwrap_or(0)).sum();
         let log_size: u64 = log_files.iter().map(|l| l.size_bytes).sum();
         let total_size = cache_size + log_size;
@@ -255,7 +409,7 @@ impl Display {
         if !cache_items.is_empty() {
             println!(
                 "Cache items: {} ({})",
-                cache_items.len().to_string().yellow().bold(),
+                group_digits(cache_items.len() as u64).yellow().bold(),
                 format_bytes(cache_size).red()
             );
         }
@@ -263,12 +417,581 @@ impl Display {
         if !log_files.is_empty() {
             println!(
                 "Log files: {} ({})",
-                log_files.len().to_string().yellow().bold(),
+                group_digits(log_files.len() as u64).yellow().bold(),
                 format_bytes(log_size).red()
             );
         }
 
         println!("Total space: {}", format_bytes(total_size).red().bold());
+        if cache_items.iter().any(|i| i.size_estimated) {
+            println!(
+                "{}",
+                "Sizes marked ~ are estimated from a sample of their files (--estimate)".dimmed()
+            );
+        }
+
+        if !by_mount.is_empty() {
+            println!("By filesystem:");
+            let width = by_mount
+                .iter()
+                .map(|u| u.mount_point.to_string_lossy().len())
+                .max()
+                .unwrap_or(0);
+            for usage in by_mount {
+                let mount_point = format!("{:<width$}", usage.mount_point.to_string_lossy());
+                // Root's reserved blocks are free but not usable by anyone else
+                let free = usage.free.map_or_else(String::new, |free| {
+                    let reserved = match free.reserved_bytes() {
+                        0 => String::new(),
+                        reserved => format!(", {} more reserved for root", format_bytes(reserved)),
+                    };
+                    format!(
+                        " ({} available to users{})",
+                        format_bytes(free.available_bytes),
+                        reserved
+                    )
+                });
+                println!(
+                    "  {} : {}{}",
+                    mount_point.cyan(),
+                    format_bytes(usage.bytes).red(),
+                    free.dimmed()
+                );
+            }
+        }
+
+        // A single owner is the common case and not worth a breakdown
+        if by_owner.len() > 1 {
+            println!("By owner:");
+            let width = by_owner.iter().map(|u| u.owner.len()).max().unwrap_or(0);
+            for usage in by_owner {
+                let owner = format!("{:<width$}", usage.owner);
+                println!("  {} : {}", owner.cyan(), format_bytes(usage.bytes).red());
+            }
+        }
+
+        if total_size > 0 {
+            println!("By age (last modified):");
+            let width = by_age
+                .iter()
+                .map(|u| u.age.label().len())
+                .max()
+                .unwrap_or(0);
+            for usage in by_age {
+                let age = format!("{:<width$}", usage.age.label());
+                println!(
+                    "  {} : {} {}",
+                    age.cyan(),
+                    format_bytes(usage.bytes).red(),
+                    format!("({:.0}%)", usage.bytes as f64 * 100.0 / total_size as f64).dimmed()
+                );
+            }
+        }
+    }
+
+    /// Show oversized mailboxes and the deferred mail queue
+    pub fn show_mail_report(&self, mail: &MailReport, cleaning_dead_queue: bool) {
+        if mail.is_empty() {
+            return;
+        }
+        let age = |time: Option<std::time::SystemTime>| {
+            time.and_then(|t| std::time::SystemTime::now().duration_since(t).ok())
+                .map_or_else(
+                    || "at an unknown time".to_string(),
+                    |age| format!("{} ago", format_duration(age)),
+                )
+        };
+
+        println!();
+        println!("{}", "MAIL".blue().bold());
+        for spool in &mail.spools {
+            println!(
+                "  {} mailbox {} ({}, last written {}, not cleaned)",
+                format_bytes(spool.size_bytes).yellow(),
+                spool.path.display(),
+                spool
+                    .owner
+                    .map_or_else(|| "unknown owner".to_string(), |o| owners::user_name(o.uid)),
+                age(spool.last_modified)
+            );
+        }
+        for queue in &mail.queues {
+            println!(
+                "  {} in {} queued messages at {}, oldest queued {}",
+                format_bytes(queue.size_bytes).yellow(),
+                queue.file_count,
+                queue.path.display(),
+                age(queue.oldest)
+            );
+            if queue.dead_count() > 0 {
+                println!(
+                    "    {} dead ({}), {}",
+                    queue.dead_count(),
+                    format_bytes(queue.dead_bytes()),
+                    if cleaning_dead_queue {
+                        "listed for cleaning".green()
+                    } else {
+                        "set mail.clean_dead_queue to clean them".dimmed()
+                    }
+                );
+            }
+        }
+    }
+
+    /// Show browser profiles nobody opened in a long time
+    pub fn show_unused_profiles(&self, profiles: &[UnusedProfile], cleaning: bool) {
+        if profiles.is_empty() {
+            return;
+        }
+        let total: u64 = profiles.iter().map(|p| p.size_bytes).sum();
+
+        println!();
+        println!(
+            "{} {}",
+            "UNUSED BROWSER PROFILES".blue().bold(),
+            if cleaning {
+                "(listed for cleaning)".green()
+            } else {
+                "(advisory, not cleaned)".dimmed()
+            }
+        );
+        println!(
+            "  {} in {} profile{} nobody opened for a long time",
+            format_bytes(total).yellow(),
+            profiles.len(),
+            if profiles.len() == 1 { "" } else { "s" }
+        );
+        if !self.summary_only {
+            for profile in profiles {
+                let idle = std::time::SystemTime::now()
+                    .duration_since(profile.last_used)
+                    .unwrap_or_default();
+                println!(
+                    "    {} ({}, {}, last used {} ago)",
+                    profile.path.display(),
+                    profile.browser.cyan(),
+                    format_bytes(profile.size_bytes),
+                    format_duration(idle)
+                );
+            }
+        }
+        if !cleaning {
+            println!(
+                "  {}",
+                "They hold bookmarks and passwords; pass --clean-unused-profiles to clean them."
+                    .dimmed()
+            );
+        }
+    }
+
+    /// Rank the projects whose build output was found, marking stale ones
+    pub fn show_projects(&self, projects: &[ProjectUsage], stale_after: Duration, sweeping: bool) {
+        if projects.is_empty() {
+            return;
+        }
+        let now = std::time::SystemTime::now();
+        let stale: Vec<&ProjectUsage> = projects
+            .iter()
+            .filter(|p| p.is_stale(stale_after, now))
+            .collect();
+
+        println!();
+        println!("{}", "PROJECTS BY BUILD OUTPUT".blue().bold());
+        if !self.summary_only {
+            for project in projects {
+                let idle = project
+                    .last_activity
+                    .and_then(|activity| now.duration_since(activity).ok())
+                    .map(|idle| format!("idle {} days", idle.as_secs() / (24 * 60 * 60)))
+                    .unwrap_or_else(|| "activity unknown".to_string());
+                println!(
+                    "    {:>10}  {:<16} {}{}",
+                    format_bytes(project.bytes).yellow(),
+                    idle,
+                    project.dir.display(),
+                    if project.is_stale(stale_after, now) {
+                        " stale".red()
+                    } else {
+                        "".normal()
+                    }
+                );
+            }
+        }
+        if !stale.is_empty() {
+            println!(
+                "  {} in {} stale project{} nobody touched for {} days{}",
+                format_bytes(stale.iter().map(|p| p.bytes).sum()).yellow(),
+                stale.len(),
+                if stale.len() == 1 { "" } else { "s" },
+                stale_after.as_secs() / (24 * 60 * 60),
+                if sweeping {
+                    "".normal()
+                } else {
+                    "; pass --sweep to clean only them".dimmed()
+                }
+            );
+        }
+    }
+
+    /// Show leftovers of removed kernels as advice; they are never cleaned
+    pub fn show_kernel_artifacts(&self, artifacts: &[KernelArtifact]) {
+        if artifacts.is_empty() {
+            return;
+        }
+        let total: u64 = artifacts.iter().map(|a| a.size_bytes).sum();
+
+        println!();
+        println!(
+            "{} {}",
+            "OLD KERNEL ARTIFACTS".blue().bold(),
+            "(advisory, not cleaned)".dimmed()
+        );
+        println!(
+            "  {} held by kernels that are neither running nor installed",
+            format_bytes(total).yellow()
+        );
+        if !self.summary_only {
+            for artifact in artifacts {
+                println!(
+                    "    {} ({}, kernel {})",
+                    artifact.path.display(),
+                    format_bytes(artifact.size_bytes),
+                    artifact.kernel.cyan()
+                );
+            }
+        }
+        println!(
+            "  {}",
+            "Remove them with your package manager or update-initramfs/dracut.".dimmed()
+        );
+    }
+
+    /// Show apparent against actually reclaimable space
+    pub fn show_savings(&self, savings: &Savings) {
+        println!(
+            "Actually reclaimable: {} {}",
+            format_bytes(savings.reclaimable).green().bold(),
+            format!("(apparent {})", format_bytes(savings.apparent)).dimmed()
+        );
+        if savings.reclaimable < savings.apparent {
+            println!(
+                "  {}",
+                format!(
+                    "{} stays allocated through hard links or shared extents",
+                    format_bytes(savings.apparent - savings.reclaimable)
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// Show the fastest growing items and when their filesystems fill up
+    pub fn show_growth(&self, growth: &GrowthReport) {
+        if growth.growers.is_empty() {
+            return;
+        }
+
+        println!(
+            "Fastest growing {}:",
+            format!(
+                "(over {} scans, {:.1} days)",
+                growth.scans, growth.span_days
+            )
+            .dimmed()
+        );
+        for grower in growth.growers.iter().take(MAX_GROWERS_SHOWN) {
+            println!(
+                "  {} : {}/day ({})",
+                grower.path.display().to_string().cyan(),
+                format_bytes(grower.bytes_per_day as u64).red(),
+                format_bytes(grower.current_bytes)
+            );
+        }
+
+        for projection in &growth.projections {
+            if let Some(days) = projection.days_until_full {
+                println!(
+                    "  {} {} fills up in about {} at this rate ({} free)",
+                    "→".dimmed(),
+                    projection.mount_point.display().to_string().bold(),
+                    format!("{:.0} days", days).yellow().bold(),
+                    format_bytes(projection.available_bytes)
+                );
+            }
+        }
+    }
+
+    /// Show what the scan history of each root adds up to
+    pub fn show_stats(&self, stats: &[RootStats]) {
+        if stats.is_empty() {
+            println!("{}", "No recorded scans.".yellow());
+            return;
+        }
+
+        let date = |at: &chrono::DateTime<chrono::Utc>| {
+            at.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
+        for root in stats {
+            println!();
+            println!("{}", root.scan_root.display().to_string().blue().bold());
+            println!(
+                "  Scans: {} ({} to {})",
+                group_digits(root.scans as u64),
+                date(&root.first_scan_at),
+                date(&root.last_scan_at)
+            );
+            println!(
+                "  Cleans: {}, {} items totaling {}",
+                group_digits(root.cleans as u64),
+                group_digits(root.items_cleaned as u64),
+                format_bytes(root.bytes_cleaned).green()
+            );
+            println!(
+                "  Latest scan: {} items totaling {}",
+                group_digits(root.latest_items as u64),
+                format_bytes(root.latest_bytes).yellow()
+            );
+            if self.summary_only || root.recurring.is_empty() {
+                continue;
+            }
+            println!("  {}:", "Cleaned again and again".bold());
+            for item in &root.recurring {
+                println!(
+                    "    {} {} ({} cleans)",
+                    "→".dimmed(),
+                    item.path.display(),
+                    item.cleans
+                );
+            }
+        }
+    }
+
+    /// Show what changed since the previous scan of the same root
+    pub fn show_scan_diff(&self, diff: &ScanDiff) {
+        println!();
+        println!(
+            "{} {}",
+            "CHANGES SINCE".blue().bold(),
+            diff.previous_scan_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+                .blue()
+                .bold()
+        );
+
+        if diff.new_items.is_empty() && diff.grown.is_empty() && diff.removed.is_empty() {
+            println!("{}", "Nothing changed.".green());
+            return;
+        }
+
+        let rows = |len: usize| {
+            if self.verbose {
+                len
+            } else {
+                len.min(MAX_DIFF_ROWS)
+            }
+        };
+        let show_items = |title: &str, items: &[SnapshotItem]| {
+            if items.is_empty() {
+                return;
+            }
+            println!();
+            println!("  {} ({}):", title.bold(), group_digits(items.len() as u64));
+            for item in &items[..rows(items.len())] {
+                println!(
+                    "    {} {} ({})",
+                    "→".dimmed(),
+                    item.path.display(),
+                    format_bytes(item.size_bytes).yellow()
+                );
+            }
+            if rows(items.len()) < items.len() {
+                println!("    {}", "…".dimmed());
+            }
+        };
+
+        show_items("New", &diff.new_items);
+
+        if !diff.grown.is_empty() {
+            println!();
+            println!(
+                "  {} ({}):",
+                "Grew".bold(),
+                group_digits(diff.grown.len() as u64)
+            );
+            for change in &diff.grown[..rows(diff.grown.len())] {
+                println!(
+                    "    {} {} grew {} ({} → {})",
+                    "→".dimmed(),
+                    change.path.display(),
+                    format_bytes(change.growth()).red().bold(),
+                    format_bytes(change.before_bytes),
+                    format_bytes(change.after_bytes)
+                );
+            }
+            if rows(diff.grown.len()) < diff.grown.len() {
+                println!("    {}", "…".dimmed());
+            }
+        }
+
+        show_items("Gone", &diff.removed);
+    }
+
+    /// Show configuration lint findings, one per line, prefixed with their location
+    pub fn show_lint_issues(&self, config_path: &Path, issues: &[LintIssue]) {
+        for issue in issues {
+            let location = match issue.line {
+                Some(line) => format!("{}:{}", config_path.display(), line),
+                None => config_path.display().to_string(),
+            };
+            let severity = match issue.severity {
+                Severity::Danger => "danger".red().bold(),
+                Severity::Warning => "warning".yellow().bold(),
+            };
+            println!("{}: {}: {}", location.white().bold(), severity, issue);
+        }
+
+        if issues.is_empty() {
+            println!("{}", "No problems found in the configuration.".green());
+        } else {
+            println!();
+            println!(
+                "{} problem{} found",
+                group_digits(issues.len() as u64).yellow().bold(),
+                if issues.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    /// Show users over the cache quota and what cleaning brings them back under
+    pub fn show_quota_violations(&self, violations: &[QuotaViolation]) {
+        println!();
+        println!("{}", "QUOTA".blue().bold());
+
+        if violations.is_empty() {
+            println!("{}", "All users are within their cache quota.".green());
+            return;
+        }
+
+        for violation in violations {
+            println!(
+                "  {} uses {} of {} ({} over)",
+                violation.owner.cyan().bold(),
+                format_bytes(violation.used_bytes).red(),
+                format_bytes(violation.limit_bytes),
+                format_bytes(violation.used_bytes - violation.limit_bytes).yellow()
+            );
+            println!(
+                "    cleaning {} cache{} frees {}",
+                violation.to_clean.len(),
+                if violation.to_clean.len() == 1 {
+                    ""
+                } else {
+                    "s"
+                },
+                format_bytes(violation.freed_bytes()).green()
+            );
+            if !self.summary_only {
+                for item in &violation.to_clean {
+                    println!(
+                        "    → {} ({})",
+                        item.path.display(),
+                        format_bytes(item.size_bytes.unwrap_or(0))
+                    );
+                }
+            }
+        }
+    }
+
+    /// Show the alert thresholds a monitor run found crossed
+    pub fn show_alerts(&self, alerts: &[Alert]) {
+        println!();
+        println!("{}", "ALERTS".blue().bold());
+
+        if alerts.is_empty() {
+            println!("{}", "No alert thresholds crossed.".green());
+            return;
+        }
+        for alert in alerts {
+            println!("  {} {}", "!".red().bold(), alert.message);
+        }
+    }
+
+    /// Show reclaimable space per owning application, largest first
+    pub fn show_app_breakdown(&self, by_app: &[AppUsage]) {
+        println!();
+        println!("{}", "BY APPLICATION".blue().bold());
+
+        if by_app.is_empty() {
+            println!("{}", "Nothing to attribute.".dimmed());
+            return;
+        }
+
+        let width = by_app.iter().map(|u| u.app.len()).max().unwrap_or(0);
+        for usage in by_app {
+            println!(
+                "  {} {:>10}  {}",
+                format!("{:<width$}", usage.app).cyan(),
+                format_bytes(usage.bytes).red(),
+                format!(
+                    "({} item{})",
+                    usage.items,
+                    if usage.items == 1 { "" } else { "s" }
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// Show what a dry run would do, grouped by operation
+    pub fn show_planned_operations(&self, planned: &[PlannedOperation]) {
+        if planned.is_empty() {
+            return;
+        }
+
+        let mut groups: BTreeMap<String, Vec<&PlannedOperation>> = BTreeMap::new();
+        for operation in planned {
+            let group = match &operation.action {
+                Ok(action) => action.label().to_string(),
+                Err(reason) => format!("keep ({})", reason),
+            };
+            groups.entry(group).or_default().push(operation);
+        }
+
+        println!();
+        println!("{}", "PLANNED OPERATIONS".cyan().bold());
+        for (group, operations) in &groups {
+            let bytes: u64 = operations.iter().map(|o| o.bytes).sum();
+            println!(
+                "  {} {}",
+                group.bold(),
+                format!(
+                    "({} item{}, {})",
+                    group_digits(operations.len() as u64),
+                    if operations.len() == 1 { "" } else { "s" },
+                    format_bytes(bytes)
+                )
+                .dimmed()
+            );
+
+            if self.summary_only {
+                continue;
+            }
+            for operation in operations {
+                println!(
+                    "    {} {} {}",
+                    "→".dimmed(),
+                    operation.path.display(),
+                    format!("({})", format_bytes(operation.bytes)).dimmed()
+                );
+                for hook in &operation.hooks {
+                    println!("        {}", hook.dimmed());
+                }
+            }
+        }
     }
 
     /// Show cleaning results
@@ -312,17 +1035,21 @@ impl Display {
 
             println!(
                 "Items processed: {}",
-                combined_summary.total_items.to_string().cyan().bold()
+                group_digits(combined_summary.total_items as u64)
+                    .cyan()
+                    .bold()
             );
             println!(
                 "Successful: {}",
-                combined_summary.successful.to_string().green().bold()
+                group_digits(combined_summary.successful as u64)
+                    .green()
+                    .bold()
             );
 
             if combined_summary.failed > 0 {
                 println!(
                     "Failed: {}",
-                    combined_summary.failed.to_string().red().bold()
+                    group_digits(combined_summary.failed as u64).red().bold()
                 );
             }
 
@@ -351,6 +1078,92 @@ impl Display {
         }
     }
 
+    /// List deleted files whose space running processes still hold
+    pub fn show_held_open_files(&self, held: &[HeldOpenFile]) {
+        if held.is_empty() {
+            return;
+        }
+        let total: u64 = held.iter().map(|h| h.disk_bytes).sum();
+        println!();
+        println!(
+            "{} {}",
+            "SPACE STILL IN USE".yellow().bold(),
+            format!("({} held by running processes)", format_bytes(total)).dimmed()
+        );
+        for file in held {
+            println!(
+                "  {} {} {}",
+                format_bytes(file.disk_bytes).yellow(),
+                file.path.display(),
+                format!("open in {}", file.holder).dimmed()
+            );
+        }
+        let mut holders: Vec<&str> = held.iter().map(|h| h.holder.command.as_str()).collect();
+        holders.sort_unstable();
+        holders.dedup();
+        println!(
+            "{}",
+            format!(
+                "The space is freed once they close the files; restarting or reloading {} frees it now.",
+                holders.join(", ")
+            )
+            .dimmed()
+        );
+    }
+
+    /// Note that the work slowed down for other processes' I/O
+    pub fn show_throttled(&self) {
+        println!(
+            "{}",
+            "Ran with fewer workers for a while, as the system was stalled on I/O.".dimmed()
+        );
+    }
+
+    /// Note that trashed items still take their space
+    pub fn show_trashed(&self) {
+        println!(
+            "{}",
+            "Items were moved to the trash; their space is freed once it is emptied.".dimmed()
+        );
+    }
+
+    /// Warn what cleaning the items costs later, before the user confirms
+    pub fn show_rebuild_costs(&self, costs: &[RebuildCost]) {
+        if costs.is_empty() {
+            return;
+        }
+        println!();
+        println!("{}", "REBUILD COSTS".yellow().bold());
+        for cost in costs {
+            println!(
+                "  {} {}",
+                cost.hint,
+                format!(
+                    "({} item{})",
+                    cost.items,
+                    if cost.items == 1 { "" } else { "s" }
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// Name the categories a mixed run cleaned and those it only reported
+    pub fn show_mixed_run(&self, cleaned: &[&str], reported: &[&str]) {
+        let list = |names: &[&str]| {
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        };
+        println!();
+        println!("{}", "MIXED RUN".magenta().bold());
+        println!("{}", "─".repeat(30).dimmed());
+        println!("Cleaned: {}", list(cleaned).green().bold());
+        println!("Reported only (dry run): {}", list(reported).cyan().bold());
+    }
+
     /// Show operation summary for a specific type
     fn show_operation_summary(&self, title: &str, summary: &OperationSummary, dry_run: bool) {
         println!("{} {}", "".cyan(), title.cyan().bold());
@@ -362,7 +1175,7 @@ impl Display {
                 "Processed"
             },
             "items".dimmed(),
-            summary.total_items.to_string().cyan()
+            group_digits(summary.total_items as u64).cyan()
         );
         println!(
             "  {} {}: {}",
@@ -372,7 +1185,7 @@ impl Display {
                 "Successful"
             },
             "".dimmed(),
-            summary.successful.to_string().green()
+            group_digits(summary.successful as u64).green()
         );
 
         if summary.failed > 0 {
@@ -380,7 +1193,7 @@ impl Display {
                 "  {} {}: {}",
                 "Failed".red(),
                 "".dimmed(),
-                summary.failed.to_string().red()
+                group_digits(summary.failed as u64).red()
             );
         }
 
@@ -389,7 +1202,7 @@ impl Display {
                 "  {} {}: {} {}",
                 "Permission denied".yellow(),
                 "".dimmed(),
-                summary.permission_denied.to_string().yellow(),
+                group_digits(summary.permission_denied as u64).yellow(),
                 "(try sudo)".dimmed()
             );
         }
@@ -414,13 +1227,308 @@ impl Display {
         let response = input.trim().to_lowercase();
         Ok(matches!(response.as_str(), "y" | "yes"))
     }
+
+    /// Ask whether to go ahead with a scan estimated to take a while
+    pub fn confirm_long_scan(&self, estimate: &Estimate) -> io::Result<bool> {
+        print!(
+            "This scan will take {}, walking about {} directories and {} files. Continue? {} ",
+            rough_duration(estimate.duration).bold(),
+            group_digits(estimate.directories),
+            group_digits(estimate.files),
+            "[y/N]:".dimmed()
+        );
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+
+        let response = input.trim().to_lowercase();
+        Ok(matches!(response.as_str(), "y" | "yes"))
+    }
+
+    /// Walk through the items of a dry run, asking which ones to keep out
+    /// of future runs. Returns the paths the user wants to keep.
+    pub fn review_kept_items(
+        &self,
+        cache_items: &[CacheItem],
+        log_files: &[LogFile],
+    ) -> io::Result<Vec<PathBuf>> {
+        println!();
+        print!(
+            "Mark any of these items to keep in future runs? {} ",
+            "[y/N]:".dimmed()
+        );
+        io::stdout().flush()?;
+        if !Self::read_answer()?.is_some_and(|answer| answer == "y" || answer == "yes") {
+            return Ok(Vec::new());
+        }
+
+        let items = cache_items
+            .iter()
+            .map(|i| (&i.path, i.size_bytes.unwrap_or(0)))
+            .chain(log_files.iter().map(|l| (&l.path, l.size_bytes)));
+        let mut kept = Vec::new();
+        for (path, bytes) in items {
+            print!(
+                "Keep {} ({})? {} ",
+                path.display().to_string().bold(),
+                format_bytes(bytes),
+                "[y/N/q]:".dimmed()
+            );
+            io::stdout().flush()?;
+            match Self::read_answer()?.as_deref() {
+                Some("y" | "yes") => kept.push(path.clone()),
+                Some("q" | "quit") | None => break,
+                _ => {}
+            }
+        }
+        Ok(kept)
+    }
+
+    /// List the items the last `runs` cleaning runs found but the user never
+    /// chose to clean. When `ask` is set, offer to exclude each with a `y`,
+    /// returning the paths to exclude.
+    pub fn suggest_exclusions(
+        &self,
+        items: &[SnapshotItem],
+        runs: usize,
+        ask: bool,
+    ) -> io::Result<Vec<PathBuf>> {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        println!();
+        println!("{}", "NEVER CLEANED".blue().bold());
+        println!(
+            "  Found by each of your last {} cleaning runs, but never chosen to be cleaned:",
+            runs
+        );
+        if !ask {
+            for item in items {
+                println!(
+                    "    {} ({})",
+                    item.path.display(),
+                    format_bytes(item.size_bytes)
+                );
+            }
+            println!(
+                "  {}",
+                "Add them to safety.exclude_paths, or clean with --pick, --ask or --select \
+                 to be offered to exclude each."
+                    .dimmed()
+            );
+            return Ok(Vec::new());
+        }
+
+        let mut excluded = Vec::new();
+        for item in items {
+            print!(
+                "  Exclude {} ({}) from future runs? {} ",
+                item.path.display().to_string().bold(),
+                format_bytes(item.size_bytes),
+                "[y/N/q]:".dimmed()
+            );
+            io::stdout().flush()?;
+            match Self::read_answer()?.as_deref() {
+                Some("y" | "yes") => excluded.push(item.path.clone()),
+                Some("q" | "quit") | None => break,
+                _ => {}
+            }
+        }
+        Ok(excluded)
+    }
+
+    /// Ask for the numbers of the listed items to clean; `None` when nothing
+    /// was entered
+    pub fn prompt_selection(&self) -> io::Result<Option<String>> {
+        print!(
+            "Numbers of the items to clean {} ",
+            "(e.g. 1,3,7-12):".dimmed()
+        );
+        io::stdout().flush()?;
+        Ok(Self::read_answer()?.filter(|answer| !answer.is_empty()))
+    }
+
+    /// Ask about every item whether to clean it, keeping the approved ones.
+    /// The end of input keeps everything not yet approved.
+    pub fn ask_each(
+        &self,
+        cache_items: Vec<CacheItem>,
+        log_files: Vec<LogFile>,
+    ) -> io::Result<(Vec<CacheItem>, Vec<LogFile>)> {
+        let rows: Vec<(&Path, u64, &str, &str)> = cache_items
+            .iter()
+            .map(|i| {
+                (
+                    i.path.as_path(),
+                    i.size_bytes.unwrap_or(0),
+                    i.cache_type.description(),
+                    i.cache_type.category(),
+                )
+            })
+            .chain(log_files.iter().map(|l| {
+                (
+                    l.path.as_path(),
+                    l.size_bytes,
+                    l.log_type.description(),
+                    LOG_CATEGORY,
+                )
+            }))
+            .collect();
+        let categories: Vec<&str> = rows.iter().map(|row| row.3).collect();
+
+        let approved = approve_each(&categories, |index| {
+            let (path, bytes, description, category) = rows[index];
+            loop {
+                print!(
+                    "Clean {} ({}, {})? {} ",
+                    path.display().to_string().bold(),
+                    format_bytes(bytes),
+                    description,
+                    "[y/n/a/q/s]:".dimmed()
+                );
+                io::stdout().flush()?;
+                let Some(answer) = Self::read_answer()? else {
+                    return Ok(Answer::Quit);
+                };
+                match Answer::parse(&answer) {
+                    Some(answer) => return Ok(answer),
+                    None => println!(
+                        "y: clean it, n: keep it, a: clean it and all the rest, q: keep it and \
+                         all the rest, s: keep every remaining {} item",
+                        category
+                    ),
+                }
+            }
+        })?;
+
+        let mut approved = approved.into_iter();
+        let cache_items = cache_items
+            .into_iter()
+            .zip(approved.by_ref())
+            .filter_map(|(item, yes)| yes.then_some(item))
+            .collect();
+        let log_files = log_files
+            .into_iter()
+            .zip(approved)
+            .filter_map(|(log, yes)| yes.then_some(log))
+            .collect();
+        Ok((cache_items, log_files))
+    }
+
+    /// One lowercased line of input, or `None` at end of input
+    fn read_answer() -> io::Result<Option<String>> {
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(input.trim().to_lowercase()))
+    }
+
+    /// Ask the user to type `expected` exactly before proceeding
+    pub fn prompt_typed_confirmation(&self, message: &str, expected: &str) -> io::Result<bool> {
+        println!("{}", "CONFIRMATION REQUIRED".red().bold());
+        println!("{}", message);
+        print!("Type {} to delete it: ", expected.bold());
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+
+        Ok(input.trim_end_matches(['\r', '\n']) == expected)
+    }
+}
+
+/// A reply to `--ask` about one item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    /// This item and every one after it
+    All,
+    /// Keep this item and every one after it
+    Quit,
+    /// Keep this item and the remaining ones of its category
+    SkipType,
+}
+
+impl Answer {
+    fn parse(answer: &str) -> Option<Self> {
+        match answer {
+            "y" | "yes" => Some(Answer::Yes),
+            "n" | "no" => Some(Answer::No),
+            "a" | "all" => Some(Answer::All),
+            "q" | "quit" => Some(Answer::Quit),
+            "s" | "skip" => Some(Answer::SkipType),
+            _ => None,
+        }
+    }
+}
+
+/// Which items to clean, given the category of each in order, asking `ask`
+/// about the index of those the earlier answers left open
+fn approve_each(
+    categories: &[&str],
+    mut ask: impl FnMut(usize) -> io::Result<Answer>,
+) -> io::Result<Vec<bool>> {
+    let mut rest = None;
+    let mut skipped = HashSet::new();
+    let mut approved = Vec::with_capacity(categories.len());
+    for (index, category) in categories.iter().enumerate() {
+        let yes = match rest {
+            Some(yes) => yes,
+            None if skipped.contains(category) => false,
+            None => match ask(index)? {
+                Answer::Yes => true,
+                Answer::No => false,
+                Answer::All => *rest.insert(true),
+                Answer::Quit => *rest.insert(false),
+                Answer::SkipType => {
+                    skipped.insert(category);
+                    false
+                }
+            },
+        };
+        approved.push(yes);
+    }
+    Ok(approved)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::cache_detector::CacheType;
-    use std::path::PathBuf;
+
+    #[test]
+    fn test_approve_each() {
+        let categories = [
+            "browser", "package", "browser", "package", "temp", "temp", "logs",
+        ];
+        let answers = |replies: Vec<Answer>| {
+            let mut replies = replies.into_iter();
+            let mut asked = Vec::new();
+            let approved = approve_each(&categories, |index| {
+                asked.push(index);
+                Ok(replies.next().unwrap())
+            })
+            .unwrap();
+            (approved, asked)
+        };
+
+        // Skipping a type keeps the rest of it without asking
+        let (approved, asked) =
+            answers(vec![Answer::Yes, Answer::SkipType, Answer::No, Answer::All]);
+        assert_eq!(approved, [true, false, false, false, true, true, true]);
+        assert_eq!(asked, [0, 1, 2, 4]);
+
+        let (approved, asked) = answers(vec![Answer::Yes, Answer::Quit]);
+        assert_eq!(approved, [true, false, false, false, false, false, false]);
+        assert_eq!(asked, [0, 1]);
+
+        assert_eq!(Answer::parse("s"), Some(Answer::SkipType));
+        assert_eq!(Answer::parse("maybe"), None);
+    }
 
     #[test]
     fn test_display_creation() {
@@ -432,11 +1540,9 @@ mod tests {
     #[test]
     fn test_cache_item_display() {
         let item = CacheItem {
-            path: PathBuf::from("/tmp/test"),
-            cache_type: CacheType::UserCache,
             size_bytes: Some(1024),
             file_count: Some(10),
-            last_modified: None,
+            ..CacheItem::new("/tmp/test", CacheType::UserCache, "")
         };
 
         let display = Display::new(false, true);
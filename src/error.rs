@@ -0,0 +1,184 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors produced while detecting, scanning and cleaning
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CleanerError {
+    /// The current user is not allowed to access or remove the path
+    #[error("Permission denied: {}", .0.display())]
+    PermissionDenied(PathBuf),
+    /// The path disappeared or never existed
+    #[error("Path does not exist: {}", .0.display())]
+    NotFound(PathBuf),
+    /// A directory was expected but the path is something else
+    #[error("Not a directory: {}", .0.display())]
+    NotADirectory(PathBuf),
+    /// The path is in use (mount point, running executable, locked file)
+    #[error("Resource busy: {}", .0.display())]
+    Busy(PathBuf),
+    /// The path lives on a read-only filesystem
+    #[error("{}: not cleanable: read-only filesystem", .0.display())]
+    ReadOnly(PathBuf),
+    /// The operation would have to cross a filesystem boundary
+    #[error("Cross-device operation: {}", .0.display())]
+    CrossDevice(PathBuf),
+    /// Any other I/O failure on a specific path
+    #[error("I/O error on {}: {message}", path.display())]
+    Io {
+        path: PathBuf,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// The directory was left out because it was too large or too slow to list
+    #[error("Skipped {}: {reason}", path.display())]
+    Skipped { path: PathBuf, reason: String },
+    /// The scan deadline passed before everything below the path was walked
+    #[error("{}: walk cut short by --timeout", .0.display())]
+    TimedOut(PathBuf),
+    /// The path is protected by a hard safety check and was left alone
+    #[error("Refusing to delete {}: {reason}", path.display())]
+    Protected { path: PathBuf, reason: String },
+    /// A configured hook or policy kept the path from being deleted
+    #[error("Deletion of {} vetoed: {reason}", path.display())]
+    Vetoed { path: PathBuf, reason: String },
+    /// Directory traversal failed without a usable I/O error
+    #[error("Directory traversal failed: {0}")]
+    Walk(String),
+    /// A configured glob pattern could not be parsed
+    #[error("Invalid pattern '{pattern}': {message}")]
+    Pattern { pattern: String, message: String },
+    /// A plan file could not be read, written or trusted
+    #[error("Plan error: {0}")]
+    Plan(String),
+    /// The configuration file could not be parsed or written
+    #[error("Configuration error: {0}")]
+    Config(String),
+    /// The process could not confine itself to a cgroup
+    #[error("Cannot confine to a cgroup: {0}")]
+    Cgroup(String),
+    /// A new release could not be found, checked or installed
+    #[error("Update failed: {0}")]
+    Update(String),
+}
+
+impl CleanerError {
+    /// Classify an I/O error that occurred on `path`
+    pub fn from_io(path: &Path, err: &io::Error) -> Self {
+        let path = path.to_path_buf();
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => CleanerError::PermissionDenied(path),
+            io::ErrorKind::NotFound => CleanerError::NotFound(path),
+            io::ErrorKind::NotADirectory => CleanerError::NotADirectory(path),
+            io::ErrorKind::ResourceBusy | io::ErrorKind::ExecutableFileBusy => {
+                CleanerError::Busy(path)
+            }
+            io::ErrorKind::CrossesDevices => CleanerError::CrossDevice(path),
+            io::ErrorKind::ReadOnlyFilesystem => CleanerError::ReadOnly(path),
+            kind => CleanerError::Io {
+                path,
+                kind,
+                message: err.to_string(),
+            },
+        }
+    }
+
+    /// The path the error is about, if it names one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            CleanerError::PermissionDenied(path)
+            | CleanerError::NotFound(path)
+            | CleanerError::NotADirectory(path)
+            | CleanerError::Busy(path)
+            | CleanerError::ReadOnly(path)
+            | CleanerError::CrossDevice(path)
+            | CleanerError::Io { path, .. }
+            | CleanerError::TimedOut(path)
+            | CleanerError::Skipped { path, .. }
+            | CleanerError::Protected { path, .. }
+            | CleanerError::Vetoed { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Why `path` can't be scanned at all, if it is missing, unreadable or
+    /// not a directory
+    pub fn for_scan_root(path: &Path) -> Option<Self> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => None,
+            Ok(_) => Some(CleanerError::NotADirectory(path.to_path_buf())),
+            Err(e) => Some(CleanerError::from_io(path, &e)),
+        }
+    }
+}
+
+impl From<jwalk::Error> for CleanerError {
+    fn from(err: jwalk::Error) -> Self {
+        match (err.path(), err.io_error()) {
+            (Some(path), Some(io_err)) => CleanerError::from_io(path, io_err),
+            _ => CleanerError::Walk(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_io_classification() {
+        let path = Path::new("/tmp/test");
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            CleanerError::from_io(path, &denied),
+            CleanerError::PermissionDenied(path.to_path_buf())
+        );
+
+        let busy = io::Error::from_raw_os_error(libc::EBUSY);
+        assert_eq!(
+            CleanerError::from_io(path, &busy),
+            CleanerError::Busy(path.to_path_buf())
+        );
+
+        let cross = io::Error::from_raw_os_error(libc::EXDEV);
+        assert_eq!(
+            CleanerError::from_io(path, &cross),
+            CleanerError::CrossDevice(path.to_path_buf())
+        );
+
+        let read_only = io::Error::from_raw_os_error(libc::EROFS);
+        assert_eq!(
+            CleanerError::from_io(path, &read_only),
+            CleanerError::ReadOnly(path.to_path_buf())
+        );
+
+        let other = io::Error::other("boom");
+        assert!(matches!(
+            CleanerError::from_io(path, &other),
+            CleanerError::Io { .. }
+        ));
+    }
+
+    #[test]
+    fn test_scan_root_errors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "x").unwrap();
+        let missing = temp_dir.path().join("missing");
+
+        assert_eq!(CleanerError::for_scan_root(temp_dir.path()), None);
+        assert_eq!(
+            CleanerError::for_scan_root(&file),
+            Some(CleanerError::NotADirectory(file.clone()))
+        );
+        assert_eq!(
+            CleanerError::for_scan_root(&missing),
+            Some(CleanerError::NotFound(missing))
+        );
+        assert_eq!(
+            CleanerError::for_scan_root(&file.join("below")),
+            Some(CleanerError::NotADirectory(file.join("below")))
+        );
+    }
+}
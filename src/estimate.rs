@@ -0,0 +1,326 @@
+use crate::config::Config;
+use crate::walker::HiddenDirs;
+use rayon::prelude::*;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Full walks of the scan root made by cache detection: one for cache
+/// directories and one for temporary files
+pub const DETECTION_WALKS: u64 = 2;
+
+/// Random descents made below the unread directories to estimate their size
+const PROBES: usize = 64;
+
+/// How often the progress line is redrawn
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How much a walk of the scan root is expected to cover, from a breadth-first
+/// pass that reads a sample of its directories
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub directories: u64,
+    pub files: u64,
+    /// Whether the pass read every directory, making the counts exact
+    pub exact: bool,
+    /// Rough time the detection walks will take at the pass's reading speed
+    pub duration: Duration,
+}
+
+impl Estimate {
+    /// Entries the walker is expected to yield over all detection walks
+    pub fn walked_entries(&self) -> u64 {
+        (self.directories + self.files) * DETECTION_WALKS
+    }
+}
+
+/// Estimate the walk of `root` by reading up to `performance.estimate_sample`
+/// directories breadth first, within the same depth limit and exclusions as
+/// the scan. Whatever lies below the last level read is extrapolated from
+/// random descents into it.
+pub fn estimate(root: &Path, config: &Config) -> Estimate {
+    let start = Instant::now();
+    let max_depth = config.performance.max_depth.unwrap_or(10);
+    let sample = sample(root, max_depth, false, config);
+
+    let per_directory = start.elapsed() / sample.read.max(1) as u32;
+    Estimate {
+        directories: sample.directories,
+        files: sample.files,
+        exact: sample.exact,
+        duration: per_directory
+            .saturating_mul((sample.directories * DETECTION_WALKS).min(u32::MAX as u64) as u32),
+    }
+}
+
+/// Size of a tree, counted or extrapolated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeSize {
+    pub bytes: u64,
+    pub files: u64,
+    /// Whether every directory was read, making the numbers exact
+    pub exact: bool,
+}
+
+/// Size the tree at `dir` the way [`estimate`] sizes up the scan: read up to
+/// `performance.estimate_sample` directories breadth first and extrapolate
+/// the rest from random descents. Seconds instead of minutes on caches of
+/// millions of files, at the price of precision.
+pub fn estimate_size(dir: &Path, config: &Config) -> TreeSize {
+    let sample = sample(dir, usize::MAX, true, config);
+    TreeSize {
+        bytes: sample.bytes,
+        files: sample.files,
+        exact: sample.exact,
+    }
+}
+
+/// What a sampling pass found below its root
+struct Sample {
+    directories: u64,
+    files: u64,
+    /// Bytes of the regular files, when sizes were read
+    bytes: u64,
+    exact: bool,
+    /// Directories read
+    read: usize,
+}
+
+/// Read `root` breadth first down to `max_depth` while the directory budget
+/// lasts, then probe the level left unread, reading file sizes if `sizes`
+fn sample(root: &Path, max_depth: usize, sizes: bool, config: &Config) -> Sample {
+    let budget = config.performance.estimate_sample;
+    let hidden = HiddenDirs::new(config);
+
+    let mut level = vec![root.to_path_buf()];
+    let mut depth = 0;
+    let mut sample = Sample {
+        directories: 0,
+        files: 0,
+        bytes: 0,
+        exact: false,
+        read: 0,
+    };
+    while !level.is_empty() && depth < max_depth && sample.read + level.len() <= budget {
+        let listings: Vec<Listing> = level
+            .par_iter()
+            .map(|dir| list(dir, sizes, config, &hidden))
+            .collect();
+        sample.read += level.len();
+        level = listings
+            .into_iter()
+            .flat_map(|l| {
+                sample.files += l.files;
+                sample.bytes += l.bytes;
+                l.directories
+            })
+            .collect();
+        sample.directories += level.len() as u64;
+        depth += 1;
+    }
+
+    sample.exact = level.is_empty() || depth >= max_depth;
+    if !sample.exact {
+        // Probe evenly spread directories of the level that wasn't read
+        let step = level.len().div_ceil(PROBES);
+        let probed: Vec<Counts> = level
+            .par_iter()
+            .step_by(step)
+            .enumerate()
+            .map(|(i, dir)| probe(dir, max_depth - depth, sizes, config, &hidden, i as u64 + 1))
+            .collect();
+        let count = probed.len() as u64;
+        let unread = level.len() as u64;
+        let extrapolate = |total: u64| (total as u128 * unread as u128 / count as u128) as u64;
+        sample.directories += extrapolate(probed.iter().map(|p| p.directories).sum());
+        sample.files += extrapolate(probed.iter().map(|p| p.files).sum());
+        sample.bytes += extrapolate(probed.iter().map(|p| p.bytes).sum());
+        sample.read += probed.len();
+    }
+    sample
+}
+
+/// Subdirectories, file count and, if read, file sizes of one directory
+#[derive(Default)]
+struct Listing {
+    directories: Vec<PathBuf>,
+    files: u64,
+    bytes: u64,
+}
+
+fn list(dir: &Path, sizes: bool, config: &Config, hidden: &HiddenDirs) -> Listing {
+    let mut listing = Listing::default();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return listing;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if config.is_excluded_path(&path) {
+            continue;
+        }
+        // Symlinks and hidden directories the scan won't enter are counted as
+        // entries but never followed
+        let file_type = entry.file_type();
+        if file_type.as_ref().is_ok_and(|t| t.is_dir()) && hidden.descends(&entry.file_name()) {
+            listing.directories.push(path);
+            continue;
+        }
+        listing.files += 1;
+        if sizes && file_type.is_ok_and(|t| t.is_file()) {
+            listing.bytes += entry.metadata().map_or(0, |m| m.len());
+        }
+    }
+    listing
+}
+
+/// Directories, files and bytes a probe estimates below one directory
+#[derive(Default)]
+struct Counts {
+    directories: u64,
+    files: u64,
+    bytes: u64,
+}
+
+/// Knuth's estimate of the directories, files and bytes below `dir` (not
+/// counting `dir` itself): descend along one random path, weighting what each
+/// level holds by the product of the branching factors above it
+fn probe(
+    dir: &Path,
+    levels: usize,
+    sizes: bool,
+    config: &Config,
+    hidden: &HiddenDirs,
+    seed: u64,
+) -> Counts {
+    let mut rng = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let mut weight = 1u64;
+    let mut estimate = Counts::default();
+    let mut current = dir.to_path_buf();
+    for _ in 0..levels {
+        let listing = list(&current, sizes, config, hidden);
+        let branches = listing.directories.len() as u64;
+        estimate.directories = estimate
+            .directories
+            .saturating_add(weight.saturating_mul(branches));
+        estimate.files = estimate
+            .files
+            .saturating_add(weight.saturating_mul(listing.files));
+        estimate.bytes = estimate
+            .bytes
+            .saturating_add(weight.saturating_mul(listing.bytes));
+        if branches == 0 {
+            break;
+        }
+        weight = weight.saturating_mul(branches);
+        // xorshift64
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        current = listing.directories[(rng % branches) as usize].clone();
+    }
+    estimate
+}
+
+/// Rough duration for the "continue?" prompt, e.g. "~8 minutes"
+pub fn rough_duration(duration: Duration) -> String {
+    let minutes = (duration.as_secs() + 30) / 60;
+    match minutes {
+        0 => "under a minute".to_string(),
+        1 => "~1 minute".to_string(),
+        2..120 => format!("~{} minutes", minutes),
+        _ => format!("~{} hours", (minutes + 30) / 60),
+    }
+}
+
+/// A percentage line on stderr tracking a counter, such as the walker's
+/// entries, against the expected total, redrawn until dropped
+pub struct Progress {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Progress {
+    pub fn start(label: &'static str, expected: u64, count: fn() -> u64) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let baseline = count();
+        let handle = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let done = count() - baseline;
+                // The estimate may fall short; never claim to be done early
+                let percent = (done * 100 / expected.max(1)).min(99);
+                // The cursor goes back to the start, so lines printed
+                // meanwhile write over the percentage instead of after it
+                eprint!("\r{} {:>2}%\r", label, percent);
+                io::stderr().flush().ok();
+                thread::sleep(PROGRESS_INTERVAL);
+            }
+            eprint!("\r{}\r", " ".repeat(label.len() + 4));
+            io::stderr().flush().ok();
+        });
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_estimate_walk() {
+        let temp_dir = TempDir::new().unwrap();
+        for a in 0..4 {
+            for b in 0..3 {
+                let dir = temp_dir.path().join(format!("a{a}/b{b}/c"));
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("f"), "x").unwrap();
+            }
+        }
+
+        let mut config = Config::default();
+        let exact = estimate(temp_dir.path(), &config);
+        assert!(exact.exact);
+        assert_eq!((exact.directories, exact.files), (4 + 12 + 12, 12));
+        assert_eq!(exact.walked_entries(), 40 * DETECTION_WALKS);
+
+        // A uniform tree is extrapolated exactly from the levels read
+        config.performance.estimate_sample = 5;
+        let sampled = estimate(temp_dir.path(), &config);
+        assert!(!sampled.exact);
+        assert_eq!((sampled.directories, sampled.files), (28, 12));
+        let size = estimate_size(temp_dir.path(), &config);
+        assert_eq!(
+            size,
+            TreeSize {
+                bytes: 12,
+                files: 12,
+                exact: false
+            }
+        );
+        config.performance.estimate_sample = 2000;
+        assert!(estimate_size(temp_dir.path(), &config).exact);
+
+        assert_eq!(
+            rough_duration(Duration::from_secs(8 * 60 + 10)),
+            "~8 minutes"
+        );
+        assert_eq!(rough_duration(Duration::from_secs(20)), "under a minute");
+    }
+}
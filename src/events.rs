@@ -0,0 +1,263 @@
+use crate::cache_detector::{CacheItem, LOG_CATEGORY};
+use crate::file_operations::OperationResult;
+use crate::log_cleaner::LogFile;
+use crate::output::{self, SCHEMA_VERSION};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether `--events` asked for events on stdout
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What the run found and deleted so far, for the closing summary
+static TALLY: Mutex<Tally> = Mutex::new(Tally::new());
+
+/// How `--events` streams the steps of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFormat {
+    /// One JSON object per line on stdout
+    Ndjson,
+}
+
+impl EventFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "ndjson" => Ok(EventFormat::Ndjson),
+            _ => Err(format!(
+                "unknown event format '{}' (supported: ndjson)",
+                name
+            )),
+        }
+    }
+}
+
+/// One line of the event stream
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EventRecord {
+    pub schema_version: u32,
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// A step of a run, printed the moment it happens
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// Scanning of a root began
+    ScanStarted { scan_root: PathBuf },
+    /// A cache item or old log was found, once measured and filtered
+    ItemFound {
+        path: PathBuf,
+        /// Category as `--clean-types` takes it
+        category: String,
+        size_bytes: Option<u64>,
+    },
+    /// An item was deleted, or would have been in a dry run
+    ItemDeleted {
+        path: PathBuf,
+        bytes_freed: u64,
+        dry_run: bool,
+    },
+    /// An item was left in place
+    ItemFailed { path: PathBuf, error: String },
+    /// Totals of the whole run, printed last
+    Summary(Tally),
+}
+
+/// Totals of a run, over every scanned root
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Tally {
+    pub items_found: usize,
+    pub bytes_found: u64,
+    pub deleted: usize,
+    /// Deletions only simulated by a dry run
+    pub simulated: usize,
+    pub failed: usize,
+    pub bytes_freed: u64,
+}
+
+impl Tally {
+    const fn new() -> Self {
+        Self {
+            items_found: 0,
+            bytes_found: 0,
+            deleted: 0,
+            simulated: 0,
+            failed: 0,
+            bytes_freed: 0,
+        }
+    }
+
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::ItemFound { size_bytes, .. } => {
+                self.items_found += 1;
+                self.bytes_found += size_bytes.unwrap_or(0);
+            }
+            Event::ItemDeleted { dry_run: true, .. } => self.simulated += 1,
+            Event::ItemDeleted { bytes_freed, .. } => {
+                self.deleted += 1;
+                self.bytes_freed += bytes_freed;
+            }
+            Event::ItemFailed { .. } => self.failed += 1,
+            Event::ScanStarted { .. } | Event::Summary(_) => {}
+        }
+    }
+}
+
+impl Event {
+    /// The outcome of deleting the item at `path`
+    fn of_result(path: &Path, result: &OperationResult, dry_run: bool) -> Self {
+        if result.success {
+            Event::ItemDeleted {
+                path: path.to_path_buf(),
+                bytes_freed: result.bytes_freed,
+                dry_run,
+            }
+        } else {
+            Event::ItemFailed {
+                path: path.to_path_buf(),
+                error: result
+                    .error
+                    .as_ref()
+                    .map(|e| e.to_string())
+                    .unwrap_or_else(|| "Unknown error".to_string()),
+            }
+        }
+    }
+}
+
+/// Print events on stdout from now on; call after reserving stdout
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Print `event` as one line, when events are enabled. A reader that went
+/// away doesn't stop the run.
+pub fn emit(event: Event) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    TALLY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record(&event);
+    output::print_json(&EventRecord {
+        schema_version: SCHEMA_VERSION,
+        time: Utc::now(),
+        event,
+    })
+    .ok();
+}
+
+/// Emit the items a scan settled on
+pub fn found(cache_items: &[CacheItem], log_files: &[LogFile]) {
+    for item in cache_items {
+        emit(Event::ItemFound {
+            path: item.path.clone(),
+            category: item.cache_type.category().to_string(),
+            size_bytes: item.size_bytes,
+        });
+    }
+    for log in log_files {
+        emit(Event::ItemFound {
+            path: log.path.clone(),
+            category: LOG_CATEGORY.to_string(),
+            size_bytes: Some(log.size_bytes),
+        });
+    }
+}
+
+/// Emit the outcome of deleting the item at `path`
+pub fn deletion(path: &Path, result: &OperationResult, dry_run: bool) {
+    emit(Event::of_result(path, result, dry_run));
+}
+
+/// Emit the totals of the run, however it ended
+pub fn summary() {
+    let tally = TALLY.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    emit(Event::Summary(tally));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CleanerError;
+    use serde_json::json;
+
+    #[test]
+    fn test_events() {
+        let path = Path::new("/home/u/.cache/thumbnails");
+        let deleted = Event::of_result(
+            path,
+            &OperationResult {
+                success: true,
+                error: None,
+                bytes_freed: 4096,
+            },
+            false,
+        );
+        let failed = Event::of_result(
+            path,
+            &OperationResult {
+                success: false,
+                error: Some(CleanerError::PermissionDenied(path.to_path_buf())),
+                bytes_freed: 0,
+            },
+            false,
+        );
+        let record = serde_json::to_value(EventRecord {
+            schema_version: SCHEMA_VERSION,
+            time: DateTime::UNIX_EPOCH,
+            event: deleted.clone(),
+        })
+        .unwrap();
+        assert_eq!(
+            record,
+            json!({
+                "schema_version": SCHEMA_VERSION,
+                "time": "1970-01-01T00:00:00Z",
+                "event": "item-deleted",
+                "path": "/home/u/.cache/thumbnails",
+                "bytes_freed": 4096,
+                "dry_run": false,
+            })
+        );
+
+        let mut tally = Tally::new();
+        for event in [
+            Event::ItemFound {
+                path: path.to_path_buf(),
+                category: "user".to_string(),
+                size_bytes: Some(4096),
+            },
+            deleted,
+            failed,
+            Event::ItemDeleted {
+                path: path.to_path_buf(),
+                bytes_freed: 10,
+                dry_run: true,
+            },
+        ] {
+            tally.record(&event);
+        }
+        assert_eq!(
+            tally,
+            Tally {
+                items_found: 1,
+                bytes_found: 4096,
+                deleted: 1,
+                simulated: 1,
+                failed: 1,
+                bytes_freed: 4096,
+            }
+        );
+        let summary = serde_json::to_value(Event::Summary(tally)).unwrap();
+        assert_eq!(summary["event"], "summary");
+        assert_eq!(summary["failed"], 1);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::cache_detector::{self, CacheItem};
+use crate::config::Config;
+use crate::file_operations::format_bytes;
+use inquire::{InquireError, Select};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Rows shown at once in the explorer
+const PAGE_SIZE: usize = 20;
+
+/// One entry of a directory being explored
+#[derive(Debug, Clone)]
+struct Child {
+    path: PathBuf,
+    is_dir: bool,
+    bytes: u64,
+}
+
+/// What a row of the explorer does when chosen
+#[derive(Debug, Clone)]
+enum Action {
+    /// Open a directory, or toggle a file
+    Open(usize),
+    /// Toggle the directory being shown
+    MarkCurrent,
+    Up,
+    Finish,
+}
+
+#[derive(Debug, Clone)]
+struct Row {
+    label: String,
+    action: Action,
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+/// Walks into detected cache items and collects what the user marks for
+/// cleaning, at whatever depth
+struct Explorer<'a> {
+    config: &'a Config,
+    /// The detected item each explored path was found below
+    items: Vec<CacheItem>,
+    marked: Vec<CacheItem>,
+}
+
+impl Explorer<'_> {
+    /// The entries of `dir`, largest first, with directories sized in
+    /// parallel the same way cache items are
+    fn children(&self, dir: &Path, parent: &CacheItem) -> Vec<Child> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if self.config.is_excluded_path(&path) {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => dirs.push(sub_item(parent, path, 0)),
+                _ => files.push(Child {
+                    bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                    path,
+                    is_dir: false,
+                }),
+            }
+        }
+
+        let sized = cache_detector::calculate_sizes(dirs, self.config)
+            .map(|report| report.items)
+            .unwrap_or_default();
+        let mut children: Vec<Child> = sized
+            .into_iter()
+            .map(|item| Child {
+                path: item.path,
+                is_dir: true,
+                bytes: item.size_bytes.unwrap_or(0),
+            })
+            .chain(files)
+            .collect();
+        children.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+        children
+    }
+
+    /// Whether `path` is marked itself or lies inside a marked directory
+    fn covered(&self, path: &Path) -> bool {
+        self.marked.iter().any(|m| path.starts_with(&m.path))
+    }
+
+    /// Mark or unmark `path`, found below `parent`
+    fn toggle(&mut self, parent: &CacheItem, path: &Path, bytes: u64) {
+        toggle(
+            &mut self.marked,
+            sub_item(parent, path.to_path_buf(), bytes),
+        );
+    }
+
+    fn marker(&self, path: &Path) -> &'static str {
+        if self.covered(path) { "[x]" } else { "[ ]" }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "Done: clean {} marked item{} ({})",
+            self.marked.len(),
+            if self.marked.len() == 1 { "" } else { "s" },
+            format_bytes(self.marked.iter().map(|m| m.size_bytes.unwrap_or(0)).sum())
+        )
+    }
+
+    /// The top level: every detected item. Returns `None` when cancelled.
+    fn run(&mut self) -> io::Result<Option<Vec<CacheItem>>> {
+        loop {
+            let mut rows: Vec<Row> = self
+                .items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| Row {
+                    label: format!(
+                        "{} {:>10}  {}",
+                        self.marker(&item.path),
+                        format_bytes(item.size_bytes.unwrap_or(0)),
+                        item.path.display()
+                    ),
+                    action: Action::Open(index),
+                })
+                .collect();
+            rows.insert(
+                0,
+                Row {
+                    label: self.summary(),
+                    action: Action::Finish,
+                },
+            );
+
+            let Some(row) = prompt("Explore the detected items:", rows)? else {
+                return Ok(None);
+            };
+            match row.action {
+                Action::Open(index) => {
+                    let item = self.items[index].clone();
+                    if item.target_path().is_dir() {
+                        self.explore(&item, item.target_path())?;
+                    } else {
+                        toggle(&mut self.marked, item);
+                    }
+                }
+                _ => return Ok(Some(std::mem::take(&mut self.marked))),
+            }
+        }
+    }
+
+    /// Show the entries of `dir` below the detected `item` until the user
+    /// goes back up
+    fn explore(&mut self, item: &CacheItem, dir: &Path) -> io::Result<()> {
+        let children = self.children(dir, item);
+        let total = children.iter().map(|c| c.bytes).sum();
+        loop {
+            let mut rows = vec![
+                Row {
+                    label: "..".to_string(),
+                    action: Action::Up,
+                },
+                Row {
+                    label: format!(
+                        "{} {:>10}  {} (this directory)",
+                        self.marker(dir),
+                        format_bytes(total),
+                        dir.display()
+                    ),
+                    action: Action::MarkCurrent,
+                },
+            ];
+            rows.extend(children.iter().enumerate().map(|(index, child)| Row {
+                label: format!(
+                    "{} {:>10}  {}{}",
+                    self.marker(&child.path),
+                    format_bytes(child.bytes),
+                    child.path.file_name().unwrap_or_default().to_string_lossy(),
+                    if child.is_dir { "/" } else { "" }
+                ),
+                action: Action::Open(index),
+            }));
+
+            let message = format!("{} ({})", dir.display(), self.summary());
+            let Some(row) = prompt(&message, rows)? else {
+                return Ok(());
+            };
+            match row.action {
+                Action::Open(index) if children[index].is_dir => {
+                    self.explore(item, &children[index].path)?
+                }
+                Action::Open(index) => {
+                    self.toggle(item, &children[index].path, children[index].bytes)
+                }
+                Action::MarkCurrent => self.toggle(item, dir, total),
+                Action::Up | Action::Finish => return Ok(()),
+            }
+        }
+    }
+}
+
+/// A path found below the detected `parent`, cleaned as an item of its own
+fn sub_item(parent: &CacheItem, path: PathBuf, bytes: u64) -> CacheItem {
+    let is_parent = path == parent.target_path();
+    CacheItem {
+        reason: if is_parent {
+            parent.reason.clone()
+        } else {
+            format!("picked in the explorer below {}", parent.path.display())
+        },
+        path: if is_parent { parent.path.clone() } else { path },
+        resolved_path: if is_parent {
+            parent.resolved_path.clone()
+        } else {
+            None
+        },
+        size_bytes: Some(bytes),
+        file_count: None,
+        ..parent.clone()
+    }
+}
+
+/// Mark `item`, replacing marks inside it, or unmark it if it was marked.
+/// Paths inside a marked directory are already covered and stay as they are.
+fn toggle(marked: &mut Vec<CacheItem>, item: CacheItem) {
+    if let Some(index) = marked.iter().position(|m| m.path == item.path) {
+        marked.remove(index);
+    } else if !marked.iter().any(|m| item.path.starts_with(&m.path)) {
+        marked.retain(|m| !m.path.starts_with(&item.path));
+        marked.push(item);
+    }
+}
+
+/// One explorer screen; `None` when the user backs out with escape
+fn prompt(message: &str, rows: Vec<Row>) -> io::Result<Option<Row>> {
+    match Select::new(message, rows)
+        .with_page_size(PAGE_SIZE)
+        .with_help_message("type to filter, enter to open or mark, esc to go back")
+        .prompt()
+    {
+        Ok(row) => Ok(Some(row)),
+        Err(InquireError::OperationCanceled) => Ok(None),
+        Err(InquireError::OperationInterrupted) => Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "explorer interrupted",
+        )),
+        Err(InquireError::IO(e)) => Err(e),
+        Err(e) => Err(io::Error::other(e.to_string())),
+    }
+}
+
+/// Let the user drill into the detected cache items, seeing the size of
+/// every entry below them, and mark what to clean at any depth: whole items,
+/// single subdirectories or files. Cancelling at the top marks nothing.
+pub fn explore(cache_items: Vec<CacheItem>, config: &Config) -> io::Result<Vec<CacheItem>> {
+    let mut explorer = Explorer {
+        config,
+        items: cache_items,
+        marked: Vec::new(),
+    };
+    Ok(explorer.run()?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_explorer_sizes_children_and_marks_at_any_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        fs::create_dir_all(cache.join("big").join("inner")).unwrap();
+        fs::create_dir(cache.join("small")).unwrap();
+        fs::write(
+            cache.join("big").join("inner").join("blob"),
+            vec![0u8; 4096],
+        )
+        .unwrap();
+        fs::write(cache.join("small").join("f"), "x").unwrap();
+        fs::write(cache.join("index"), "xy").unwrap();
+
+        let item = CacheItem {
+            size_bytes: Some(4099),
+            file_count: Some(3),
+            ..CacheItem::new(
+                cache.clone(),
+                CacheType::ApplicationCache,
+                "matched */cache",
+            )
+        };
+        let config = Config::default();
+        let explorer = Explorer {
+            config: &config,
+            items: vec![item.clone()],
+            marked: Vec::new(),
+        };
+        let children = explorer.children(&cache, &item);
+        let sizes: Vec<(&str, u64)> = children
+            .iter()
+            .map(|c| (c.path.file_name().unwrap().to_str().unwrap(), c.bytes))
+            .collect();
+        assert_eq!(sizes, [("big", 4096), ("index", 2), ("small", 1)]);
+
+        let mut marked = Vec::new();
+        toggle(&mut marked, sub_item(&item, cache.join("big/inner"), 4096));
+        toggle(&mut marked, sub_item(&item, cache.join("small"), 1));
+        assert_eq!(marked.len(), 2);
+        assert_eq!(marked[0].cache_type, CacheType::ApplicationCache);
+
+        // Marking a directory takes over the marks inside it
+        toggle(&mut marked, sub_item(&item, cache.join("big"), 4096));
+        let paths: Vec<&Path> = marked.iter().map(|m| m.path.as_path()).collect();
+        assert_eq!(paths, [cache.join("small"), cache.join("big")]);
+        toggle(&mut marked, sub_item(&item, cache.join("big/inner"), 4096));
+        assert_eq!(marked.len(), 2);
+
+        toggle(&mut marked, sub_item(&item, cache.clone(), 4099));
+        assert_eq!(marked.len(), 1);
+        assert_eq!(marked[0].reason, "matched */cache");
+        toggle(&mut marked, sub_item(&item, cache, 4099));
+        assert!(marked.is_empty());
+    }
+}
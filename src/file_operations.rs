@@ -1,38 +1,386 @@
-use crate::cache_detector::CacheItem;
-use crate::log_cleaner::LogFile;
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::checkpoint;
+use crate::config::{Config, JournalConfig, LiveLogAction, LogAction};
+use crate::error::CleanerError;
+use crate::events;
+use crate::hooks::Hooks;
+use crate::journal;
+use crate::log_cleaner::{self, LogFile};
+use crate::mail;
+use crate::mounts::MountTable;
+use crate::plan::Plan;
+use crate::policy::Policy;
+use crate::printing;
+use crate::profiles::ProtectionList;
+use crate::project;
+use crate::reclaim;
+use crate::shred;
+use crate::state;
+use crate::throttle;
+use crate::trash;
+use crate::units::group_digits;
+use crate::unlink;
+use crate::vcs;
+use crate::writers::{Writer, WriterTable};
 use rayon::prelude::*;
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs;
-use std::io::{self, Write};
-use std::path::Path;
+use std::io;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Name prefix of directories renamed aside to be deleted. One that survives
+/// a run was left by an interrupted deletion and is picked up again by the
+/// next scan.
+pub const DELETING_PREFIX: &str = ".cleaner-deleting-";
+
+/// Whether `name` is exactly what `remove_tree` renames a directory to,
+/// `DELETING_PREFIX` followed by `<pid>-<counter>`, and not merely a look-alike
+pub fn is_deleting_name(name: &str) -> bool {
+    let numeric = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    name.strip_prefix(DELETING_PREFIX)
+        .and_then(|rest| rest.split_once('-'))
+        .is_some_and(|(pid, counter)| numeric(pid) && numeric(counter))
+}
 
 /// Result of a file operation
 #[derive(Debug, Clone)]
 pub struct OperationResult {
     pub success: bool,
-    pub error: Option<String>,
+    pub error: Option<CleanerError>,
     pub bytes_freed: u64,
 }
 
+/// Extra care a path needs before it may be deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Protection {
+    /// No special handling
+    None,
+    /// Must be confirmed by typing its name, even with `--force`
+    TypedConfirmation(String),
+    /// Never deleted, even with `--force`
+    Refused(String),
+}
+
+/// How an item is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    /// Delete a directory and everything below it
+    RemoveTree,
+    /// Delete a single file or symlink
+    RemoveFile,
+    /// Delete the contents of a symlinked cache's target, keeping the link
+    /// and the target directory
+    EmptyDirectory,
+    /// Delete a cargo target directory with `cargo clean`
+    CargoClean,
+    /// Empty a file in place, for logs a running process still writes to
+    Truncate,
+    /// Move to the trash, where it can be restored from
+    Trash,
+    /// Move the contents of a symlinked cache's target to the trash
+    TrashContents,
+    /// Overwrite the files with random data, then delete them
+    Shred,
+    /// Overwrite and delete the contents of a symlinked cache's target
+    ShredContents,
+    /// Remove the archived files of a systemd journal past the vacuum limits
+    Vacuum,
+    /// Remove the objects of an ostree repository no ref reaches with
+    /// `ostree prune`
+    OstreePrune,
+    /// Remove a stale DKMS build with `dkms remove`
+    DkmsRemove,
+    /// Delete a deferred mail queue file with `postsuper -d`
+    PostsuperDelete,
+    /// Purge a leftover print job with `cancel -x`
+    CancelPrintJob,
+}
+
+impl Action {
+    pub fn of(item: &CacheItem) -> Self {
+        if item.cache_type == CacheType::Journal {
+            Action::Vacuum
+        } else if item.cache_type == CacheType::DeadMailQueue {
+            Action::PostsuperDelete
+        } else if item.cache_type == CacheType::PrintJob {
+            Action::CancelPrintJob
+        } else if item.cache_type == CacheType::SystemReclaim {
+            if reclaim::is_ostree_repo(&item.path) {
+                Action::OstreePrune
+            } else {
+                Action::DkmsRemove
+            }
+        } else if item.resolved_path.is_some() {
+            Action::EmptyDirectory
+        } else if project::is_cargo_target(&item.path) {
+            Action::CargoClean
+        } else if item.path.is_dir() && !item.path.is_symlink() {
+            Action::RemoveTree
+        } else {
+            Action::RemoveFile
+        }
+    }
+
+    /// How the operation reads in dry runs and deletion scripts
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::RemoveTree => "rm -rf",
+            Action::RemoveFile => "rm -f",
+            Action::EmptyDirectory => "empty target directory",
+            Action::CargoClean => "cargo clean",
+            Action::Truncate => "truncate -s 0",
+            Action::Trash => "gio trash",
+            Action::TrashContents => "move target contents to trash",
+            Action::Shred => "shred -u",
+            Action::ShredContents => "shred target contents",
+            Action::Vacuum => "journalctl --vacuum",
+            Action::OstreePrune => "ostree prune",
+            Action::DkmsRemove => "dkms remove",
+            Action::PostsuperDelete => "postsuper -d",
+            Action::CancelPrintJob => "cancel -x",
+        }
+    }
+}
+
+/// What a run would do to one item, as shown by a dry run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedOperation {
+    pub path: PathBuf,
+    /// The operation, or why the item is left alone
+    pub action: Result<Action, String>,
+    pub bytes: u64,
+    /// Hook commands run around the operation
+    pub hooks: Vec<String>,
+}
+
 /// File operations manager
 pub struct FileOperations {
     dry_run: bool,
+    scan_root: PathBuf,
+    home: Option<PathBuf>,
+    mounts: MountTable,
+    /// Recognized cache locations directly inside a home directory
+    home_caches: Vec<glob::Pattern>,
+    /// Paths the user confirmed by typing their name
+    confirmed: HashSet<PathBuf>,
+    /// Commands run around the deletion of matching cache items
+    hooks: Hooks,
+    /// External command that may veto any deletion
+    policy: Option<Policy>,
+    /// Whether version control metadata is off limits
+    protect_vcs_metadata: bool,
+    /// Browser credentials and history, off limits whatever matched them
+    browser_files: ProtectionList,
+    /// Unused browser profiles asked to be cleaned whole, protected files
+    /// and all
+    released_profiles: Vec<PathBuf>,
+    /// Whether log files must look like text to be deleted
+    verify_log_content: bool,
+    /// What happens to logs a running process writes to
+    live_logs: LiveLogAction,
+    /// What happens to the other logs
+    old_logs: LogAction,
+    /// Items with at least this many files are deleted in parallel (0: never)
+    parallel_delete_files: usize,
+    /// Whether items are moved to the trash instead of deleted
+    trash: bool,
+    /// Whether files are overwritten before they are deleted
+    shred: bool,
+    /// How many times shredded files are overwritten
+    shred_passes: u32,
+    /// How far systemd journals are vacuumed
+    journal: JournalConfig,
 }
 
 impl FileOperations {
-    pub fn new(dry_run: bool) -> Self {
-        Self { dry_run }
+    pub fn new(dry_run: bool, config: &Config, scan_root: &Path) -> Self {
+        let patterns = &config.cache_patterns;
+        let home_caches = patterns
+            .user_cache_dirs
+            .iter()
+            .chain(&patterns.package_manager_caches)
+            .map(|pattern| pattern.strip_prefix("~/").unwrap_or(pattern))
+            .filter(|pattern| !pattern.starts_with('/'))
+            .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+            .collect();
+
+        Self {
+            dry_run,
+            scan_root: normalize(scan_root),
+            home: std::env::var_os("HOME").map(|home| normalize(Path::new(&home))),
+            mounts: MountTable::load(),
+            home_caches,
+            confirmed: HashSet::new(),
+            hooks: Hooks::new(config),
+            policy: Policy::new(config, dry_run),
+            protect_vcs_metadata: config.safety.protect_vcs_metadata,
+            browser_files: ProtectionList::new(config),
+            released_profiles: Vec::new(),
+            verify_log_content: config.safety.verify_log_content,
+            live_logs: config.log_cleanup.live_logs,
+            old_logs: config.log_cleanup.log_action,
+            parallel_delete_files: config.performance.parallel_delete_files,
+            trash: false,
+            shred: false,
+            shred_passes: config.safety.shred_passes,
+            journal: config.journal.clone(),
+        }
+    }
+
+    /// Move items to the trash instead of deleting them. Trash directories
+    /// themselves and live logs being truncated are still cleaned in place.
+    pub fn use_trash(&mut self) {
+        self.trash = true;
+    }
+
+    /// Overwrite files with random data before deleting them, so their
+    /// contents can't be read back from the disk. Live logs that are
+    /// truncated are left as they are.
+    pub fn use_shred(&mut self) {
+        self.shred = true;
+    }
+
+    /// How `item` is removed, taking the trash and shredding into account
+    fn action_of(&self, item: &CacheItem) -> Action {
+        self.trashed(Action::of(item), &item.path)
+    }
+
+    /// `action` on `path`, or a move to the trash or shredding if it would
+    /// remove it
+    fn trashed(&self, action: Action, path: &Path) -> Action {
+        match action {
+            Action::RemoveTree | Action::RemoveFile | Action::CargoClean
+                if self.trash && !trash::holds(path) =>
+            {
+                Action::Trash
+            }
+            Action::EmptyDirectory if self.trash => Action::TrashContents,
+            Action::RemoveTree | Action::RemoveFile | Action::CargoClean if self.shred => {
+                Action::Shred
+            }
+            Action::EmptyDirectory if self.shred => Action::ShredContents,
+            action => action,
+        }
+    }
+
+    /// Classify `path` against the hard safety checks
+    pub fn protection(&self, path: &Path) -> Protection {
+        let path = normalize(path);
+
+        if path.parent().is_none() {
+            return Protection::Refused("filesystem root".to_string());
+        }
+        if let Some(home) = &self.home
+            && home.starts_with(&path)
+        {
+            return Protection::Refused("home directory".to_string());
+        }
+        if self.scan_root.starts_with(&path) {
+            return Protection::Refused("scan root".to_string());
+        }
+        if self.mounts.is_mount_point(&path) {
+            return Protection::Refused("mount point".to_string());
+        }
+        if self.protect_vcs_metadata && vcs::is_in_metadata(&path) {
+            return Protection::Refused("inside version control metadata".to_string());
+        }
+        if !self.released_profiles.iter().any(|p| path.starts_with(p))
+            && let Some(file) = self.browser_files.blocking(&path)
+        {
+            return Protection::Refused(format!("blocked by protection list: {}", file));
+        }
+        if self.is_directly_under_home(&path) && !self.is_recognized_home_cache(&path) {
+            return Protection::TypedConfirmation(
+                "directly inside a home directory and not a recognized cache location".to_string(),
+            );
+        }
+
+        Protection::None
+    }
+
+    /// Record that the user typed the confirmation for `path`
+    pub fn confirm(&mut self, path: &Path) {
+        self.confirmed.insert(normalize(path));
+    }
+
+    /// Let the unused browser profile at `path` be deleted whole, as
+    /// `--clean-unused-profiles` asks, despite the protection list
+    pub fn release_profile(&mut self, path: &Path) {
+        self.released_profiles.push(normalize(path));
+    }
+
+    /// The error to report instead of deleting `path`, if any
+    fn refusal(&self, path: &Path) -> Option<CleanerError> {
+        let reason = match self.protection(path) {
+            Protection::None => return None,
+            Protection::TypedConfirmation(_)
+                if self.dry_run || self.confirmed.contains(&normalize(path)) =>
+            {
+                return None;
+            }
+            Protection::TypedConfirmation(reason) => format!("{reason}, not confirmed"),
+            Protection::Refused(reason) => reason,
+        };
+        Some(CleanerError::Protected {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    /// Why a log file is skipped as it doesn't look like a log
+    fn content_refusal(&self, log: &LogFile) -> Option<CleanerError> {
+        if !self.verify_log_content {
+            return None;
+        }
+        let format = log_cleaner::non_log_content(&log.path)?;
+        Some(CleanerError::Skipped {
+            path: log.path.clone(),
+            reason: format!("looks like {}, not a log", format),
+        })
+    }
+
+    /// How the log at `path` is removed given the process writing to it, or
+    /// why it is kept
+    fn log_action(&self, path: &Path, writer: Option<&Writer>) -> Result<Action, String> {
+        match (writer, self.live_logs) {
+            (Some(writer), LiveLogAction::Skip) => Err(format!("open for writing by {}", writer)),
+            (Some(_), LiveLogAction::Truncate) => Ok(Action::Truncate),
+            _ if self.old_logs == LogAction::Truncate => Ok(Action::Truncate),
+            (None, _) | (Some(_), LiveLogAction::Delete) => {
+                Ok(self.trashed(Action::RemoveFile, path))
+            }
+        }
+    }
+
+    fn is_directly_under_home(&self, path: &Path) -> bool {
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+        self.home.as_deref() == Some(parent) || parent.parent() == Some(Path::new("/home"))
+    }
+
+    fn is_recognized_home_cache(&self, path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| self.home_caches.iter().any(|p| p.matches(name)))
     }
 
     /// Delete cache items with parallel processing
     pub fn delete_cache_items(
         &self,
         items: &[CacheItem],
-    ) -> Result<Vec<OperationResult>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<OperationResult>, CleanerError> {
         if items.is_empty() {
             return Ok(Vec::new());
         }
 
-        println!("Starting cleanup of {} cache items...", items.len());
+        println!(
+            "Starting cleanup of {} cache items...",
+            group_digits(items.len() as u64)
+        );
 
         let total = items.len();
         let dry_run = self.dry_run;
@@ -42,50 +390,55 @@ impl FileOperations {
             .par_iter()
             .enumerate()
             .map(|(index, item)| {
-                // Show progress with less frequent updates to avoid overwhelming output
-                if index % 10 == 0 || index == total - 1 {
-                    print!(
-                        "  {} {} [{}/{}] ",
-                        if dry_run { "DRY RUN" } else { "DELETING" },
-                        item.path.display(),
-                        index + 1,
-                        total
-                    );
-                    io::stdout().flush().ok();
-                }
-
-                let result = if dry_run {
+                let refusal = self
+                    .refusal(&item.path)
+                    .or_else(|| self.refusal(item.target_path()))
+                    .or_else(|| self.policy.as_ref()?.check_cache_item(item));
+                let result = if let Some(error) = refusal {
+                    Ok(OperationResult {
+                        success: false,
+                        error: Some(error),
+                        bytes_freed: 0,
+                    })
+                } else if dry_run {
                     Self::simulate_deletion(item)
+                } else if let Some(veto) = self.hooks.before(item) {
+                    Ok(OperationResult {
+                        success: false,
+                        error: Some(veto),
+                        bytes_freed: 0,
+                    })
                 } else {
-                    Self::perform_deletion(item)
+                    checkpoint::started(&item.path);
+                    let result = {
+                        let _permit = throttle::permit();
+                        self.perform_deletion(
+                            item,
+                            self.action_of(item),
+                            self.deletes_in_parallel(item),
+                        )
+                    };
+                    checkpoint::done(&item.path);
+                    if result.as_ref().is_ok_and(|r| r.success)
+                        && let Some(failure) = self.hooks.after(item)
+                    {
+                        eprintln!("Warning: {}", failure);
+                    }
+                    result
                 };
 
-                match &result {
-                    Ok(op_result) => {
-                        if op_result.success && (index % 10 == 0 || index == total - 1) {
-                            println!(" SUCCESS ({})", format_bytes(op_result.bytes_freed));
-                        } else if !op_result.success && (index % 10 == 0 || index == total - 1) {
-                            println!(
-                                " FAILED: {}",
-                                op_result
-                                    .error
-                                    .as_ref()
-                                    .unwrap_or(&"Unknown error".to_string())
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        if index % 10 == 0 || index == total - 1 {
-                            println!(" ERROR: {}", e);
-                        }
-                    }
+                // Show progress with less frequent updates to avoid overwhelming output
+                if index % 10 == 0 || index == total - 1 {
+                    print_outcome(&item.path, index, total, dry_run, &result);
                 }
 
-                result.unwrap_or_else(|e| OperationResult {
+                let result = result.unwrap_or_else(|e| OperationResult {
                     success: false,
-                    error: Some(e.to_string()),
+                    error: Some(e),
                     bytes_freed: 0,
-                })
+                });
+                events::deletion(&item.path, &result, dry_run);
+                result
             })
             .collect();
 
@@ -93,68 +446,70 @@ impl FileOperations {
     }
 
     /// Delete log files with parallel processing
-    pub fn delete_log_files(
-        &self,
-        logs: &[LogFile],
-    ) -> Result<Vec<OperationResult>, Box<dyn std::error::Error>> {
+    pub fn delete_log_files(&self, logs: &[LogFile]) -> Result<Vec<OperationResult>, CleanerError> {
         if logs.is_empty() {
             return Ok(Vec::new());
         }
 
-        println!("Starting cleanup of {} log files...", logs.len());
+        println!(
+            "Starting cleanup of {} log files...",
+            group_digits(logs.len() as u64)
+        );
 
         let total = logs.len();
         let dry_run = self.dry_run;
+        // Looked up again, as processes may have opened or closed logs since
+        // the scan
+        let writers = match self.live_logs {
+            LiveLogAction::Delete => WriterTable::default(),
+            _ => WriterTable::load(),
+        };
 
         // Use rayon for parallel processing
         let results: Vec<OperationResult> = logs
             .par_iter()
             .enumerate()
             .map(|(index, log)| {
-                // Show progress with less frequent updates to avoid overwhelming output
-                if index % 10 == 0 || index == total - 1 {
-                    print!(
-                        "  {} {} [{}/{}] ",
-                        if dry_run { "DRY RUN" } else { "DELETING" },
-                        log.path.display(),
-                        index + 1,
-                        total
-                    );
-                    io::stdout().flush().ok();
-                }
-
-                let result = if dry_run {
-                    Self::simulate_log_deletion(log)
-                } else {
-                    Self::perform_log_deletion(log)
+                let refusal = self
+                    .refusal(&log.path)
+                    .or_else(|| self.content_refusal(log))
+                    .or_else(|| self.policy.as_ref()?.check_log_file(log));
+                let action = self
+                    .log_action(&log.path, writers.writer_of(&log.path).as_ref())
+                    .map_err(|reason| CleanerError::Skipped {
+                        path: log.path.clone(),
+                        reason,
+                    });
+                let result = match (refusal, action) {
+                    (Some(error), _) | (None, Err(error)) => Ok(OperationResult {
+                        success: false,
+                        error: Some(error),
+                        bytes_freed: 0,
+                    }),
+                    (None, Ok(_)) if dry_run => Self::simulate_log_deletion(log),
+                    (None, Ok(action)) => {
+                        checkpoint::started(&log.path);
+                        let result = {
+                            let _permit = throttle::permit();
+                            self.perform_log_deletion(log, action)
+                        };
+                        checkpoint::done(&log.path);
+                        result
+                    }
                 };
 
-                match &result {
-                    Ok(op_result) => {
-                        if op_result.success && (index % 10 == 0 || index == total - 1) {
-                            println!(" SUCCESS ({})", format_bytes(op_result.bytes_freed));
-                        } else if !op_result.success && (index % 10 == 0 || index == total - 1) {
-                            println!(
-                                " FAILED: {}",
-                                op_result
-                                    .error
-                                    .as_ref()
-                                    .unwrap_or(&"Unknown error".to_string())
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        if index % 10 == 0 || index == total - 1 {
-                            println!(" ERROR: {}", e);
-                        }
-                    }
+                // Show progress with less frequent updates to avoid overwhelming output
+                if index % 10 == 0 || index == total - 1 {
+                    print_outcome(&log.path, index, total, dry_run, &result);
                 }
 
-                result.unwrap_or_else(|e| OperationResult {
+                let result = result.unwrap_or_else(|e| OperationResult {
                     success: false,
-                    error: Some(e.to_string()),
+                    error: Some(e),
                     bytes_freed: 0,
-                })
+                });
+                events::deletion(&log.path, &result, dry_run);
+                result
             })
             .collect();
 
@@ -162,12 +517,12 @@ impl FileOperations {
     }
 
     /// Simulate deletion of a cache item (dry run)
-    fn simulate_deletion(item: &CacheItem) -> Result<OperationResult, Box<dyn std::error::Error>> {
+    fn simulate_deletion(item: &CacheItem) -> Result<OperationResult, CleanerError> {
         // Check if we can read the item
         if !item.path.exists() {
             return Ok(OperationResult {
                 success: false,
-                error: Some("Path does not exist".to_string()),
+                error: Some(CleanerError::NotFound(item.path.clone())),
                 bytes_freed: 0,
             });
         }
@@ -181,34 +536,64 @@ impl FileOperations {
         })
     }
 
-    /// Perform actual deletion of a cache item
-    fn perform_deletion(item: &CacheItem) -> Result<OperationResult, Box<dyn std::error::Error>> {
+    /// Whether `item` has files enough to spread its deletion over the pool
+    fn deletes_in_parallel(&self, item: &CacheItem) -> bool {
+        self.parallel_delete_files > 0
+            && item
+                .file_count
+                .is_some_and(|count| count >= self.parallel_delete_files)
+    }
+
+    /// Perform `action` on a cache item
+    fn perform_deletion(
+        &self,
+        item: &CacheItem,
+        action: Action,
+        parallel: bool,
+    ) -> Result<OperationResult, CleanerError> {
         let size = item.size_bytes.unwrap_or(0);
 
         // Check if path exists
         if !item.path.exists() {
             return Ok(OperationResult {
                 success: false,
-                error: Some("Path does not exist".to_string()),
+                error: Some(CleanerError::NotFound(item.path.clone())),
                 bytes_freed: 0,
             });
         }
 
+        let target = item.target_path();
+
         // Check permissions
-        if !Self::is_deletable(&item.path)? {
+        if !Self::is_deletable(target)? {
             return Ok(OperationResult {
                 success: false,
-                error: Some("Permission denied".to_string()),
+                error: Some(CleanerError::PermissionDenied(target.to_path_buf())),
                 bytes_freed: 0,
             });
         }
 
-        // Perform deletion
-        let result = if item.path.is_dir() {
-            fs::remove_dir_all(&item.path)
-        } else {
-            fs::remove_file(&item.path)
+        // Perform deletion. A resolved symlink keeps both the link and its
+        // target directory; only the target's contents are removed.
+        let result = match action {
+            Action::EmptyDirectory | Action::TrashContents | Action::ShredContents => {
+                self.empty_directory(target, action, parallel)
+            }
+            Action::RemoveTree => remove_tree(&item.path, parallel),
+            Action::RemoveFile => fs::remove_file(&item.path),
+            Action::CargoClean => project::cargo_clean(&item.path, parallel),
+            Action::Truncate => truncate(&item.path, None),
+            Action::Trash => trash::move_to_trash(&item.path, &self.mounts).map(drop),
+            Action::Shred => self.shred(&item.path, parallel),
+            Action::Vacuum => journal::vacuum(&item.path, &self.journal).map(drop),
+            Action::OstreePrune => reclaim::ostree_prune(&item.path),
+            Action::DkmsRemove => reclaim::dkms_remove(&item.path),
+            Action::PostsuperDelete => mail::delete_deferred(&item.path),
+            Action::CancelPrintJob => printing::cancel_job(&item.path),
         };
+        if result.is_ok() && item.cache_type == CacheType::VolumeTrash {
+            trash::forget(&item.path);
+        }
 
         match result {
             Ok(()) => Ok(OperationResult {
@@ -218,18 +603,44 @@ impl FileOperations {
             }),
             Err(e) => Ok(OperationResult {
                 success: false,
-                error: Some(e.to_string()),
+                error: Some(CleanerError::from_io(target, &e)),
                 bytes_freed: 0,
             }),
         }
     }
 
+    /// Remove everything inside `dir`, keeping the directory itself, or
+    /// move it to the trash or shred it as `action` says
+    fn empty_directory(&self, dir: &Path, action: Action, parallel: bool) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            match action {
+                Action::TrashContents => trash::move_to_trash(&path, &self.mounts).map(drop)?,
+                Action::ShredContents => self.shred(&path, parallel)?,
+                _ if path.is_dir() && !path.is_symlink() => remove_tree(&path, parallel)?,
+                _ => fs::remove_file(&path)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Overwrite the file or every file of the tree at `path`, then delete it
+    fn shred(&self, path: &Path, parallel: bool) -> io::Result<()> {
+        if path.is_dir() && !path.is_symlink() {
+            shred::overwrite_tree(path, self.shred_passes)?;
+            remove_tree(path, parallel)
+        } else {
+            shred::overwrite(path, self.shred_passes)?;
+            fs::remove_file(path)
+        }
+    }
+
     /// Simulate deletion of a log file (dry run)
-    fn simulate_log_deletion(log: &LogFile) -> Result<OperationResult, Box<dyn std::error::Error>> {
+    fn simulate_log_deletion(log: &LogFile) -> Result<OperationResult, CleanerError> {
         if !log.path.exists() {
             return Ok(OperationResult {
                 success: false,
-                error: Some("File does not exist".to_string()),
+                error: Some(CleanerError::NotFound(log.path.clone())),
                 bytes_freed: 0,
             });
         }
@@ -241,13 +652,17 @@ impl FileOperations {
         })
     }
 
-    /// Perform actual deletion of a log file
-    fn perform_log_deletion(log: &LogFile) -> Result<OperationResult, Box<dyn std::error::Error>> {
+    /// Perform actual deletion or truncation of a log file
+    fn perform_log_deletion(
+        &self,
+        log: &LogFile,
+        action: Action,
+    ) -> Result<OperationResult, CleanerError> {
         // Check if file exists
         if !log.path.exists() {
             return Ok(OperationResult {
                 success: false,
-                error: Some("File does not exist".to_string()),
+                error: Some(CleanerError::NotFound(log.path.clone())),
                 bytes_freed: 0,
             });
         }
@@ -256,13 +671,19 @@ impl FileOperations {
         if !Self::is_deletable(&log.path)? {
             return Ok(OperationResult {
                 success: false,
-                error: Some("Permission denied".to_string()),
+                error: Some(CleanerError::PermissionDenied(log.path.clone())),
                 bytes_freed: 0,
             });
         }
 
         // Perform deletion
-        match fs::remove_file(&log.path) {
+        let result = match action {
+            Action::Truncate => truncate(&log.path, log.file_id),
+            Action::Trash => trash::move_to_trash(&log.path, &self.mounts).map(drop),
+            Action::Shred => self.shred(&log.path, false),
+            _ => fs::remove_file(&log.path),
+        };
+        match result {
             Ok(()) => Ok(OperationResult {
                 success: true,
                 error: None,
@@ -270,14 +691,14 @@ impl FileOperations {
             }),
             Err(e) => Ok(OperationResult {
                 success: false,
-                error: Some(e.to_string()),
+                error: Some(CleanerError::from_io(&log.path, &e)),
                 bytes_freed: 0,
             }),
         }
     }
 
     /// Check if a path can be deleted
-    fn is_deletable(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    fn is_deletable(path: &Path) -> Result<bool, CleanerError> {
         // Try to access the parent directory
         if let Some(parent) = path.parent() {
             match fs::read_dir(parent) {
@@ -300,70 +721,400 @@ impl FileOperations {
         &self,
         cache_items: &[CacheItem],
         log_files: &[LogFile],
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        reported_items: &[CacheItem],
+        reported_logs: &[LogFile],
+    ) -> Result<(), CleanerError> {
         let backup_file = Self::get_backup_file_path()?;
 
-        // Create backup directory if it doesn't exist
-        if let Some(parent) = backup_file.parent() {
-            fs::create_dir_all(parent)?;
+        let mut content = String::new();
+        content.push_str(&format!(
+            "# Cleaner Backup List - {}\n",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+        content.push_str("# This file contains a list of items that were cleaned\n");
+        if !reported_items.is_empty() || !reported_logs.is_empty() {
+            content.push_str("# Mixed run: some categories were only reported, as in a dry run\n");
+        }
+        content.push('\n');
+
+        backup_sections(&mut content, "", cache_items, log_files);
+        backup_sections(
+            &mut content,
+            " - Reported Only (dry run)",
+            reported_items,
+            reported_logs,
+        );
+
+        state::write_atomic(&backup_file, content.as_bytes())?;
+        println!("Backup list created: {}", backup_file.display());
+
+        // The same items for `cleaner restore` to find in the trash
+        let manifest = Plan::from_items(&self.scan_root, cache_items, log_files);
+        let manifest_file = backup_file.with_extension("json");
+        let content =
+            serde_json::to_vec_pretty(&manifest).map_err(|e| CleanerError::Plan(e.to_string()))?;
+        state::write_atomic(&manifest_file, &content)?;
+        if self.trash {
+            println!(
+                "Undo with: {} restore {}",
+                env!("CARGO_PKG_NAME"),
+                manifest_file.display()
+            );
         }
 
+        Ok(())
+    }
+
+    /// Write a shell script performing the deletions instead of deleting
+    pub fn write_deletion_script(
+        &self,
+        script_path: &Path,
+        cache_items: &[CacheItem],
+        log_files: &[LogFile],
+    ) -> Result<(), CleanerError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let content = self.deletion_script(cache_items, log_files);
+        fs::write(script_path, content).map_err(|e| CleanerError::from_io(script_path, &e))?;
+        fs::set_permissions(script_path, fs::Permissions::from_mode(0o755))
+            .map_err(|e| CleanerError::from_io(script_path, &e))?;
+
+        Ok(())
+    }
+
+    /// Render the commands that deleting these items would run
+    fn deletion_script(&self, cache_items: &[CacheItem], log_files: &[LogFile]) -> String {
+        let total_size: u64 = cache_items
+            .iter()
+            .map(|i| i.size_bytes.unwrap_or(0))
+            .sum::<u64>()
+            + log_files.iter().map(|l| l.size_bytes).sum::<u64>();
+
         let mut content = String::new();
+        content.push_str("#!/bin/sh\n");
         content.push_str(&format!(
-            "# Cleaner Backup List - {}\n",
+            "# Generated by {} {} on {}\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
             chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
         ));
-        content.push_str("# This file contains a list of items that were cleaned\n\n");
+        push_comment(
+            &mut content,
+            &format!("Scan root: {}", self.scan_root.display()),
+        );
+        content.push_str(&format!(
+            "# {} items, {} total. Review before running.\n",
+            cache_items.len() + log_files.len(),
+            format_bytes(total_size)
+        ));
+        content.push_str("set -eu\n");
 
         if !cache_items.is_empty() {
-            content.push_str("## Cache Items\n");
+            content.push_str("\n## Cache Items\n");
             for item in cache_items {
-                content.push_str(&format!(
-                    "{} # {} - {}\n",
-                    item.path.display(),
-                    item.cache_type.description(),
-                    item.size_bytes
-                        .map(format_bytes)
-                        .unwrap_or_else(|| "Unknown size".to_string())
-                ));
+                let size = item
+                    .size_bytes
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "Unknown size".to_string());
+                let command = self.script_command(self.action_of(item), item);
+                self.push_script_entry(
+                    &mut content,
+                    &[&item.path, item.target_path()],
+                    &format!("{} - {}", size, item.cache_type.description()),
+                    &command,
+                );
             }
-            content.push('\n');
         }
 
         if !log_files.is_empty() {
-            content.push_str("## Log Files\n");
+            content.push_str("\n## Log Files\n");
             for log in log_files {
-                content.push_str(&format!(
-                    "{} # {} - {} - {} old\n",
-                    log.path.display(),
-                    log.log_type.description(),
+                let action = self.log_action(&log.path, log.writer.as_ref());
+                let command =
+                    self.path_command(action.clone().unwrap_or(Action::RemoveFile), &log.path);
+                let comment = format!(
+                    "{} - {} - {} old",
                     format_bytes(log.size_bytes),
+                    log.log_type.description(),
                     format_duration(log.age)
-                ));
+                );
+                match action {
+                    Ok(_) => self.push_script_entry(&mut content, &[&log.path], &comment, &command),
+                    // Kept for its writer, so listed disabled like refused items
+                    Err(reason) => {
+                        push_comment(&mut content, &comment);
+                        push_comment(&mut content, &format!("SKIPPED ({}): {}", reason, command));
+                    }
+                }
             }
         }
 
-        fs::write(&backup_file, content)?;
-        println!("Backup list created: {}", backup_file.display());
-
-        Ok(())
+        content
     }
 
-    /// Get the backup file path
-    fn get_backup_file_path() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
-        let config_home = std::env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| {
-            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-            format!("{}/.config", home)
+    /// The operation a real run would perform on every item, for dry runs.
+    /// Items refused outright are listed with the reason they are kept.
+    pub fn planned_operations(
+        &self,
+        cache_items: &[CacheItem],
+        log_files: &[LogFile],
+    ) -> Vec<PlannedOperation> {
+        let refused = |paths: &[&Path]| {
+            paths.iter().find_map(|path| match self.protection(path) {
+                Protection::Refused(reason) => Some(reason),
+                _ => None,
+            })
+        };
+
+        let cache_operations = cache_items.iter().map(|item| PlannedOperation {
+            path: item.path.clone(),
+            action: match refused(&[&item.path, item.target_path()]) {
+                Some(reason) => Err(reason),
+                None => Ok(self.action_of(item)),
+            },
+            bytes: item.size_bytes.unwrap_or(0),
+            hooks: self.hooks.planned(item),
+        });
+        let log_operations = log_files.iter().map(|log| PlannedOperation {
+            path: log.path.clone(),
+            action: match refused(&[&log.path]) {
+                Some(reason) => Err(reason),
+                None => self.log_action(&log.path, log.writer.as_ref()),
+            },
+            bytes: log.size_bytes,
+            hooks: Vec::new(),
         });
 
-        let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-        Ok(std::path::PathBuf::from(config_home)
-            .join("cleaner")
+        cache_operations.chain(log_operations).collect()
+    }
+
+    /// The shell command performing `action` on a cache item
+    fn script_command(&self, action: Action, item: &CacheItem) -> String {
+        let target = shell_quote(&item.target_path().to_string_lossy());
+        match action {
+            Action::EmptyDirectory => format!("find {} -mindepth 1 -delete", target),
+            Action::TrashContents => format!(
+                "find {} -mindepth 1 -maxdepth 1 -exec gio trash -- {{}} +",
+                target
+            ),
+            Action::ShredContents => format!(
+                "find {target} -mindepth 1 -type f -exec shred -n {} -u -- {{}} + \
+                 && find {target} -mindepth 1 -delete",
+                self.shred_passes
+            ),
+            Action::Vacuum => self.vacuum_command(&item.path),
+            Action::CargoClean => format!(
+                "cargo {}",
+                quote_args(&project::cargo_clean_args(&item.path))
+            ),
+            Action::OstreePrune => format!(
+                "ostree {}",
+                quote_args(&reclaim::ostree_prune_args(&item.path))
+            ),
+            Action::DkmsRemove => format!(
+                "dkms {}",
+                quote_args(&reclaim::dkms_remove_args(&item.path).unwrap_or_default())
+            ),
+            Action::PostsuperDelete => format!(
+                "postsuper {}",
+                quote_args(&mail::postsuper_args(&item.path))
+            ),
+            Action::CancelPrintJob => format!(
+                "cancel {}",
+                quote_args(&printing::cancel_args(&item.path).unwrap_or_default())
+            ),
+            action => self.path_command(action, &item.path),
+        }
+    }
+
+    /// The shell command performing `action` on `path` itself
+    fn path_command(&self, action: Action, path: &Path) -> String {
+        let quoted = shell_quote(&path.to_string_lossy());
+        match action {
+            // Like a real run: overwrite every file below a directory, then
+            // remove the tree
+            Action::Shred if path.is_dir() && !path.is_symlink() => format!(
+                "find {quoted} -type f -exec shred -n {} -u -- {{}} + && rm -rf -- {quoted}",
+                self.shred_passes
+            ),
+            Action::Shred => format!("shred -n {} -u -- {}", self.shred_passes, quoted),
+            action => format!("{} -- {}", action.label(), quoted),
+        }
+    }
+
+    /// The journalctl call applying the configured vacuum limits to `dir`
+    fn vacuum_command(&self, dir: &Path) -> String {
+        let mut command = format!(
+            "journalctl --directory={}",
+            shell_quote(&dir.to_string_lossy())
+        );
+        if !self.journal.vacuum_time.is_zero() {
+            command.push_str(&format!(
+                " --vacuum-time={}s",
+                self.journal.vacuum_time.as_secs()
+            ));
+        }
+        if self.journal.vacuum_size > 0 {
+            command.push_str(&format!(" --vacuum-size={}", self.journal.vacuum_size));
+        }
+        command
+    }
+
+    /// Append one commented command, disabled if a path is refused outright
+    fn push_script_entry(
+        &self,
+        content: &mut String,
+        paths: &[&Path],
+        comment: &str,
+        command: &str,
+    ) {
+        push_comment(content, comment);
+        for path in paths {
+            match self.protection(path) {
+                Protection::None => {}
+                Protection::TypedConfirmation(reason) => {
+                    push_comment(content, &format!("REVIEW: {}", reason));
+                }
+                Protection::Refused(reason) => {
+                    push_comment(content, &format!("SKIPPED ({}): {}", reason, command));
+                    return;
+                }
+            }
+        }
+        content.push_str(command);
+        content.push('\n');
+    }
+
+    /// Get the backup file path
+    fn get_backup_file_path() -> Result<std::path::PathBuf, CleanerError> {
+        let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S%3f");
+        Ok(state::dir()
             .join("backups")
             .join(format!("cleanup_{}.txt", timestamp)))
     }
 }
 
+/// Append `text` to a deletion script as one comment line. Newlines and
+/// other control characters, as file names may hold, are escaped so they
+/// can't end the comment and smuggle in a command.
+fn push_comment(content: &mut String, text: &str) {
+    content.push_str("# ");
+    for c in text.chars() {
+        if c.is_control() {
+            content.extend(c.escape_default());
+        } else {
+            content.push(c);
+        }
+    }
+    content.push('\n');
+}
+
+/// Empty the file at `path` in place, so a process writing to it keeps a
+/// valid handle and the space is freed at once. A symbolic link, or a file
+/// other than `file_id` (device and inode) the scan found, is left alone.
+fn truncate(path: &Path, file_id: Option<(u64, u64)>) -> io::Result<()> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::Error::other("not a regular file"));
+    }
+    if file_id.is_some_and(|id| id != (metadata.dev(), metadata.ino())) {
+        return Err(io::Error::other("replaced since the scan"));
+    }
+    file.set_len(0)
+}
+
+/// Delete the directory `path` in two steps: rename it to a unique sibling
+/// first, then remove that. An application recreating its cache meanwhile
+/// gets a fresh directory instead of racing `remove_dir_all`, and an
+/// interrupted deletion leaves a recognizable `.cleaner-deleting-*` behind.
+/// Directories that can't be renamed (e.g. busy mount points) are removed
+/// in place. Huge trees are removed `parallel`ly.
+pub fn remove_tree(path: &Path, parallel: bool) -> io::Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let aside = loop {
+        let name = format!(
+            "{}{}-{}",
+            DELETING_PREFIX,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let aside = path.with_file_name(name);
+        if fs::symlink_metadata(&aside).is_err() {
+            break aside;
+        }
+    };
+    let removed = match fs::rename(path, &aside) {
+        Ok(()) => aside,
+        Err(_) => path.to_path_buf(),
+    };
+    // Throttled runs keep to one worker per item, as the workers taking
+    // over children couldn't wait for a permit without blocking the holder
+    if parallel && !throttle::is_limited() {
+        unlink::remove_dir_all_parallel(&removed)
+    } else {
+        unlink::remove_dir_all(&removed)
+    }
+}
+
+/// Print how deleting the `index`th of `total` items went, as one line so
+/// parallel deletions and progress lines don't break into it
+fn print_outcome(
+    path: &Path,
+    index: usize,
+    total: usize,
+    dry_run: bool,
+    result: &Result<OperationResult, CleanerError>,
+) {
+    let outcome = match result {
+        Ok(r) if r.success => format!("SUCCESS ({})", format_bytes(r.bytes_freed)),
+        Ok(r) => format!(
+            "FAILED: {}",
+            r.error
+                .as_ref()
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| "Unknown error".to_string())
+        ),
+        Err(e) => format!("ERROR: {}", e),
+    };
+    println!(
+        "  {} {} [{}/{}] {}",
+        if dry_run { "DRY RUN" } else { "DELETING" },
+        path.display(),
+        index + 1,
+        total,
+        outcome
+    );
+}
+
+/// Quote a string for POSIX sh using single quotes
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quote each argument of a command line
+fn quote_args(args: &[impl AsRef<OsStr>]) -> String {
+    args.iter()
+        .map(|arg| shell_quote(&arg.as_ref().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Resolve the parent directory so `..` and symlinked ancestors can't dodge
+/// the safety checks, without following a symlink at `path` itself
+fn normalize(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
 /// Summary of operation results
 #[derive(Debug)]
 pub struct OperationSummary {
@@ -382,12 +1133,7 @@ impl OperationSummary {
         let total_bytes_freed = results.iter().map(|r| r.bytes_freed).sum();
         let permission_denied = results
             .iter()
-            .filter(|r| {
-                !r.success
-                    && r.error
-                        .as_ref()
-                        .is_some_and(|e| e.contains("Permission denied"))
-            })
+            .filter(|r| !r.success && matches!(r.error, Some(CleanerError::PermissionDenied(_))))
             .count();
 
         Self {
@@ -400,6 +1146,50 @@ impl OperationSummary {
     }
 }
 
+/// The cache item and log file sections of a backup list, headed with
+/// `suffix`
+fn backup_sections(
+    content: &mut String,
+    suffix: &str,
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+) {
+    if !cache_items.is_empty() {
+        content.push_str(&format!("## Cache Items{}\n", suffix));
+        for item in cache_items {
+            let target = item
+                .resolved_path
+                .as_ref()
+                .map(|target| format!(" -> {}", target.display()))
+                .unwrap_or_default();
+            content.push_str(&format!(
+                "{}{} # {} - {}\n",
+                item.path.display(),
+                target,
+                item.cache_type.description(),
+                item.size_bytes
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "Unknown size".to_string())
+            ));
+        }
+        content.push('\n');
+    }
+
+    if !log_files.is_empty() {
+        content.push_str(&format!("## Log Files{}\n", suffix));
+        for log in log_files {
+            content.push_str(&format!(
+                "{} # {} - {} - {} old\n",
+                log.path.display(),
+                log.log_type.description(),
+                format_bytes(log.size_bytes),
+                format_duration(log.age)
+            ));
+        }
+        content.push('\n');
+    }
+}
+
 /// Format bytes into human-readable format
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -439,6 +1229,7 @@ pub fn format_duration(duration: std::time::Duration) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn test_format_bytes() {
@@ -467,7 +1258,9 @@ mod tests {
             },
             OperationResult {
                 success: false,
-                error: Some("Permission denied".to_string()),
+                error: Some(CleanerError::PermissionDenied(PathBuf::from(
+                    "/var/cache/locked",
+                ))),
                 bytes_freed: 0,
             },
         ];
@@ -479,4 +1272,373 @@ mod tests {
         assert_eq!(summary.total_bytes_freed, 1024);
         assert_eq!(summary.permission_denied, 1);
     }
+
+    #[test]
+    fn test_resolved_symlink_empties_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        std::fs::create_dir_all(target.join("nested")).unwrap();
+        std::fs::write(target.join("file"), "cache").unwrap();
+        let link = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let item = CacheItem {
+            size_bytes: Some(5),
+            file_count: Some(1),
+            resolved_path: Some(target.clone()),
+            ..CacheItem::new(
+                link.clone(),
+                crate::cache_detector::CacheType::UserCache,
+                "",
+            )
+        };
+
+        let file_ops = FileOperations::new(false, &Config::default(), temp_dir.path());
+        let result = file_ops
+            .perform_deletion(&item, Action::of(&item), false)
+            .unwrap();
+        assert!(result.success);
+        assert!(link.is_symlink());
+        assert!(target.is_dir());
+        assert_eq!(std::fs::read_dir(&target).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_remove_tree_renames_before_removing() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        std::fs::create_dir_all(cache.join("nested")).unwrap();
+        std::fs::write(cache.join("nested/file"), "cache").unwrap();
+
+        remove_tree(&cache, false).unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+        assert!(remove_tree(&cache, false).is_err());
+        // Only that exact name counts as an interrupted deletion
+        assert!(is_deleting_name(".cleaner-deleting-4242-7"));
+        assert!(!is_deleting_name(".cleaner-deleting-notes"));
+        assert!(!is_deleting_name(".cleaner-deleting-4242-"));
+        assert!(!is_deleting_name(".cleaner-deleting-4242-7-backup"));
+    }
+
+    #[test]
+    fn test_remove_tree_in_parallel() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = temp_dir.path().join("node_modules");
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("kept"), "kept").unwrap();
+        for package in 0..20 {
+            let dir = cache.join(format!("pkg{}/lib/deep", package));
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in 0..10 {
+                std::fs::write(dir.join(format!("{}.js", file)), "x").unwrap();
+            }
+        }
+        std::os::unix::fs::symlink(&outside, cache.join("link")).unwrap();
+
+        remove_tree(&cache, true).unwrap();
+        assert!(!cache.exists());
+        assert!(outside.join("kept").exists());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_protection_refuses_critical_roots() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let scan_root = temp_dir.path().join("scan");
+        std::fs::create_dir_all(scan_root.join("cache")).unwrap();
+        let ops = FileOperations::new(false, &Config::default(), &scan_root);
+
+        assert!(matches!(
+            ops.protection(Path::new("/")),
+            Protection::Refused(_)
+        ));
+        assert!(matches!(ops.protection(&scan_root), Protection::Refused(_)));
+        assert!(matches!(
+            ops.protection(&scan_root.join("cache/..")),
+            Protection::Refused(_)
+        ));
+        assert!(matches!(
+            ops.protection(temp_dir.path()),
+            Protection::Refused(_)
+        ));
+        assert_eq!(ops.protection(&scan_root.join("cache")), Protection::None);
+        assert!(matches!(
+            ops.protection(&scan_root.join("vendor/.git/objects/tmp")),
+            Protection::Refused(_)
+        ));
+        assert!(ops.refusal(Path::new("/")).is_some());
+
+        // Browser credentials stay, unless their unused profile goes whole
+        let profile = scan_root.join(".mozilla/firefox/x1y2z3.default");
+        std::fs::create_dir_all(&profile).unwrap();
+        std::fs::write(profile.join("logins.json"), "{}").unwrap();
+        let mut ops = ops;
+        assert_eq!(
+            ops.protection(&profile),
+            Protection::Refused("blocked by protection list: Firefox logins.json".to_string())
+        );
+        ops.release_profile(&profile);
+        assert_eq!(ops.protection(&profile), Protection::None);
+    }
+
+    #[test]
+    fn test_protection_requires_confirmation_in_home() {
+        let mut ops = FileOperations::new(false, &Config::default(), Path::new("/nonexistent"));
+        let documents = Path::new("/home/someone/Documents");
+
+        assert!(matches!(
+            ops.protection(documents),
+            Protection::TypedConfirmation(_)
+        ));
+        assert_eq!(
+            ops.protection(Path::new("/home/someone/.cache")),
+            Protection::None
+        );
+        assert_eq!(
+            ops.protection(Path::new("/home/someone/Documents/build")),
+            Protection::None
+        );
+
+        assert!(ops.refusal(documents).is_some());
+        ops.confirm(documents);
+        assert!(ops.refusal(documents).is_none());
+    }
+
+    #[test]
+    fn test_planned_operations() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        std::fs::create_dir(&cache).unwrap();
+        std::fs::write(temp_dir.path().join("stale.bin"), "x").unwrap();
+        let config = Config {
+            hooks: vec![crate::config::CleanHook {
+                pre: Some("true".to_string()),
+                ..Default::default()
+            }],
+            ..Config::default()
+        };
+        let ops = FileOperations::new(true, &config, temp_dir.path());
+
+        let item = CacheItem {
+            size_bytes: Some(10),
+            file_count: Some(1),
+            ..CacheItem::new(
+                cache.clone(),
+                crate::cache_detector::CacheType::UserCache,
+                "",
+            )
+        };
+        let file = CacheItem {
+            path: temp_dir.path().join("stale.bin"),
+            ..item.clone()
+        };
+        let root = CacheItem {
+            path: temp_dir.path().to_path_buf(),
+            ..item.clone()
+        };
+
+        let planned = ops.planned_operations(&[item, file, root], &[]);
+        let actions: Vec<_> = planned.iter().map(|p| p.action.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                Ok(Action::RemoveTree),
+                Ok(Action::RemoveFile),
+                Err("scan root".to_string())
+            ]
+        );
+        assert_eq!(planned[0].hooks, vec!["pre-clean: true".to_string()]);
+    }
+
+    #[test]
+    fn test_truncate_checks_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log = temp_dir.path().join("app.log");
+        let secret = temp_dir.path().join("secret");
+        std::fs::write(&log, "log line").unwrap();
+        std::fs::write(&secret, "keep me").unwrap();
+        let metadata = std::fs::metadata(&log).unwrap();
+        let scanned = (metadata.dev(), metadata.ino());
+
+        // A link swapped in for the log is never followed
+        let link = temp_dir.path().join("link.log");
+        std::os::unix::fs::symlink(&secret, &link).unwrap();
+        assert!(truncate(&link, None).is_err());
+        assert_eq!(std::fs::read_to_string(&secret).unwrap(), "keep me");
+
+        // Nor is another file now at the log's path emptied
+        std::fs::rename(&log, temp_dir.path().join("app.log.1")).unwrap();
+        std::fs::write(&log, "new log").unwrap();
+        assert!(truncate(&log, Some(scanned)).is_err());
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "new log");
+
+        let metadata = std::fs::metadata(&log).unwrap();
+        truncate(&log, Some((metadata.dev(), metadata.ino()))).unwrap();
+        assert_eq!(std::fs::metadata(&log).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_truncate_old_logs() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("daemon.log");
+        std::fs::write(&path, "started\n").unwrap();
+        let mut config = Config::default();
+        config.log_cleanup.log_action = LogAction::Truncate;
+        let ops = FileOperations::new(false, &config, temp_dir.path());
+        let metadata = std::fs::metadata(&path).unwrap();
+        let log = LogFile {
+            path: path.clone(),
+            size_bytes: 8,
+            last_modified: std::time::SystemTime::UNIX_EPOCH,
+            age: std::time::Duration::ZERO,
+            log_type: crate::log_cleaner::LogType::Application,
+            confidence: crate::log_cleaner::Confidence::High,
+            owner: None,
+            writer: None,
+            file_id: Some((metadata.dev(), metadata.ino())),
+            reason: String::new(),
+        };
+
+        // A log rotated away and recreated since the scan is left alone
+        std::fs::rename(&path, temp_dir.path().join("daemon.log.1")).unwrap();
+        std::fs::write(&path, "started\n").unwrap();
+        let result = ops.perform_log_deletion(&log, Action::Truncate).unwrap();
+        assert!(!result.success);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 8);
+        std::fs::rename(temp_dir.path().join("daemon.log.1"), &path).unwrap();
+
+        let planned = ops.planned_operations(&[], std::slice::from_ref(&log));
+        assert_eq!(planned[0].action, Ok(Action::Truncate));
+        let result = ops.perform_log_deletion(&log, Action::Truncate).unwrap();
+        assert!(result.success);
+        assert_eq!(result.bytes_freed, 8);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_deletion_script() {
+        assert_eq!(shell_quote("/tmp/it's here"), "'/tmp/it'\\''s here'");
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = temp_dir.path().join("my cache");
+        std::fs::create_dir(&cache).unwrap();
+        let ops = FileOperations::new(false, &Config::default(), temp_dir.path());
+
+        let item = CacheItem {
+            size_bytes: Some(2048),
+            file_count: Some(1),
+            ..CacheItem::new(
+                cache.clone(),
+                crate::cache_detector::CacheType::UserCache,
+                "",
+            )
+        };
+        let root = CacheItem {
+            path: temp_dir.path().to_path_buf(),
+            ..item.clone()
+        };
+
+        let script = ops.deletion_script(&[item.clone(), root], &[]);
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("# 2.00 KB - User cache directory\n"));
+        assert!(script.contains(&format!("\nrm -rf -- '{}'\n", cache.display())));
+        assert!(script.contains(&format!(
+            "# SKIPPED (scan root): rm -rf -- '{}'\n",
+            temp_dir.path().display()
+        )));
+        assert!(script.contains("\nset -eu\n"));
+
+        // A newline in a refused path can't end its comment
+        let root = temp_dir.path().join("root\ntouch pwned #");
+        std::fs::create_dir(&root).unwrap();
+        let ops = FileOperations::new(false, &Config::default(), &root);
+        let item = CacheItem {
+            path: root.clone(),
+            ..item
+        };
+        let script = ops.deletion_script(&[item], &[]);
+        assert!(script.contains("root\\ntouch pwned #'\n"));
+        assert!(!script.lines().any(|line| line.starts_with("touch")));
+    }
+
+    #[test]
+    fn test_deletion_script_trash() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        std::fs::create_dir(&cache).unwrap();
+        let log = temp_dir.path().join("old.log");
+        std::fs::write(&log, "x").unwrap();
+        let mut ops = FileOperations::new(false, &Config::default(), temp_dir.path());
+        ops.use_trash();
+
+        let item = CacheItem::new(
+            cache.clone(),
+            crate::cache_detector::CacheType::UserCache,
+            "",
+        );
+        let log_file = LogFile {
+            path: log.clone(),
+            size_bytes: 1,
+            last_modified: std::time::SystemTime::UNIX_EPOCH,
+            age: std::time::Duration::ZERO,
+            log_type: crate::log_cleaner::LogType::Application,
+            confidence: crate::log_cleaner::Confidence::High,
+            owner: None,
+            writer: None,
+            file_id: None,
+            reason: String::new(),
+        };
+        let script = ops.deletion_script(&[item], &[log_file]);
+        assert!(script.contains(&format!("\ngio trash -- '{}'\n", cache.display())));
+        assert!(script.contains(&format!("\ngio trash -- '{}'\n", log.display())));
+        assert!(!script.contains("rm -"));
+    }
+
+    #[test]
+    fn test_deletion_script_shred() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        let target = temp_dir.path().join("target dir");
+        std::fs::create_dir(&cache).unwrap();
+        std::fs::create_dir(&target).unwrap();
+        let file = temp_dir.path().join("blob");
+        std::fs::write(&file, "x").unwrap();
+        let mut ops = FileOperations::new(false, &Config::default(), temp_dir.path());
+        ops.use_shred();
+
+        let user_cache = crate::cache_detector::CacheType::UserCache;
+        let linked = CacheItem {
+            resolved_path: Some(target.clone()),
+            ..CacheItem::new(temp_dir.path().join("link"), user_cache.clone(), "")
+        };
+        let items = [
+            CacheItem::new(cache.clone(), user_cache.clone(), ""),
+            CacheItem::new(file.clone(), user_cache, ""),
+            linked,
+        ];
+        let passes = Config::default().safety.shred_passes;
+        let script = ops.deletion_script(&items, &[]);
+        assert!(script.contains(&format!(
+            "\nfind '{0}' -type f -exec shred -n {1} -u -- {{}} + && rm -rf -- '{0}'\n",
+            cache.display(),
+            passes
+        )));
+        assert!(script.contains(&format!(
+            "\nshred -n {} -u -- '{}'\n",
+            passes,
+            file.display()
+        )));
+        assert!(script.contains(&format!(
+            "\nfind '{0}' -mindepth 1 -type f -exec shred -n {1} -u -- {{}} + \
+             && find '{0}' -mindepth 1 -delete\n",
+            target.display(),
+            passes
+        )));
+        assert!(!script.contains("\nrm -"));
+    }
 }
@@ -0,0 +1,172 @@
+use crate::history::Snapshot;
+use crate::mounts::{self, MountTable};
+use crate::plan::PlanKind;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+const SECS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// History shorter than this is too noisy to extrapolate from
+const MIN_SPAN_SECS: f64 = 60.0 * 60.0;
+
+/// How fast one item has been growing across the recorded scans
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct GrowthRate {
+    pub path: PathBuf,
+    pub kind: PlanKind,
+    pub current_bytes: u64,
+    /// Least-squares slope of the size over time
+    pub bytes_per_day: f64,
+}
+
+/// When a filesystem fills up if the tracked items keep growing as they did
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FullProjection {
+    pub mount_point: PathBuf,
+    pub available_bytes: u64,
+    pub bytes_per_day: f64,
+    /// Absent when the tracked items are not growing in total
+    pub days_until_full: Option<f64>,
+}
+
+/// Growth of the scanned items, derived from the scan history
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct GrowthReport {
+    /// Number of scans the rates are based on
+    pub scans: usize,
+    /// Days between the oldest and the newest scan
+    pub span_days: f64,
+    /// Growing items, fastest first
+    pub growers: Vec<GrowthRate>,
+    pub projections: Vec<FullProjection>,
+}
+
+impl GrowthReport {
+    /// Fit a growth rate to every item seen in at least two of `snapshots`
+    /// (oldest first, the current scan last). Returns `None` until the history
+    /// spans at least an hour.
+    pub fn from_snapshots(snapshots: &[Snapshot], mounts: &MountTable) -> Option<Self> {
+        let (first, last) = (snapshots.first()?, snapshots.last()?);
+        let span_secs = (last.created_at - first.created_at).num_milliseconds() as f64 / 1000.0;
+        if snapshots.len() < 2 || span_secs < MIN_SPAN_SECS {
+            return None;
+        }
+
+        let mut samples: HashMap<&Path, Vec<(f64, f64)>> = HashMap::new();
+        for snapshot in snapshots {
+            let t = (snapshot.created_at - first.created_at).num_milliseconds() as f64 / 1000.0;
+            for item in &snapshot.items {
+                samples
+                    .entry(item.path.as_path())
+                    .or_default()
+                    .push((t, item.size_bytes as f64));
+            }
+        }
+
+        let mut growers: Vec<GrowthRate> = last
+            .items
+            .iter()
+            .filter_map(|item| {
+                let slope = slope(samples.get(item.path.as_path())?)?;
+                (slope > 0.0).then(|| GrowthRate {
+                    path: item.path.clone(),
+                    kind: item.kind.clone(),
+                    current_bytes: item.size_bytes,
+                    bytes_per_day: slope * SECS_PER_DAY,
+                })
+            })
+            .collect();
+        growers.sort_by(|a, b| b.bytes_per_day.total_cmp(&a.bytes_per_day));
+
+        let mut per_mount: BTreeMap<&Path, f64> = BTreeMap::new();
+        for grower in &growers {
+            *per_mount
+                .entry(mounts.mount_point_of(&grower.path))
+                .or_default() += grower.bytes_per_day;
+        }
+        let projections = per_mount
+            .into_iter()
+            .filter_map(|(mount_point, bytes_per_day)| {
+                let available_bytes = mounts::available_bytes(mount_point)?;
+                Some(FullProjection {
+                    mount_point: mount_point.to_path_buf(),
+                    available_bytes,
+                    bytes_per_day,
+                    days_until_full: (bytes_per_day > 0.0)
+                        .then(|| available_bytes as f64 / bytes_per_day),
+                })
+            })
+            .collect();
+
+        Some(Self {
+            scans: snapshots.len(),
+            span_days: span_secs / SECS_PER_DAY,
+            growers,
+            projections,
+        })
+    }
+}
+
+/// Least-squares slope of `(time, size)` samples, in bytes per second
+fn slope(samples: &[(f64, f64)]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let n = samples.len() as f64;
+    let mean_t = samples.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_s = samples.iter().map(|(_, s)| s).sum::<f64>() / n;
+    let covariance: f64 = samples
+        .iter()
+        .map(|(t, s)| (t - mean_t) * (s - mean_s))
+        .sum();
+    let variance: f64 = samples.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::history::SnapshotItem;
+    use chrono::{Duration, Utc};
+
+    fn snapshot(days_ago: i64, items: &[(&str, u64)]) -> Snapshot {
+        Snapshot {
+            version: 1,
+            created_at: Utc::now() - Duration::days(days_ago),
+            scan_root: PathBuf::from("/"),
+            items: items
+                .iter()
+                .map(|(path, size_bytes)| SnapshotItem {
+                    path: PathBuf::from(path),
+                    kind: PlanKind::Cache(CacheType::UserCache),
+                    size_bytes: *size_bytes,
+                    file_count: None,
+                })
+                .collect(),
+            cleaned: None,
+            reported: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_growth_rates() {
+        let snapshots = vec![
+            snapshot(2, &[("/a", 1000), ("/b", 500)]),
+            snapshot(1, &[("/a", 2000), ("/b", 500)]),
+            snapshot(0, &[("/a", 3000), ("/b", 400), ("/new", 10)]),
+        ];
+        let mounts = MountTable::parse("1 0 8:1 / / rw - ext4 /dev/sda1 rw\n");
+
+        let report = GrowthReport::from_snapshots(&snapshots, &mounts).unwrap();
+        assert_eq!(report.scans, 3);
+        assert_eq!(report.growers.len(), 1);
+        assert_eq!(report.growers[0].path, PathBuf::from("/a"));
+        assert!((report.growers[0].bytes_per_day - 1000.0).abs() < 1.0);
+        assert_eq!(report.projections[0].mount_point, PathBuf::from("/"));
+
+        assert!(GrowthReport::from_snapshots(&snapshots[..1], &mounts).is_none());
+    }
+}
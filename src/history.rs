@@ -0,0 +1,482 @@
+use crate::cache_detector::CacheItem;
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::log_cleaner::LogFile;
+use crate::output::SCHEMA_VERSION;
+use crate::plan::PlanKind;
+use crate::state;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version written into new snapshot files
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The items one scan found, persisted so later scans can be compared to it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Snapshot {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub scan_root: PathBuf,
+    pub items: Vec<SnapshotItem>,
+    /// Paths the run cleaned, for runs that went on to clean for real
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cleaned: Option<Vec<PathBuf>>,
+    /// Paths of the categories a mixed `--clean-types` run only reported,
+    /// which the user had no say in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reported: Vec<PathBuf>,
+}
+
+/// One item of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotItem {
+    pub path: PathBuf,
+    pub kind: PlanKind,
+    pub size_bytes: u64,
+    /// Files below the item, when it was sized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<usize>,
+}
+
+/// An item found by both scans whose size changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct ItemChange {
+    pub path: PathBuf,
+    pub kind: PlanKind,
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+}
+
+impl ItemChange {
+    pub fn growth(&self) -> u64 {
+        self.after_bytes.saturating_sub(self.before_bytes)
+    }
+}
+
+/// Differences between the previous and the current scan of a root
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ScanDiff {
+    pub schema_version: u32,
+    pub scan_root: PathBuf,
+    pub previous_scan_at: DateTime<Utc>,
+    pub current_scan_at: DateTime<Utc>,
+    /// Items the previous scan did not find
+    pub new_items: Vec<SnapshotItem>,
+    /// Items that grew, largest growth first
+    pub grown: Vec<ItemChange>,
+    /// Items the current scan no longer finds
+    pub removed: Vec<SnapshotItem>,
+}
+
+impl Snapshot {
+    pub fn from_items(scan_root: &Path, cache_items: &[CacheItem], log_files: &[LogFile]) -> Self {
+        let cache_entries = cache_items.iter().map(|item| SnapshotItem {
+            path: item.path.clone(),
+            kind: PlanKind::Cache(item.cache_type.clone()),
+            size_bytes: item.size_bytes.unwrap_or(0),
+            file_count: item.file_count,
+        });
+        let log_entries = log_files.iter().map(|log| SnapshotItem {
+            path: log.path.clone(),
+            kind: PlanKind::Log(log.log_type.clone()),
+            size_bytes: log.size_bytes,
+            file_count: None,
+        });
+
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            scan_root: fs::canonicalize(scan_root).unwrap_or_else(|_| scan_root.to_path_buf()),
+            items: cache_entries.chain(log_entries).collect(),
+            cleaned: None,
+            reported: Vec::new(),
+        }
+    }
+
+    /// What changed between `previous` and this snapshot
+    pub fn diff(&self, previous: &Snapshot) -> ScanDiff {
+        let before: HashMap<&Path, &SnapshotItem> = previous
+            .items
+            .iter()
+            .map(|item| (item.path.as_path(), item))
+            .collect();
+        let after: HashMap<&Path, &SnapshotItem> = self
+            .items
+            .iter()
+            .map(|item| (item.path.as_path(), item))
+            .collect();
+
+        let new_items = self
+            .items
+            .iter()
+            .filter(|item| !before.contains_key(item.path.as_path()))
+            .cloned()
+            .collect();
+        let removed = previous
+            .items
+            .iter()
+            .filter(|item| !after.contains_key(item.path.as_path()))
+            .cloned()
+            .collect();
+
+        let mut grown: Vec<ItemChange> = self
+            .items
+            .iter()
+            .filter_map(|item| {
+                let old = before.get(item.path.as_path())?;
+                (item.size_bytes > old.size_bytes).then(|| ItemChange {
+                    path: item.path.clone(),
+                    kind: item.kind.clone(),
+                    before_bytes: old.size_bytes,
+                    after_bytes: item.size_bytes,
+                })
+            })
+            .collect();
+        grown.sort_by_key(|change| std::cmp::Reverse(change.growth()));
+
+        ScanDiff {
+            schema_version: SCHEMA_VERSION,
+            scan_root: self.scan_root.clone(),
+            previous_scan_at: previous.created_at,
+            current_scan_at: self.created_at,
+            new_items,
+            grown,
+            removed,
+        }
+    }
+}
+
+/// What the recorded scans of one root add up to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RootStats {
+    pub scan_root: PathBuf,
+    pub scans: usize,
+    pub first_scan_at: DateTime<Utc>,
+    pub last_scan_at: DateTime<Utc>,
+    /// Scans of runs that went on to clean
+    pub cleans: usize,
+    /// Items the cleans were asked to remove, and their size when found
+    pub items_cleaned: usize,
+    pub bytes_cleaned: u64,
+    /// What the latest scan found
+    pub latest_items: usize,
+    pub latest_bytes: u64,
+    /// Paths cleaned by more than one run, most often first
+    pub recurring: Vec<RecurringItem>,
+}
+
+/// A path that keeps coming back to be cleaned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RecurringItem {
+    pub path: PathBuf,
+    pub cleans: usize,
+}
+
+/// Recurring items listed per root
+const RECURRING_SHOWN: usize = 10;
+
+/// Sum up `snapshots`, given oldest first, per scan root
+pub fn stats<'a>(snapshots: impl IntoIterator<Item = &'a Snapshot>) -> Vec<RootStats> {
+    let mut by_root: BTreeMap<&Path, Vec<&Snapshot>> = BTreeMap::new();
+    for snapshot in snapshots {
+        by_root
+            .entry(snapshot.scan_root.as_path())
+            .or_default()
+            .push(snapshot);
+    }
+
+    by_root
+        .into_iter()
+        .filter_map(|(scan_root, snapshots)| {
+            let (first, latest) = (snapshots.first()?, snapshots.last()?);
+            let mut cleaned: HashMap<&Path, usize> = HashMap::new();
+            let mut bytes_cleaned = 0;
+            for snapshot in &snapshots {
+                let Some(paths) = &snapshot.cleaned else {
+                    continue;
+                };
+                for path in paths {
+                    *cleaned.entry(path.as_path()).or_default() += 1;
+                    bytes_cleaned += snapshot
+                        .items
+                        .iter()
+                        .find(|item| item.path == *path)
+                        .map_or(0, |item| item.size_bytes);
+                }
+            }
+
+            let mut recurring: Vec<RecurringItem> = cleaned
+                .into_iter()
+                .filter(|(_, cleans)| *cleans > 1)
+                .map(|(path, cleans)| RecurringItem {
+                    path: path.to_path_buf(),
+                    cleans,
+                })
+                .collect();
+            recurring.sort_by(|a, b| b.cleans.cmp(&a.cleans).then(a.path.cmp(&b.path)));
+            recurring.truncate(RECURRING_SHOWN);
+
+            Some(RootStats {
+                scan_root: scan_root.to_path_buf(),
+                scans: snapshots.len(),
+                first_scan_at: first.created_at,
+                last_scan_at: latest.created_at,
+                cleans: snapshots.iter().filter(|s| s.cleaned.is_some()).count(),
+                items_cleaned: snapshots
+                    .iter()
+                    .filter_map(|s| s.cleaned.as_ref())
+                    .map(Vec::len)
+                    .sum(),
+                bytes_cleaned,
+                latest_items: latest.items.len(),
+                latest_bytes: latest.items.iter().map(|item| item.size_bytes).sum(),
+                recurring,
+            })
+        })
+        .collect()
+}
+
+/// Items of the latest snapshot that each of the last `runs` cleaning runs
+/// found and offered for cleaning, but that were never cleaned, largest
+/// first; they only clutter the listings and are better excluded
+pub fn never_cleaned(snapshots: &[Snapshot], runs: usize) -> Vec<SnapshotItem> {
+    let cleaning: Vec<&Snapshot> = snapshots.iter().filter(|s| s.cleaned.is_some()).collect();
+    if runs == 0 || cleaning.len() < runs {
+        return Vec::new();
+    }
+    let window = &cleaning[cleaning.len() - runs..];
+
+    let mut items: Vec<SnapshotItem> = window[runs - 1]
+        .items
+        .iter()
+        .filter(|item| {
+            window.iter().all(|snapshot| {
+                snapshot.items.iter().any(|i| i.path == item.path)
+                    && !snapshot.reported.contains(&item.path)
+                    && !snapshot.cleaned.iter().flatten().any(|p| *p == item.path)
+            })
+        })
+        .cloned()
+        .collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.size_bytes));
+    items
+}
+
+/// Snapshots of past scans, newest kept up to the configured count
+pub struct History {
+    dir: PathBuf,
+    keep: usize,
+}
+
+impl History {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            dir: Self::default_dir(),
+            keep: config.history.keep,
+        }
+    }
+
+    /// Every recorded snapshot of `scan_root`, oldest first
+    pub fn snapshots(&self, scan_root: &Path) -> Result<Vec<Snapshot>, CleanerError> {
+        let mut snapshots = self.all_snapshots()?;
+        snapshots.retain(|snapshot| snapshot.scan_root == scan_root);
+        Ok(snapshots)
+    }
+
+    /// Every recorded snapshot of any scan root, oldest first
+    pub fn all_snapshots(&self) -> Result<Vec<Snapshot>, CleanerError> {
+        let mut snapshots = Vec::new();
+        for path in self.snapshot_files()? {
+            let content =
+                fs::read_to_string(&path).map_err(|e| CleanerError::from_io(&path, &e))?;
+            // Snapshots of other versions or damaged files are not comparable
+            let Ok(snapshot) = serde_json::from_str::<Snapshot>(&content) else {
+                continue;
+            };
+            if snapshot.version == SNAPSHOT_VERSION {
+                snapshots.push(snapshot);
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// Persist `snapshot` and prune the oldest ones beyond the retention count
+    pub fn record(&self, snapshot: &Snapshot) -> Result<PathBuf, CleanerError> {
+        // Concurrent runs would otherwise prune the same snapshots
+        let _lock = state::lock(&self.dir)?;
+
+        let path = self.dir.join(format!(
+            "scan_{}.json",
+            snapshot.created_at.format("%Y%m%d_%H%M%S%3f")
+        ));
+        let content = serde_json::to_string(snapshot).map_err(|e| CleanerError::Io {
+            path: path.clone(),
+            kind: std::io::ErrorKind::Other,
+            message: e.to_string(),
+        })?;
+        state::write_atomic(&path, content.as_bytes())?;
+
+        if self.keep > 0 {
+            let files = self.snapshot_files()?;
+            for old in &files[..files.len().saturating_sub(self.keep)] {
+                fs::remove_file(old).map_err(|e| CleanerError::from_io(old, &e))?;
+            }
+        }
+
+        Ok(path)
+    }
+
+    /// Note in the snapshot recorded at `path` which items the run cleaned
+    /// and which it only reported
+    pub fn record_cleaned(
+        &self,
+        path: &Path,
+        cleaned: &[PathBuf],
+        reported: &[PathBuf],
+    ) -> Result<(), CleanerError> {
+        let _lock = state::lock(&self.dir)?;
+        let content = fs::read_to_string(path).map_err(|e| CleanerError::from_io(path, &e))?;
+        let invalid = |e: serde_json::Error| CleanerError::Io {
+            path: path.to_path_buf(),
+            kind: std::io::ErrorKind::InvalidData,
+            message: e.to_string(),
+        };
+        let mut snapshot: Snapshot = serde_json::from_str(&content).map_err(invalid)?;
+        snapshot.cleaned = Some(cleaned.to_vec());
+        snapshot.reported = reported.to_vec();
+        let content = serde_json::to_string(&snapshot).map_err(invalid)?;
+        state::write_atomic(path, content.as_bytes())
+    }
+
+    /// Snapshot files, oldest first; their names sort chronologically
+    fn snapshot_files(&self) -> Result<Vec<PathBuf>, CleanerError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(CleanerError::from_io(&self.dir, &e)),
+        };
+
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("scan_") && n.ends_with(".json"))
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    fn default_dir() -> PathBuf {
+        state::dir().join("history")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use tempfile::TempDir;
+
+    fn item(path: &str, size_bytes: u64) -> SnapshotItem {
+        SnapshotItem {
+            path: PathBuf::from(path),
+            kind: PlanKind::Cache(CacheType::UserCache),
+            size_bytes,
+            file_count: None,
+        }
+    }
+
+    fn snapshot(items: Vec<SnapshotItem>) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            scan_root: PathBuf::from("/home/alice"),
+            items,
+            cleaned: None,
+            reported: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_diff_and_history() {
+        let previous = snapshot(vec![item("/a", 100), item("/b", 100), item("/gone", 5)]);
+        let current = snapshot(vec![item("/a", 150), item("/b", 900), item("/new", 7)]);
+
+        let diff = current.diff(&previous);
+        assert_eq!(diff.new_items, vec![item("/new", 7)]);
+        assert_eq!(diff.removed, vec![item("/gone", 5)]);
+        assert_eq!(diff.grown.len(), 2);
+        assert_eq!(diff.grown[0].path, PathBuf::from("/b"));
+        assert_eq!(diff.grown[0].growth(), 800);
+
+        let temp_dir = TempDir::new().unwrap();
+        let history = History {
+            dir: temp_dir.path().to_path_buf(),
+            keep: 1,
+        };
+        assert!(history.snapshots(&previous.scan_root).unwrap().is_empty());
+
+        let mut older = previous.clone();
+        older.created_at -= chrono::Duration::seconds(60);
+        history.record(&older).unwrap();
+        history.record(&current).unwrap();
+        let snapshots = history.snapshots(&current.scan_root).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].items, current.items);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut first = snapshot(vec![item("/a", 100), item("/b", 900)]);
+        first.cleaned = Some(vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+        let mut second = snapshot(vec![item("/a", 300)]);
+        second.cleaned = Some(vec![PathBuf::from("/a")]);
+        let mut other = snapshot(vec![item("/tmp/x", 1)]);
+        other.scan_root = PathBuf::from("/tmp");
+
+        let stats = stats([&first, &other, &second, &snapshot(vec![item("/c", 5)])]);
+        assert_eq!(stats.len(), 2);
+        let home = &stats[0];
+        assert_eq!(home.scan_root, PathBuf::from("/home/alice"));
+        assert_eq!((home.scans, home.cleans, home.items_cleaned), (3, 2, 3));
+        assert_eq!(home.bytes_cleaned, 1300);
+        assert_eq!((home.latest_items, home.latest_bytes), (1, 5));
+        assert_eq!(
+            home.recurring,
+            vec![RecurringItem {
+                path: PathBuf::from("/a"),
+                cleans: 2
+            }]
+        );
+        assert_eq!(stats[1].cleans, 0);
+    }
+
+    #[test]
+    fn test_never_cleaned() {
+        let run = |cleaned: Option<&[&str]>| Snapshot {
+            cleaned: cleaned.map(|paths| paths.iter().map(PathBuf::from).collect()),
+            ..snapshot(vec![item("/a", 100), item("/b", 900), item("/c", 5)])
+        };
+        // Scans that didn't clean say nothing about what the user wants
+        let mut snapshots = vec![run(Some(&["/a"])), run(None), run(Some(&["/c"]))];
+        assert!(never_cleaned(&snapshots, 3).is_empty());
+        assert_eq!(never_cleaned(&snapshots, 2), vec![item("/b", 900)]);
+
+        // Nor do items that came and went
+        snapshots.push(run(Some(&[])));
+        snapshots.last_mut().unwrap().items.remove(1);
+        assert_eq!(never_cleaned(&snapshots, 2), vec![item("/a", 100)]);
+        assert_eq!(never_cleaned(&snapshots, 0), Vec::new());
+
+        // Nor do runs that only reported the item's category
+        snapshots.last_mut().unwrap().reported = vec![PathBuf::from("/a")];
+        assert!(never_cleaned(&snapshots, 2).is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::{CleanHook, Config};
+use crate::error::CleanerError;
+use crate::state;
+use chrono::Utc;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// When a hook runs relative to the deletion of its item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Pre,
+    Post,
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Pre => "pre",
+            Phase::Post => "post",
+        }
+    }
+}
+
+/// A configured hook with its path glob compiled
+struct Hook {
+    config: CleanHook,
+    path: Option<glob::Pattern>,
+}
+
+impl Hook {
+    fn matches(&self, item: &CacheItem) -> bool {
+        (self.config.cache_types.is_empty() || self.config.cache_types.contains(&item.cache_type))
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|p| p.matches_path(&item.path))
+    }
+
+    fn command(&self, phase: Phase) -> Option<&str> {
+        match phase {
+            Phase::Pre => self.config.pre.as_deref(),
+            Phase::Post => self.config.post.as_deref(),
+        }
+    }
+}
+
+/// Runs the configured pre- and post-clean hooks and logs every execution
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    log_path: PathBuf,
+    /// Opened on the first execution, so runs without hooks leave no log
+    log: Mutex<Option<File>>,
+}
+
+impl Hooks {
+    pub fn new(config: &Config) -> Self {
+        Self::with_log(config, Self::default_log_path())
+    }
+
+    fn with_log(config: &Config, log_path: PathBuf) -> Self {
+        let regeneration = config
+            .regeneration
+            .enabled
+            .then(regeneration_hooks)
+            .unwrap_or_default();
+        let hooks = config
+            .hooks
+            .iter()
+            .chain(&regeneration)
+            .map(|hook| Hook {
+                config: hook.clone(),
+                path: hook
+                    .path
+                    .as_ref()
+                    .and_then(|p| glob::Pattern::new(&Config::expand_home(p)).ok()),
+            })
+            .collect();
+
+        Self {
+            hooks,
+            log_path,
+            log: Mutex::new(None),
+        }
+    }
+
+    /// Run the pre-clean hooks of `item`, returning the veto of the first one
+    /// that fails
+    pub fn before(&self, item: &CacheItem) -> Option<CleanerError> {
+        self.run(Phase::Pre, item)
+            .map(|reason| CleanerError::Vetoed {
+                path: item.path.clone(),
+                reason,
+            })
+    }
+
+    /// Run the post-clean hooks of a deleted `item`, returning what failed
+    pub fn after(&self, item: &CacheItem) -> Option<String> {
+        self.run(Phase::Post, item)
+    }
+
+    /// The hook commands that would run around cleaning `item`, in order
+    pub fn planned(&self, item: &CacheItem) -> Vec<String> {
+        [Phase::Pre, Phase::Post]
+            .into_iter()
+            .flat_map(|phase| {
+                self.hooks
+                    .iter()
+                    .filter(|h| h.matches(item))
+                    .filter_map(move |h| {
+                        h.command(phase)
+                            .map(|command| format!("{}-clean: {}", phase.name(), command))
+                    })
+            })
+            .collect()
+    }
+
+    /// Run every matching hook of `phase` in order. Pre-clean hooks stop at
+    /// the first failure; post-clean hooks all run and report the last one.
+    fn run(&self, phase: Phase, item: &CacheItem) -> Option<String> {
+        let mut failure = None;
+        for hook in self.hooks.iter().filter(|h| h.matches(item)) {
+            let Some(command) = hook.command(phase) else {
+                continue;
+            };
+            if let Err(reason) = self.execute(phase, command, item) {
+                failure = Some(reason);
+                if phase == Phase::Pre {
+                    break;
+                }
+            }
+        }
+        failure
+    }
+
+    fn execute(&self, phase: Phase, command: &str, item: &CacheItem) -> Result<(), String> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("CLEANER_ITEM_PATH", &item.path)
+            .env("CLEANER_TARGET_PATH", item.target_path())
+            .env("CLEANER_CACHE_TYPE", cache_type_name(item))
+            .stdin(Stdio::null())
+            .output();
+
+        let result = match output {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let detail = stderr.lines().last().unwrap_or_default().trim();
+                Err(format!(
+                    "{}-clean hook `{}` failed ({}){}",
+                    phase.name(),
+                    command,
+                    output.status,
+                    if detail.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", detail)
+                    }
+                ))
+            }
+            Err(e) => Err(format!(
+                "{}-clean hook `{}` could not be started: {}",
+                phase.name(),
+                command,
+                e
+            )),
+        };
+
+        self.log(phase, command, &item.path, &result);
+        result
+    }
+
+    /// Append one line per execution to the hook log; logging is best effort
+    fn log(&self, phase: Phase, command: &str, path: &Path, result: &Result<(), String>) {
+        let Ok(mut log) = self.log.lock() else {
+            return;
+        };
+        if log.is_none() {
+            if let Some(parent) = self.log_path.parent() {
+                fs::create_dir_all(parent).ok();
+            }
+            *log = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.log_path)
+                .ok();
+        }
+        if let Some(file) = log.as_mut() {
+            let outcome = match result {
+                Ok(()) => "ok".to_string(),
+                Err(reason) => reason.clone(),
+            };
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{}",
+                Utc::now().to_rfc3339(),
+                phase.name(),
+                path.display(),
+                command,
+                outcome
+            )
+            .ok();
+        }
+    }
+
+    fn default_log_path() -> PathBuf {
+        state::dir().join("hooks.log")
+    }
+}
+
+/// Built-in hooks rebuilding font and icon caches after they were cleaned
+fn regeneration_hooks() -> Vec<CleanHook> {
+    let hook = |path: &str, post: &str| CleanHook {
+        cache_types: vec![CacheType::RegeneratingCache],
+        path: Some(path.to_string()),
+        pre: None,
+        post: Some(post.to_string()),
+    };
+    vec![
+        hook("*/fontconfig", "fc-cache -f"),
+        hook(
+            "*/icon-theme.cache",
+            "gtk-update-icon-cache -q -f -t \"$(dirname \"$CLEANER_ITEM_PATH\")\"",
+        ),
+    ]
+}
+
+/// The config spelling of the item's cache type, e.g. `user_cache`
+fn cache_type_name(item: &CacheItem) -> String {
+    serde_json::to_value(&item.cache_type)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hooks_veto_and_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config {
+            hooks: vec![
+                CleanHook {
+                    path: Some("/home/*/.cache/fontconfig".to_string()),
+                    post: Some("echo \"$CLEANER_CACHE_TYPE\"".to_string()),
+                    ..CleanHook::default()
+                },
+                CleanHook {
+                    cache_types: vec![CacheType::BrowserCache],
+                    pre: Some("echo busy >&2; exit 3".to_string()),
+                    ..CleanHook::default()
+                },
+            ],
+            ..Config::default()
+        };
+        let log_path = temp_dir.path().join("hooks.log");
+        let hooks = Hooks::with_log(&config, log_path.clone());
+
+        let fonts = CacheItem::new("/home/u/.cache/fontconfig", CacheType::UserCache, "");
+        assert!(hooks.before(&fonts).is_none());
+        assert!(hooks.after(&fonts).is_none());
+
+        let browser = CacheItem::new("/home/u/.mozilla/cache", CacheType::BrowserCache, "");
+        let veto = hooks.before(&browser).unwrap();
+        assert!(veto.to_string().contains("busy"));
+
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        assert!(log.contains("post\t/home/u/.cache/fontconfig"));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::profiles;
+use crate::reclaim;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// VS Code and its rebuilds keep their data here, relative to a home directory
+const VSCODE_DIRS: &[&str] = &[
+    ".config/Code",
+    ".config/Code - Insiders",
+    ".config/Code - OSS",
+    ".config/VSCodium",
+];
+
+/// Parts of a VS Code data directory that are downloaded or compiled again
+const VSCODE_CACHES: &[&str] = &["CachedExtensionVSIXs", "Code Cache", "CachedData"];
+
+/// Settings, snippets, local history and workspace state of VS Code
+const VSCODE_USER: &str = "User";
+
+/// JetBrains IDEs keep one system directory per product and version here,
+/// holding local history next to the caches
+const JETBRAINS_DIR: &str = ".cache/JetBrains";
+
+/// Parts of a JetBrains system directory that the IDE rebuilds
+const JETBRAINS_CACHES: &[&str] = &["caches", "index"];
+
+/// What the IDE cache detection found in the home directories
+#[derive(Debug, Default)]
+pub struct IdeCaches {
+    /// Regenerable IDE caches to clean
+    pub items: Vec<CacheItem>,
+    /// IDE directories holding settings or local history, which no other
+    /// pattern may clean
+    pub kept: Vec<PathBuf>,
+}
+
+impl IdeCaches {
+    /// Merge into `items` found by the patterns: items inside kept
+    /// directories are dropped, items containing one are split around it,
+    /// and the IDE caches are added where nothing covers them yet
+    pub fn merge_into(self, items: &mut Vec<CacheItem>) {
+        let kept = &self.kept;
+        items.retain(|i| !kept.iter().any(|k| i.path.starts_with(k)));
+        *items = std::mem::take(items)
+            .into_iter()
+            .flat_map(|item| split_around(item, kept))
+            .collect();
+
+        let ide_items: Vec<CacheItem> = self
+            .items
+            .into_iter()
+            .filter(|ide| !items.iter().any(|i| ide.path.starts_with(&i.path)))
+            .collect();
+        items.retain(|i| !ide_items.iter().any(|ide| i.path.starts_with(&ide.path)));
+        items.extend(ide_items);
+    }
+}
+
+/// Find the regenerable caches of VS Code and JetBrains IDEs in the home
+/// directories below `scan_root`. Their settings and local history are
+/// reported as kept whether or not the IDE's cleaning is enabled.
+pub fn detect(scan_root: &Path, config: &Config) -> IdeCaches {
+    let mut caches = IdeCaches::default();
+    let now = SystemTime::now();
+    for home in profiles::homes(scan_root) {
+        for data in VSCODE_DIRS.iter().map(|dir| home.join(dir)) {
+            if !data.is_dir() {
+                continue;
+            }
+            caches.kept.push(data.join(VSCODE_USER));
+            if config.ide_caches.vscode {
+                caches.items.extend(vscode_caches(
+                    &data,
+                    config.ide_caches.workspace_storage_age,
+                    now,
+                ));
+            }
+        }
+
+        let jetbrains = home.join(JETBRAINS_DIR);
+        if jetbrains.is_dir() {
+            caches.kept.push(jetbrains.clone());
+            if config.ide_caches.jetbrains {
+                caches.items.extend(jetbrains_caches(&jetbrains));
+            }
+        }
+    }
+    caches
+        .items
+        .retain(|item| !config.is_excluded_path(&item.path));
+    caches
+}
+
+/// Caches of the VS Code data directory `data`, with the storage of
+/// workspaces not opened for `workspace_storage_age`
+fn vscode_caches(data: &Path, workspace_storage_age: Duration, now: SystemTime) -> Vec<CacheItem> {
+    let mut items: Vec<CacheItem> = VSCODE_CACHES
+        .iter()
+        .map(|name| data.join(name))
+        .filter(|path| path.is_dir())
+        .map(|path| {
+            CacheItem::new(
+                path,
+                CacheType::DevelopmentCache,
+                format!("VS Code cache in {}", data.display()),
+            )
+            .dated()
+        })
+        .collect();
+
+    if !workspace_storage_age.is_zero() {
+        let storage = data.join(VSCODE_USER).join("workspaceStorage");
+        items.extend(
+            reclaim::subdirectories(&storage)
+                .into_iter()
+                .map(|path| {
+                    CacheItem::new(
+                        path,
+                        CacheType::DevelopmentCache,
+                        "VS Code workspace storage older than ide_caches.workspace_storage_age",
+                    )
+                    .dated()
+                })
+                .filter(|item| {
+                    item.last_modified
+                        .and_then(|modified| now.duration_since(modified).ok())
+                        .is_some_and(|age| age >= workspace_storage_age)
+                }),
+        );
+    }
+    items
+}
+
+/// The `caches` and `index` directories of every JetBrains product in
+/// `jetbrains`, leaving its local history and logs alone
+fn jetbrains_caches(jetbrains: &Path) -> Vec<CacheItem> {
+    reclaim::subdirectories(jetbrains)
+        .into_iter()
+        .flat_map(|product| {
+            JETBRAINS_CACHES
+                .iter()
+                .map(move |name| product.join(name))
+                .filter(|path| path.is_dir())
+                .collect::<Vec<_>>()
+        })
+        .map(|path| {
+            let product = path
+                .parent()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            CacheItem::new(
+                path,
+                CacheType::DevelopmentCache,
+                format!("JetBrains {} cache", product),
+            )
+            .dated()
+        })
+        .collect()
+}
+
+/// `item` if it doesn't contain a kept directory, otherwise its entries
+/// without the ones leading to kept directories
+fn split_around(item: CacheItem, kept: &[PathBuf]) -> Vec<CacheItem> {
+    if !kept.iter().any(|k| k.starts_with(&item.path)) {
+        return vec![item];
+    }
+    let Ok(entries) = fs::read_dir(&item.path) else {
+        return Vec::new();
+    };
+
+    let mut parts: Vec<CacheItem> = entries
+        .flatten()
+        .filter(|entry| !kept.contains(&entry.path()))
+        .flat_map(|entry| {
+            let metadata = entry.metadata().ok();
+            let part = CacheItem::new(
+                entry.path(),
+                item.cache_type.clone(),
+                format!("{}, without IDE settings and history", item.reason),
+            )
+            .with_metadata(metadata.as_ref());
+            split_around(part, kept)
+        })
+        .collect();
+    parts.sort_by(|a, b| a.path.cmp(&b.path));
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ide_caches_keep_settings_and_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let home = temp_dir.path();
+        let code = home.join(".config/Code");
+        for dir in [
+            "CachedExtensionVSIXs",
+            "Code Cache/js",
+            "User/History",
+            "User/workspaceStorage/4f2c81a0",
+        ] {
+            fs::create_dir_all(code.join(dir)).unwrap();
+        }
+        let idea = home.join(".cache/JetBrains/IntelliJIdea2024.1");
+        for dir in ["caches", "index", "LocalHistory"] {
+            fs::create_dir_all(idea.join(dir)).unwrap();
+        }
+        fs::create_dir_all(home.join(".cache/pip")).unwrap();
+
+        let config = Config::default();
+        let caches = detect(home, &config);
+        let mut found: Vec<_> = caches.items.iter().map(|i| i.path.clone()).collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                idea.join("caches"),
+                idea.join("index"),
+                code.join("CachedExtensionVSIXs"),
+                code.join("Code Cache"),
+            ]
+        );
+
+        // Old workspace storage is cleaned too
+        let day = Duration::from_secs(24 * 60 * 60);
+        let later = SystemTime::now() + 60 * day;
+        assert_eq!(vscode_caches(&code, 30 * day, later).len(), 3);
+
+        // The generic `.cache` item is split around the JetBrains directory
+        let mut items = vec![
+            CacheItem::new(
+                home.join(".cache"),
+                CacheType::DevelopmentCache,
+                "matches .cache",
+            )
+            .dated(),
+        ];
+        caches.merge_into(&mut items);
+        let mut paths: Vec<_> = items.iter().map(|i| i.path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                idea.join("caches"),
+                idea.join("index"),
+                home.join(".cache/pip"),
+                code.join("CachedExtensionVSIXs"),
+                code.join("Code Cache"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::{Config, JournalConfig};
+use crate::file_operations::format_bytes;
+use crate::owners::Owner;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where journald keeps a persistent journal, one directory per machine ID
+const JOURNAL_DIR: &str = "/var/log/journal";
+
+/// One file of a journal directory
+struct JournalFile {
+    path: PathBuf,
+    /// Rotated away, or set aside as corrupt, so journald no longer writes it
+    archived: bool,
+    modified: SystemTime,
+    /// Bytes taken on disk, which is what journald's limits count
+    disk_bytes: u64,
+}
+
+/// Find journal directories with archived files past `journal.vacuum_time`
+/// or `journal.vacuum_size`, when `/var/log/journal` lies below `scan_root`.
+/// Each is sized by what vacuuming it would free.
+pub fn detect(scan_root: &Path, config: &Config) -> Vec<CacheItem> {
+    let root = Path::new(JOURNAL_DIR);
+    if !root.starts_with(scan_root) || config.is_excluded_path(root) {
+        return Vec::new();
+    }
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<CacheItem> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && !path.is_symlink() && !config.is_excluded_path(path))
+        .filter_map(|dir| vacuumable(&dir, &config.journal, SystemTime::now()))
+        .collect();
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    items
+}
+
+/// The journal directory `dir` as an item, if vacuuming would remove anything
+fn vacuumable(dir: &Path, config: &JournalConfig, now: SystemTime) -> Option<CacheItem> {
+    let files = journal_files(dir).ok()?;
+    let total: u64 = files.iter().map(|file| file.disk_bytes).sum();
+    let removed = to_vacuum(&files, config, now);
+    if removed.is_empty() {
+        return None;
+    }
+
+    let metadata = fs::symlink_metadata(dir).ok()?;
+    Some(CacheItem {
+        size_bytes: Some(removed.iter().map(|file| file.disk_bytes).sum()),
+        file_count: Some(removed.len()),
+        last_modified: removed.iter().map(|file| file.modified).max(),
+        owner: Some(Owner::of(&metadata)),
+        ..CacheItem::new(
+            dir.to_path_buf(),
+            CacheType::Journal,
+            format!(
+                "journal takes {}; archived files past journal.vacuum_time or journal.vacuum_size",
+                format_bytes(total)
+            ),
+        )
+    })
+}
+
+/// Remove the archived files of the journal in `dir` that the vacuum limits
+/// select, returning the bytes freed
+pub fn vacuum(dir: &Path, config: &JournalConfig) -> io::Result<u64> {
+    let files = journal_files(dir)?;
+    let mut freed = 0;
+    for file in to_vacuum(&files, config, SystemTime::now()) {
+        match fs::remove_file(&file.path) {
+            Ok(()) => freed += file.disk_bytes,
+            // Rotated or vacuumed by journald in the meantime
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(freed)
+}
+
+/// The archived files journalctl's `--vacuum-time` and `--vacuum-size` would
+/// remove: oldest first, while they are too old or the journal too large.
+/// Files journald still writes to are never picked.
+fn to_vacuum<'a>(
+    files: &'a [JournalFile],
+    config: &JournalConfig,
+    now: SystemTime,
+) -> Vec<&'a JournalFile> {
+    let mut total: u64 = files.iter().map(|file| file.disk_bytes).sum();
+    let mut archived: Vec<&JournalFile> = files.iter().filter(|file| file.archived).collect();
+    archived.sort_by_key(|file| file.modified);
+
+    let mut removed = Vec::new();
+    for file in archived {
+        let too_old = !config.vacuum_time.is_zero()
+            && now
+                .duration_since(file.modified)
+                .is_ok_and(|age| age >= config.vacuum_time);
+        let too_large = config.vacuum_size > 0 && total > config.vacuum_size;
+        if !too_old && !too_large {
+            break;
+        }
+        total -= file.disk_bytes;
+        removed.push(file);
+    }
+    removed
+}
+
+/// The journal files directly inside `dir`
+fn journal_files(dir: &Path) -> io::Result<Vec<JournalFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let archived = match name {
+            _ if name.ends_with(".journal~") => true,
+            _ if name.ends_with(".journal") => name.contains('@'),
+            _ => continue,
+        };
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        files.push(JournalFile {
+            path: entry.path(),
+            archived,
+            modified: metadata.modified()?,
+            disk_bytes: metadata.blocks() * 512,
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_vacuum_keeps_active_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        for (name, age) in [
+            ("system.journal", 90),
+            ("system@0005-0001.journal", 60),
+            ("user-1000@0005-0002.journal", 10),
+            ("system@0005-0003.journal~", 2),
+            ("notes.txt", 90),
+        ] {
+            fs::write(dir.join(name), vec![1u8; 64 * 1024]).unwrap();
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.join(name))
+                .unwrap();
+            file.set_modified(now - age * day).unwrap();
+        }
+        let names = |config: &JournalConfig| -> Vec<String> {
+            to_vacuum(&journal_files(dir).unwrap(), config, now)
+                .iter()
+                .map(|file| {
+                    file.path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+
+        let by_age = JournalConfig {
+            vacuum_time: 30 * day,
+            vacuum_size: 0,
+        };
+        assert_eq!(names(&by_age), vec!["system@0005-0001.journal"]);
+
+        // Removing every archived file can't bring it below the limit, and
+        // the active journal is still kept
+        let by_size = JournalConfig {
+            vacuum_time: Duration::ZERO,
+            vacuum_size: 1,
+        };
+        assert_eq!(
+            names(&by_size),
+            vec![
+                "system@0005-0001.journal",
+                "user-1000@0005-0002.journal",
+                "system@0005-0003.journal~"
+            ]
+        );
+
+        vacuum(dir, &by_age).unwrap();
+        assert!(!dir.join("system@0005-0001.journal").exists());
+        assert!(dir.join("system.journal").exists());
+        assert!(dir.join("notes.txt").exists());
+    }
+}
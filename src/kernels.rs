@@ -0,0 +1,213 @@
+use crate::reclaim;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BOOT_ROOT: &str = "/boot";
+
+/// One directory per kernel that has modules installed
+const MODULES_ROOT: &str = "/lib/modules";
+
+const OSRELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+const DPKG_STATUS_PATH: &str = "/var/lib/dpkg/status";
+
+/// Debian package name prefixes that carry a kernel release in their name
+const DPKG_KERNEL_PACKAGES: &[&str] = &["linux-image-unsigned-", "linux-image-", "linux-modules-"];
+
+/// An initramfs image or module tree left behind by a kernel that is neither
+/// running nor installed. Only ever reported, never cleaned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelArtifact {
+    pub path: PathBuf,
+    /// Kernel release the artifact was built for, e.g. `6.8.0-31-generic`
+    pub kernel: String,
+    pub size_bytes: u64,
+}
+
+/// Find leftover kernel artifacts in `/boot` and `/lib/modules` when they lie
+/// below `scan_root`. These are only read, so `exclude_paths` (which covers
+/// both by default) doesn't hide them.
+///
+/// Nothing is reported when the installed kernels can't be told apart from
+/// leftovers, i.e. without a dpkg database or kernel images in the module
+/// trees as rpm and pacman install them.
+pub fn detect(scan_root: &Path) -> Vec<KernelArtifact> {
+    let boot = Path::new(BOOT_ROOT);
+    let modules = Path::new(MODULES_ROOT);
+    if !boot.starts_with(scan_root) && !modules.starts_with(scan_root) {
+        return Vec::new();
+    }
+
+    let Some(mut kept) = packaged_kernels(Path::new(DPKG_STATUS_PATH), modules) else {
+        return Vec::new();
+    };
+    if let Ok(running) = fs::read_to_string(OSRELEASE_PATH) {
+        kept.insert(running.trim().to_string());
+    }
+
+    let mut artifacts = Vec::new();
+    if boot.starts_with(scan_root) {
+        artifacts.extend(stale_initramfs(boot, &kept));
+    }
+    if modules.starts_with(scan_root) {
+        artifacts.extend(stale_module_trees(modules, &kept));
+    }
+    artifacts
+}
+
+/// Kernel releases the package manager knows about, or `None` when there is
+/// no way to tell
+fn packaged_kernels(dpkg_status: &Path, modules_root: &Path) -> Option<HashSet<String>> {
+    let dpkg = fs::read_to_string(dpkg_status).ok();
+    let mut kernels: HashSet<String> = dpkg.as_deref().map(dpkg_kernels).unwrap_or_default();
+
+    // rpm and pacman packages ship the kernel image inside its module tree
+    for tree in reclaim::subdirectories(modules_root) {
+        if tree.join("vmlinuz").is_file()
+            && let Some(release) = tree.file_name().and_then(|n| n.to_str())
+        {
+            kernels.insert(release.to_string());
+        }
+    }
+
+    (dpkg.is_some() || !kernels.is_empty()).then_some(kernels)
+}
+
+/// Releases of installed kernel packages in a dpkg status file
+fn dpkg_kernels(status: &str) -> HashSet<String> {
+    status
+        .split("\n\n")
+        .filter(|stanza| {
+            stanza
+                .lines()
+                .any(|line| line.starts_with("Status:") && line.ends_with(" installed"))
+        })
+        .filter_map(|stanza| {
+            let package = stanza
+                .lines()
+                .find_map(|line| line.strip_prefix("Package: "))?;
+            DPKG_KERNEL_PACKAGES
+                .iter()
+                .find_map(|prefix| package.strip_prefix(prefix))
+                // Meta-packages like `linux-image-generic` carry no release
+                .filter(|release| release.starts_with(|c: char| c.is_ascii_digit()))
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Initramfs images in `boot` for kernels not in `kept`
+fn stale_initramfs(boot: &Path, kept: &HashSet<String>) -> Vec<KernelArtifact> {
+    let Ok(entries) = fs::read_dir(boot) else {
+        return Vec::new();
+    };
+    let mut artifacts: Vec<KernelArtifact> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let kernel = initramfs_kernel(&name)?;
+            (!kept.contains(kernel)).then(|| KernelArtifact {
+                kernel: kernel.to_string(),
+                size_bytes: entry.metadata().map_or(0, |m| m.len()),
+                path: entry.path(),
+            })
+        })
+        .collect();
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    artifacts
+}
+
+/// Kernel release of an initramfs file name: Debian's `initrd.img-$REL` and
+/// dracut's `initramfs-$REL.img`, `initramfs-$REL-fallback.img` and
+/// `initramfs-${REL}kdump.img`
+fn initramfs_kernel(name: &str) -> Option<&str> {
+    if let Some(release) = name.strip_prefix("initrd.img-") {
+        return Some(release.strip_suffix(".old-dkms").unwrap_or(release));
+    }
+    let release = name.strip_prefix("initramfs-")?.strip_suffix(".img")?;
+    let release = release
+        .strip_suffix("-fallback")
+        .or_else(|| release.strip_suffix("kdump"))
+        .unwrap_or(release);
+    // Arch names images after the package (`initramfs-linux.img`), not a release
+    release
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then_some(release)
+}
+
+/// Module trees in `modules_root` for kernels not in `kept`
+fn stale_module_trees(modules_root: &Path, kept: &HashSet<String>) -> Vec<KernelArtifact> {
+    reclaim::subdirectories(modules_root)
+        .into_iter()
+        .filter_map(|tree| {
+            let kernel = tree.file_name()?.to_str()?.to_string();
+            (!kept.contains(&kernel)).then(|| KernelArtifact {
+                kernel,
+                size_bytes: reclaim::size_of(&tree).0,
+                path: tree,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_artifacts_of_removed_kernels() {
+        let temp_dir = TempDir::new().unwrap();
+        let boot = temp_dir.path().join("boot");
+        let modules = temp_dir.path().join("modules");
+        fs::create_dir_all(modules.join("6.8.0-45-generic/kernel")).unwrap();
+        fs::create_dir_all(modules.join("6.8.0-31-generic")).unwrap();
+        fs::write(modules.join("6.8.0-31-generic/modules.dep"), "1234").unwrap();
+        fs::create_dir_all(modules.join("6.9.1-200.fc40.x86_64")).unwrap();
+        fs::write(modules.join("6.9.1-200.fc40.x86_64/vmlinuz"), "").unwrap();
+        fs::create_dir_all(&boot).unwrap();
+        for name in [
+            "initrd.img-6.8.0-45-generic",
+            "initrd.img-6.8.0-31-generic",
+            "initramfs-6.9.1-200.fc40.x86_64.img",
+            "initramfs-linux.img",
+            "vmlinuz-6.8.0-31-generic",
+        ] {
+            fs::write(boot.join(name), "12").unwrap();
+        }
+        let status = temp_dir.path().join("status");
+        fs::write(
+            &status,
+            "Package: linux-image-6.8.0-45-generic\nStatus: install ok installed\n\n\
+             Package: linux-image-6.8.0-31-generic\nStatus: deinstall ok config-files\n\n\
+             Package: linux-image-generic\nStatus: install ok installed\n",
+        )
+        .unwrap();
+
+        let kept = packaged_kernels(&status, &modules).unwrap();
+        assert_eq!(
+            kept,
+            HashSet::from([
+                "6.8.0-45-generic".to_string(),
+                "6.9.1-200.fc40.x86_64".to_string(),
+            ])
+        );
+
+        let boot_artifacts = stale_initramfs(&boot, &kept);
+        assert_eq!(boot_artifacts.len(), 1);
+        assert_eq!(
+            boot_artifacts[0].path,
+            boot.join("initrd.img-6.8.0-31-generic")
+        );
+        assert_eq!(
+            stale_module_trees(&modules, &kept),
+            vec![KernelArtifact {
+                path: modules.join("6.8.0-31-generic"),
+                kernel: "6.8.0-31-generic".to_string(),
+                size_bytes: 4,
+            }]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::mounts::MountTable;
+use crate::reclaim;
+use crate::scan_report::ScanReport;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// Container logs, one `<namespace>_<name>_<uid>` directory per pod
+const POD_LOGS: &str = "/var/log/pods";
+
+/// Kubelet's per-pod state, one directory per pod uid
+const KUBELET_PODS: &str = "/var/lib/kubelet/pods";
+
+/// Volume plugin directory holding a pod's emptyDir volumes
+const EMPTY_DIR_PLUGIN: &str = "volumes/kubernetes.io~empty-dir";
+
+/// Find what deleted pods left behind on a Kubernetes node: their container
+/// logs and emptyDir volumes. A pod counts as deleted when the container
+/// runtime has no sandbox for it and its directory is older than
+/// `kubernetes.orphan_grace`. Without an answer from the runtime nothing is
+/// reported, since every pod would look deleted.
+///
+/// `exclude_paths` are honored as path prefixes, like the system reclaim.
+pub fn detect(scan_root: &Path, config: &Config, mounts: &MountTable) -> ScanReport<CacheItem> {
+    let mut report = ScanReport::new();
+    let logs = Path::new(POD_LOGS);
+    let pods = Path::new(KUBELET_PODS);
+    if !logs.starts_with(scan_root) && !pods.starts_with(scan_root) {
+        return report;
+    }
+
+    let live = match live_pod_uids(&config.kubernetes.cri_endpoint) {
+        Ok(live) => live,
+        Err(reason) => {
+            report.warnings.push(CleanerError::Skipped {
+                path: PathBuf::from(&config.kubernetes.cri_endpoint),
+                reason,
+            });
+            return report;
+        }
+    };
+
+    let now = SystemTime::now();
+    let grace = config.kubernetes.orphan_grace;
+    if logs.starts_with(scan_root) {
+        report.items.extend(dead_pod_logs(logs, &live, grace, now));
+    }
+    if pods.starts_with(scan_root) {
+        for volume in orphaned_empty_dirs(pods, &live, grace, now) {
+            // Memory-backed emptyDirs stay mounted until kubelet tears them down
+            if mounts.is_mount_point(&volume.path) {
+                report.warnings.push(CleanerError::Skipped {
+                    path: volume.path,
+                    reason: "emptyDir volume of a deleted pod is still mounted".to_string(),
+                });
+            } else {
+                report.items.push(volume);
+            }
+        }
+    }
+
+    report
+        .items
+        .retain(|item| !reclaim::is_excluded(config, &item.path));
+    report
+}
+
+/// Uids of the pods the container runtime at `endpoint` has a sandbox for,
+/// ready or not, as reported by `crictl`
+fn live_pod_uids(endpoint: &str) -> Result<HashSet<String>, String> {
+    let output = Command::new("crictl")
+        .args(["--runtime-endpoint", endpoint, "pods", "--output", "json"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("crictl could not be started: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "crictl failed ({}): {}",
+            output.status,
+            stderr.lines().last().unwrap_or_default().trim()
+        ));
+    }
+    parse_pod_uids(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Pod uids in the output of `crictl pods --output json`
+fn parse_pod_uids(json: &str) -> Result<HashSet<String>, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| format!("unreadable crictl output: {}", e))?;
+    let pods = value
+        .get("items")
+        .and_then(|items| items.as_array())
+        .ok_or("crictl output lists no pods")?;
+    Ok(pods
+        .iter()
+        .filter_map(|pod| pod.pointer("/metadata/uid")?.as_str())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Log directories in `logs` of pods not in `live`
+fn dead_pod_logs(
+    logs: &Path,
+    live: &HashSet<String>,
+    grace: Duration,
+    now: SystemTime,
+) -> Vec<CacheItem> {
+    reclaim::subdirectories(logs)
+        .into_iter()
+        .filter(|dir| {
+            dir.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.rsplit('_').next())
+                .is_some_and(|uid| !live.contains(uid))
+        })
+        .filter(|dir| is_past_grace(dir, grace, now))
+        .map(|dir| {
+            reclaim::sized(CacheItem::new(
+                dir,
+                CacheType::OrphanedPodData,
+                "container logs of a pod the runtime no longer runs",
+            ))
+        })
+        .collect()
+}
+
+/// emptyDir volumes in `pods` of pods not in `live`
+fn orphaned_empty_dirs(
+    pods: &Path,
+    live: &HashSet<String>,
+    grace: Duration,
+    now: SystemTime,
+) -> Vec<CacheItem> {
+    reclaim::subdirectories(pods)
+        .into_iter()
+        .filter(|pod| {
+            pod.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|uid| !live.contains(uid))
+        })
+        .filter(|pod| is_past_grace(pod, grace, now))
+        .flat_map(|pod| reclaim::subdirectories(&pod.join(EMPTY_DIR_PLUGIN)))
+        .map(|volume| {
+            reclaim::sized(CacheItem::new(
+                volume,
+                CacheType::OrphanedPodData,
+                "emptyDir volume of a pod the runtime no longer runs",
+            ))
+        })
+        .collect()
+}
+
+/// Whether `dir` was last changed at least `grace` before `now`, so a pod
+/// that is still being set up isn't mistaken for a deleted one
+fn is_past_grace(dir: &Path, grace: Duration, now: SystemTime) -> bool {
+    fs::symlink_metadata(dir)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| now.duration_since(modified).is_ok_and(|age| age >= grace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_leftovers_of_deleted_pods() {
+        let live = parse_pod_uids(
+            r#"{"items": [{"id": "9b1c", "metadata": {"name": "web-0",
+                "uid": "5a7e0c1e-live", "namespace": "default"}, "state": "SANDBOX_READY"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(live, HashSet::from(["5a7e0c1e-live".to_string()]));
+        assert!(parse_pod_uids("{}").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let logs = temp_dir.path().join("logs");
+        let pods = temp_dir.path().join("pods");
+        for dir in [
+            "logs/default_web-0_5a7e0c1e-live/nginx",
+            "logs/default_job-x_0d3f9b2a-gone/worker",
+            "pods/5a7e0c1e-live/volumes/kubernetes.io~empty-dir/scratch",
+            "pods/0d3f9b2a-gone/volumes/kubernetes.io~empty-dir/scratch",
+            "pods/0d3f9b2a-gone/volumes/kubernetes.io~secret/token",
+        ] {
+            fs::create_dir_all(temp_dir.path().join(dir)).unwrap();
+        }
+        fs::write(
+            logs.join("default_job-x_0d3f9b2a-gone/worker/0.log"),
+            "12345",
+        )
+        .unwrap();
+
+        let hour = Duration::from_secs(60 * 60);
+        let later = SystemTime::now() + 2 * hour;
+        let dead_logs = dead_pod_logs(&logs, &live, hour, later);
+        assert_eq!(dead_logs.len(), 1);
+        assert_eq!(dead_logs[0].path, logs.join("default_job-x_0d3f9b2a-gone"));
+        assert_eq!(dead_logs[0].size_bytes, Some(5));
+
+        let volumes = orphaned_empty_dirs(&pods, &live, hour, later);
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(
+            volumes[0].path,
+            pods.join("0d3f9b2a-gone/volumes/kubernetes.io~empty-dir/scratch")
+        );
+
+        // Pods younger than the grace period are left alone
+        assert!(dead_pod_logs(&logs, &live, 3 * hour, later).is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+//! Finds and cleans caches, build output and old logs.
+//!
+//! The `cleaner` binary is a thin wrapper over this library. Other tools can
+//! scan and clean through [`scan`] and [`clean`], which find and delete what
+//! the command line would, or use the public modules directly for logs, plans
+//! and reports. New detection sources implement [`Detector`] and are passed
+//! to [`scan_with`].
+
+pub mod ages;
+pub mod apps;
+pub mod audit;
+pub mod cache_detector;
+pub mod cgroup;
+pub mod checkpoint;
+pub mod cli;
+pub mod config;
+pub mod csv;
+pub mod detector;
+pub mod display;
+pub mod error;
+pub mod estimate;
+pub mod events;
+#[cfg(feature = "tui")]
+pub mod explorer;
+pub mod file_operations;
+pub mod growth;
+pub mod history;
+pub(crate) mod hooks;
+pub(crate) mod ide;
+pub(crate) mod journal;
+pub mod kernels;
+pub(crate) mod kubelet;
+pub mod lint;
+pub mod log_cleaner;
+pub mod mail;
+pub mod monitor;
+pub mod mounts;
+pub mod ncdu;
+pub(crate) mod ostree;
+pub mod output;
+pub mod owners;
+#[cfg(feature = "tui")]
+pub mod picker;
+pub mod plan;
+pub(crate) mod policy;
+pub mod power;
+pub(crate) mod printing;
+pub mod profiles;
+pub mod project;
+pub mod quota;
+pub mod rebuild;
+pub(crate) mod reclaim;
+pub mod redact;
+pub mod restore;
+pub mod result_file;
+pub mod savings;
+pub mod scan_report;
+pub mod selection;
+pub(crate) mod shred;
+pub mod size_cache;
+pub(crate) mod state;
+pub mod storage;
+pub mod temperature;
+pub mod throttle;
+pub(crate) mod trash;
+pub mod units;
+pub mod unlink;
+#[cfg(feature = "self-update")]
+pub mod update;
+pub(crate) mod vcs;
+pub mod walker;
+pub mod writers;
+
+pub use cache_detector::{CacheItem, CacheType};
+pub use config::Config;
+pub use detector::Detector;
+pub use error::CleanerError;
+pub use file_operations::OperationResult;
+pub use log_cleaner::LogFile;
+pub use mail::MailReport;
+pub use scan_report::ScanReport;
+
+use cache_detector::CacheDetector;
+use detector::Stage;
+use file_operations::FileOperations;
+use std::path::{Path, PathBuf};
+
+/// What [`scan`] looks for besides what the config enables
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub config: Config,
+    /// Find unreferenced flatpak objects and DKMS modules of removed kernels,
+    /// as `--system-reclaim` does; this needs root
+    pub system_reclaim: bool,
+    /// Find the leftovers of deleted pods, as `--kubernetes-node` does
+    pub kubernetes_node: bool,
+    /// User whose trash on other volumes is emptied; the effective user
+    /// when unset
+    pub trash_owner: Option<u32>,
+}
+
+impl ScanOptions {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            system_reclaim: false,
+            kubernetes_node: false,
+            trash_owner: None,
+        }
+    }
+}
+
+/// What one [`scan`] found
+#[derive(Debug, Default)]
+pub struct Findings {
+    pub cache_items: Vec<CacheItem>,
+    pub log_files: Vec<LogFile>,
+    /// Mail piling up; its dead queue files are among the cache items when
+    /// `mail.clean_dead_queue` is set
+    pub mail: MailReport,
+    /// Directories that couldn't be read and sources that failed
+    pub warnings: Vec<CleanerError>,
+}
+
+/// Find the cache items and old logs under `root` and measure the items,
+/// the way the command line does. Directories that could not be read are
+/// returned as warnings.
+pub fn scan(root: &Path, options: &ScanOptions) -> Result<Findings, CleanerError> {
+    scan_with(root, options, detector::builtin())
+}
+
+/// Like [`scan`], running `detectors` instead of the built-in ones; extend
+/// [`detector::builtin`] to add sources rather than replace them
+pub fn scan_with(
+    root: &Path,
+    options: &ScanOptions,
+    detectors: Vec<Box<dyn Detector>>,
+) -> Result<Findings, CleanerError> {
+    scan_sized(root, options, detectors, |items| {
+        cache_detector::calculate_sizes(items, &options.config)
+    })
+}
+
+/// Like [`scan_with`], measuring the items of the detectors before the
+/// [`Stage::Sized`] stage with `size`, which may estimate them or take them
+/// from a cache instead
+pub fn scan_sized(
+    root: &Path,
+    options: &ScanOptions,
+    detectors: Vec<Box<dyn Detector>>,
+    size: impl FnOnce(Vec<CacheItem>) -> Result<ScanReport<CacheItem>, CleanerError>,
+) -> Result<Findings, CleanerError> {
+    let (patterns, sources): (Vec<_>, Vec<_>) = detectors
+        .into_iter()
+        .partition(|d| d.stage() == Stage::Patterns);
+    let mut found =
+        CacheDetector::with_detectors(options.config.clone(), patterns).detect_cache_items(root)?;
+    for detector in sources.iter().filter(|d| d.stage() == Stage::Unsized) {
+        detector.merge(root, options, &mut found)?;
+    }
+
+    let sized = size(found.items)?;
+    found.items = sized.items;
+    found.warnings.extend(sized.warnings);
+    for detector in sources.iter().filter(|d| d.stage() == Stage::Sized) {
+        detector.merge(root, options, &mut found)?;
+    }
+
+    let mut logs = ScanReport::new();
+    for detector in &sources {
+        logs.merge(detector.scan_logs(root, options)?);
+    }
+    found.warnings.extend(logs.warnings);
+
+    // Compared where they really are, nothing found by two sources may be
+    // cleaned twice or while an item holding it is being deleted; logs of
+    // deleted pods go with their pod's log directory
+    let cache_items = cache_detector::top_level(found.items);
+    let log_files = cache_detector::logs_outside(logs.items, &cache_items);
+    Ok(Findings {
+        cache_items,
+        log_files,
+        mail: mail::detect(root, &options.config),
+        warnings: found.warnings,
+    })
+}
+
+/// How [`clean`] deletes
+#[derive(Debug, Clone)]
+pub struct CleanOptions {
+    pub config: Config,
+    /// Directory the items were found in; it is never deleted itself
+    pub scan_root: PathBuf,
+    /// Only report what would be deleted
+    pub dry_run: bool,
+}
+
+impl CleanOptions {
+    pub fn new(config: Config, scan_root: impl Into<PathBuf>) -> Self {
+        Self {
+            dry_run: config.safety.dry_run,
+            config,
+            scan_root: scan_root.into(),
+        }
+    }
+}
+
+/// Delete `items` behind the same safety checks, hooks and policy as the
+/// command line, with one result per item. Items needing a typed
+/// confirmation are refused, as there is nobody to ask.
+pub fn clean(
+    items: &[CacheItem],
+    options: &CleanOptions,
+) -> Result<Vec<OperationResult>, CleanerError> {
+    FileOperations::new(options.dry_run, &options.config, &options.scan_root)
+        .delete_cache_items(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_and_clean() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR")).join("target");
+        fs::create_dir_all(&base).unwrap();
+        let temp_dir = TempDir::new_in(&base).unwrap();
+        let theme = temp_dir.path().join(".local/share/icons/hicolor");
+        fs::create_dir_all(&theme).unwrap();
+        let cache = theme.join("icon-theme.cache");
+        fs::write(&cache, vec![0u8; 4096]).unwrap();
+
+        let config = Config::default();
+        let findings = scan(temp_dir.path(), &ScanOptions::new(config.clone())).unwrap();
+        let item = findings
+            .cache_items
+            .iter()
+            .find(|i| i.path == cache)
+            .unwrap();
+        assert_eq!(item.size_bytes, Some(4096));
+
+        let mut options = CleanOptions::new(config, temp_dir.path());
+        options.dry_run = true;
+        let results = clean(&findings.cache_items, &options).unwrap();
+        assert!(results.iter().all(|r| r.success));
+        assert!(cache.exists());
+
+        options.dry_run = false;
+        clean(&findings.cache_items, &options).unwrap();
+        assert!(!cache.exists());
+    }
+}
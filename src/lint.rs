@@ -0,0 +1,257 @@
+use crate::config::{self, Config};
+use std::fmt;
+use std::path::Path;
+
+/// Patterns that would match (nearly) everything
+const DANGEROUS_PATTERNS: &[&str] = &["", "/", "/*", "*", "**", "*/*", "~", "~/", "~/*"];
+
+/// How bad a lint finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Danger,
+}
+
+/// One problem found in the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub severity: Severity,
+    /// Config field holding the offending entry
+    pub field: &'static str,
+    pub entry: String,
+    /// 1-based line of the entry in the config file, when it was found there
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} '{}': {}", self.field, self.entry, self.message)
+    }
+}
+
+/// Every pattern list that selects paths for deletion
+fn pattern_lists(config: &Config) -> Vec<(&'static str, &[String])> {
+    let patterns = &config.cache_patterns;
+    vec![
+        ("user_cache_dirs", &patterns.user_cache_dirs),
+        ("system_cache_dirs", &patterns.system_cache_dirs),
+        ("app_cache_patterns", &patterns.app_cache_patterns),
+        ("package_manager_caches", &patterns.package_manager_caches),
+        ("dev_tool_caches", &patterns.dev_tool_caches),
+        ("browser_caches", &patterns.browser_caches),
+        ("temp_patterns", &patterns.temp_patterns),
+        ("build_artifacts", &patterns.build_artifacts),
+        ("regenerating_caches", &patterns.regenerating_caches),
+        ("log_patterns", &config.log_cleanup.log_patterns),
+    ]
+}
+
+/// Collects findings, pointing each at the right line of the config source
+struct Findings<'a> {
+    source: &'a str,
+    /// Entries visited so far, to tell repeated entries apart
+    seen: Vec<&'a str>,
+    issues: Vec<LintIssue>,
+}
+
+impl<'a> Findings<'a> {
+    fn visit(&mut self, entry: &'a str) {
+        self.seen.push(entry);
+    }
+
+    /// Record an issue with the most recently visited occurrence of `entry`
+    fn report(&mut self, severity: Severity, field: &'static str, entry: &str, message: String) {
+        let occurrence = self
+            .seen
+            .iter()
+            .filter(|s| **s == entry)
+            .count()
+            .saturating_sub(1);
+        self.issues.push(LintIssue {
+            severity,
+            field,
+            entry: entry.to_string(),
+            line: line_of(self.source, entry, occurrence),
+            message,
+        });
+    }
+}
+
+/// Analyze `config` for dangerous, dead and redundant entries. `source` is the
+/// text of the config file, used to point at the offending lines.
+pub fn lint(config: &Config, source: &str) -> Vec<LintIssue> {
+    let mut findings = Findings {
+        source,
+        seen: Vec::new(),
+        issues: Vec::new(),
+    };
+    let lists = pattern_lists(config);
+    let excludes = &config.safety.exclude_paths;
+
+    for (list_index, (field, patterns)) in lists.iter().enumerate() {
+        for (index, pattern) in patterns.iter().enumerate() {
+            findings.visit(pattern);
+            if DANGEROUS_PATTERNS.contains(&pattern.trim()) {
+                findings.report(
+                    Severity::Danger,
+                    field,
+                    pattern,
+                    "matches everything below the scan root".to_string(),
+                );
+                continue;
+            }
+
+            // Exclusions are substring matches, so a pattern containing one
+            // can never select anything
+            if let Some(exclude) = excludes.iter().find(|e| pattern.contains(e.as_str())) {
+                findings.report(
+                    Severity::Warning,
+                    field,
+                    pattern,
+                    format!("is shadowed by exclusion '{}' and never matches", exclude),
+                );
+            }
+
+            if patterns[..index].contains(pattern) {
+                findings.report(
+                    Severity::Warning,
+                    field,
+                    pattern,
+                    "is listed twice".to_string(),
+                );
+            } else if let Some(other) = covering_pattern(patterns, pattern) {
+                findings.report(
+                    Severity::Warning,
+                    field,
+                    pattern,
+                    format!("is entirely covered by '{}' in the same list", other),
+                );
+            } else if let Some((other_field, _)) = lists[..list_index]
+                .iter()
+                .find(|(_, other)| other.contains(pattern))
+            {
+                findings.report(
+                    Severity::Warning,
+                    field,
+                    pattern,
+                    format!("duplicates the same entry in {}", other_field),
+                );
+            }
+        }
+    }
+
+    for exclude in excludes {
+        findings.visit(exclude);
+        if exclude.trim().is_empty() || exclude.trim() == "/" {
+            findings.report(
+                Severity::Danger,
+                "exclude_paths",
+                exclude,
+                "excludes every path, nothing will ever be cleaned".to_string(),
+            );
+        } else if let Some(other) = excludes
+            .iter()
+            .find(|other| *other != exclude && exclude.contains(other.as_str()))
+        {
+            findings.report(
+                Severity::Warning,
+                "exclude_paths",
+                exclude,
+                format!("is already covered by exclusion '{}'", other),
+            );
+        } else if names_missing_path(exclude) {
+            findings.report(
+                Severity::Warning,
+                "exclude_paths",
+                exclude,
+                "names a path that does not exist and matches nothing".to_string(),
+            );
+        }
+    }
+
+    findings.issues
+}
+
+/// A wildcard pattern in the same list that already matches the literal `pattern`
+fn covering_pattern<'a>(patterns: &'a [String], pattern: &str) -> Option<&'a str> {
+    if pattern.contains(['*', '?', '[']) {
+        return None;
+    }
+    patterns
+        .iter()
+        .find(|other| {
+            other.contains('*') && glob::Pattern::new(other).is_ok_and(|glob| glob.matches(pattern))
+        })
+        .map(String::as_str)
+}
+
+/// Whether an exclusion spells out a concrete path (home-relative or with more
+/// than one component) that does not exist. Single components such as `/.git`
+/// are matched anywhere and are not checked.
+fn names_missing_path(exclude: &str) -> bool {
+    let exclude = exclude.strip_prefix(config::ANCHOR).unwrap_or(exclude);
+    let is_concrete = exclude.starts_with('~')
+        || (exclude.starts_with('/') && exclude.trim_end_matches('/').matches('/').count() > 1);
+    is_concrete
+        && !exclude.contains(['*', '?', '['])
+        && !Path::new(&Config::expand_home(exclude)).exists()
+}
+
+/// Line of the `occurrence`-th (0-based) quoted appearance of `entry` in the
+/// config source
+fn line_of(source: &str, entry: &str, occurrence: usize) -> Option<usize> {
+    let quoted = format!("\"{}\"", entry);
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.contains(&quoted))
+        .nth(occurrence)
+        .map(|(index, _)| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_findings() {
+        let mut config = Config::default();
+        config.cache_patterns.user_cache_dirs = vec![
+            ".cache".to_string(),
+            ".cache".to_string(),
+            ".ssh/cache".to_string(),
+        ];
+        config.cache_patterns.dev_tool_caches = vec!["*".to_string()];
+        config.cache_patterns.temp_patterns = vec!["tmp/*".to_string(), "tmp/build".to_string()];
+        config.cache_patterns.browser_caches = vec![".cache".to_string()];
+        config.safety.exclude_paths = vec![
+            ".ssh".to_string(),
+            "/home/nobody/.ssh".to_string(),
+            "/no/such/place".to_string(),
+        ];
+        let source =
+            "user_cache_dirs = [\n    \".cache\",\n    \".cache\",\n]\ndev_tool_caches = [\"*\"]\n";
+
+        let issues = lint(&config, source);
+        let find = |entry: &str| issues.iter().find(|i| i.entry == entry).unwrap();
+
+        assert_eq!(find("*").severity, Severity::Danger);
+        assert_eq!(find("*").line, Some(5));
+        assert!(find(".cache").message.contains("listed twice"));
+        assert_eq!(find(".cache").line, Some(3));
+        assert!(
+            issues
+                .iter()
+                .any(|i| i.field == "browser_caches" && i.message.contains("in user_cache_dirs"))
+        );
+        assert!(
+            find(".ssh/cache")
+                .message
+                .contains("shadowed by exclusion '.ssh'")
+        );
+        assert!(find("tmp/build").message.contains("covered by 'tmp/*'"));
+        assert!(find("/home/nobody/.ssh").message.contains("covered by"));
+        assert!(find("/no/such/place").message.contains("does not exist"));
+    }
+}
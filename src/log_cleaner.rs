@@ -1,7 +1,17 @@
 use crate::config::Config;
-use jwalk::WalkDir;
+use crate::error::CleanerError;
+use crate::owners::Owner;
+use crate::scan_report::ScanReport;
+use crate::units;
+use crate::vcs;
+use crate::walker::ScanWalker;
+use crate::writers::{Writer, WriterTable};
 use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::{self, Read};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 
@@ -13,10 +23,23 @@ pub struct LogFile {
     pub last_modified: SystemTime,
     pub age: Duration,
     pub log_type: LogType,
+    /// How sure the classifier is of `log_type`
+    pub confidence: Confidence,
+    pub owner: Option<Owner>,
+    /// A running process writing to the file, if any
+    pub writer: Option<Writer>,
+    /// Device and inode of the file when it was found, so truncating it
+    /// can tell it wasn't replaced meanwhile
+    pub file_id: Option<(u64, u64)>,
+    /// Why the file was picked up, e.g. the age rule it exceeds
+    pub reason: String,
 }
 
 /// Types of log files
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
 pub enum LogType {
     System,
     Application,
@@ -41,8 +64,95 @@ impl LogType {
             LogType::Developer => "Development log",
         }
     }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "system" => Ok(LogType::System),
+            "application" => Ok(LogType::Application),
+            "user" => Ok(LogType::User),
+            "debug" => Ok(LogType::Debug),
+            "error" => Ok(LogType::Error),
+            "access" => Ok(LogType::Access),
+            "security" => Ok(LogType::Security),
+            "developer" => Ok(LogType::Developer),
+            _ => Err(format!(
+                "unknown log type '{}' (system, application, user, debug, error, access, \
+                 security or developer)",
+                name
+            )),
+        }
+    }
+}
+
+/// How sure the classifier is of a log's type
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    /// Little or conflicting evidence; the type is mostly a guess
+    #[default]
+    Low,
+    Medium,
+    /// Several signals agree, e.g. the name and the content
+    High,
+}
+
+impl Confidence {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
 }
 
+/// Line format recognised in the first lines of a log
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LogFormat {
+    /// Syslog lines; `security` when most come from login and privilege tools
+    Syslog { security: bool },
+    /// Apache/NCSA common or combined log format
+    CommonLog,
+    /// One JSON object per line, with the most common `level` if there is one
+    JsonLines { level: Option<LogType> },
+}
+
+/// Bytes read from the start of a log to recognise its format
+const SNIFF_BYTES: u64 = 4096;
+
+/// Lines of the start of a log that are looked at
+const SNIFF_LINES: usize = 5;
+
+/// Programs whose syslog lines make a log a security log
+const SECURITY_PROGRAMS: &[&str] = &[
+    "sshd",
+    "sudo",
+    "su",
+    "login",
+    "passwd",
+    "useradd",
+    "usermod",
+    "groupadd",
+    "polkitd",
+    "auditd",
+    "systemd-logind",
+    "gdm-password",
+];
+
+/// Types in the order ties between equal scores are resolved
+const CLASSIFY_ORDER: [LogType; 8] = [
+    LogType::System,
+    LogType::Security,
+    LogType::Error,
+    LogType::Debug,
+    LogType::Access,
+    LogType::User,
+    LogType::Developer,
+    LogType::Application,
+];
+
 /// Log file detection and cleanup engine
 pub struct LogCleaner {
     config: Config,
@@ -57,28 +167,81 @@ impl LogCleaner {
     pub fn find_old_log_files<P: AsRef<Path>>(
         &self,
         root: P,
-    ) -> Result<Vec<LogFile>, Box<dyn std::error::Error>> {
+    ) -> Result<ScanReport<LogFile>, CleanerError> {
         if !self.config.log_cleanup.enabled {
-            return Ok(Vec::new());
+            return Ok(ScanReport::new());
         }
 
         let root_path = root.as_ref();
         let now = SystemTime::now();
         let age_threshold = self.config.log_age_threshold();
-        let mut log_files = Vec::new();
+        let mut report = ScanReport::new();
 
         // Search in configured log patterns
         for pattern in &self.config.log_cleanup.log_patterns {
-            log_files.extend(self.scan_log_pattern(pattern, now, age_threshold)?);
+            report.merge(self.scan_log_pattern(pattern, now, age_threshold)?);
         }
 
         // Scan the root directory if it's not covered by patterns
         if !self.is_path_covered_by_patterns(root_path) {
-            log_files.extend(self.scan_directory_for_logs(root_path, now, age_threshold)?);
+            report.merge(self.scan_directory_for_logs(root_path, now, age_threshold)?);
+        }
+
+        // Reflogs and other version control internals are history, not logs
+        report.items.retain(|log| !vcs::is_in_metadata(&log.path));
+
+        // Logs whose type is too uncertain are kept and reported
+        let min_confidence = self.config.log_cleanup.min_confidence;
+        let (certain, uncertain): (Vec<_>, Vec<_>) = report
+            .items
+            .into_iter()
+            .partition(|log| log.confidence >= min_confidence);
+        report.items = certain;
+        report
+            .warnings
+            .extend(uncertain.into_iter().map(|log| CleanerError::Skipped {
+                reason: format!(
+                    "{} kept as its type is uncertain ({} confidence)",
+                    log.log_type.description(),
+                    log.confidence.label()
+                ),
+                path: log.path,
+            }));
+
+        // Logs still inside their minimum retention are kept and reported
+        let (eligible, retained): (Vec<_>, Vec<_>) = report.items.into_iter().partition(|log| {
+            self.config
+                .log_retention_floor(&log.log_type, log.confidence)
+                .is_none_or(|floor| log.age >= floor)
+        });
+        report.items = eligible;
+        report.warnings.extend(retained.into_iter().map(|log| {
+            let floor = self
+                .config
+                .log_retention_floor(&log.log_type, log.confidence)
+                .unwrap_or_default();
+            CleanerError::Skipped {
+                reason: format!(
+                    "{} kept for its minimum retention of {}",
+                    log.log_type.description(),
+                    units::format_duration(floor)
+                ),
+                path: log.path,
+            }
+        }));
+
+        // Logs still being written are emptied or kept rather than deleted
+        if !report.items.is_empty() {
+            let writers = WriterTable::load();
+            for log in &mut report.items {
+                log.writer = writers.writer_of(&log.path);
+            }
         }
 
         // Filter and sort
-        self.filter_and_sort_logs(log_files)
+        report.items = self.filter_and_sort_logs(report.items)?;
+        report.dedup_warnings();
+        Ok(report)
     }
 
     /// Scan a specific pattern for log files
@@ -87,29 +250,24 @@ impl LogCleaner {
         pattern: &str,
         now: SystemTime,
         age_threshold: Duration,
-    ) -> Result<Vec<LogFile>, Box<dyn std::error::Error>> {
-        let mut logs = Vec::new();
+    ) -> Result<ScanReport<LogFile>, CleanerError> {
+        let mut report = ScanReport::new();
 
         // Expand ~ to home directory
-        let expanded_pattern = if pattern.starts_with('~') {
-            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-            pattern.replacen('~', &home, 1)
-        } else {
-            pattern.to_string()
-        };
+        let expanded_pattern = Config::expand_home(pattern);
 
         // Handle glob patterns
         if expanded_pattern.contains('*') {
-            logs.extend(self.scan_glob_pattern(&expanded_pattern, now, age_threshold)?);
+            report.merge(self.scan_glob_pattern(&expanded_pattern, now, age_threshold)?);
         } else {
             // Direct directory scan
             let path = PathBuf::from(expanded_pattern);
             if path.exists() && path.is_dir() {
-                logs.extend(self.scan_directory_for_logs(&path, now, age_threshold)?);
+                report.merge(self.scan_directory_for_logs(&path, now, age_threshold)?);
             }
         }
 
-        Ok(logs)
+        Ok(report)
     }
 
     /// Scan using glob patterns
@@ -118,28 +276,45 @@ impl LogCleaner {
         pattern: &str,
         now: SystemTime,
         age_threshold: Duration,
-    ) -> Result<Vec<LogFile>, Box<dyn std::error::Error>> {
+    ) -> Result<ScanReport<LogFile>, CleanerError> {
         use glob::glob;
-        let mut logs = Vec::new();
+        let mut report = ScanReport::new();
+
+        let paths = match glob(pattern) {
+            Ok(paths) => paths,
+            Err(e) => {
+                report.warnings.push(CleanerError::Pattern {
+                    pattern: pattern.to_string(),
+                    message: e.to_string(),
+                });
+                return Ok(report);
+            }
+        };
 
-        for entry in glob(pattern)? {
+        for entry in paths {
             match entry {
                 Ok(path) => {
+                    if self.config.performance.skip_symlinks && path.is_symlink() {
+                        continue;
+                    }
+
                     if path.is_file() {
                         if let Some(log_file) = self.check_log_file(&path, now, age_threshold)? {
-                            logs.push(log_file);
+                            report.items.push(log_file);
                         }
                     } else if path.is_dir() {
-                        logs.extend(self.scan_directory_for_logs(&path, now, age_threshold)?);
+                        report.merge(self.scan_directory_for_logs(&path, now, age_threshold)?);
                     }
                 }
                 Err(e) => {
-                    eprintln!("Warning: Error processing glob pattern {}: {}", pattern, e);
+                    report
+                        .warnings
+                        .push(CleanerError::from_io(e.path(), e.error()));
                 }
             }
         }
 
-        Ok(logs)
+        Ok(report)
     }
 
     /// Scan a directory for log files using parallel processing
@@ -148,59 +323,26 @@ impl LogCleaner {
         dir: &Path,
         now: SystemTime,
         age_threshold: Duration,
-    ) -> Result<Vec<LogFile>, Box<dyn std::error::Error>> {
-        if self.config.is_excluded_path(dir) {
-            return Ok(Vec::new());
-        }
-
-        // Configure parallel walking with jwalk
-        let max_threads = self
-            .config
-            .performance
-            .max_threads
-            .unwrap_or(rayon::current_num_threads());
-        let parallelism = if max_threads == 1 {
-            jwalk::Parallelism::Serial
-        } else {
-            jwalk::Parallelism::RayonNewPool(max_threads)
-        };
-
-        // Use parallel directory traversal with jwalk
-        let entries: Result<Vec<_>, _> = WalkDir::new(dir)
-            .parallelism(parallelism)
-            .max_depth(self.config.performance.max_depth.unwrap_or(10))
-            .follow_links(!self.config.performance.skip_symlinks)
-            .into_iter()
-            .filter_map(|entry_result| match entry_result {
-                Ok(entry) => {
-                    if entry.file_type().is_file() {
-                        Some(Ok(entry))
-                    } else {
-                        None
-                    }
-                }
-                Err(e) => Some(Err(e)),
-            })
+    ) -> Result<ScanReport<LogFile>, CleanerError> {
+        let mut report = ScanReport::new();
+
+        // Keep files only; unreadable directories are recorded as warnings
+        let entries: Vec<_> = ScanWalker::new(&self.config)
+            .walk(dir)
+            .entries(&mut report.warnings)
+            .filter(|entry| entry.file_type().is_file())
             .collect();
 
-        let entries = entries?;
-
         // Use rayon for parallel processing of file classification
-        let logs: Result<Vec<_>, _> = entries
+        report.items = entries
             .into_par_iter()
-            .filter_map(
-                |entry| match self.check_log_file(&entry.path(), now, age_threshold) {
-                    Ok(Some(log_file)) => Some(Ok(log_file)),
-                    Ok(None) => None,
-                    Err(e) => Some(Err(format!("Error checking log file: {}", e))),
-                },
-            )
-            .collect();
+            .filter_map(|entry| {
+                self.check_log_file(&entry.path(), now, age_threshold)
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        match logs {
-            Ok(log_files) => Ok(log_files),
-            Err(e) => Err(e.into()),
-        }
+        Ok(report)
     }
 
     /// Check if a file is a log file and meets age criteria
@@ -209,7 +351,7 @@ impl LogCleaner {
         path: &Path,
         now: SystemTime,
         age_threshold: Duration,
-    ) -> Result<Option<LogFile>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<LogFile>, CleanerError> {
         // Check if it's a log file by extension
         if !self.is_log_file(path) {
             return Ok(None);
@@ -227,12 +369,14 @@ impl LogCleaner {
         };
 
         // Check minimum size
-        if metadata.len() < self.config.log_cleanup.min_size_bytes {
+        if metadata.len() < self.config.log_cleanup.min_size {
             return Ok(None);
         }
 
         // Check age
-        let modified = metadata.modified()?;
+        let modified = metadata
+            .modified()
+            .map_err(|e| CleanerError::from_io(path, &e))?;
         let age = now
             .duration_since(modified)
             .unwrap_or(Duration::from_secs(0));
@@ -242,7 +386,8 @@ impl LogCleaner {
         }
 
         // Classify log type
-        let log_type = self.classify_log_file(path);
+        let owner = Owner::of(&metadata);
+        let (log_type, confidence) = self.classify_log_file(path, Some(owner));
 
         Ok(Some(LogFile {
             path: path.to_path_buf(),
@@ -250,6 +395,14 @@ impl LogCleaner {
             last_modified: modified,
             age,
             log_type,
+            confidence,
+            owner: Some(owner),
+            writer: None,
+            file_id: Some((metadata.dev(), metadata.ino())),
+            reason: format!(
+                "log file older than the {} age threshold",
+                units::format_duration(age_threshold)
+            ),
         }))
     }
 
@@ -321,75 +474,10 @@ impl LogCleaner {
         false
     }
 
-    /// Classify the type of log file
-    fn classify_log_file(&self, path: &Path) -> LogType {
-        let path_str = path.to_string_lossy().to_lowercase();
-        let filename = path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_lowercase();
-
-        // System logs
-        if path_str.starts_with("/var/log")
-            || path_str.contains("/syslog")
-            || filename.contains("kern")
-            || filename.contains("auth")
-            || filename.contains("mail")
-            || filename.contains("cron")
-        {
-            return LogType::System;
-        }
-
-        // Security logs
-        if filename.contains("auth")
-            || filename.contains("security")
-            || filename.contains("audit")
-            || filename.contains("access")
-        {
-            return LogType::Security;
-        }
-
-        // Error logs
-        if filename.contains("error") || filename.contains("err") || filename.contains("exception")
-        {
-            return LogType::Error;
-        }
-
-        // Debug logs
-        if filename.contains("debug") || filename.contains("trace") || filename.contains("verbose")
-        {
-            return LogType::Debug;
-        }
-
-        // Access logs
-        if filename.contains("access") || filename.contains("request") || filename.contains("http")
-        {
-            return LogType::Access;
-        }
-
-        // User logs
-        if path_str.contains("/home/")
-            || path_str.contains("/.config/")
-            || path_str.contains("/.local/")
-        {
-            return LogType::User;
-        }
-
-        // Development logs
-        if path_str.contains("node_modules")
-            || path_str.contains("target/")
-            || path_str.contains("build/")
-            || path_str.contains(".git/")
-            || filename.contains("npm")
-            || filename.contains("cargo")
-            || filename.contains("gradle")
-        {
-            return LogType::Developer;
-        }
-
-        // Default to application log
-        LogType::Application
+    /// Classify the type of log file from its path, name, first lines and
+    /// owner, with how sure the classification is
+    fn classify_log_file(&self, path: &Path, owner: Option<Owner>) -> (LogType, Confidence) {
+        classify(path, read_head(path).as_deref(), owner)
     }
 
     /// Check if a path is covered by the configured log patterns
@@ -397,12 +485,7 @@ impl LogCleaner {
         let path_str = path.to_string_lossy();
 
         for pattern in &self.config.log_cleanup.log_patterns {
-            let expanded_pattern = if pattern.starts_with('~') {
-                let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-                pattern.replacen('~', &home, 1)
-            } else {
-                pattern.to_string()
-            };
+            let expanded_pattern = Config::expand_home(pattern);
 
             if path_str.starts_with(&expanded_pattern) {
                 return true;
@@ -413,10 +496,7 @@ impl LogCleaner {
     }
 
     /// Filter and sort log files
-    fn filter_and_sort_logs(
-        &self,
-        mut logs: Vec<LogFile>,
-    ) -> Result<Vec<LogFile>, Box<dyn std::error::Error>> {
+    fn filter_and_sort_logs(&self, mut logs: Vec<LogFile>) -> Result<Vec<LogFile>, CleanerError> {
         // Remove duplicates
         logs.sort_by(|a, b| a.path.cmp(&b.path));
         logs.dedup_by(|a, b| a.path == b.path);
@@ -432,6 +512,332 @@ impl LogCleaner {
     }
 }
 
+/// Score every log type on the evidence of `path`, the first lines of the
+/// file and its `owner`, and pick the best one. The confidence depends on
+/// how much evidence there is and how far the best type leads the next one.
+fn classify(path: &Path, head: Option<&str>, owner: Option<Owner>) -> (LogType, Confidence) {
+    let path_str = path.to_string_lossy().to_lowercase();
+    let filename = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let named = |words: &[&str]| words.iter().any(|w| filename.contains(w));
+
+    let mut scores = [0u32; CLASSIFY_ORDER.len()];
+    let mut add = |log_type: LogType, points: u32| {
+        let index = CLASSIFY_ORDER.iter().position(|t| *t == log_type);
+        scores[index.unwrap_or(0)] += points;
+    };
+
+    // Where the file lives
+    if path_str.starts_with("/var/log") {
+        add(LogType::System, 2);
+    }
+    if path_str.contains("/home/")
+        || path_str.contains("/.config/")
+        || path_str.contains("/.local/")
+    {
+        add(LogType::User, 2);
+    }
+    if path_str.contains("node_modules")
+        || path_str.contains("target/")
+        || path_str.contains("build/")
+    {
+        add(LogType::Developer, 3);
+    }
+
+    // What it is called
+    if named(&[
+        "syslog", "messages", "kern", "daemon", "cron", "mail", "dmesg", "boot",
+    ]) {
+        add(LogType::System, 3);
+    }
+    if named(&["auth", "secure", "security", "audit", "faillog"]) {
+        add(LogType::Security, 3);
+    }
+    if named(&["error", "err", "exception", "crash"]) {
+        add(LogType::Error, 3);
+    }
+    if named(&["debug", "trace", "verbose"]) {
+        add(LogType::Debug, 3);
+    }
+    if named(&["access", "request", "http"]) {
+        add(LogType::Access, 3);
+    }
+    if named(&["npm", "cargo", "gradle", "yarn", "pip"]) {
+        add(LogType::Developer, 3);
+    }
+
+    // What it contains weighs most, as names and places are only conventions
+    match head.and_then(sniff_format) {
+        Some(LogFormat::Syslog { security: true }) => add(LogType::Security, 4),
+        Some(LogFormat::Syslog { security: false }) => add(LogType::System, 4),
+        Some(LogFormat::CommonLog) => add(LogType::Access, 4),
+        Some(LogFormat::JsonLines { level }) => add(level.unwrap_or(LogType::Application), 4),
+        None => {}
+    }
+
+    // Who writes it: system accounts or a person
+    if let Some(owner) = owner {
+        add(
+            if owner.uid < 1000 {
+                LogType::System
+            } else {
+                LogType::User
+            },
+            1,
+        );
+    }
+
+    // Ties go to the type listed first in `CLASSIFY_ORDER`
+    let (best, &top) = scores
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, score)| **score)
+        .unwrap_or((CLASSIFY_ORDER.len() - 1, &0));
+    let runner_up = scores
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != best)
+        .map(|(_, score)| *score)
+        .max()
+        .unwrap_or(0);
+    let lead = top - runner_up;
+    let confidence = if top >= 6 && lead >= 3 {
+        Confidence::High
+    } else if top >= 3 && lead >= 2 {
+        Confidence::Medium
+    } else {
+        Confidence::Low
+    };
+    // The owner alone only backs up other evidence
+    let log_type = if top <= 1 {
+        LogType::Application
+    } else {
+        CLASSIFY_ORDER[best].clone()
+    };
+    (log_type, confidence)
+}
+
+/// Open `path` for reading without updating its access time, so sniffing
+/// content doesn't make old logs look recently used to `--not-accessed-for`
+/// and temperature. Only the owner or root may do so; other files are
+/// opened normally.
+fn open_noatime(path: &Path) -> io::Result<fs::File> {
+    match fs::File::options()
+        .read(true)
+        .custom_flags(libc::O_NOATIME)
+        .open(path)
+    {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => fs::File::open(path),
+        result => result,
+    }
+}
+
+/// The first complete lines of a text file; `None` for binary or
+/// unreadable files such as compressed rotations
+fn read_head(path: &Path) -> Option<String> {
+    let mut bytes = Vec::new();
+    open_noatime(path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    if bytes.contains(&0) {
+        return None;
+    }
+    let mut text = String::from_utf8_lossy(&bytes).into_owned();
+    // A cut-off last line would not parse
+    if bytes.len() as u64 == SNIFF_BYTES {
+        text.truncate(text.rfind('\n')?);
+    }
+    Some(text)
+}
+
+/// Signatures of binary formats that are no logs, whatever their name
+const BINARY_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"SQLite format 3\0", "an SQLite database"),
+    (b"\x89PNG", "a PNG image"),
+    (b"\xff\xd8\xff", "a JPEG image"),
+    (b"GIF8", "a GIF image"),
+    (b"RIFF", "a RIFF media file"),
+    (b"OggS", "an Ogg media file"),
+    (b"ID3", "an MP3 file"),
+    (b"%PDF", "a PDF document"),
+    (b"PK\x03\x04", "a ZIP archive"),
+    (b"7z\xbc\xaf\x27\x1c", "a 7-Zip archive"),
+    (b"\x7fELF", "an executable"),
+];
+
+/// Signatures and extensions of compressed files, as rotated logs are
+/// usually compressed
+const COMPRESSED_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x1f\x8b", "gz"),
+    (b"\xfd7zXZ\0", "xz"),
+    (b"\x28\xb5\x2f\xfd", "zst"),
+    (b"BZh", "bz2"),
+    (b"\x04\x22\x4d\x18", "lz4"),
+];
+
+/// What a file named like a log holds if its first bytes show it is no log:
+/// a known binary format, or binary data in general. Text, empty and
+/// unreadable files, and compressed files named as such, pass.
+pub fn non_log_content(path: &Path) -> Option<&'static str> {
+    let mut bytes = Vec::new();
+    open_noatime(path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    sniff_binary(path, &bytes)
+}
+
+fn sniff_binary(path: &Path, bytes: &[u8]) -> Option<&'static str> {
+    if let Some((_, format)) = BINARY_SIGNATURES
+        .iter()
+        .find(|(signature, _)| bytes.starts_with(signature))
+    {
+        return Some(format);
+    }
+    if bytes.get(4..8) == Some(b"ftyp") {
+        return Some("an MP4 or QuickTime video");
+    }
+
+    let extension = path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let compressed = COMPRESSED_SIGNATURES
+        .iter()
+        .find(|(signature, _)| bytes.starts_with(signature));
+    match compressed {
+        Some((_, ext)) if *ext == extension => return None,
+        Some(_) => return Some("compressed data under a log name"),
+        None => {}
+    }
+
+    // Tabs, line ends, form feeds and colour escapes are common in logs
+    let control = bytes
+        .iter()
+        .filter(|b| **b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    (bytes.contains(&0) || control * 10 > bytes.len()).then_some("binary data")
+}
+
+/// The format most of the first lines of a log are in, if any
+fn sniff_format(head: &str) -> Option<LogFormat> {
+    let lines: Vec<&str> = head
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .take(SNIFF_LINES)
+        .collect();
+    let majority = |count: usize| !lines.is_empty() && count * 2 >= lines.len().max(2);
+
+    let programs: Vec<Option<&str>> = lines.iter().map(|line| syslog_program(line)).collect();
+    let syslog = programs.iter().flatten().count();
+    if majority(syslog) {
+        let security = programs
+            .iter()
+            .flatten()
+            .filter(|program| SECURITY_PROGRAMS.contains(program))
+            .count();
+        return Some(LogFormat::Syslog {
+            security: security * 2 > syslog,
+        });
+    }
+
+    if majority(lines.iter().filter(|line| is_common_log(line)).count()) {
+        return Some(LogFormat::CommonLog);
+    }
+
+    let objects: Vec<serde_json::Map<String, serde_json::Value>> = lines
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    if majority(objects.len()) {
+        let levels: Vec<LogType> = objects
+            .iter()
+            .filter_map(|object| {
+                ["level", "severity", "lvl", "log.level"]
+                    .iter()
+                    .find_map(|key| object.get(*key)?.as_str())
+            })
+            .filter_map(|level| match level.to_lowercase().as_str() {
+                "error" | "err" | "fatal" | "critical" | "crit" | "panic" => Some(LogType::Error),
+                "debug" | "trace" => Some(LogType::Debug),
+                _ => None,
+            })
+            .collect();
+        let level = [LogType::Error, LogType::Debug]
+            .into_iter()
+            .find(|log_type| majority(levels.iter().filter(|l| *l == log_type).count()));
+        return Some(LogFormat::JsonLines { level });
+    }
+
+    None
+}
+
+/// The program of a syslog line: BSD style `Jan  2 03:04:05 host prog[1]:`,
+/// ISO timestamped `2024-01-02T03:04:05+00:00 host prog[1]:` or RFC 5424
+/// `<34>1 2024-01-02T03:04:05Z host prog 1 - -`
+fn syslog_program(line: &str) -> Option<&str> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let is_time = |t: &str| {
+        t.len() >= 8
+            && t.as_bytes()[2] == b':'
+            && t.as_bytes()[5] == b':'
+            && t.get(..2).is_some_and(|h| h.parse::<u8>().is_ok())
+    };
+    let is_iso = |t: &str| {
+        t.len() >= 19 && t.as_bytes()[4] == b'-' && t.as_bytes()[10] == b'T' && is_time(&t[11..])
+    };
+
+    if let Some(rest) = line.strip_prefix('<') {
+        let (priority, rest) = rest.split_once('>')?;
+        priority.parse::<u8>().ok()?;
+        let mut fields = rest.split_whitespace();
+        let (_version, timestamp, _host) = (fields.next()?, fields.next()?, fields.next()?);
+        return (is_iso(timestamp) || timestamp == "-").then_some(fields.next()?);
+    }
+
+    let mut fields = line.split_whitespace();
+    let first = fields.next()?;
+    if MONTHS.contains(&first) {
+        fields.next()?.parse::<u8>().ok()?;
+        if !is_time(fields.next()?) {
+            return None;
+        }
+    } else if !is_iso(first) {
+        return None;
+    }
+    let _host = fields.next()?;
+    let tag = fields.next()?.strip_suffix(':')?;
+    Some(tag.split('[').next().unwrap_or(tag))
+}
+
+/// Whether a line is in the common log format:
+/// `127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.0" 200 2326`
+fn is_common_log(line: &str) -> bool {
+    let Some((client, rest)) = line.split_once(" [") else {
+        return false;
+    };
+    let Some((timestamp, request)) = rest.split_once("] \"") else {
+        return false;
+    };
+    let method = request.split(' ').next().unwrap_or("");
+    client.split(' ').count() == 3
+        && timestamp.matches('/').count() == 2
+        && timestamp.contains(':')
+        && !method.is_empty()
+        && method.bytes().all(|b| b.is_ascii_uppercase())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -460,23 +866,90 @@ mod tests {
         let cleaner = LogCleaner::new(config);
 
         assert_eq!(
-            cleaner.classify_log_file(Path::new("/var/log/syslog")),
+            cleaner
+                .classify_log_file(Path::new("/var/log/syslog"), None)
+                .0,
             LogType::System
         );
         assert_eq!(
-            cleaner.classify_log_file(Path::new("error.log")),
+            cleaner.classify_log_file(Path::new("error.log"), None).0,
             LogType::Error
         );
         assert_eq!(
-            cleaner.classify_log_file(Path::new("debug.log")),
+            cleaner.classify_log_file(Path::new("debug.log"), None).0,
             LogType::Debug
         );
         assert_eq!(
-            cleaner.classify_log_file(Path::new("/home/user/app.log")),
+            cleaner
+                .classify_log_file(Path::new("/home/user/app.log"), None)
+                .0,
             LogType::User
         );
     }
 
+    #[test]
+    fn test_classify_log_content() {
+        let syslog = "Jan  2 03:04:05 host sshd[812]: Accepted publickey for u\n\
+                      Jan  2 03:04:06 host sudo: u : TTY=pts/0 ; COMMAND=/bin/ls\n";
+        let access = "127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] \"GET / HTTP/1.0\" 200 2326\n";
+        let json = "{\"level\":\"error\",\"msg\":\"a\"}\n{\"level\":\"ERROR\",\"msg\":\"b\"}\n";
+        let root = Some(Owner { uid: 0, gid: 0 });
+
+        // Content outweighs a misleading name
+        let path = Path::new("/srv/app/output.log");
+        assert_eq!(
+            classify(path, Some(syslog), root),
+            (LogType::Security, Confidence::Medium)
+        );
+        assert_eq!(classify(path, Some(access), None).0, LogType::Access);
+        assert_eq!(classify(path, Some(json), None).0, LogType::Error);
+        assert_eq!(
+            classify(Path::new("/var/log/auth.log"), Some(syslog), root),
+            (LogType::Security, Confidence::High)
+        );
+
+        // Name and content agreeing is certain, a bare name is not
+        assert_eq!(
+            classify(Path::new("/srv/access.log"), Some(access), None).1,
+            Confidence::High
+        );
+        assert_eq!(
+            classify(Path::new("/srv/error.log"), Some("oops\n"), None).1,
+            Confidence::Medium
+        );
+        assert_eq!(
+            classify(path, Some("started\n"), None),
+            (LogType::Application, Confidence::Low)
+        );
+    }
+
+    #[test]
+    fn test_non_log_content() {
+        let log = Path::new("/srv/game/save.log");
+        assert_eq!(
+            sniff_binary(log, b"SQLite format 3\0\x10\0"),
+            Some("an SQLite database")
+        );
+        assert_eq!(
+            sniff_binary(log, b"\0\0\0\x18ftypmp42"),
+            Some("an MP4 or QuickTime video")
+        );
+        assert_eq!(
+            sniff_binary(log, b"\x01\x02\x03slot 1\x04"),
+            Some("binary data")
+        );
+        assert_eq!(
+            sniff_binary(log, b"\x1f\x8b\x08\0"),
+            Some("compressed data under a log name")
+        );
+        assert_eq!(
+            sniff_binary(Path::new("/var/log/syslog.2.gz"), b"\x1f\x8b\x08\0"),
+            None
+        );
+        assert_eq!(sniff_binary(log, b"\x1b[32mINFO\x1b[0m\tstarted\r\n"), None);
+        assert_eq!(sniff_binary(log, b""), None);
+    }
+
     #[test]
     fn test_log_detection() {
         let temp_dir = TempDir::new().unwrap();
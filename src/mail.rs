@@ -0,0 +1,225 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::owners::Owner;
+use crate::reclaim;
+use std::cmp::Reverse;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Local mailboxes, one file per user
+const SPOOL_ROOT: &str = "/var/mail";
+
+/// Postfix queue of messages waiting for another delivery attempt
+const POSTFIX_DEFERRED: &str = "/var/spool/postfix/deferred";
+
+/// A mailbox above `mail.spool_warn_size`. Only reported, never cleaned.
+#[derive(Debug, Clone)]
+pub struct MailSpool {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub last_modified: Option<SystemTime>,
+    pub owner: Option<Owner>,
+}
+
+/// One message file in the deferred queue
+#[derive(Debug, Clone)]
+struct QueueFile {
+    path: PathBuf,
+    size_bytes: u64,
+    modified: SystemTime,
+    owner: Owner,
+}
+
+/// The deferred queue of an MTA
+#[derive(Debug, Clone)]
+pub struct MailQueue {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub file_count: usize,
+    pub oldest: Option<SystemTime>,
+    /// Queue files untouched for `mail.dead_queue_age`
+    dead: Vec<QueueFile>,
+}
+
+impl MailQueue {
+    pub fn dead_count(&self) -> usize {
+        self.dead.len()
+    }
+
+    pub fn dead_bytes(&self) -> u64 {
+        self.dead.iter().map(|f| f.size_bytes).sum()
+    }
+}
+
+/// Mail that piles up unnoticed on servers
+#[derive(Debug, Clone, Default)]
+pub struct MailReport {
+    pub spools: Vec<MailSpool>,
+    pub queues: Vec<MailQueue>,
+}
+
+impl MailReport {
+    pub fn is_empty(&self) -> bool {
+        self.spools.is_empty() && self.queues.is_empty()
+    }
+
+    /// Dead queue files as cleanable items. They are deleted with
+    /// `postsuper`, which removes the defer logs along with them.
+    pub fn dead_queue_items(&self) -> Vec<CacheItem> {
+        self.queues
+            .iter()
+            .flat_map(|queue| &queue.dead)
+            .map(queue_item)
+            .collect()
+    }
+}
+
+/// Arguments of the `postsuper` command that deletes the deferred message
+/// whose queue file is `path`; Postfix names queue files by their queue id
+pub fn postsuper_args(path: &Path) -> Vec<OsString> {
+    let id = path.file_name().unwrap_or_default();
+    vec!["-d".into(), id.into(), "deferred".into()]
+}
+
+/// Delete the deferred message whose queue file is `path` through Postfix
+pub fn delete_deferred(path: &Path) -> io::Result<()> {
+    reclaim::run_tool("postsuper", &postsuper_args(path))
+}
+
+/// Find oversized mail spools and the deferred Postfix queue when they lie
+/// below `scan_root`
+pub fn detect(scan_root: &Path, config: &Config) -> MailReport {
+    let applies =
+        |location: &Path| location.starts_with(scan_root) && !config.is_excluded_path(location);
+
+    let mut report = MailReport::default();
+    let spools = Path::new(SPOOL_ROOT);
+    if applies(spools) && config.mail.spool_warn_size > 0 {
+        report.spools = oversized_spools(spools, config.mail.spool_warn_size);
+    }
+    let deferred = Path::new(POSTFIX_DEFERRED);
+    if applies(deferred)
+        && let Some(queue) = read_queue(deferred, config.mail.dead_queue_age, SystemTime::now())
+    {
+        report.queues.push(queue);
+    }
+    report
+}
+
+/// Mailboxes in `spool_root` larger than `warn_size`, largest first
+fn oversized_spools(spool_root: &Path, warn_size: u64) -> Vec<MailSpool> {
+    let Ok(entries) = fs::read_dir(spool_root) else {
+        return Vec::new();
+    };
+    let mut spools: Vec<MailSpool> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            (metadata.is_file() && metadata.len() > warn_size).then(|| MailSpool {
+                path: entry.path(),
+                size_bytes: metadata.len(),
+                last_modified: metadata.modified().ok(),
+                owner: Some(Owner::of(&metadata)),
+            })
+        })
+        .collect();
+    spools.sort_by_key(|s| Reverse(s.size_bytes));
+    spools
+}
+
+/// Size and age of the queue at `dir`, `None` when it's empty or unreadable
+fn read_queue(dir: &Path, dead_age: Duration, now: SystemTime) -> Option<MailQueue> {
+    let mut files = Vec::new();
+    collect_queue_files(dir, &mut files);
+    if files.is_empty() {
+        return None;
+    }
+
+    let dead = files
+        .iter()
+        .filter(|f| {
+            now.duration_since(f.modified)
+                .is_ok_and(|age| age >= dead_age)
+        })
+        .cloned()
+        .collect();
+    Some(MailQueue {
+        path: dir.to_path_buf(),
+        size_bytes: files.iter().map(|f| f.size_bytes).sum(),
+        file_count: files.len(),
+        oldest: files.iter().map(|f| f.modified).min(),
+        dead,
+    })
+}
+
+/// Queue files below `dir`; Postfix spreads them over hashed subdirectories
+fn collect_queue_files(dir: &Path, files: &mut Vec<QueueFile>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() && !entry.file_type().is_ok_and(|t| t.is_symlink()) {
+            collect_queue_files(&entry.path(), files);
+        } else if metadata.is_file()
+            && let Ok(modified) = metadata.modified()
+        {
+            files.push(QueueFile {
+                path: entry.path(),
+                size_bytes: metadata.len(),
+                modified,
+                owner: Owner::of(&metadata),
+            });
+        }
+    }
+}
+
+fn queue_item(file: &QueueFile) -> CacheItem {
+    CacheItem {
+        size_bytes: Some(file.size_bytes),
+        file_count: Some(1),
+        last_modified: Some(file.modified),
+        owner: Some(file.owner),
+        ..CacheItem::new(
+            file.path.clone(),
+            CacheType::DeadMailQueue,
+            "deferred mail queue file past mail.dead_queue_age",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dead_queue_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let deferred = temp_dir.path().join("deferred");
+        fs::create_dir_all(deferred.join("A")).unwrap();
+        fs::create_dir_all(deferred.join("B")).unwrap();
+        fs::write(deferred.join("A/4F2C81A0B3"), "12345").unwrap();
+        fs::write(deferred.join("B/5D1E22C9F7"), "123").unwrap();
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        let later = SystemTime::now() + 10 * day;
+        let queue = read_queue(&deferred, 5 * day, later).unwrap();
+        assert_eq!(queue.size_bytes, 8);
+        assert_eq!(queue.file_count, 2);
+        assert_eq!(queue.dead_count(), 2);
+
+        let args: Vec<OsString> = postsuper_args(&queue.dead[0].path);
+        assert_eq!(args[1], *queue.dead[0].path.file_name().unwrap());
+        assert_eq!(args[2], "deferred");
+
+        let queue = read_queue(&deferred, 30 * day, later).unwrap();
+        assert_eq!(queue.dead_count(), 0);
+        assert!(read_queue(&temp_dir.path().join("missing"), day, later).is_none());
+    }
+}
@@ -1,28 +1,326 @@
-mod cache_detector;
-mod cli;
-mod config;
-mod display;
-mod file_operations;
-mod log_cleaner;
-
-use cache_detector::{CacheDetector, calculate_sizes};
-use cli::parse_args;
+use ages::AgeBucket;
+use audit::AuditManifest;
+use cache_detector::{
+    CATEGORIES, CacheItem, LOG_CATEGORY, SizedFile, calculate_sizes_with_files, estimate_sizes,
+};
+#[cfg(feature = "self-update")]
+use cleaner::update;
+use cleaner::{
+    Findings, ScanOptions, ages, apps, audit, cache_detector, cgroup, checkpoint, cli, config, csv,
+    detector, display, error, estimate, events, file_operations, growth, history, kernels, lint,
+    log_cleaner, mail, monitor, mounts, ncdu, output, owners, plan, power, profiles, project,
+    quota, rebuild, redact, restore, result_file, savings, scan_report, selection, size_cache,
+    storage, temperature, throttle, units, unlink, walker, writers,
+};
+#[cfg(feature = "tui")]
+use cleaner::{explorer, picker};
+use cli::{CliArgs, CliCommand, ExportFormat, parse_args};
 use config::Config;
 use display::Display;
-use file_operations::FileOperations;
-use log_cleaner::LogCleaner;
-use std::io;
+use error::CleanerError;
+use estimate::{Estimate, Progress};
+use events::Event;
+use file_operations::{FileOperations, Protection};
+use growth::GrowthReport;
+use history::{History, Snapshot, SnapshotItem};
+use log_cleaner::{LogCleaner, LogFile};
+use mail::MailReport;
+use monitor::MonitorEvent;
+use mounts::MountTable;
+use output::{CleanOutput, OutputFormat, RunStatus};
+use plan::Plan;
+use profiles::UnusedProfile;
+use scan_report::ScanReport;
+use selection::Selection;
+use size_cache::SizeCache;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use storage::StorageKind;
+use temperature::Temperature;
 
 fn main() -> io::Result<()> {
     // Parse command line arguments
     let args = parse_args();
+    if args.output_format == OutputFormat::Json || args.events.is_some() {
+        output::reserve_stdout_for_json()?;
+    }
+    if args.events.is_some() {
+        events::enable();
+    }
+
+    // Takes over the stop signals, so it must come before any thread starts
+    if let Some(path) = &args.result_file {
+        result_file::init(path.clone());
+    }
+    if args.changed_when_freed {
+        result_file::record(|result| result.changed = Some(false));
+    }
+
+    let outcome = run_roots(&args);
+    events::summary();
+    match outcome {
+        Ok(()) => {
+            result_file::finish(RunStatus::Success, 0, None);
+            Ok(())
+        }
+        Err(e) => {
+            result_file::finish(RunStatus::Failed, 1, Some(e.to_string()));
+            Err(e)
+        }
+    }
+}
+
+/// Run once for the given path, or once per detected default root when no
+/// path was given
+fn run_roots(args: &CliArgs) -> io::Result<()> {
+    if !args.default_roots
+        || matches!(
+            args.command,
+            Some(
+                CliCommand::Schema { .. }
+                    | CliCommand::ConfigLint
+                    | CliCommand::Apply { .. }
+                    | CliCommand::Resume
+                    | CliCommand::Restore { .. }
+                    | CliCommand::SelfUpdate { .. }
+                    | CliCommand::Stats
+            )
+        )
+    {
+        return run(args);
+    }
+
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let roots = cli::default_roots(home, unsafe { libc::geteuid() } == 0);
+    let writes_one_file = args.export.is_some()
+        || matches!(
+            args.command,
+            Some(
+                CliCommand::Plan { .. }
+                    | CliCommand::Report {
+                        output: Some(_),
+                        ..
+                    }
+                    | CliCommand::Diff {
+                        output: Some(_),
+                        ..
+                    }
+            )
+        );
+    if roots.len() > 1 && writes_one_file {
+        fail(format!(
+            "Error: without a path {} would be scanned separately into one output file; \
+             pass a path or --all",
+            join_paths(&roots)
+        ));
+    }
+
+    println!(
+        "No path given, scanning {} ({} scans the whole system)",
+        join_paths(&roots).bold(),
+        "--all".green()
+    );
+    if !args.force && io::stdin().is_terminal() {
+        print!("Continue? {} ", "[Y/n]:".dimmed());
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        if matches!(input.trim().to_lowercase().as_str(), "n" | "no") {
+            println!("Nothing scanned.");
+            return Ok(());
+        }
+    }
+
+    // One pool serves every root, sized for the storage that takes the
+    // fewest threads
+    let config = Config::load_from_file(
+        args.config
+            .clone()
+            .unwrap_or_else(Config::default_config_path),
+    )
+    .unwrap_or_default();
+    thread_pool(|| {
+        roots
+            .iter()
+            .map(|root| match args.threads {
+                Some(threads) => threads,
+                None => config.effective_thread_count(StorageKind::of(root)),
+            })
+            .min()
+            .unwrap_or(1)
+    });
+
+    for root in &roots {
+        run(&CliArgs {
+            path: root.clone(),
+            nested_roots: cli::nested_roots(root, &roots),
+            ..args.clone()
+        })?;
+    }
+    Ok(())
+}
+
+/// Size the shared rayon pool the first time it is asked for, returning its
+/// thread count. Every parallel stage and the walkers run on it, so the count
+/// follows the storage being scanned; runs over several roots size it once
+/// for all of them.
+fn thread_pool(thread_count: impl FnOnce() -> usize) -> usize {
+    static THREADS: OnceLock<usize> = OnceLock::new();
+    *THREADS.get_or_init(|| {
+        let thread_count = thread_count();
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(thread_count)
+            .build_global()
+        {
+            eprintln!("Warning: Could not configure thread pool: {}", e);
+        }
+        thread_count
+    })
+}
+
+/// Replace the running binary with the latest signed release, if newer
+#[cfg(feature = "self-update")]
+fn self_update(check_only: bool) -> io::Result<()> {
+    let running = env!("CARGO_PKG_VERSION");
+    let update = match update::check() {
+        Ok(Some(update)) => update,
+        Ok(None) => {
+            println!("cleaner {} is the latest release", running);
+            return Ok(());
+        }
+        Err(e) => fail(format!("Error: {}", e)),
+    };
+    println!(
+        "cleaner {} is available (running {})",
+        update.version.green(),
+        running
+    );
+    if check_only {
+        return Ok(());
+    }
+
+    let target = std::env::current_exe()?;
+    if let Err(e) = update::install(&update, &target) {
+        fail(format!("Error: {}", e));
+    }
+    println!("Updated {} to {}", target.display(), update.version);
+    Ok(())
+}
+
+/// Move the items of the clean `backup` recorded back from the trash
+fn restore_backup(backup: &Path) -> io::Result<()> {
+    let manifest = match Plan::load_from_file(&restore::manifest_path(backup)) {
+        Ok(manifest) => manifest,
+        Err(e) => fail(format!("Error loading backup manifest: {}", e)),
+    };
+    let restored = restore::restore(&manifest, &MountTable::load());
+    for entry in &restored {
+        match &entry.outcome {
+            Ok(1) => println!("  {} {}", "RESTORED".green(), entry.path.display()),
+            Ok(count) => println!(
+                "  {} {} ({} entries)",
+                "RESTORED".green(),
+                entry.path.display(),
+                units::group_digits(*count as u64)
+            ),
+            Err(reason) => println!(
+                "  {} {}: {}",
+                "NOT RESTORED".red(),
+                entry.path.display(),
+                reason
+            ),
+        }
+    }
+    let recovered = restored.iter().filter(|r| r.outcome.is_ok()).count();
+    let summary = format!(
+        "Restored {} of {} items",
+        units::group_digits(recovered as u64),
+        units::group_digits(restored.len() as u64)
+    );
+    if recovered < restored.len() {
+        fail(summary);
+    }
+    println!("{}", summary);
+    Ok(())
+}
+
+/// `paths` as "a, b and c"
+fn join_paths(paths: &[PathBuf]) -> String {
+    let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => names.concat(),
+    }
+}
+
+fn run(args: &CliArgs) -> io::Result<()> {
+    // The time limit covers the whole run, so start the clock right away
+    if let Some(timeout) = args.timeout {
+        walker::set_scan_timeout(timeout);
+    }
+
+    // Schemas don't depend on configuration or the filesystem
+    if let Some(CliCommand::Schema { name }) = &args.command {
+        let schemas: serde_json::Map<_, _> = output::SCHEMA_NAMES
+            .iter()
+            .filter(|n| name.as_deref().is_none_or(|name| name == **n))
+            .filter_map(|n| Some((n.to_string(), output::schema(n)?.to_value())))
+            .collect();
+        let document = match name {
+            Some(name) => schemas[name.as_str()].clone(),
+            None => serde_json::Value::Object(schemas),
+        };
+        println!("{}", serde_json::to_string_pretty(&document)?);
+        return Ok(());
+    }
+
+    if let Some(CliCommand::Restore { backup }) = &args.command {
+        return restore_backup(backup);
+    }
+
+    #[cfg(feature = "self-update")]
+    if let Some(CliCommand::SelfUpdate { check_only }) = &args.command {
+        return self_update(*check_only);
+    }
 
     // Load configuration
     let config_path = args
         .config
         .clone()
         .unwrap_or_else(Config::default_config_path);
+    // Lint the configuration as written, before any command line overrides
+    if let Some(CliCommand::ConfigLint) = &args.command {
+        let config = match Config::load_from_file(&config_path) {
+            Ok(config) => config,
+            Err(e) => {
+                fail(format!("Configuration error: {}", e));
+            }
+        };
+        let source = fs::read_to_string(&config_path).unwrap_or_default();
+        let issues = lint::lint(&config, &source);
+        Display::new(args.verbose, args.summary_only).show_lint_issues(&config_path, &issues);
+        if !issues.is_empty() {
+            fail(format!(
+                "{} issue{} in {}",
+                issues.len(),
+                if issues.len() == 1 { "" } else { "s" },
+                config_path.display()
+            ));
+        }
+        return Ok(());
+    }
+
+    // Stats only read the history, of every root unless one was given
+    if let Some(CliCommand::Stats) = &args.command {
+        let config = Config::load_from_file(&config_path).unwrap_or_default();
+        return show_stats(args, &config);
+    }
+
     let mut config = match Config::load_from_file(&config_path) {
         Ok(config) => config,
         Err(e) => {
@@ -37,11 +335,19 @@ fn main() -> io::Result<()> {
     };
 
     // Override config with command line arguments
-    if let Some(log_age_days) = args.log_age_days {
-        config.log_cleanup.max_age_days = log_age_days;
+    if let Some(log_age) = args.log_age {
+        config.log_cleanup.max_age = log_age;
+    }
+    if let Some(log_action) = args.log_action {
+        config.log_cleanup.log_action = log_action;
     }
 
-    if args.clean_logs {
+    if let Some(threads) = args.threads {
+        config.performance.max_threads = Some(threads);
+    }
+
+    let logs_only = matches!(args.command, Some(CliCommand::Logs));
+    if args.clean_logs || args.log_types.is_some() || logs_only {
         config.log_cleanup.enabled = true;
     }
 
@@ -50,13 +356,20 @@ fn main() -> io::Result<()> {
     }
 
     if args.force {
-        config.safety.confirm_threshold_bytes = u64::MAX; // Disable confirmation
+        config.safety.confirm_threshold = u64::MAX; // Disable confirmation
     }
 
     // Validate configuration
     if let Err(e) = config.validate() {
-        eprintln!("Configuration error: {}", e);
-        process::exit(1);
+        fail(format!("Configuration error: {}", e));
+    }
+
+    if matches!(args.command, Some(CliCommand::Quota { .. })) && config.quota.per_user == 0 {
+        fail("Configuration error: quota mode needs [quota] per_user to be set".to_string());
+    }
+
+    if args.system_reclaim && unsafe { libc::geteuid() } != 0 {
+        fail("Error: --system-reclaim needs root; run it with sudo".to_string());
     }
 
     // Save updated config if it was modified
@@ -66,6 +379,26 @@ fn main() -> io::Result<()> {
         eprintln!("Warning: Could not save config: {}", e);
     }
 
+    // Redaction learns the cache names before patterns name other users'
+    // homes, which would make their user names known
+    if args.redact_paths {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        redact::enable(&config, owners::user_homes().into_iter().chain(home));
+    }
+
+    // Roots scanned on their own are left out of the roots holding them.
+    // Like the patterns resolved below, this stays out of the saved config.
+    config
+        .safety
+        .exclude_paths
+        .extend(args.nested_roots.iter().map(|root| config::anchored(root)));
+
+    // Patterns aimed at other users' homes are resolved after saving, so the
+    // config keeps them as written
+    config
+        .cache_patterns
+        .expand_user_homes(&owners::user_homes());
+
     // Initialize display
     let display = Display::new(args.verbose, args.summary_only);
 
@@ -90,61 +423,596 @@ fn main() -> io::Result<()> {
         println!();
     }
 
+    // Load the plan up front so its scan root drives the safety checks; a
+    // resumed clean goes on with what its checkpoint left to do
+    let resume = match &args.command {
+        Some(CliCommand::Resume) => match checkpoint::load() {
+            Ok(Some(resume)) => Some(resume),
+            Ok(None) => {
+                println!("No interrupted clean to resume.");
+                return Ok(());
+            }
+            Err(e) => fail(format!("Error loading checkpoint: {}", e)),
+        },
+        _ => None,
+    };
+    let plan = match &args.command {
+        Some(CliCommand::Apply { plan }) => match Plan::load_from_file(plan) {
+            Ok(plan) => Some(plan),
+            Err(e) => {
+                fail(format!("Error loading plan: {}", e));
+            }
+        },
+        _ => resume.as_ref().map(|resume| resume.plan.clone()),
+    };
+    let scan_root = plan
+        .as_ref()
+        .map_or_else(|| args.path.clone(), |plan| plan.scan_root.clone());
+
+    // A missing root would otherwise scan as an empty, clean tree
+    if plan.is_none()
+        && let Some(e) = CleanerError::for_scan_root(&scan_root)
+    {
+        if args.ignore_missing {
+            println!("{} {} (--ignore-missing)", "Skipping".yellow(), e);
+            return Ok(());
+        }
+        fail(format!("Error: invalid scan root: {}", e));
+    }
+
+    // Confine the run before any heavy work starts
+    if !args.cgroup_limits.is_empty() {
+        match cgroup::confine(&args.cgroup_limits, &scan_root) {
+            Ok(group) => {
+                if args.verbose {
+                    println!("Running in cgroup {}", group.display());
+                }
+            }
+            Err(e) => fail(format!("Error: {}", e)),
+        }
+    }
+
+    // Timers leave laptops on battery and busy machines alone for a while
+    if args.scheduled
+        && let Err(reason) = power::wait_until_idle(&config.schedule)
+    {
+        let message = format!("Skipping this run, still {}", reason);
+        println!("{}", message.yellow());
+        result_file::finish(RunStatus::Deferred, 0, Some(message));
+        process::exit(0);
+    }
+
+    let storage = StorageKind::of(&scan_root);
+    let thread_count = thread_pool(|| config.effective_thread_count(storage));
+    throttle::start(&config.performance, thread_count);
+
     // Show scanning information
-    let thread_count = config.effective_thread_count();
     display.show_scan_info(
-        &args.path.to_string_lossy(),
+        &scan_root.to_string_lossy(),
         thread_count,
+        storage,
+        !logs_only,
         config.log_cleanup.enabled,
     );
 
+    if config.log_cleanup.enabled {
+        display.show_retention_floors(&config.retention_overrides(), config.log_cleanup.max_age);
+    }
+
     // Initialize components
-    let cache_detector = CacheDetector::new(config.clone());
-    let log_cleaner = LogCleaner::new(config.clone());
-    let file_ops = FileOperations::new(args.dry_run || config.safety.dry_run);
+    let mut file_ops =
+        FileOperations::new(args.dry_run || config.safety.dry_run, &config, &scan_root);
+    if args.trash {
+        file_ops.use_trash();
+    }
+    if args.shred {
+        file_ops.use_shred();
+    }
 
-    // Detect cache items
-    let mut cache_items = match cache_detector.detect_cache_items(&args.path) {
-        Ok(items) => items,
-        Err(e) => {
-            eprintln!("Error detecting cache items: {}", e);
-            process::exit(1);
+    // A quick sampling pass sizes up the scan, so interactive runs can show
+    // its progress and confirm long ones before starting
+    let estimate = (plan.is_none()
+        && !logs_only
+        && config.performance.estimate_sample > 0
+        && io::stderr().is_terminal())
+    .then(|| estimate::estimate(&scan_root, &config));
+    if let Some(estimate) = &estimate {
+        if args.verbose {
+            println!(
+                "Estimated {}{} directories and {} files",
+                if estimate.exact { "" } else { "~" },
+                units::group_digits(estimate.directories),
+                units::group_digits(estimate.files)
+            );
+        }
+        let threshold = config.performance.confirm_scans_over;
+        if !args.force
+            && !threshold.is_zero()
+            && estimate.duration > threshold
+            && io::stdin().is_terminal()
+            && !display.confirm_long_scan(estimate)?
+        {
+            println!("Scan cancelled.");
+            return Ok(());
+        }
+    }
+
+    // Apply mode works on the recorded plan instead of a fresh scan
+    let mut scan_warnings = Vec::new();
+    let mut sized_files = Vec::new();
+    let mut mail = MailReport::default();
+    let (mut cache_items, mut log_files) = match &plan {
+        Some(plan) => match plan.verify(&config) {
+            Ok(report) => {
+                scan_warnings.extend(report.warnings);
+                (
+                    report
+                        .items
+                        .iter()
+                        .filter_map(|e| e.to_cache_item())
+                        .collect(),
+                    report
+                        .items
+                        .iter()
+                        .filter_map(|e| e.to_log_file())
+                        .collect(),
+                )
+            }
+            Err(e) => {
+                fail(format!("Error verifying plan: {}", e));
+            }
+        },
+        None => {
+            let findings = scan(args, &config, estimate.as_ref(), &mut sized_files);
+            scan_warnings.extend(findings.warnings);
+            mail = findings.mail;
+            (findings.cache_items, findings.log_files)
         }
     };
+    if let Some(resume) = &resume {
+        let (partial_items, partial_logs) = resume.partial_items();
+        cache_items.extend(partial_items);
+        log_files.extend(partial_logs);
+    }
 
-    // Calculate cache sizes if enabled
-    if args.show_sizes {
-        if args.verbose {
-            println!("Calculating cache sizes...");
+    // Unused browser profiles are only reported unless cleaning them was asked
+    // for, in which case they replace the caches found inside them
+    let unused_profiles = if plan.is_some() || logs_only {
+        Vec::new()
+    } else {
+        profiles::detect(&args.path, &config)
+    };
+    if args.clean_unused_profiles {
+        cache_items.retain(|i| !unused_profiles.iter().any(|p| i.path.starts_with(&p.path)));
+        cache_items.extend(unused_profiles.iter().map(UnusedProfile::to_cache_item));
+        for profile in &unused_profiles {
+            file_ops.release_profile(&profile.path);
+        }
+    }
+
+    // Unused profiles, resumed items and plans may overlap the rest; nothing
+    // may be cleaned twice or while an item holding it is being deleted
+    cache_items = cache_detector::top_level(cache_items);
+    log_files = cache_detector::logs_outside(log_files, &cache_items);
+
+    // Remember fresh scans so `cleaner diff` and the growth rates can use them
+    let mut snapshots = Vec::new();
+    let mut recorded = None;
+    if plan.is_none() {
+        let history = History::new(&config);
+        let snapshot = Snapshot::from_items(&scan_root, &cache_items, &log_files);
+        match history.snapshots(&snapshot.scan_root) {
+            Ok(recorded) => snapshots = recorded,
+            Err(e) => eprintln!("Warning: Could not read scan history: {}", e),
+        }
+
+        if let Some(CliCommand::Diff { output }) = &args.command {
+            match snapshots.last() {
+                Some(previous) => {
+                    let diff = snapshot.diff(previous);
+                    display.show_scan_diff(&diff);
+                    if let Some(output) = output
+                        && let Err(e) = output::write_json(&diff, output)
+                    {
+                        fail(format!("Error writing diff: {}", e));
+                    }
+                }
+                None => println!(
+                    "{}",
+                    "No previous scan of this path to compare with.".yellow()
+                ),
+            }
+        }
+
+        // Monitoring always keeps its history, it is what the trends come from
+        let monitoring = matches!(args.command, Some(CliCommand::Monitor { .. }));
+        // Scans of logs alone would look like every cache went away, and
+        // cached sizes would hide growth deep inside the items
+        if (config.history.enabled || monitoring) && !logs_only && !reuses_sizes(args, &config) {
+            match history.record(&snapshot) {
+                Ok(path) => recorded = Some(path),
+                Err(e) => eprintln!("Warning: Could not record scan history: {}", e),
+            }
         }
-        match calculate_sizes(cache_items.clone(), thread_count) {
-            Ok(updated_items) => cache_items = updated_items,
-            Err(e) => eprintln!("Warning: Error calculating sizes: {}", e),
+        snapshots.push(snapshot);
+
+        if matches!(args.command, Some(CliCommand::Diff { .. })) {
+            return Ok(());
         }
     }
 
-    // Find old log files if enabled
-    let log_files = if config.log_cleanup.enabled {
-        if args.verbose {
-            println!("Scanning for old log files...");
+    // Leave out anything modified more recently than --older-than
+    if let Some(older_than) = args.older_than {
+        let now = SystemTime::now();
+        cache_items.retain(|item| {
+            item.last_modified
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age >= older_than)
+        });
+        log_files.retain(|log| log.age >= older_than);
+    }
+
+    // Leave out log files of types not asked for
+    log_files.retain(|log| args.includes_log_type(&log.log_type));
+
+    // Leave out anything not owned by the --owner user
+    if let Some(uid) = args.owner {
+        cache_items.retain(|item| item.owner.is_some_and(|o| o.uid == uid));
+        log_files.retain(|log| log.owner.is_some_and(|o| o.uid == uid));
+    }
+
+    // Items on read-only filesystems can't be cleaned; report them instead of
+    // counting them towards the projected savings
+    let mounts = MountTable::load();
+    let (writable, warnings) = mounts.split_read_only(cache_items, |i| i.target_path());
+    cache_items = writable;
+    scan_warnings.extend(warnings);
+    let (writable, warnings) = mounts.split_read_only(log_files, |l| l.path.as_path());
+    log_files = writable;
+    scan_warnings.extend(warnings);
+
+    // Leave out anything read within --not-accessed-for; access times on
+    // noatime mounts never move, so nothing there can be judged
+    if let Some(not_accessed_for) = args.not_accessed_for {
+        let now = SystemTime::now();
+        let mut noatime_mounts = BTreeSet::new();
+        let mut unread = |path: &Path, accessed: Option<SystemTime>| {
+            if mounts.is_noatime(path) {
+                noatime_mounts.insert(mounts.mount_point_of(path).to_path_buf());
+                return false;
+            }
+            accessed
+                .and_then(|accessed| now.duration_since(accessed).ok())
+                .is_some_and(|idle| idle >= not_accessed_for)
+        };
+        cache_items.retain(|item| unread(item.target_path(), item.last_accessed));
+        log_files.retain(|log| {
+            let accessed = fs::metadata(&log.path).and_then(|m| m.accessed()).ok();
+            unread(&log.path, accessed)
+        });
+        for mount_point in noatime_mounts {
+            eprintln!(
+                "Warning: {} is mounted noatime, so its access times don't show reads; \
+                 its items were left out of --not-accessed-for",
+                mount_point.display()
+            );
+        }
+    }
+
+    // Rate how actively each cache is used against the previous scan, then
+    // keep only the --only temperature
+    let previous_items: HashMap<&Path, &SnapshotItem> = snapshots
+        .len()
+        .checked_sub(2)
+        .map(|i| {
+            snapshots[i]
+                .items
+                .iter()
+                .map(|s| (s.path.as_path(), s))
+                .collect()
+        })
+        .unwrap_or_default();
+    let now = SystemTime::now();
+    for item in &mut cache_items {
+        let previous = previous_items.get(item.path.as_path()).copied();
+        let atime_usable = !mounts.is_noatime(item.target_path());
+        item.temperature = Some(Temperature::of(item, previous, atime_usable, now));
+    }
+    if let Some(only) = args.only {
+        cache_items.retain(|item| item.temperature == Some(only));
+        log_files.clear();
+    }
+
+    // Narrow the findings down to what the user picks
+    #[cfg(feature = "tui")]
+    if args.pick && !(cache_items.is_empty() && log_files.is_empty()) {
+        match picker::pick(cache_items, log_files) {
+            Ok((picked_items, picked_logs)) => {
+                cache_items = picked_items;
+                log_files = picked_logs;
+            }
+            Err(e) => {
+                fail(format!("Error running the picker: {}", e));
+            }
         }
-        match log_cleaner.find_old_log_files(&args.path) {
-            Ok(logs) => logs,
+    }
+
+    // Or let the user browse into them and mark what to clean at any depth;
+    // only marked caches are cleaned
+    #[cfg(feature = "tui")]
+    if args.explore && !cache_items.is_empty() {
+        match explorer::explore(cache_items, &config) {
+            Ok(marked) => {
+                cache_items = marked;
+                log_files.clear();
+            }
             Err(e) => {
-                eprintln!("Warning: Error finding log files: {}", e);
-                Vec::new()
+                fail(format!("Error running the explorer: {}", e));
             }
         }
-    } else {
-        Vec::new()
-    };
+    }
 
     // Display results
-    display.show_cache_items(&cache_items);
-    if config.log_cleanup.enabled {
-        display.show_log_files(&log_files);
+    if !logs_only {
+        display.show_cache_items(&cache_items);
+    }
+    if config.log_cleanup.enabled || !log_files.is_empty() {
+        display.show_log_files(&log_files, cache_items.len() + 1);
+    }
+    display.show_scan_warnings(&scan_warnings);
+    let timed_out = walker::scan_timed_out();
+    if let Some(timeout) = args.timeout.filter(|_| timed_out) {
+        display.show_incomplete_scan(timeout);
+    }
+    let mut by_mount = mounts.usage_by_mount(
+        cache_items
+            .iter()
+            .map(|i| (i.target_path(), i.size_bytes.unwrap_or(0)))
+            .chain(log_files.iter().map(|l| (l.path.as_path(), l.size_bytes))),
+    );
+    mounts::read_free_space(&mut by_mount);
+    let by_owner = owners::usage_by_owner(
+        cache_items
+            .iter()
+            .map(|i| (i.owner, i.size_bytes.unwrap_or(0)))
+            .chain(log_files.iter().map(|l| (l.owner, l.size_bytes))),
+    );
+    let by_age = ages::usage_by_age(
+        cache_items
+            .iter()
+            .map(|i| {
+                (
+                    AgeBucket::of_time(i.last_modified),
+                    i.size_bytes.unwrap_or(0),
+                )
+            })
+            .chain(
+                log_files
+                    .iter()
+                    .map(|l| (AgeBucket::of(Some(l.age)), l.size_bytes)),
+            ),
+    );
+    display.show_total_summary(
+        &cache_items,
+        &log_files,
+        &by_mount,
+        &by_owner,
+        &by_age,
+        &scan_root.to_string_lossy(),
+    );
+    if throttle::take_throttled() {
+        display.show_throttled();
+    }
+    display.show_mail_report(&mail, config.mail.clean_dead_queue);
+    display.show_unused_profiles(&unused_profiles, args.clean_unused_profiles);
+    // Leftover kernels are only pointed out; /boot is never touched
+    if args.system_reclaim && !timed_out {
+        display.show_kernel_artifacts(&kernels::detect(&scan_root));
+    }
+    result_file::record(|result| {
+        result.scan_root = Some(scan_root.clone());
+        result.dry_run = args.dry_run || config.safety.dry_run;
+        result.items_found = cache_items.len() + log_files.len();
+        result.bytes_found = by_mount.iter().map(|u| u.bytes).sum();
+    });
+    events::found(&cache_items, &log_files);
+    if args.output_format == OutputFormat::Json && args.summary_only {
+        output::print_json(&output::ScanSummaryOutput {
+            schema_version: output::SCHEMA_VERSION,
+            scan_root: redact::path(&scan_root),
+            total_items: cache_items.len() + log_files.len(),
+            total_bytes: by_mount.iter().map(|u| u.bytes).sum(),
+            warnings: scan_warnings.len(),
+            by_type: cache_detector::usage_by_category(
+                cache_items
+                    .iter()
+                    .map(|i| (i.cache_type.category(), i.size_bytes.unwrap_or(0)))
+                    .chain(log_files.iter().map(|l| (LOG_CATEGORY, l.size_bytes))),
+            ),
+            by_filesystem: redact::mounts(by_mount.clone()),
+            by_age: by_age.clone(),
+        })?;
+    } else if args.output_format == OutputFormat::Json {
+        output::print_json(&output::ScanOutput {
+            schema_version: output::SCHEMA_VERSION,
+            scan_root: redact::path(&scan_root),
+            cache_items: cache_items.iter().map(Into::into).collect(),
+            log_files: log_files.iter().map(Into::into).collect(),
+            warnings: scan_warnings.iter().map(redact::error).collect(),
+            by_filesystem: redact::mounts(by_mount.clone()),
+            by_age: by_age.clone(),
+            total_bytes: by_mount.iter().map(|u| u.bytes).sum(),
+        })?;
+    }
+    let savings = args
+        .actual_savings
+        .then(|| savings::estimate(&cache_items, &log_files, &config));
+    if let Some(savings) = &savings {
+        display.show_savings(savings);
+    }
+    let growth = GrowthReport::from_snapshots(&snapshots, &mounts);
+    if let Some(growth) = &growth {
+        display.show_growth(growth);
+    }
+
+    if let Some((format, export_path)) = &args.export {
+        let (name, written) = match format {
+            ExportFormat::Ncdu => (
+                "ncdu",
+                ncdu::export(
+                    &scan_root,
+                    &cache_items,
+                    &log_files,
+                    &sized_files,
+                    export_path,
+                ),
+            ),
+            ExportFormat::Csv => ("CSV", csv::export(&cache_items, &log_files, export_path)),
+        };
+        if let Err(e) = written {
+            fail(format!("Error writing {} export: {}", name, e));
+        }
+        println!();
+        println!(
+            "{} {}",
+            format!("{} export written to", name).green(),
+            export_path.display().to_string().bold()
+        );
+    }
+
+    // Project sweeps clean only the output of projects nobody works on
+    let sweep = match &args.command {
+        Some(CliCommand::Project { sweep, stale_after }) => {
+            let stale_after = stale_after.unwrap_or(config.projects.stale_after);
+            let projects = project::rank(&cache_items);
+            display.show_projects(&projects, stale_after, *sweep);
+            if *sweep {
+                let now = SystemTime::now();
+                let stale: Vec<&Path> = projects
+                    .iter()
+                    .filter(|p| p.is_stale(stale_after, now))
+                    .map(|p| p.dir.as_path())
+                    .collect();
+                cache_items.retain(|i| i.path.parent().is_some_and(|dir| stale.contains(&dir)));
+            }
+            *sweep
+        }
+        _ => false,
+    };
+
+    // Quota mode cleans only what brings each user back under the limit
+    let enforce_quota = match &args.command {
+        Some(CliCommand::Quota { report_only }) => {
+            let violations = quota::find_violations(&cache_items, &config.quota);
+            display.show_quota_violations(&violations);
+            if *report_only {
+                return Ok(());
+            }
+            cache_items = violations.into_iter().flat_map(|v| v.to_clean).collect();
+            log_files.clear();
+            true
+        }
+        _ => false,
+    };
+
+    // Reports only summarize; nothing below this point runs for them
+    if let Some(CliCommand::Report { by_app, output }) = &args.command {
+        let by_app = by_app.then(|| {
+            let attributed = apps::split_app_roots(&cache_items, &sized_files).items;
+            apps::usage_by_app(
+                attributed
+                    .iter()
+                    .map(|i| (i.path.as_path(), i.size_bytes.unwrap_or(0)))
+                    .chain(log_files.iter().map(|l| (l.path.as_path(), l.size_bytes))),
+            )
+        });
+        if let Some(by_app) = &by_app {
+            display.show_app_breakdown(by_app);
+        }
+
+        if let Some(output) = output {
+            let report = output::ReportOutput {
+                schema_version: output::SCHEMA_VERSION,
+                scan_root: redact::path(&scan_root),
+                total_items: cache_items.len() + log_files.len(),
+                total_bytes: by_mount.iter().map(|u| u.bytes).sum(),
+                complete: !timed_out,
+                reclaimable_bytes: savings.map(|s| s.reclaimable),
+                by_filesystem: redact::mounts(by_mount),
+                by_owner: redact::owners(by_owner),
+                by_age,
+                by_app,
+                growth: growth.map(redact::growth),
+            };
+            if let Err(e) = output::write_json(&report, output) {
+                fail(format!("Error writing report: {}", e));
+            }
+            println!();
+            println!(
+                "{} {}",
+                "Report written to".green(),
+                output.display().to_string().bold()
+            );
+        }
+        return Ok(());
+    }
+
+    // Monitoring only records, exports and alerts; it never reaches the
+    // cleaning below, whatever the configuration says
+    if let Some(CliCommand::Monitor { metrics }) = &args.command {
+        let alerts = monitor::check(&by_mount, &config.monitor);
+        display.show_alerts(&alerts);
+
+        if let Some(path) = metrics.as_ref().or(config.monitor.metrics_file.as_ref()) {
+            let text = monitor::metrics(&cache_items, &log_files, &by_mount, &alerts, !timed_out);
+            if let Err(e) = monitor::write_metrics(path, &text) {
+                fail(format!("Error writing metrics: {}", e));
+            }
+            println!();
+            println!(
+                "{} {}",
+                "Metrics written to".green(),
+                path.display().to_string().bold()
+            );
+        }
+
+        if !alerts.is_empty() {
+            let event = MonitorEvent::new(&scan_root, alerts, &by_mount);
+            for error in monitor::notify(&config.monitor, &event) {
+                eprintln!("Warning: {}", error);
+            }
+        }
+        return Ok(());
+    }
+
+    // Record the deletion set for a later `apply` instead of deleting
+    if let Some(CliCommand::Plan { output }) = &args.command {
+        let plan = Plan::from_items(&scan_root, &cache_items, &log_files);
+        if let Err(e) = plan.save_to_file(output) {
+            fail(format!("Error writing plan: {}", e));
+        }
+        println!();
+        println!(
+            "{} {}",
+            "Plan written to".green(),
+            output.display().to_string().bold()
+        );
+        return Ok(());
+    }
+
+    // Write the deletion plan as a script instead of deleting
+    if let Some(script_path) = &args.emit_script {
+        if let Err(e) = file_ops.write_deletion_script(script_path, &cache_items, &log_files) {
+            fail(format!("Error writing deletion script: {}", e));
+        }
+        println!();
+        println!(
+            "{} {}",
+            "Deletion script written to".green(),
+            script_path.display().to_string().bold()
+        );
+        return Ok(());
     }
-    display.show_total_summary(&cache_items, &log_files, &args.path.to_string_lossy());
 
     // Exit if nothing to clean
     if cache_items.is_empty() && log_files.is_empty() {
@@ -158,8 +1026,89 @@ fn main() -> io::Result<()> {
         return Ok(());
     }
 
+    // Leave a filesystem alone while users still have enough space on it
+    if let Some(threshold) = args.only_if_free_below {
+        match mounts::free_space(&scan_root) {
+            Some(free) if threshold.is_reached(&free) => {}
+            Some(free) => {
+                println!();
+                println!(
+                    "{}",
+                    format!(
+                        "{} available to users on {} is not below {}; nothing was cleaned.",
+                        file_operations::format_bytes(free.available_bytes),
+                        mounts.mount_point_of(&scan_root).display(),
+                        threshold
+                    )
+                    .green()
+                );
+                return Ok(());
+            }
+            None => {
+                eprintln!(
+                    "Warning: Could not read the free space of {}; nothing was cleaned",
+                    scan_root.display()
+                );
+                return Ok(());
+            }
+        }
+    }
+
     // Handle cleaning
-    if args.clean || config.safety.dry_run {
+    if args.clean
+        || args.pick
+        || args.explore
+        || args.ask
+        || args.select.is_some()
+        || args.mixes_modes()
+        || config.safety.dry_run
+        || plan.is_some()
+        || enforce_quota
+        || sweep
+    {
+        if timed_out && args.abort_on_timeout {
+            fail(
+                "Error: the scan hit --timeout; nothing was cleaned (--abort-on-timeout)"
+                    .to_string(),
+            );
+        }
+
+        // Numbers refer to the listing above, before anything is split off
+        if let Some(selection) = &args.select {
+            let selection = match selection {
+                Some(selection) => selection.clone(),
+                None => match display.prompt_selection()? {
+                    Some(list) => Selection::parse(&list).unwrap_or_else(|e| fail(e)),
+                    None => {
+                        println!("{}", "Operation cancelled.".yellow());
+                        return Ok(());
+                    }
+                },
+            };
+            (cache_items, log_files) = selection
+                .retain(cache_items, log_files)
+                .unwrap_or_else(|e| fail(format!("Error: {}", e)));
+        }
+
+        // A mixed run cleans the chosen categories and only reports the rest,
+        // which take no part in confirmations
+        let dry_run = args.dry_run || config.safety.dry_run;
+        let mixed = args.mixes_modes() && !dry_run;
+        let (mut reported_items, mut reported_logs) = (Vec::new(), Vec::new());
+        if mixed {
+            (cache_items, reported_items) = cache_items
+                .into_iter()
+                .partition(|i| args.cleans_category(i.cache_type.category()));
+            if !args.cleans_category(LOG_CATEGORY) {
+                reported_logs = std::mem::take(&mut log_files);
+            }
+        }
+
+        // Asked about every item, the user needs no other confirmation
+        if args.ask {
+            (cache_items, log_files) = display.ask_each(cache_items, log_files)?;
+        }
+
         let total_size: u64 = cache_items
             .iter()
             .map(|i| i.size_bytes.unwrap_or(0))
@@ -167,10 +1116,21 @@ fn main() -> io::Result<()> {
             + log_files.iter().map(|l| l.size_bytes).sum::<u64>();
         let total_items = cache_items.len() + log_files.len();
 
+        // Caches that rebuild themselves never need confirming
+        let regenerating_size: u64 = cache_items
+            .iter()
+            .filter(|i| i.cache_type.is_regenerating())
+            .map(|i| i.size_bytes.unwrap_or(0))
+            .sum();
+
+        // Let the user weigh what the space costs later before confirming
+        display.show_rebuild_costs(&rebuild::costs(&cache_items, &config));
+
         // Check confirmation threshold
         if !args.force
+            && !args.ask
             && !config.safety.dry_run
-            && total_size > config.safety.confirm_threshold_bytes
+            && total_size - regenerating_size > config.safety.confirm_threshold
         {
             let message = format!(
                 "Are you sure you want to {} {} items totaling {}?",
@@ -179,7 +1139,7 @@ fn main() -> io::Result<()> {
                 } else {
                     "delete"
                 },
-                total_items,
+                units::group_digits(total_items as u64),
                 file_operations::format_bytes(total_size)
             );
 
@@ -189,10 +1149,52 @@ fn main() -> io::Result<()> {
             }
         }
 
+        // Items directly inside a home directory need a typed confirmation,
+        // even with --force
+        if !args.dry_run && !config.safety.dry_run {
+            let mut declined = HashSet::new();
+            let paths = cache_items
+                .iter()
+                .map(|i| &i.path)
+                .chain(log_files.iter().map(|l| &l.path));
+            for path in paths {
+                if let Protection::TypedConfirmation(reason) = file_ops.protection(path) {
+                    let name = path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| path.display().to_string());
+                    let message = format!("{} is {}.", path.display(), reason);
+                    if display.prompt_typed_confirmation(&message, &name)? {
+                        file_ops.confirm(path);
+                    } else {
+                        println!("{} {}", "Skipping".yellow(), path.display());
+                        declined.insert(path.clone());
+                    }
+                }
+            }
+            cache_items.retain(|i| !declined.contains(&i.path));
+            log_files.retain(|l| !declined.contains(&l.path));
+        }
+
+        // Record what is about to be removed before anything is touched
+        if config.safety.checksum_manifest && !args.dry_run && !config.safety.dry_run {
+            match AuditManifest::new(&config).write(&cache_items, &log_files) {
+                Ok(path) => println!("Audit manifest created: {}", path.display()),
+                Err(e) => {
+                    fail(format!("Error writing audit manifest: {}", e));
+                }
+            }
+        }
+
         // Create backup list if enabled
         if config.safety.create_backup_list
             && !args.dry_run
-            && let Err(e) = file_ops.create_backup_list(&cache_items, &log_files)
+            && let Err(e) = file_ops.create_backup_list(
+                &cache_items,
+                &log_files,
+                &reported_items,
+                &reported_logs,
+            )
         {
             eprintln!("Warning: Could not create backup list: {}", e);
         }
@@ -203,10 +1205,31 @@ fn main() -> io::Result<()> {
                 "{}",
                 "DRY RUN - Simulating cleanup operations...".cyan().bold()
             );
+            display.show_planned_operations(&file_ops.planned_operations(&cache_items, &log_files));
         } else {
             println!("{}", "Starting cleanup operations...".green().bold());
+            // A killed clean can then be continued by `cleaner resume`
+            if resume.is_none() && checkpoint::exists() {
+                eprintln!(
+                    "Warning: Replacing the checkpoint of an interrupted clean; `cleaner resume` \
+                     can no longer continue it"
+                );
+            }
+            if let Err(e) = checkpoint::begin(&scan_root, &cache_items, &log_files) {
+                eprintln!("Warning: Could not write checkpoint: {}", e);
+            }
         }
 
+        // Huge trees take long to delete, so show how many of their files are
+        // gone; unsized items can't be tracked
+        let expected_files: u64 = cache_items
+            .iter()
+            .filter_map(|i| i.file_count)
+            .map(|count| count as u64)
+            .sum();
+        let progress = (!dry_run && expected_files > 0 && io::stderr().is_terminal())
+            .then(|| Progress::start("Deleting...", expected_files, unlink::files_removed));
+
         // Clean cache items
         let cache_results = if !cache_items.is_empty() {
             match file_ops.delete_cache_items(&cache_items) {
@@ -233,12 +1256,160 @@ fn main() -> io::Result<()> {
             Vec::new()
         };
 
+        drop(progress);
+        checkpoint::finish();
+
+        let results = cache_results.iter().chain(&log_results);
+        result_file::record(|result| {
+            for outcome in results {
+                if outcome.success {
+                    result.items_cleaned += 1;
+                    result.bytes_freed += outcome.bytes_freed;
+                } else {
+                    result.items_failed += 1;
+                }
+            }
+            if let Some(changed) = result.changed.as_mut() {
+                *changed = !dry_run && result.items_cleaned > 0;
+            }
+        });
+
+        let cleaned_items: Vec<&CacheItem> = cache_items
+            .iter()
+            .zip(&cache_results)
+            .filter(|(_, result)| result.success && !dry_run)
+            .map(|(item, _)| item)
+            .collect();
+        let cleaned_logs: Vec<&LogFile> = log_files
+            .iter()
+            .zip(&log_results)
+            .filter(|(_, result)| result.success && !dry_run)
+            .map(|(log, _)| log)
+            .collect();
+
+        // Remember what was cleaned, so what never is can be suggested as
+        // exclusions. Dry runs clean nothing, and the categories a mixed run
+        // only reports were not up for cleaning.
+        if !dry_run {
+            let cleaned: Vec<PathBuf> = cleaned_items
+                .iter()
+                .map(|i| i.path.clone())
+                .chain(cleaned_logs.iter().map(|l| l.path.clone()))
+                .collect();
+            let reported: Vec<PathBuf> = reported_items
+                .iter()
+                .map(|i| i.path.clone())
+                .chain(reported_logs.iter().map(|l| l.path.clone()))
+                .collect();
+            if let Some(path) = &recorded
+                && let Err(e) = History::new(&config).record_cleaned(path, &cleaned, &reported)
+            {
+                eprintln!("Warning: Could not record the cleaned items: {}", e);
+            }
+            if let Some(snapshot) = snapshots.last_mut().filter(|_| recorded.is_some()) {
+                snapshot.cleaned = Some(cleaned);
+                snapshot.reported = reported;
+            }
+        }
+
+        // Deleted files a process still has open keep taking space until it
+        // closes them, which only the process can be made to do
+        let held_open = if dry_run {
+            Vec::new()
+        } else {
+            let cleaned: Vec<PathBuf> = cleaned_items
+                .iter()
+                .flat_map(|item| [item.path.clone(), item.target_path().to_path_buf()])
+                .chain(cleaned_logs.iter().map(|log| log.path.clone()))
+                .collect();
+            writers::held_open_files(&cleaned)
+        };
+
         // Show results
-        display.show_cleaning_results(
-            &cache_results,
-            &log_results,
-            args.dry_run || config.safety.dry_run,
-        );
+        display.show_cleaning_results(&cache_results, &log_results, dry_run);
+        display.show_held_open_files(&held_open);
+        if args.output_format == OutputFormat::Json {
+            let mut output = CleanOutput::new(
+                dry_run,
+                &cache_items,
+                &log_files,
+                &cache_results,
+                &log_results,
+            );
+            output.held_open = redact::held_open(held_open);
+            if args.summary_only {
+                output.results.clear();
+            }
+            output::print_json(&output)?;
+        }
+        if throttle::take_throttled() {
+            display.show_throttled();
+        }
+        if args.trash && !dry_run {
+            display.show_trashed();
+        }
+
+        // Simulate the categories that were only to be reported, so the run
+        // shows what cleaning them would have done
+        if mixed {
+            if !reported_items.is_empty() || !reported_logs.is_empty() {
+                let simulated = FileOperations::new(true, &config, &scan_root);
+                let reported_cache_results = simulated
+                    .delete_cache_items(&reported_items)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error simulating cache items: {}", e);
+                        Vec::new()
+                    });
+                let reported_log_results = simulated
+                    .delete_log_files(&reported_logs)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error simulating log files: {}", e);
+                        Vec::new()
+                    });
+                display.show_cleaning_results(&reported_cache_results, &reported_log_results, true);
+                if args.output_format == OutputFormat::Json {
+                    let mut output = CleanOutput::new(
+                        true,
+                        &reported_items,
+                        &reported_logs,
+                        &reported_cache_results,
+                        &reported_log_results,
+                    );
+                    if args.summary_only {
+                        output.results.clear();
+                    }
+                    output::print_json(&output)?;
+                }
+            }
+            display.show_mixed_run(
+                &categories(&cache_items, &log_files),
+                &categories(&reported_items, &reported_logs),
+            );
+        }
+
+        // Point out what the user keeps finding and never cleans; in the
+        // interactive modes it is excluded with an answer
+        if args.output_format == OutputFormat::Text {
+            let runs = config.history.suggest_exclusions_after;
+            let interactive =
+                (args.pick || args.ask || args.select.is_some()) && io::stdin().is_terminal();
+            let excluded = display.suggest_exclusions(
+                &history::never_cleaned(&snapshots, runs),
+                runs,
+                interactive,
+            )?;
+            exclude(&config_path, &excluded);
+        }
+
+        // Let the user pick out surprises of a dry run, so later runs leave
+        // them alone without hand-editing the config
+        if (args.dry_run || config.safety.dry_run)
+            && args.output_format == OutputFormat::Text
+            && io::stdin().is_terminal()
+        {
+            let kept = display.review_kept_items(&cache_items, &log_files)?;
+            exclude(&config_path, &kept);
+        }
     } else {
         println!();
         println!("{}", "Use --clean flag to delete these items.".dimmed());
@@ -261,5 +1432,233 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-// Import the colored trait for string coloring
+/// The categories of `cache_items` and `log_files`, in the order they are
+/// listed on the command line
+fn categories(cache_items: &[CacheItem], log_files: &[LogFile]) -> Vec<&'static str> {
+    let mut found: HashSet<&str> = cache_items
+        .iter()
+        .map(|i| i.cache_type.category())
+        .collect();
+    if !log_files.is_empty() {
+        found.insert(LOG_CATEGORY);
+    }
+    CATEGORIES
+        .iter()
+        .copied()
+        .filter(|c| found.contains(c))
+        .collect()
+}
+
+/// Report a fatal error and exit, recording it in the result file
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    events::summary();
+    result_file::finish(RunStatus::Failed, 1, Some(message));
+    process::exit(1)
+}
+
+/// Add `paths` to the exclusions of the config file at `config_path`,
+/// leaving command line overrides out of it, and tell how many were new
+fn exclude(config_path: &Path, paths: &[PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    match remember_exclusions(config_path, paths) {
+        Ok(added) => println!(
+            "{} {} to {}",
+            "Added".green(),
+            format!("{} exclusion{}", added, if added == 1 { "" } else { "s" }).bold(),
+            config_path.display()
+        ),
+        Err(e) => eprintln!("Error saving exclusions: {}", e),
+    }
+}
+
+fn remember_exclusions(config_path: &Path, paths: &[PathBuf]) -> Result<usize, CleanerError> {
+    let mut config = Config::load_from_file(config_path)?;
+    let added = config.add_exclusions(paths);
+    if added > 0 {
+        config.save_exclusions(config_path)?;
+    }
+    Ok(added)
+}
+
+/// Whether sizing keeps every file it walks: exports and the per-app
+/// breakdown need them
+fn keeps_files(args: &CliArgs) -> bool {
+    matches!(args.export, Some((ExportFormat::Ncdu, _)))
+        || matches!(args.command, Some(CliCommand::Report { by_app: true, .. }))
+}
+
+/// Whether sizes come from the size cache. Cached sizes may lag behind
+/// changes deep inside an item, so only runs that merely report use them,
+/// and they carry no access times. Diffs compare against the previous
+/// snapshot, which must not be built from stale sizes either.
+fn reuses_sizes(args: &CliArgs, config: &Config) -> bool {
+    config.performance.size_cache
+        && args.show_sizes
+        && !args.estimate
+        && !keeps_files(args)
+        && args.not_accessed_for.is_none()
+        && args.only.is_none()
+        && !args.clean
+        && !args.pick
+        && !args.explore
+        && !args.ask
+        && matches!(args.command, None | Some(CliCommand::Report { .. }))
+}
+
+/// Detect cache items, measure them and find old log files under the scan root
+fn scan(
+    args: &CliArgs,
+    config: &Config,
+    estimate: Option<&Estimate>,
+    sized_files: &mut Vec<SizedFile>,
+) -> Findings {
+    events::emit(Event::ScanStarted {
+        scan_root: args.path.clone(),
+    });
+    if matches!(args.command, Some(CliCommand::Logs)) {
+        let mut findings = Findings::default();
+        findings.log_files = find_old_logs(args, config, &mut findings.warnings);
+        return findings;
+    }
+
+    // `project` finds build output by the projects' manifests. Inside a git
+    // working tree only the build output git ignores is considered; anything
+    // else there may be someone's work. Both leave the other detections out.
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let repository = project::working_tree(&args.path, home.as_deref());
+    let project_scan = matches!(args.command, Some(CliCommand::Project { .. }));
+    let found = match &repository {
+        _ if project_scan => Some(project::detect_build_outputs(&args.path, config)),
+        Some(repo) => {
+            println!(
+                "{} {} is inside the git working tree {}; only build output ignored by git \
+                 is considered",
+                "NOTE".bold().yellow(),
+                args.path.display(),
+                repo.display()
+            );
+            Some(project::detect(repo, &args.path, config))
+        }
+        None => None,
+    };
+    let scanned = match found {
+        Some(found) => size_items(args, config, found.items, sized_files).map(|sized| Findings {
+            cache_items: sized.items,
+            warnings: found.warnings.into_iter().chain(sized.warnings).collect(),
+            ..Findings::default()
+        }),
+        None => {
+            let options = ScanOptions {
+                system_reclaim: args.system_reclaim,
+                kubernetes_node: args.kubernetes_node,
+                trash_owner: args.owner,
+                ..ScanOptions::new(config.clone())
+            };
+            // Show how far the walks got against the estimate
+            let progress = estimate.map(|e| {
+                Progress::start("Scanning...", e.walked_entries(), walker::walked_entries)
+            });
+            cleaner::scan_sized(&args.path, &options, detector::builtin(), |items| {
+                drop(progress);
+                size_items(args, config, items, sized_files)
+            })
+        }
+    };
+    scanned.unwrap_or_else(|e| fail(format!("Error detecting cache items: {}", e)))
+}
+
+/// Measure `items` if sizes are shown: estimated, from the size cache, or
+/// walked, keeping the files walked where exports need them
+fn size_items(
+    args: &CliArgs,
+    config: &Config,
+    items: Vec<CacheItem>,
+    sized_files: &mut Vec<SizedFile>,
+) -> Result<ScanReport<CacheItem>, CleanerError> {
+    if !args.show_sizes {
+        return Ok(ScanReport {
+            items,
+            warnings: Vec::new(),
+        });
+    }
+    if args.verbose {
+        println!("Calculating cache sizes...");
+    }
+    let keep_files = keeps_files(args);
+    let sized = if args.estimate && !keep_files {
+        Ok((estimate_sizes(items.clone(), config), Vec::new()))
+    } else if reuses_sizes(args, config) {
+        let mut size_cache = SizeCache::load();
+        let report = size_cache.calculate_sizes(items.clone(), config);
+        if let Err(e) = size_cache.save() {
+            eprintln!("Warning: Could not save size cache: {}", e);
+        }
+        report.map(|report| (report, Vec::new()))
+    } else {
+        calculate_sizes_with_files(items.clone(), config, keep_files)
+    };
+    match sized {
+        Ok((report, files)) => {
+            *sized_files = files;
+            Ok(report)
+        }
+        Err(e) => {
+            eprintln!("Warning: Error calculating sizes: {}", e);
+            Ok(ScanReport {
+                items,
+                warnings: Vec::new(),
+            })
+        }
+    }
+}
+
+/// Old log files under the scan root
+fn find_old_logs(
+    args: &CliArgs,
+    config: &Config,
+    scan_warnings: &mut Vec<CleanerError>,
+) -> Vec<LogFile> {
+    if args.verbose {
+        println!("Scanning for old log files...");
+    }
+    match LogCleaner::new(config.clone()).find_old_log_files(&args.path) {
+        Ok(report) => {
+            scan_warnings.extend(report.warnings);
+            report.items
+        }
+        Err(e) => {
+            eprintln!("Warning: Error finding log files: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Summarize the recorded scan history of the scan root, or of every root
+/// when none was given
+fn show_stats(args: &CliArgs, config: &Config) -> io::Result<()> {
+    let snapshots = match History::new(config).all_snapshots() {
+        Ok(snapshots) => snapshots,
+        Err(e) => fail(format!("Error reading scan history: {}", e)),
+    };
+    let root = (!args.default_roots)
+        .then(|| fs::canonicalize(&args.path).unwrap_or_else(|_| args.path.clone()));
+    let stats = history::stats(
+        snapshots
+            .iter()
+            .filter(|s| root.as_ref().is_none_or(|root| s.scan_root == *root)),
+    );
+
+    if args.output_format == OutputFormat::Json {
+        return output::print_json(&output::StatsOutput {
+            schema_version: output::SCHEMA_VERSION,
+            roots: stats,
+        });
+    }
+    Display::new(args.verbose, args.summary_only).show_stats(&stats);
+    Ok(())
+}
+
 use colored::*;
@@ -0,0 +1,314 @@
+use crate::cache_detector::CacheItem;
+use crate::config::MonitorConfig;
+use crate::error::CleanerError;
+use crate::file_operations::format_bytes;
+use crate::log_cleaner::LogFile;
+use crate::mounts::MountUsage;
+use crate::output::SCHEMA_VERSION;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// How long a webhook may take before the run gives up on it
+#[cfg(feature = "webhooks")]
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// One threshold a monitor run found crossed
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// Filesystem the alert is about, for low space alerts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mount_point: Option<PathBuf>,
+    pub message: String,
+}
+
+/// Which threshold was crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// More is reclaimable than `alert_reclaimable_above`
+    Reclaimable,
+    /// A filesystem has less available than `alert_free_below`
+    LowSpace,
+}
+
+/// What the notify command and webhook receive when thresholds are crossed
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MonitorEvent {
+    pub schema_version: u32,
+    pub host: String,
+    pub scan_root: PathBuf,
+    pub time: DateTime<Utc>,
+    pub alerts: Vec<Alert>,
+    pub total_bytes: u64,
+    /// Reclaimable and available space per filesystem
+    pub by_filesystem: Vec<MountUsage>,
+}
+
+impl MonitorEvent {
+    pub fn new(scan_root: &Path, alerts: Vec<Alert>, by_mount: &[MountUsage]) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            host: hostname(),
+            scan_root: scan_root.to_path_buf(),
+            time: Utc::now(),
+            alerts,
+            total_bytes: by_mount.iter().map(|u| u.bytes).sum(),
+            by_filesystem: by_mount.to_vec(),
+        }
+    }
+}
+
+/// The thresholds of `config` crossed by a scan's findings
+pub fn check(by_mount: &[MountUsage], config: &MonitorConfig) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    let total: u64 = by_mount.iter().map(|u| u.bytes).sum();
+    if config.alert_reclaimable_above > 0 && total > config.alert_reclaimable_above {
+        alerts.push(Alert {
+            kind: AlertKind::Reclaimable,
+            mount_point: None,
+            message: format!(
+                "{} reclaimable, above the alert threshold of {}",
+                format_bytes(total),
+                format_bytes(config.alert_reclaimable_above)
+            ),
+        });
+    }
+
+    if let Some(threshold) = config.alert_free_below {
+        for usage in by_mount {
+            if let Some(free) = usage.free.filter(|free| threshold.is_reached(free)) {
+                alerts.push(Alert {
+                    kind: AlertKind::LowSpace,
+                    mount_point: Some(usage.mount_point.clone()),
+                    message: format!(
+                        "{} has {} ({:.1}%) available, below {}",
+                        usage.mount_point.display(),
+                        format_bytes(free.available_bytes),
+                        free.available_percent(),
+                        threshold
+                    ),
+                });
+            }
+        }
+    }
+
+    alerts
+}
+
+/// The findings as Prometheus text exposition, for node_exporter's textfile
+/// collector
+pub fn metrics(
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+    by_mount: &[MountUsage],
+    alerts: &[Alert],
+    complete: bool,
+) -> String {
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, samples: Vec<(String, u64)>| {
+        let _ = writeln!(out, "# HELP cleaner_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE cleaner_{} gauge", name);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "cleaner_{}{} {}", name, labels, value);
+        }
+    };
+
+    let mount_label = |usage: &MountUsage| {
+        format!(
+            "{{mount_point=\"{}\"}}",
+            escape_label(&usage.mount_point.to_string_lossy())
+        )
+    };
+    gauge(
+        "reclaimable_bytes",
+        "Space held by detected caches and old logs.",
+        by_mount.iter().map(|u| (mount_label(u), u.bytes)).collect(),
+    );
+    gauge(
+        "reclaimable_items",
+        "Detected caches and old logs.",
+        by_mount
+            .iter()
+            .map(|u| (mount_label(u), u.items as u64))
+            .collect(),
+    );
+    gauge(
+        "filesystem_available_bytes",
+        "Space available to unprivileged users on filesystems with findings.",
+        by_mount
+            .iter()
+            .filter_map(|u| Some((mount_label(u), u.free?.available_bytes)))
+            .collect(),
+    );
+
+    let mut by_type: BTreeMap<String, u64> = BTreeMap::new();
+    for item in cache_items {
+        let name = serde_json::to_value(&item.cache_type)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        *by_type.entry(name).or_default() += item.size_bytes.unwrap_or(0);
+    }
+    if !log_files.is_empty() {
+        *by_type.entry("log".to_string()).or_default() +=
+            log_files.iter().map(|l| l.size_bytes).sum::<u64>();
+    }
+    gauge(
+        "reclaimable_type_bytes",
+        "Reclaimable space per cache type.",
+        by_type
+            .into_iter()
+            .map(|(name, bytes)| (format!("{{type=\"{}\"}}", name), bytes))
+            .collect(),
+    );
+
+    gauge(
+        "alerts",
+        "Alert thresholds crossed by the last scan.",
+        vec![(String::new(), alerts.len() as u64)],
+    );
+    gauge(
+        "scan_complete",
+        "Whether the last scan covered the whole tree.",
+        vec![(String::new(), complete as u64)],
+    );
+    gauge(
+        "last_scan_timestamp_seconds",
+        "When the last scan finished.",
+        vec![(String::new(), Utc::now().timestamp().max(0) as u64)],
+    );
+    out
+}
+
+/// Write the metrics through a temporary file, so the collector never reads
+/// a half-written one
+pub fn write_metrics(path: &Path, metrics: &str) -> Result<(), CleanerError> {
+    let tmp = path.with_extension("prom.tmp");
+    fs::write(&tmp, metrics).map_err(|e| CleanerError::from_io(&tmp, &e))?;
+    fs::rename(&tmp, path).map_err(|e| CleanerError::from_io(path, &e))
+}
+
+/// Send `event` to the configured notify command and webhook, returning
+/// what went wrong with either
+pub fn notify(config: &MonitorConfig, event: &MonitorEvent) -> Vec<String> {
+    let mut errors = Vec::new();
+    let body = match serde_json::to_vec(event) {
+        Ok(body) => body,
+        Err(e) => return vec![format!("could not encode alerts: {}", e)],
+    };
+
+    if let Some(command) = config
+        .notify_command
+        .as_ref()
+        .filter(|c| !c.trim().is_empty())
+        && let Err(e) = run_command(command, &body)
+    {
+        errors.push(format!("notify command failed: {}", e));
+    }
+
+    if let Some(url) = config.webhook_url.as_ref().filter(|u| !u.trim().is_empty())
+        && let Err(e) = post_webhook(url, &body)
+    {
+        errors.push(format!("webhook {} failed: {}", url, e));
+    }
+
+    errors
+}
+
+#[cfg(feature = "webhooks")]
+fn post_webhook(url: &str, body: &[u8]) -> Result<(), String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn post_webhook(_url: &str, _body: &[u8]) -> Result<(), String> {
+    Err("cleaner was built without the `webhooks` feature".to_string())
+}
+
+fn run_command(command: &str, input: &[u8]) -> Result<(), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    // Commands may not read their input, so a closed pipe is not an error
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).ok();
+    }
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(status.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mounts::{FreeSpace, FreeThreshold};
+
+    #[test]
+    fn test_monitor_alerts_and_metrics() {
+        let by_mount = vec![MountUsage {
+            mount_point: PathBuf::from("/var"),
+            items: 3,
+            bytes: 5000,
+            free: Some(FreeSpace {
+                total_bytes: 100_000,
+                free_bytes: 8000,
+                available_bytes: 4000,
+            }),
+        }];
+        let mut config = MonitorConfig::default();
+        assert!(check(&by_mount, &config).is_empty());
+
+        config.alert_reclaimable_above = 1000;
+        config.alert_free_below = Some(FreeThreshold::Percent(10.0));
+        let alerts = check(&by_mount, &config);
+        assert_eq!(
+            alerts.iter().map(|a| a.kind).collect::<Vec<_>>(),
+            [AlertKind::Reclaimable, AlertKind::LowSpace]
+        );
+        assert_eq!(alerts[1].mount_point, Some(PathBuf::from("/var")));
+
+        let text = metrics(&[], &[], &by_mount, &alerts, true);
+        assert!(text.contains("cleaner_reclaimable_bytes{mount_point=\"/var\"} 5000\n"));
+        assert!(text.contains("cleaner_filesystem_available_bytes{mount_point=\"/var\"} 4000\n"));
+        assert!(text.contains("cleaner_alerts 2\n"));
+        assert!(text.contains("# TYPE cleaner_scan_complete gauge\n"));
+    }
+}
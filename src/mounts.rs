@@ -0,0 +1,407 @@
+use crate::error::CleanerError;
+use crate::file_operations::format_bytes;
+use crate::units;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// Reclaimable space on one filesystem
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct MountUsage {
+    pub mount_point: PathBuf,
+    pub items: usize,
+    pub bytes: u64,
+    /// Space left on the filesystem, when it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free: Option<FreeSpace>,
+}
+
+/// Space left on one filesystem. Blocks reserved for root (ext4's reserved
+/// block count, typically 5%) count as free but not as available, so
+/// unprivileged users hit ENOSPC while `free_bytes` is still above zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct FreeSpace {
+    pub total_bytes: u64,
+    /// Free blocks, including the reserved ones
+    pub free_bytes: u64,
+    /// Free blocks unprivileged users can write to
+    pub available_bytes: u64,
+}
+
+impl FreeSpace {
+    /// Free space only root can use
+    pub fn reserved_bytes(&self) -> u64 {
+        self.free_bytes.saturating_sub(self.available_bytes)
+    }
+
+    /// Available space as a percentage of the filesystem size
+    pub fn available_percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        self.available_bytes as f64 * 100.0 / self.total_bytes as f64
+    }
+}
+
+/// Threshold of `--only-if-free-below`, in bytes or percent of the filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum FreeThreshold {
+    Bytes(u64),
+    Percent(f64),
+}
+
+impl FreeThreshold {
+    /// Parse `10%` or a size such as `5GiB`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().strip_suffix('%') {
+            Some(percent) => percent
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(FreeThreshold::Percent)
+                .ok_or_else(|| format!("invalid percentage '{}'", value)),
+            None => units::parse_size(value).map(FreeThreshold::Bytes),
+        }
+    }
+
+    /// Whether the space available to unprivileged users is below the threshold
+    pub fn is_reached(&self, free: &FreeSpace) -> bool {
+        match *self {
+            FreeThreshold::Bytes(bytes) => free.available_bytes < bytes,
+            FreeThreshold::Percent(percent) => free.available_percent() < percent,
+        }
+    }
+}
+
+impl TryFrom<String> for FreeThreshold {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+/// Written back to the config in a form `parse` reads exactly
+impl From<FreeThreshold> for String {
+    fn from(threshold: FreeThreshold) -> Self {
+        match threshold {
+            FreeThreshold::Bytes(bytes) => bytes.to_string(),
+            FreeThreshold::Percent(percent) => format!("{}%", percent),
+        }
+    }
+}
+
+impl std::fmt::Display for FreeThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FreeThreshold::Bytes(bytes) => write!(f, "{}", format_bytes(*bytes)),
+            FreeThreshold::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Mount options that change what the cleaner may rely on
+#[derive(Debug, Clone, Copy, Default)]
+struct MountFlags {
+    read_only: bool,
+    /// Access times are never updated
+    noatime: bool,
+}
+
+/// Mount points of the running system
+#[derive(Debug, Clone, Default)]
+pub struct MountTable {
+    mount_points: HashMap<PathBuf, MountFlags>,
+}
+
+impl MountTable {
+    /// Read the mount table, falling back to an empty table when unavailable
+    pub fn load() -> Self {
+        fs::read_to_string(MOUNTINFO_PATH)
+            .map(|content| Self::parse(&content))
+            .unwrap_or_default()
+    }
+
+    /// Parse the contents of a `/proc/<pid>/mountinfo` file
+    pub fn parse(content: &str) -> Self {
+        // Later lines shadow earlier mounts on the same point
+        let mount_points = content
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let mount_point = fields.get(4)?;
+                let mount_options = fields.get(5)?;
+                let super_options = fields
+                    .iter()
+                    .position(|f| *f == "-")
+                    .and_then(|sep| fields.get(sep + 3));
+                let has = |option: &str| {
+                    [Some(mount_options), super_options]
+                        .into_iter()
+                        .flatten()
+                        .any(|options| options.split(',').any(|o| o == option))
+                };
+                let flags = MountFlags {
+                    read_only: has("ro"),
+                    noatime: has("noatime"),
+                };
+                Some((PathBuf::from(unescape(mount_point)), flags))
+            })
+            .collect();
+        Self { mount_points }
+    }
+
+    /// Whether deleting `path` would hit a read-only filesystem, either per
+    /// the mount table or because a write probe fails with EROFS
+    pub fn is_read_only(&self, path: &Path) -> bool {
+        let mounted_read_only = path
+            .ancestors()
+            .find_map(|ancestor| self.mount_points.get(ancestor))
+            .is_some_and(|flags| flags.read_only);
+        mounted_read_only || path.parent().is_some_and(probe_read_only)
+    }
+
+    /// Mount points not mounted read-only, in no particular order
+    pub fn writable_mount_points(&self) -> impl Iterator<Item = &Path> {
+        self.mount_points
+            .iter()
+            .filter(|(_, flags)| !flags.read_only)
+            .map(|(mount_point, _)| mount_point.as_path())
+    }
+
+    /// Whether the filesystem holding `path` is mounted `noatime`, so access
+    /// times say nothing about reads. Under the default `relatime` they lag
+    /// by at most a day.
+    pub fn is_noatime(&self, path: &Path) -> bool {
+        path.ancestors()
+            .find_map(|ancestor| self.mount_points.get(ancestor))
+            .is_some_and(|flags| flags.noatime)
+    }
+
+    /// The mount point of the filesystem holding `path`
+    pub fn mount_point_of<'a>(&'a self, path: &'a Path) -> &'a Path {
+        path.ancestors()
+            .find_map(|ancestor| self.mount_points.get_key_value(ancestor))
+            .map_or(Path::new("/"), |(mount_point, _)| mount_point.as_path())
+    }
+
+    /// Total the sizes of items per filesystem, ordered by mount point
+    pub fn usage_by_mount<'a>(
+        &self,
+        sizes: impl IntoIterator<Item = (&'a Path, u64)>,
+    ) -> Vec<MountUsage> {
+        let mut usage: BTreeMap<PathBuf, (usize, u64)> = BTreeMap::new();
+        for (path, bytes) in sizes {
+            let entry = usage
+                .entry(self.mount_point_of(path).to_path_buf())
+                .or_default();
+            entry.0 += 1;
+            entry.1 += bytes;
+        }
+        usage
+            .into_iter()
+            .map(|(mount_point, (items, bytes))| MountUsage {
+                mount_point,
+                items,
+                bytes,
+                free: None,
+            })
+            .collect()
+    }
+
+    /// Move items on read-only filesystems out of `items`, returning a warning
+    /// for each so they show up as not cleanable instead of failing later
+    pub fn split_read_only<T>(
+        &self,
+        items: Vec<T>,
+        path_of: impl Fn(&T) -> &Path,
+    ) -> (Vec<T>, Vec<CleanerError>) {
+        let mut warnings = Vec::new();
+        let writable = items
+            .into_iter()
+            .filter(|item| {
+                let path = path_of(item);
+                let read_only = self.is_read_only(path);
+                if read_only {
+                    warnings.push(CleanerError::ReadOnly(path.to_path_buf()));
+                }
+                !read_only
+            })
+            .collect();
+        (writable, warnings)
+    }
+
+    /// Whether `path` is a mount point, either listed in the table or sitting
+    /// on a different device than its parent
+    pub fn is_mount_point(&self, path: &Path) -> bool {
+        if self.mount_points.contains_key(path) {
+            return true;
+        }
+
+        let Some(parent) = path.parent() else {
+            return true;
+        };
+        match (fs::symlink_metadata(path), fs::metadata(parent)) {
+            (Ok(meta), Ok(parent_meta)) => meta.is_dir() && meta.dev() != parent_meta.dev(),
+            _ => false,
+        }
+    }
+}
+
+/// Space available to unprivileged users on the filesystem holding `path`
+pub fn available_bytes(path: &Path) -> Option<u64> {
+    free_space(path).map(|free| free.available_bytes)
+}
+
+/// Size and free space of the filesystem holding `path`
+pub fn free_space(path: &Path) -> Option<FreeSpace> {
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is NUL-terminated and stat is a valid out pointer
+    let result = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    let block = stat.f_frsize as u64;
+    (result == 0).then(|| FreeSpace {
+        total_bytes: stat.f_blocks as u64 * block,
+        free_bytes: stat.f_bfree as u64 * block,
+        available_bytes: stat.f_bavail as u64 * block,
+    })
+}
+
+/// Fill in the free space of each filesystem in `usage`
+pub fn read_free_space(usage: &mut [MountUsage]) {
+    for mount in usage {
+        mount.free = free_space(&mount.mount_point);
+    }
+}
+
+/// Ask the kernel whether `dir` could be written, catching read-only mounts
+/// the table doesn't show (e.g. a filesystem remounted read-only after errors)
+fn probe_read_only(dir: &Path) -> bool {
+    let Ok(c_path) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: c_path is a valid NUL-terminated string for the whole call
+    let result = unsafe { libc::access(c_path.as_ptr(), libc::W_OK) };
+    result != 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EROFS)
+}
+
+/// Decode the octal escapes (`\040` for space etc.) used in mountinfo paths
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(octal) = field.get(i + 1..i + 4)
+            && let Ok(value) = u8::from_str_radix(octal, 8)
+        {
+            out.push(value);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let table = MountTable::parse(
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+             35 22 8:2 / /mnt/my\\040disk rw,relatime shared:2 - ext4 /dev/sda2 rw\n",
+        );
+
+        assert!(table.is_mount_point(Path::new("/")));
+        assert!(!table.is_noatime(Path::new("/var")));
+        assert!(table.is_mount_point(Path::new("/mnt/my disk")));
+        assert!(!table.is_mount_point(Path::new("/mnt/other-missing-dir")));
+
+        let usage = table.usage_by_mount([
+            (Path::new("/var/cache/apt"), 10),
+            (Path::new("/mnt/my disk/.cache"), 5),
+            (Path::new("/tmp/x"), 1),
+        ]);
+        assert_eq!(
+            usage,
+            vec![
+                MountUsage {
+                    mount_point: PathBuf::from("/"),
+                    items: 2,
+                    bytes: 11,
+                    free: None,
+                },
+                MountUsage {
+                    mount_point: PathBuf::from("/mnt/my disk"),
+                    items: 1,
+                    bytes: 5,
+                    free: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_only_mounts() {
+        let table = MountTable::parse(
+            "22 1 8:1 / /nonexistent-root rw,relatime - ext4 /dev/sda1 rw\n\
+             35 22 8:2 / /nonexistent-root/media ro,relatime - iso9660 /dev/sr0 ro\n\
+             36 22 8:3 / /nonexistent-root/snap rw,relatime - squashfs /dev/loop0 ro\n\
+             37 22 8:4 / /nonexistent-root/data rw,noatime - xfs /dev/sdb1 rw\n",
+        );
+
+        assert!(table.is_read_only(Path::new("/nonexistent-root/media/cache")));
+        assert!(table.is_read_only(Path::new("/nonexistent-root/snap/app/.cache")));
+        assert!(!table.is_read_only(Path::new("/nonexistent-root/home/.cache")));
+        assert!(table.is_noatime(Path::new("/nonexistent-root/data/cache")));
+
+        let (writable, warnings) = table.split_read_only(
+            vec![
+                PathBuf::from("/nonexistent-root/media/a"),
+                PathBuf::from("/nonexistent-root/b"),
+            ],
+            |p| p.as_path(),
+        );
+        assert_eq!(writable, vec![PathBuf::from("/nonexistent-root/b")]);
+        assert_eq!(
+            warnings,
+            vec![CleanerError::ReadOnly(PathBuf::from(
+                "/nonexistent-root/media/a"
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_free_threshold_counts_only_available_space() {
+        // 5% of a 100GB filesystem reserved for root, 3GB of it free
+        let free = FreeSpace {
+            total_bytes: 100_000_000_000,
+            free_bytes: 3_000_000_000,
+            available_bytes: 0,
+        };
+        assert_eq!(free.reserved_bytes(), 3_000_000_000);
+
+        assert!(FreeThreshold::parse("2%").unwrap().is_reached(&free));
+        assert!(FreeThreshold::parse("1GB").unwrap().is_reached(&free));
+        assert!(!FreeThreshold::parse("0%").unwrap().is_reached(&free));
+        assert!(FreeThreshold::parse("120%").is_err());
+        assert_eq!(
+            FreeThreshold::parse(" 10 %").unwrap(),
+            FreeThreshold::Percent(10.0)
+        );
+    }
+}
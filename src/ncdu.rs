@@ -0,0 +1,184 @@
+use crate::cache_detector::{CacheItem, SizedFile};
+use crate::error::CleanerError;
+use crate::log_cleaner::LogFile;
+use crate::redact;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path};
+
+/// Major and minor version of the ncdu export format written
+const FORMAT_VERSION: (u32, u32) = (1, 2);
+
+/// A directory of the exported tree
+#[derive(Default)]
+struct Dir {
+    dirs: BTreeMap<String, Dir>,
+    files: BTreeMap<String, (u64, u64)>,
+}
+
+impl Dir {
+    /// The directory at `relative`, created along with its parents
+    fn dir_at(&mut self, relative: &Path) -> &mut Dir {
+        relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .fold(self, |dir, name| dir.dirs.entry(name).or_default())
+    }
+
+    fn add_file(&mut self, relative: &Path, apparent_size: u64, disk_size: u64) {
+        let Some(name) = relative.file_name() else {
+            return;
+        };
+        let parent = self.dir_at(relative.parent().unwrap_or(Path::new("")));
+        parent.files.insert(
+            name.to_string_lossy().into_owned(),
+            (apparent_size, disk_size),
+        );
+    }
+
+    /// ncdu's representation: an array headed by the directory's info object
+    fn to_json(&self, name: &str) -> Value {
+        let mut entries = vec![json!({ "name": name })];
+        entries.extend(
+            self.files.iter().map(
+                |(name, (asize, dsize))| json!({ "name": name, "asize": asize, "dsize": dsize }),
+            ),
+        );
+        entries.extend(self.dirs.iter().map(|(name, dir)| dir.to_json(name)));
+        Value::Array(entries)
+    }
+}
+
+/// Build the ncdu JSON document for the items kept after filtering. `files`
+/// are the per-file sizes recorded while sizing; files of items that were
+/// filtered out and anything outside `scan_root` are left out.
+fn build_export(
+    scan_root: &Path,
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+    files: &[SizedFile],
+) -> Value {
+    let mut root = Dir::default();
+    let kept: HashSet<&Path> = cache_items.iter().map(|i| i.path.as_path()).collect();
+    let mut covered: HashSet<&Path> = HashSet::new();
+    // Paths are redacted before they are made relative, as the root is
+    let shown_root = redact::path(scan_root);
+
+    for file in files {
+        let Some(item) = file.path.ancestors().find(|a| kept.contains(a)) else {
+            continue;
+        };
+        let shown = redact::path_in(&file.path, item);
+        if let Ok(relative) = shown.strip_prefix(&shown_root) {
+            root.add_file(relative, file.apparent_size, file.disk_size);
+            covered.insert(item);
+        }
+    }
+
+    // Items no file was recorded for (empty directories, or sizes taken from
+    // a plan) still show up with what is known about them
+    for item in cache_items
+        .iter()
+        .filter(|i| !covered.contains(i.path.as_path()))
+    {
+        let shown = redact::path(&item.path);
+        let Ok(relative) = shown.strip_prefix(&shown_root) else {
+            continue;
+        };
+        if item.target_path().is_dir() {
+            root.dir_at(relative);
+        } else {
+            let size = item.size_bytes.unwrap_or(0);
+            root.add_file(relative, size, size);
+        }
+    }
+
+    for log in log_files {
+        let shown = redact::path(&log.path);
+        if let Ok(relative) = shown.strip_prefix(&shown_root) {
+            let disk_size = fs::symlink_metadata(&log.path)
+                .map(|m| m.blocks() * 512)
+                .unwrap_or(log.size_bytes);
+            root.add_file(relative, log.size_bytes, disk_size);
+        }
+    }
+
+    let timestamp = chrono::Utc::now().timestamp();
+    json!([
+        FORMAT_VERSION.0,
+        FORMAT_VERSION.1,
+        {
+            "progname": env!("CARGO_PKG_NAME"),
+            "progver": env!("CARGO_PKG_VERSION"),
+            "timestamp": timestamp,
+        },
+        root.to_json(&shown_root.to_string_lossy()),
+    ])
+}
+
+/// Write the scanned tree to `path` in ncdu's JSON export format, to be
+/// browsed with `ncdu -f`
+pub fn export(
+    scan_root: &Path,
+    cache_items: &[CacheItem],
+    log_files: &[LogFile],
+    files: &[SizedFile],
+    path: &Path,
+) -> Result<(), CleanerError> {
+    let document = build_export(scan_root, cache_items, log_files, files);
+    let content = serde_json::to_string(&document).map_err(|e| CleanerError::Io {
+        path: path.to_path_buf(),
+        kind: io::ErrorKind::Other,
+        message: e.to_string(),
+    })?;
+    fs::write(path, content).map_err(|e| CleanerError::from_io(path, &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use std::path::PathBuf;
+
+    fn file(path: &str, size: u64) -> SizedFile {
+        SizedFile {
+            path: PathBuf::from(path),
+            apparent_size: size,
+            disk_size: 4096,
+        }
+    }
+
+    #[test]
+    fn test_ncdu_export_tree() {
+        let files = vec![
+            file("/home/u/.cache/a/one", 10),
+            file("/home/u/.cache/a/sub/two", 20),
+            file("/home/u/dropped/three", 30),
+        ];
+        let cache = CacheItem {
+            size_bytes: Some(0),
+            file_count: Some(0),
+            ..CacheItem::new("/home/u/.cache/a", CacheType::UserCache, "")
+        };
+        let document = build_export(Path::new("/home/u"), &[cache], &[], &files);
+
+        assert_eq!(document[0], 1);
+        assert_eq!(document[2]["progname"], env!("CARGO_PKG_NAME"));
+        let root = &document[3];
+        assert_eq!(root[0]["name"], "/home/u");
+        assert_eq!(root.as_array().unwrap().len(), 2);
+
+        let cache = &root[1];
+        assert_eq!(cache[0]["name"], ".cache");
+        let a = &cache[1];
+        assert_eq!(a[1], json!({"name": "one", "asize": 10, "dsize": 4096}));
+        assert_eq!(a[2][0]["name"], "sub");
+        assert_eq!(a[2][1]["asize"], 20);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::error::CleanerError;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Object types whose reachability is known; anything else is kept
+const PRUNABLE_EXTENSIONS: &[&str] = &[
+    "commit",
+    "commitmeta",
+    "dirtree",
+    "dirmeta",
+    "file",
+    "filez",
+];
+
+/// Length of a SHA-256 object checksum
+const CHECKSUM_LEN: usize = 32;
+
+/// Objects of the ostree repository at `repo` that no ref reaches, the same
+/// set `ostree prune --refs-only` would delete. Commits with a
+/// `state/<checksum>.commitpartial` marker belong to a pull that is in
+/// progress or was interrupted: they are kept as if a ref reached them, and
+/// the objects they are still missing are not an error. Fails instead of
+/// guessing when any other reachable object is missing or can't be parsed.
+pub fn unreferenced_objects(repo: &Path) -> Result<Vec<PathBuf>, CleanerError> {
+    let mut refs = Vec::new();
+    for refs_dir in ["refs/heads", "refs/remotes", "refs/mirrors"] {
+        read_refs(&repo.join(refs_dir), &mut refs)?;
+    }
+    let partial = partial_commits(repo)?;
+
+    // Commits to visit, and whether they are only reached as a parent
+    let mut commits: Vec<(String, bool)> = refs
+        .into_iter()
+        .chain(partial.iter().cloned())
+        .map(|c| (c, false))
+        .collect();
+    let mut reachable = HashSet::new();
+    while let Some((commit, is_parent)) = commits.pop() {
+        if !reachable.insert(format!("{}.commit", commit)) {
+            continue;
+        }
+        reachable.insert(format!("{}.commitmeta", commit));
+        let is_partial = partial.contains(&commit);
+
+        let path = object_path(repo, &commit, "commit");
+        // Pulls with limited depth leave parents out; history ends there
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && (is_parent || is_partial) => {
+                continue;
+            }
+            Err(e) => return Err(CleanerError::from_io(&path, &e)),
+        };
+        let commit = parse_commit(&data).ok_or_else(|| malformed(&path))?;
+        if let Some(parent) = commit.parent {
+            commits.push((parent, true));
+        }
+        reachable.insert(format!("{}.dirmeta", commit.root_meta));
+        mark_tree(repo, &commit.root_tree, is_partial, &mut reachable)?;
+    }
+
+    let objects_dir = repo.join("objects");
+    let mut unreferenced = Vec::new();
+    for prefix in read_dir(&objects_dir)? {
+        for object in read_dir(&prefix)? {
+            let (Some(prefix_name), Some(name)) = (file_name(&prefix), file_name(&object)) else {
+                continue;
+            };
+            let Some((_, extension)) = name.rsplit_once('.') else {
+                continue;
+            };
+            let id = format!("{}{}", prefix_name, name);
+            if PRUNABLE_EXTENSIONS.contains(&extension) && !reachable.contains(&id) {
+                unreferenced.push(object);
+            }
+        }
+    }
+    unreferenced.sort();
+    Ok(unreferenced)
+}
+
+/// Mark a dirtree and everything below it as reachable. Trees of a partial
+/// commit may not have been pulled yet.
+fn mark_tree(
+    repo: &Path,
+    tree: &str,
+    partial: bool,
+    reachable: &mut HashSet<String>,
+) -> Result<(), CleanerError> {
+    let mut trees = vec![tree.to_string()];
+    while let Some(tree) = trees.pop() {
+        if !reachable.insert(format!("{}.dirtree", tree)) {
+            continue;
+        }
+        let path = object_path(repo, &tree, "dirtree");
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && partial => continue,
+            Err(e) => return Err(CleanerError::from_io(&path, &e)),
+        };
+        let dirtree = parse_dirtree(&data).ok_or_else(|| malformed(&path))?;
+
+        for file in dirtree.files {
+            reachable.insert(format!("{}.file", file));
+            reachable.insert(format!("{}.filez", file));
+        }
+        for (subtree, meta) in dirtree.dirs {
+            reachable.insert(format!("{}.dirmeta", meta));
+            trees.push(subtree);
+        }
+    }
+    Ok(())
+}
+
+/// Collect the commit checksums of every ref file below `dir`
+fn read_refs(dir: &Path, commits: &mut Vec<String>) -> Result<(), CleanerError> {
+    for path in read_dir(dir)? {
+        if path.is_dir() {
+            read_refs(&path, commits)?;
+            continue;
+        }
+        let content = fs::read_to_string(&path).map_err(|e| CleanerError::from_io(&path, &e))?;
+        let checksum = content.trim();
+        if checksum.len() != CHECKSUM_LEN * 2 || !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(malformed(&path));
+        }
+        commits.push(checksum.to_ascii_lowercase());
+    }
+    Ok(())
+}
+
+/// Checksums of the commits a pull has not finished, from their
+/// `state/<checksum>.commitpartial` markers
+fn partial_commits(repo: &Path) -> Result<HashSet<String>, CleanerError> {
+    Ok(read_dir(&repo.join("state"))?
+        .iter()
+        .filter_map(|path| file_name(path)?.strip_suffix(".commitpartial"))
+        .filter(|checksum| {
+            checksum.len() == CHECKSUM_LEN * 2 && checksum.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+        .map(str::to_ascii_lowercase)
+        .collect())
+}
+
+/// Entries of `dir`, or none when it doesn't exist
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, CleanerError> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.flatten().map(|e| e.path()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(CleanerError::from_io(dir, &e)),
+    }
+}
+
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|n| n.to_str())
+}
+
+fn object_path(repo: &Path, checksum: &str, extension: &str) -> PathBuf {
+    let (prefix, rest) = checksum.split_at(2);
+    repo.join("objects")
+        .join(prefix)
+        .join(format!("{}.{}", rest, extension))
+}
+
+fn malformed(path: &Path) -> CleanerError {
+    CleanerError::Skipped {
+        path: path.to_path_buf(),
+        reason: "unreadable ostree metadata, not pruning this repository".to_string(),
+    }
+}
+
+/// The parts of a commit object that reference other objects
+struct Commit {
+    parent: Option<String>,
+    root_tree: String,
+    root_meta: String,
+}
+
+/// Subdirectory entries are `(tree, meta)` checksum pairs
+struct DirTree {
+    files: Vec<String>,
+    dirs: Vec<(String, String)>,
+}
+
+/// Parse a commit, GVariant `(a{sv}aya(say)sstayay)`
+fn parse_commit(data: &[u8]) -> Option<Commit> {
+    let variable = (1, None);
+    let members = gvariant::tuple(
+        data,
+        &[
+            (8, None),
+            variable,
+            variable,
+            variable,
+            variable,
+            (8, Some(8)),
+            variable,
+            variable,
+        ],
+    )?;
+    let parent = match members[1].len() {
+        0 => None,
+        _ => Some(checksum(members[1])?),
+    };
+    Some(Commit {
+        parent,
+        root_tree: checksum(members[6])?,
+        root_meta: checksum(members[7])?,
+    })
+}
+
+/// Parse a dirtree, GVariant `(a(say)a(sayay))`
+fn parse_dirtree(data: &[u8]) -> Option<DirTree> {
+    let members = gvariant::tuple(data, &[(1, None), (1, None)])?;
+    let files = gvariant::array(members[0], 1)?
+        .into_iter()
+        .map(|entry| checksum(gvariant::tuple(entry, &[(1, None), (1, None)])?[1]))
+        .collect::<Option<_>>()?;
+    let dirs = gvariant::array(members[1], 1)?
+        .into_iter()
+        .map(|entry| {
+            let fields = gvariant::tuple(entry, &[(1, None), (1, None), (1, None)])?;
+            Some((checksum(fields[1])?, checksum(fields[2])?))
+        })
+        .collect::<Option<_>>()?;
+    Some(DirTree { files, dirs })
+}
+
+fn checksum(bytes: &[u8]) -> Option<String> {
+    (bytes.len() == CHECKSUM_LEN).then(|| bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Just enough of the GVariant serialization format to walk ostree metadata
+mod gvariant {
+    /// Size of the framing offsets in a container of `len` bytes
+    fn offset_size(len: usize) -> usize {
+        match len {
+            0..=0xff => 1,
+            0x100..=0xffff => 2,
+            0x1_0000..=0xffff_ffff => 4,
+            _ => 8,
+        }
+    }
+
+    fn read_offset(data: &[u8], at: usize, size: usize) -> Option<usize> {
+        let bytes = data.get(at..at.checked_add(size)?)?;
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0usize, |value, byte| (value << 8) | usize::from(*byte)),
+        )
+    }
+
+    fn align(position: usize, alignment: usize) -> usize {
+        position.div_ceil(alignment) * alignment
+    }
+
+    /// Split a tuple into its members, each given as its alignment and, for
+    /// fixed-size types, its size
+    pub fn tuple<'a>(data: &'a [u8], members: &[(usize, Option<usize>)]) -> Option<Vec<&'a [u8]>> {
+        let size = offset_size(data.len());
+        let mut fields = Vec::with_capacity(members.len());
+        let mut position = 0;
+        let mut frames = 0;
+
+        for (index, (alignment, fixed)) in members.iter().enumerate() {
+            let start = align(position, *alignment);
+            let end = match fixed {
+                Some(len) => start + len,
+                None if index == members.len() - 1 => data.len().checked_sub(frames * size)?,
+                None => {
+                    frames += 1;
+                    read_offset(data, data.len().checked_sub(frames * size)?, size)?
+                }
+            };
+            fields.push(data.get(start..end)?);
+            position = end;
+        }
+        Some(fields)
+    }
+
+    /// Split an array of variable-size elements into its elements
+    pub fn array(data: &[u8], alignment: usize) -> Option<Vec<&[u8]>> {
+        if data.is_empty() {
+            return Some(Vec::new());
+        }
+        let size = offset_size(data.len());
+        let table = read_offset(data, data.len().checked_sub(size)?, size)?;
+        let table_len = data.len().checked_sub(table)?;
+        if table_len % size != 0 {
+            return None;
+        }
+
+        let mut elements = Vec::with_capacity(table_len / size);
+        let mut position = 0;
+        for index in 0..table_len / size {
+            let end = read_offset(data, table + index * size, size)?;
+            elements.push(data.get(align(position, alignment)..end)?);
+            position = end;
+        }
+        Some(elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Serialize a tuple of byte strings with one-byte framing offsets;
+    /// `fixed` marks members without a framing offset
+    fn tuple(members: &[(&[u8], usize, bool)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut frames = Vec::new();
+        for (index, (bytes, alignment, fixed)) in members.iter().enumerate() {
+            while data.len() % alignment != 0 {
+                data.push(0);
+            }
+            data.extend_from_slice(bytes);
+            if !fixed && index != members.len() - 1 {
+                frames.push(data.len() as u8);
+            }
+        }
+        data.extend(frames.iter().rev());
+        data
+    }
+
+    fn var(bytes: &[u8]) -> (&[u8], usize, bool) {
+        (bytes, 1, false)
+    }
+
+    fn array(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut ends = Vec::new();
+        for element in elements {
+            data.extend_from_slice(element);
+            ends.push(data.len() as u8);
+        }
+        data.extend(ends);
+        data
+    }
+
+    fn write_object(repo: &Path, id: u8, extension: &str, data: &[u8]) -> PathBuf {
+        let path = object_path(repo, &checksum(&[id; 32]).unwrap(), extension);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_unreferenced_objects() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let subtree = tuple(&[
+            var(&array(&[tuple(&[var(b"b\0"), var(&[3; 32])])])),
+            var(&[]),
+        ]);
+        let root = tuple(&[
+            var(&array(&[tuple(&[var(b"a\0"), var(&[2; 32])])])),
+            var(&array(&[tuple(&[
+                var(b"sub\0"),
+                var(&[4; 32]),
+                var(&[5; 32]),
+            ])])),
+        ]);
+        let commit = tuple(&[
+            (&[], 8, false),
+            var(&[]),
+            var(&[]),
+            var(b"\0"),
+            var(b"\0"),
+            (&[0; 8], 8, true),
+            var(&[6; 32]),
+            var(&[7; 32]),
+        ]);
+
+        write_object(repo, 1, "commit", &commit);
+        write_object(repo, 2, "file", b"a");
+        write_object(repo, 3, "file", b"b");
+        write_object(repo, 4, "dirtree", &subtree);
+        write_object(repo, 5, "dirmeta", b"");
+        write_object(repo, 6, "dirtree", &root);
+        write_object(repo, 7, "dirmeta", b"");
+        let orphan = write_object(repo, 9, "file", b"orphan");
+        let old_commit = write_object(repo, 10, "commit", &commit);
+
+        let heads = repo.join("refs/heads/app");
+        fs::create_dir_all(heads.parent().unwrap()).unwrap();
+        fs::write(&heads, format!("{}\n", checksum(&[1; 32]).unwrap())).unwrap();
+
+        assert_eq!(
+            unreferenced_objects(repo).unwrap(),
+            vec![orphan.clone(), old_commit]
+        );
+
+        // Partial commits are kept, even before their commit object arrived
+        let state = repo.join("state");
+        fs::create_dir_all(&state).unwrap();
+        for id in [10, 11] {
+            let marker = state.join(format!("{}.commitpartial", checksum(&[id; 32]).unwrap()));
+            fs::write(marker, "").unwrap();
+        }
+        assert_eq!(unreferenced_objects(repo).unwrap(), vec![orphan]);
+
+        fs::remove_file(object_path(repo, &checksum(&[4; 32]).unwrap(), "dirtree")).unwrap();
+        fs::remove_dir_all(&state).unwrap();
+        assert!(unreferenced_objects(repo).is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use crate::ages::AgeUsage;
+use crate::apps::AppUsage;
+use crate::audit::AuditRecord;
+use crate::cache_detector::{CacheItem, CacheType, CategoryUsage};
+use crate::error::CleanerError;
+use crate::events::EventRecord;
+use crate::file_operations::OperationResult;
+use crate::growth::GrowthReport;
+use crate::history::{RootStats, ScanDiff};
+use crate::log_cleaner::{Confidence, LogFile, LogType};
+use crate::monitor::MonitorEvent;
+use crate::mounts::MountUsage;
+use crate::owners::OwnerUsage;
+use crate::plan::Plan;
+use crate::redact;
+use crate::temperature::Temperature;
+use crate::writers::{HeldOpenFile, Writer};
+use chrono::{DateTime, Utc};
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::fd::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Version of the machine-readable output contracts, bumped on breaking changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Names accepted by `cleaner schema`
+pub const SCHEMA_NAMES: &[&str] = &[
+    "scan-report",
+    "scan-summary",
+    "clean-report",
+    "report",
+    "diff",
+    "stats",
+    "plan",
+    "audit-record",
+    "result",
+    "monitor-event",
+    "event",
+];
+
+/// The real stdout once `--output json` moved everything else to stderr
+static JSON_STDOUT: Mutex<Option<File>> = Mutex::new(None);
+
+/// How a run prints its findings and results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON document per line on stdout, everything else on stderr
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format '{}' (text or json)", name)),
+        }
+    }
+}
+
+/// Keep stdout for JSON documents: everything printed from now on, prompts
+/// included, goes to stderr instead
+pub fn reserve_stdout_for_json() -> io::Result<()> {
+    io::stdout().flush()?;
+    let saved = unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_DUPFD_CLOEXEC, 0) };
+    if saved < 0 || unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    *JSON_STDOUT.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some(unsafe { File::from_raw_fd(saved) });
+    Ok(())
+}
+
+/// Print one output document as a single line of JSON on stdout
+pub fn print_json<T: Serialize>(value: &T) -> io::Result<()> {
+    let line = serde_json::to_string(value).map_err(io::Error::other)?;
+    let mut json_stdout = JSON_STDOUT.lock().unwrap_or_else(|e| e.into_inner());
+    match json_stdout.as_mut() {
+        Some(file) => writeln!(file, "{}", line),
+        None => writeln!(io::stdout(), "{}", line),
+    }
+}
+
+/// Machine-readable result of a scan
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ScanOutput {
+    pub schema_version: u32,
+    pub scan_root: PathBuf,
+    pub cache_items: Vec<CacheItemOutput>,
+    pub log_files: Vec<LogFileOutput>,
+    /// Non-fatal issues hit while scanning
+    pub warnings: Vec<String>,
+    /// Reclaimable space per filesystem
+    pub by_filesystem: Vec<MountUsage>,
+    /// Reclaimable space by time since last modification
+    pub by_age: Vec<AgeUsage>,
+    pub total_bytes: u64,
+}
+
+/// Totals of a scan without its items, for `--summary --output json`; the
+/// full listing runs to megabytes on big hosts
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ScanSummaryOutput {
+    pub schema_version: u32,
+    pub scan_root: PathBuf,
+    pub total_items: usize,
+    pub total_bytes: u64,
+    /// Number of non-fatal issues hit while scanning
+    pub warnings: usize,
+    /// Reclaimable space per category, as `--clean-types` names them
+    pub by_type: Vec<CategoryUsage>,
+    /// Reclaimable space per filesystem
+    pub by_filesystem: Vec<MountUsage>,
+    /// Reclaimable space by time since last modification
+    pub by_age: Vec<AgeUsage>,
+}
+
+/// One detected cache item
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CacheItemOutput {
+    pub path: PathBuf,
+    /// Symlink target that is cleaned instead of `path`
+    pub resolved_path: Option<PathBuf>,
+    pub cache_type: CacheType,
+    pub size_bytes: Option<u64>,
+    pub file_count: Option<usize>,
+    /// Whether the size and file count were extrapolated from a sample
+    pub size_estimated: bool,
+    pub modified: Option<DateTime<Utc>>,
+    /// How actively the item is used
+    pub temperature: Option<Temperature>,
+    /// Why the item was picked up, e.g. the pattern that matched
+    pub reason: String,
+}
+
+/// One old log file
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LogFileOutput {
+    pub path: PathBuf,
+    pub log_type: LogType,
+    /// How sure the classifier is of `log_type`
+    pub confidence: Confidence,
+    /// A running process writing to the file, which is emptied or kept
+    /// rather than deleted
+    pub writer: Option<Writer>,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+    pub age_secs: u64,
+    /// Why the file was picked up, e.g. the age rule it exceeds
+    pub reason: String,
+}
+
+impl CleanOutput {
+    /// Summarize the results of cleaning `cache_items` and `log_files`,
+    /// given in the same order as the items
+    pub fn new(
+        dry_run: bool,
+        cache_items: &[CacheItem],
+        log_files: &[LogFile],
+        cache_results: &[OperationResult],
+        log_results: &[OperationResult],
+    ) -> Self {
+        let paths = cache_items
+            .iter()
+            .map(|i| &i.path)
+            .chain(log_files.iter().map(|l| &l.path));
+        let results: Vec<OperationOutput> = paths
+            .zip(cache_results.iter().chain(log_results))
+            .map(|(path, result)| OperationOutput {
+                path: redact::path(path),
+                success: result.success,
+                error: result.error.as_ref().map(redact::error),
+                bytes_freed: result.bytes_freed,
+            })
+            .collect();
+        Self {
+            schema_version: SCHEMA_VERSION,
+            dry_run,
+            total_items: results.len(),
+            successful: results.iter().filter(|r| r.success).count(),
+            failed: results.iter().filter(|r| !r.success).count(),
+            permission_denied: cache_results
+                .iter()
+                .chain(log_results)
+                .filter(|r| matches!(r.error, Some(CleanerError::PermissionDenied(_))))
+                .count(),
+            bytes_freed: results.iter().map(|r| r.bytes_freed).sum(),
+            held_open: Vec::new(),
+            results,
+        }
+    }
+}
+
+impl From<&CacheItem> for CacheItemOutput {
+    fn from(item: &CacheItem) -> Self {
+        Self {
+            path: redact::path(&item.path),
+            resolved_path: item.resolved_path.as_deref().map(redact::path),
+            cache_type: item.cache_type.clone(),
+            size_bytes: item.size_bytes,
+            file_count: item.file_count,
+            size_estimated: item.size_estimated,
+            modified: item.last_modified.map(DateTime::<Utc>::from),
+            temperature: item.temperature,
+            reason: item.reason.clone(),
+        }
+    }
+}
+
+impl From<&LogFile> for LogFileOutput {
+    fn from(log: &LogFile) -> Self {
+        Self {
+            path: redact::path(&log.path),
+            log_type: log.log_type.clone(),
+            confidence: log.confidence,
+            writer: log.writer.clone(),
+            size_bytes: log.size_bytes,
+            modified: DateTime::<Utc>::from(log.last_modified),
+            age_secs: log.age.as_secs(),
+            reason: log.reason.clone(),
+        }
+    }
+}
+
+/// Machine-readable result of a clean or dry run
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CleanOutput {
+    pub schema_version: u32,
+    pub dry_run: bool,
+    /// Outcome per item, left out with `--summary`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<OperationOutput>,
+    pub total_items: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub permission_denied: usize,
+    pub bytes_freed: u64,
+    /// Deleted files processes still hold open, whose space isn't free yet
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub held_open: Vec<HeldOpenFile>,
+}
+
+/// Outcome of deleting one item
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OperationOutput {
+    pub path: PathBuf,
+    pub success: bool,
+    pub error: Option<String>,
+    pub bytes_freed: u64,
+}
+
+/// Machine-readable breakdown written by `cleaner report --output`
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ReportOutput {
+    pub schema_version: u32,
+    pub scan_root: PathBuf,
+    pub total_items: usize,
+    pub total_bytes: u64,
+    /// False when the scan was stopped by `--timeout` and only covers part of the tree
+    pub complete: bool,
+    /// Space deleting everything would really free, when `--actual-savings` was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reclaimable_bytes: Option<u64>,
+    /// Reclaimable space per filesystem
+    pub by_filesystem: Vec<MountUsage>,
+    /// Reclaimable space per owning user
+    pub by_owner: Vec<OwnerUsage>,
+    /// Reclaimable space by time since last modification
+    pub by_age: Vec<AgeUsage>,
+    /// Growth rates and fill-up projections, once the scan history allows them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub growth: Option<GrowthReport>,
+    /// Reclaimable space per owning application, when `--by-app` was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_app: Option<Vec<AppUsage>>,
+}
+
+/// Summary of the scan history printed by `cleaner stats --output json`
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct StatsOutput {
+    pub schema_version: u32,
+    /// One entry per scan root, in path order
+    pub roots: Vec<RootStats>,
+}
+
+/// Final outcome of a run, written by `--result-file` however the run ends
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RunResult {
+    pub schema_version: u32,
+    pub status: RunStatus,
+    pub exit_code: i32,
+    /// What made the run fail, for failed runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Scan root, once it is known
+    pub scan_root: Option<PathBuf>,
+    pub dry_run: bool,
+    /// Items found by the scan, before any cleaning
+    pub items_found: usize,
+    pub bytes_found: u64,
+    /// Items cleaned successfully (or that would be, in a dry run)
+    pub items_cleaned: usize,
+    pub items_failed: usize,
+    pub bytes_freed: u64,
+    /// Whether anything was actually deleted, with `--changed-when-freed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<bool>,
+}
+
+/// How a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// Still running; only seen if the process died without a chance to finish
+    Running,
+    Success,
+    Failed,
+    /// Stopped by a signal
+    Interrupted,
+    /// Skipped because the machine stayed on battery or busy
+    Deferred,
+}
+
+/// The JSON Schema of one output format, or `None` for an unknown name
+pub fn schema(name: &str) -> Option<Schema> {
+    let mut schema = match name {
+        "scan-report" => schema_for!(ScanOutput),
+        "scan-summary" => schema_for!(ScanSummaryOutput),
+        "clean-report" => schema_for!(CleanOutput),
+        "report" => schema_for!(ReportOutput),
+        "diff" => schema_for!(ScanDiff),
+        "stats" => schema_for!(StatsOutput),
+        "plan" => schema_for!(Plan),
+        "audit-record" => schema_for!(AuditRecord),
+        "result" => schema_for!(RunResult),
+        "monitor-event" => schema_for!(MonitorEvent),
+        "event" => schema_for!(EventRecord),
+        _ => return None,
+    };
+    schema.insert(
+        "$id".to_string(),
+        format!("urn:cleaner:{}:v{}", name, SCHEMA_VERSION).into(),
+    );
+    Some(schema)
+}
+
+/// Write one output document as pretty-printed JSON
+pub fn write_json<T: Serialize>(value: &T, path: &Path) -> Result<(), CleanerError> {
+    let content = serde_json::to_string_pretty(value).map_err(|e| CleanerError::Io {
+        path: path.to_path_buf(),
+        kind: io::ErrorKind::Other,
+        message: e.to_string(),
+    })?;
+    fs::write(path, content).map_err(|e| CleanerError::from_io(path, &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemas_are_versioned() {
+        for name in SCHEMA_NAMES {
+            let schema = schema(name).unwrap();
+            assert_eq!(
+                schema.get("$id").and_then(|id| id.as_str()),
+                Some(format!("urn:cleaner:{}:v{}", name, SCHEMA_VERSION).as_str())
+            );
+        }
+        assert!(schema("nope").is_none());
+
+        let plan = serde_json::to_value(schema("plan").unwrap()).unwrap();
+        assert!(plan["properties"]["entries"].is_object());
+    }
+
+    #[test]
+    fn test_clean_output() {
+        let log = |path: &str| LogFile {
+            path: PathBuf::from(path),
+            size_bytes: 10,
+            last_modified: std::time::SystemTime::now(),
+            age: std::time::Duration::ZERO,
+            log_type: LogType::Application,
+            confidence: Confidence::Medium,
+            owner: None,
+            writer: None,
+            file_id: None,
+            reason: String::new(),
+        };
+        let result = |success: bool, error: Option<CleanerError>| OperationResult {
+            success,
+            error,
+            bytes_freed: if success { 10 } else { 0 },
+        };
+        let output = CleanOutput::new(
+            false,
+            &[],
+            &[log("/var/log/a.log"), log("/var/log/b.log")],
+            &[],
+            &[
+                result(true, None),
+                result(
+                    false,
+                    Some(CleanerError::PermissionDenied(PathBuf::from(
+                        "/var/log/b.log",
+                    ))),
+                ),
+            ],
+        );
+        assert_eq!((output.successful, output.failed), (1, 1));
+        assert_eq!(output.permission_denied, 1);
+        assert_eq!(output.bytes_freed, 10);
+        assert_eq!(output.results[1].path, PathBuf::from("/var/log/b.log"));
+        assert_eq!(OutputFormat::parse("json"), Ok(OutputFormat::Json));
+        assert!(OutputFormat::parse("yaml").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Buffer size for the passwd lookups; plenty for a single entry
+const PASSWD_BUFFER: usize = 4096;
+
+const PASSWD_PATH: &str = "/etc/passwd";
+
+const LOGIN_DEFS_PATH: &str = "/etc/login.defs";
+
+/// Uids of regular users when /etc/login.defs doesn't set `UID_MIN` and
+/// `UID_MAX`
+const DEFAULT_UID_RANGE: (u32, u32) = (1000, 60000);
+
+/// Shells of accounts nobody logs in to
+const NO_LOGIN_SHELLS: &[&str] = &["nologin", "false", "true"];
+
+/// Owning user and group of a scanned item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Owner {
+    pub fn of(metadata: &Metadata) -> Self {
+        Self {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+        }
+    }
+
+    /// Owner of `path`, following symlinks to the item that would be cleaned
+    pub fn of_path(path: &Path) -> Option<Self> {
+        fs::metadata(path).ok().map(|m| Self::of(&m))
+    }
+}
+
+/// Reclaimable space owned by one user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct OwnerUsage {
+    /// User name, or the numeric UID when it has no passwd entry
+    pub owner: String,
+    pub uid: Option<u32>,
+    pub items: usize,
+    pub bytes: u64,
+}
+
+/// Resolve `--owner`: a user name or a numeric UID
+pub fn resolve_user(name: &str) -> Result<u32, String> {
+    if let Ok(uid) = name.parse::<u32>() {
+        return Ok(uid);
+    }
+
+    let c_name = CString::new(name).map_err(|_| format!("invalid user name '{}'", name))?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; PASSWD_BUFFER];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+
+    if status == 0 && !result.is_null() {
+        Ok(passwd.pw_uid)
+    } else {
+        Err(format!("unknown user '{}'", name))
+    }
+}
+
+/// Name of the user with `uid`, or the UID itself when it has no passwd entry
+pub fn user_name(uid: u32) -> String {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; PASSWD_BUFFER];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+
+    if status == 0 && !result.is_null() {
+        unsafe { CStr::from_ptr(passwd.pw_name) }
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        uid.to_string()
+    }
+}
+
+/// Home directory of the user called `name`
+pub fn home_dir(name: &str) -> Option<PathBuf> {
+    let c_name = CString::new(name).ok()?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; PASSWD_BUFFER];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+
+    (status == 0 && !result.is_null()).then(|| {
+        PathBuf::from(
+            unsafe { CStr::from_ptr(passwd.pw_dir) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+    })
+}
+
+/// Home directories of the login accounts in /etc/passwd that exist, other
+/// than `/`. Login accounts are those of regular users, with a uid between
+/// `UID_MIN` and `UID_MAX` of /etc/login.defs, and a shell to log in with;
+/// root and the service accounts of daemons, whose homes are their data, are
+/// left out.
+pub fn user_homes() -> Vec<PathBuf> {
+    let uids = uid_range(&fs::read_to_string(LOGIN_DEFS_PATH).unwrap_or_default());
+    parse_homes(&fs::read_to_string(PASSWD_PATH).unwrap_or_default(), uids)
+        .into_iter()
+        .filter(|home| home.is_dir())
+        .collect()
+}
+
+/// The uids of regular users set by login.defs
+fn uid_range(login_defs: &str) -> (u32, u32) {
+    let setting = |key: &str| {
+        login_defs.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next() == Some(key))
+                .then(|| fields.next()?.parse().ok())
+                .flatten()
+        })
+    };
+    (
+        setting("UID_MIN").unwrap_or(DEFAULT_UID_RANGE.0),
+        setting("UID_MAX").unwrap_or(DEFAULT_UID_RANGE.1),
+    )
+}
+
+/// The distinct home directory fields of passwd entries of login accounts
+/// with a uid in `uids`
+fn parse_homes(passwd: &str, (uid_min, uid_max): (u32, u32)) -> Vec<PathBuf> {
+    let mut homes: Vec<PathBuf> = Vec::new();
+    for line in passwd.lines().filter(|l| !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split(':').collect();
+        let [_, _, uid, _, _, home, shell] = fields[..] else {
+            continue;
+        };
+        let Ok(uid) = uid.parse::<u32>() else {
+            continue;
+        };
+        let shell = Path::new(shell).file_name().and_then(|name| name.to_str());
+        if !(uid_min..=uid_max).contains(&uid)
+            || shell.is_none_or(|shell| NO_LOGIN_SHELLS.contains(&shell))
+        {
+            continue;
+        }
+        let home = PathBuf::from(home);
+        if home.is_absolute() && home.parent().is_some() && !homes.contains(&home) {
+            homes.push(home);
+        }
+    }
+    homes
+}
+
+/// Aggregate item sizes per owning user, largest first
+pub fn usage_by_owner(sizes: impl IntoIterator<Item = (Option<Owner>, u64)>) -> Vec<OwnerUsage> {
+    let mut usage: BTreeMap<Option<u32>, (usize, u64)> = BTreeMap::new();
+    for (owner, bytes) in sizes {
+        let entry = usage.entry(owner.map(|o| o.uid)).or_default();
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+
+    let mut usage: Vec<OwnerUsage> = usage
+        .into_iter()
+        .map(|(uid, (items, bytes))| OwnerUsage {
+            owner: uid.map_or_else(|| "unknown".to_string(), user_name),
+            uid,
+            items,
+            bytes,
+        })
+        .collect();
+    usage.sort_by_key(|u| Reverse(u.bytes));
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_users() {
+        assert_eq!(resolve_user("root"), Ok(0));
+        assert_eq!(resolve_user("4242"), Ok(4242));
+        assert!(
+            resolve_user("no-such-user-here")
+                .unwrap_err()
+                .contains("unknown user")
+        );
+        assert_eq!(user_name(0), "root");
+        assert_eq!(home_dir("root"), Some(PathBuf::from("/root")));
+        let uids = uid_range("# comment\nUID_MIN\t\t 500\nSYS_UID_MAX 499\n");
+        assert_eq!(uids, (500, 60000));
+        assert_eq!(
+            parse_homes(
+                "root:x:0:0:root:/root:/bin/bash\n\
+                 daemon:x:1:1::/:/usr/sbin/nologin\n\
+                 pg:x:120:120::/var/lib/postgresql:/bin/sh\n\
+                 alice:x:500:500::/home/alice:/bin/zsh\n\
+                 bob:x:1001:1001::/srv/bob:/bin/bash\n\
+                 build:x:1002:1002::/var/lib/build:/usr/sbin/nologin\n\
+                 guest:x:1003:1003::/home/guest:/bin/false\n\
+                 nobody:x:65534:65534::/nonexistent:/bin/sh\n\
+                 broken\n",
+                uids
+            ),
+            [PathBuf::from("/home/alice"), PathBuf::from("/srv/bob")]
+        );
+
+        let root = Some(Owner { uid: 0, gid: 0 });
+        let usage = usage_by_owner([(root, 5), (None, 7), (root, 10)]);
+        assert_eq!(usage[0].owner, "root");
+        assert_eq!((usage[0].items, usage[0].bytes), (2, 15));
+        assert_eq!(usage[1].owner, "unknown");
+    }
+}
@@ -0,0 +1,135 @@
+use crate::cache_detector::CacheItem;
+use crate::file_operations::format_bytes;
+use crate::log_cleaner::LogFile;
+use inquire::{InquireError, MultiSelect};
+use std::fmt;
+use std::io;
+
+/// Rows shown at once in the picker
+const PAGE_SIZE: usize = 20;
+
+/// Which detected item a picker row stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Cache(usize),
+    Log(usize),
+}
+
+/// One row of the picker, matched against what the user types
+#[derive(Debug, Clone)]
+struct Row {
+    label: String,
+    source: Source,
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+fn rows(cache_items: &[CacheItem], log_files: &[LogFile]) -> Vec<Row> {
+    let cache_rows = cache_items.iter().enumerate().map(|(index, item)| Row {
+        label: format!(
+            "{:>10}  {}",
+            item.size_bytes.map(format_bytes).unwrap_or_default(),
+            item.path.display()
+        ),
+        source: Source::Cache(index),
+    });
+    let log_rows = log_files.iter().enumerate().map(|(index, log)| Row {
+        label: format!(
+            "{:>10}  {}",
+            format_bytes(log.size_bytes),
+            log.path.display()
+        ),
+        source: Source::Log(index),
+    });
+    cache_rows.chain(log_rows).collect()
+}
+
+/// Keep only the items behind the selected rows
+fn retain_selected(
+    selected: &[Row],
+    cache_items: Vec<CacheItem>,
+    log_files: Vec<LogFile>,
+) -> (Vec<CacheItem>, Vec<LogFile>) {
+    let is_selected = |source| selected.iter().any(|row| row.source == source);
+    let cache_items = cache_items
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| is_selected(Source::Cache(*index)))
+        .map(|(_, item)| item)
+        .collect();
+    let log_files = log_files
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| is_selected(Source::Log(*index)))
+        .map(|(_, log)| log)
+        .collect();
+    (cache_items, log_files)
+}
+
+/// Let the user fuzzy-filter the detected items by typing fragments of their
+/// paths and mark the ones to clean. Cancelling selects nothing.
+pub fn pick(
+    cache_items: Vec<CacheItem>,
+    log_files: Vec<LogFile>,
+) -> io::Result<(Vec<CacheItem>, Vec<LogFile>)> {
+    let rows = rows(&cache_items, &log_files);
+    let selected = match MultiSelect::new("Select the items to clean:", rows)
+        .with_page_size(PAGE_SIZE)
+        .with_help_message("type to filter, space to toggle, → all, ← none, enter to confirm")
+        .prompt()
+    {
+        Ok(selected) => selected,
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Vec::new(),
+        Err(InquireError::IO(e)) => return Err(e),
+        Err(e) => return Err(io::Error::other(e.to_string())),
+    };
+
+    Ok(retain_selected(&selected, cache_items, log_files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::log_cleaner::{Confidence, LogType};
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_retain_selected() {
+        let cache_items: Vec<CacheItem> = ["/a/.cache", "/b/.cache"]
+            .iter()
+            .map(|path| CacheItem {
+                size_bytes: Some(2048),
+                file_count: Some(1),
+                ..CacheItem::new(PathBuf::from(path), CacheType::UserCache, "")
+            })
+            .collect();
+        let log_files = vec![LogFile {
+            path: PathBuf::from("/var/log/old.log"),
+            size_bytes: 10,
+            last_modified: SystemTime::now(),
+            age: Duration::from_secs(0),
+            log_type: LogType::Application,
+            confidence: Confidence::Low,
+            owner: None,
+            writer: None,
+            file_id: None,
+            reason: String::new(),
+        }];
+
+        let rows = rows(&cache_items, &log_files);
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].label.ends_with("/b/.cache"));
+
+        let selected = vec![rows[1].clone(), rows[2].clone()];
+        let (cache_items, log_files) = retain_selected(&selected, cache_items, log_files);
+        assert_eq!(cache_items.len(), 1);
+        assert_eq!(cache_items[0].path, PathBuf::from("/b/.cache"));
+        assert_eq!(log_files.len(), 1);
+    }
+}
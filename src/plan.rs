@@ -0,0 +1,330 @@
+use crate::cache_detector::{CacheItem, CacheType, calculate_sizes};
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::log_cleaner::{Confidence, LogFile, LogType};
+use crate::owners::Owner;
+use crate::scan_report::ScanReport;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Version written into new plan files
+const PLAN_VERSION: u32 = 1;
+
+/// A recorded deletion set that `cleaner apply` executes later
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Plan {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub scan_root: PathBuf,
+    pub entries: Vec<PlanEntry>,
+}
+
+/// One item of a plan with the metadata it had when planned
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PlanEntry {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_path: Option<PathBuf>,
+    pub kind: PlanKind,
+    pub size_bytes: Option<u64>,
+    pub file_count: Option<usize>,
+    pub modified: Option<DateTime<Utc>>,
+    /// Integrity check over all other fields, catching corrupted or
+    /// hand-edited entries. It is unkeyed, so it doesn't stop deliberate
+    /// tampering by anyone who can write the plan file.
+    pub checksum: String,
+}
+
+/// What kind of item a plan entry deletes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanKind {
+    Cache(CacheType),
+    Log(LogType),
+}
+
+impl Plan {
+    /// Record the given items together with their current metadata
+    pub fn from_items(scan_root: &Path, cache_items: &[CacheItem], log_files: &[LogFile]) -> Self {
+        let cache_entries = cache_items.iter().map(|item| {
+            PlanEntry::new(
+                item.path.clone(),
+                item.resolved_path.clone(),
+                PlanKind::Cache(item.cache_type.clone()),
+                item.size_bytes,
+                item.file_count,
+            )
+        });
+        let log_entries = log_files.iter().map(|log| {
+            PlanEntry::new(
+                log.path.clone(),
+                None,
+                PlanKind::Log(log.log_type.clone()),
+                Some(log.size_bytes),
+                None,
+            )
+        });
+
+        Self {
+            version: PLAN_VERSION,
+            created_at: Utc::now(),
+            scan_root: fs::canonicalize(scan_root).unwrap_or_else(|_| scan_root.to_path_buf()),
+            entries: cache_entries.chain(log_entries).collect(),
+        }
+    }
+
+    /// Load a plan file, rejecting unknown versions and entries failing
+    /// their integrity check
+    pub fn load_from_file(path: &Path) -> Result<Self, CleanerError> {
+        let content = fs::read_to_string(path).map_err(|e| CleanerError::from_io(path, &e))?;
+        let plan: Plan = serde_json::from_str(&content)
+            .map_err(|e| CleanerError::Plan(format!("{}: {}", path.display(), e)))?;
+
+        if plan.version != PLAN_VERSION {
+            return Err(CleanerError::Plan(format!(
+                "unsupported plan version {} (expected {})",
+                plan.version, PLAN_VERSION
+            )));
+        }
+        if let Some(entry) = plan
+            .entries
+            .iter()
+            .find(|e| e.checksum != e.compute_checksum())
+        {
+            return Err(CleanerError::Plan(format!(
+                "checksum mismatch for {}; the plan is corrupted or was edited",
+                entry.path.display()
+            )));
+        }
+
+        Ok(plan)
+    }
+
+    /// Write the plan as JSON
+    pub fn save_to_file(&self, path: &Path) -> Result<(), CleanerError> {
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| CleanerError::Plan(e.to_string()))?;
+        fs::write(path, content).map_err(|e| CleanerError::from_io(path, &e))
+    }
+
+    /// Check every entry against the filesystem, keeping only unchanged ones
+    pub fn verify(&self, config: &Config) -> Result<ScanReport<PlanEntry>, CleanerError> {
+        let mut report = ScanReport::new();
+
+        // Re-measure directories whose size was recorded
+        let to_size: Vec<CacheItem> = self
+            .entries
+            .iter()
+            .filter(|e| e.size_bytes.is_some() && matches!(e.kind, PlanKind::Cache(_)))
+            .filter_map(|e| e.to_cache_item())
+            .collect();
+        let sized = calculate_sizes(to_size, config)?;
+        report.warnings.extend(sized.warnings);
+
+        for entry in &self.entries {
+            let current_size = match &entry.kind {
+                PlanKind::Cache(_) => sized
+                    .items
+                    .iter()
+                    .find(|item| item.path == entry.path)
+                    .and_then(|item| item.size_bytes),
+                PlanKind::Log(_) => fs::metadata(&entry.path).ok().map(|m| m.len()),
+            };
+
+            match entry.change(current_size) {
+                Some(reason) => report.warnings.push(CleanerError::Skipped {
+                    path: entry.path.clone(),
+                    reason,
+                }),
+                None => report.items.push(entry.clone()),
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl PlanEntry {
+    fn new(
+        path: PathBuf,
+        resolved_path: Option<PathBuf>,
+        kind: PlanKind,
+        size_bytes: Option<u64>,
+        file_count: Option<usize>,
+    ) -> Self {
+        let modified = modified_time(resolved_path.as_deref().unwrap_or(&path));
+        let mut entry = Self {
+            path,
+            resolved_path,
+            kind,
+            size_bytes,
+            file_count,
+            modified,
+            checksum: String::new(),
+        };
+        entry.checksum = entry.compute_checksum();
+        entry
+    }
+
+    fn target_path(&self) -> &Path {
+        self.resolved_path.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Why the entry no longer matches the filesystem, if it doesn't
+    fn change(&self, current_size: Option<u64>) -> Option<String> {
+        if fs::symlink_metadata(&self.path).is_err() {
+            return Some("no longer exists".to_string());
+        }
+        if modified_time(self.target_path()) != self.modified {
+            return Some("modification time changed since planning".to_string());
+        }
+        if self.size_bytes.is_some() && current_size != self.size_bytes {
+            return Some("size changed since planning".to_string());
+        }
+        None
+    }
+
+    /// FNV-1a over the entry's fields: an integrity check, not a MAC
+    fn compute_checksum(&self) -> String {
+        let record = format!(
+            "{}\0{}\0{:?}\0{:?}\0{:?}\0{:?}",
+            self.path.display(),
+            self.resolved_path
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+            self.kind,
+            self.size_bytes,
+            self.file_count,
+            self.modified.map(|m| m.to_rfc3339()),
+        );
+        format!("{:016x}", fnv1a(record.as_bytes()))
+    }
+
+    /// The cache item this entry deletes, if it is one
+    pub fn to_cache_item(&self) -> Option<CacheItem> {
+        let PlanKind::Cache(cache_type) = &self.kind else {
+            return None;
+        };
+        Some(CacheItem {
+            size_bytes: self.size_bytes,
+            file_count: self.file_count,
+            last_modified: self.modified.map(SystemTime::from),
+            resolved_path: self.resolved_path.clone(),
+            owner: Owner::of_path(self.target_path()),
+            ..CacheItem::new(
+                self.path.clone(),
+                cache_type.clone(),
+                "recorded in the plan",
+            )
+        })
+    }
+
+    /// The log file this entry deletes, if it is one
+    pub fn to_log_file(&self) -> Option<LogFile> {
+        let PlanKind::Log(log_type) = &self.kind else {
+            return None;
+        };
+        let last_modified = self.modified.map(SystemTime::from)?;
+        Some(LogFile {
+            path: self.path.clone(),
+            size_bytes: self.size_bytes.unwrap_or(0),
+            last_modified,
+            age: SystemTime::now()
+                .duration_since(last_modified)
+                .unwrap_or_default(),
+            log_type: log_type.clone(),
+            // The type was classified when the plan was made
+            confidence: Confidence::Medium,
+            owner: Owner::of_path(&self.path),
+            writer: None,
+            // The file that passed the plan's checks before it is applied
+            file_id: fs::symlink_metadata(&self.path)
+                .ok()
+                .map(|metadata| (metadata.dev(), metadata.ino())),
+            reason: "recorded in the plan".to_string(),
+        })
+    }
+}
+
+fn modified_time(path: &Path) -> Option<DateTime<Utc>> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(DateTime::<Utc>::from)
+}
+
+/// 64-bit FNV-1a, stable across Rust versions unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache_item(path: PathBuf) -> CacheItem {
+        CacheItem::new(path, CacheType::UserCache, "")
+    }
+
+    #[test]
+    fn test_plan_round_trip_rejects_edits() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        fs::create_dir(&cache).unwrap();
+        let plan_path = temp_dir.path().join("plan.json");
+
+        let plan = Plan::from_items(temp_dir.path(), &[cache_item(cache)], &[]);
+        plan.save_to_file(&plan_path).unwrap();
+        let loaded = Plan::load_from_file(&plan_path).unwrap();
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(loaded.entries[0].checksum, plan.entries[0].checksum);
+
+        let edited = fs::read_to_string(&plan_path)
+            .unwrap()
+            .replace("user_cache", "system_cache");
+        fs::write(&plan_path, edited).unwrap();
+        assert!(matches!(
+            Plan::load_from_file(&plan_path),
+            Err(CleanerError::Plan(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_refuses_changed_items() {
+        let temp_dir = TempDir::new().unwrap();
+        let stable = temp_dir.path().join("stable");
+        let growing = temp_dir.path().join("growing");
+        for dir in [&stable, &growing] {
+            fs::create_dir(dir).unwrap();
+            fs::write(dir.join("file"), "data").unwrap();
+        }
+
+        let config = Config::default();
+        let items = calculate_sizes(
+            vec![cache_item(stable.clone()), cache_item(growing.clone())],
+            &config,
+        )
+        .unwrap()
+        .items;
+        let plan = Plan::from_items(temp_dir.path(), &items, &[]);
+
+        fs::write(growing.join("file"), "more data").unwrap();
+
+        let report = plan.verify(&config).unwrap();
+        assert_eq!(report.items.len(), 1);
+        assert_eq!(report.items[0].path, stable);
+        assert!(matches!(
+            &report.warnings[..],
+            [CleanerError::Skipped { path, .. }] if *path == growing
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::cache_detector::CacheItem;
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::log_cleaner::LogFile;
+use crate::output::{CacheItemOutput, LogFileOutput, SCHEMA_VERSION};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// What the policy command receives on stdin for one planned deletion
+#[derive(Serialize)]
+struct PolicyRequest<'a> {
+    schema_version: u32,
+    dry_run: bool,
+    #[serde(flatten)]
+    item: PolicyItem<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum PolicyItem<'a> {
+    Cache(&'a CacheItemOutput),
+    Log(&'a LogFileOutput),
+}
+
+/// External command that may veto any planned deletion
+pub struct Policy {
+    command: String,
+    dry_run: bool,
+}
+
+impl Policy {
+    /// The configured policy, if any
+    pub fn new(config: &Config, dry_run: bool) -> Option<Self> {
+        config
+            .safety
+            .policy_command
+            .as_ref()
+            .filter(|command| !command.trim().is_empty())
+            .map(|command| Self {
+                command: command.clone(),
+                dry_run,
+            })
+    }
+
+    /// The veto of the policy command against deleting `item`, if it refuses
+    pub fn check_cache_item(&self, item: &CacheItem) -> Option<CleanerError> {
+        let output = CacheItemOutput::from(item);
+        self.check(&item.path, PolicyItem::Cache(&output))
+    }
+
+    /// The veto of the policy command against deleting `log`, if it refuses
+    pub fn check_log_file(&self, log: &LogFile) -> Option<CleanerError> {
+        let output = LogFileOutput::from(log);
+        self.check(&log.path, PolicyItem::Log(&output))
+    }
+
+    /// Run the command with the item as JSON on stdin. Anything but a zero
+    /// exit, including failing to run the command at all, is a veto.
+    fn check(&self, path: &Path, item: PolicyItem) -> Option<CleanerError> {
+        let request = PolicyRequest {
+            schema_version: SCHEMA_VERSION,
+            dry_run: self.dry_run,
+            item,
+        };
+        let veto = |reason: String| {
+            Some(CleanerError::Vetoed {
+                path: path.to_path_buf(),
+                reason,
+            })
+        };
+
+        let input = match serde_json::to_vec(&request) {
+            Ok(input) => input,
+            Err(e) => return veto(format!("could not encode policy request: {}", e)),
+        };
+        let mut child = match Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => return veto(format!("policy command could not be started: {}", e)),
+        };
+
+        // Commands may decide without reading their input, so a closed pipe
+        // is not an error
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&input).ok();
+        }
+
+        match child.wait_with_output() {
+            Ok(output) if output.status.success() => None,
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                match stderr.lines().last().map(str::trim) {
+                    Some(detail) if !detail.is_empty() => {
+                        veto(format!("refused by policy command: {}", detail))
+                    }
+                    _ => veto(format!("refused by policy command ({})", output.status)),
+                }
+            }
+            Err(e) => veto(format!("policy command failed: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+
+    #[test]
+    fn test_policy_command_vetoes() {
+        let mut config = Config::default();
+        config.safety.policy_command = Some(
+            r#"input=$(cat); case "$input" in *'"kind":"cache"'*prod*) echo "in use" >&2; exit 1;; esac"#
+                .to_string(),
+        );
+        let policy = Policy::new(&config, false).unwrap();
+
+        let dev = CacheItem::new("/srv/dev/target", CacheType::BuildArtifact, "");
+        let prod = CacheItem::new("/srv/prod/target", CacheType::BuildArtifact, "");
+        assert!(policy.check_cache_item(&dev).is_none());
+        let veto = policy.check_cache_item(&prod).unwrap();
+        assert!(
+            veto.to_string()
+                .contains("refused by policy command: in use")
+        );
+
+        config.safety.policy_command = Some("  ".to_string());
+        assert!(Policy::new(&config, false).is_none());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::config::ScheduleConfig;
+use crate::units::format_duration;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+const LOADAVG_PATH: &str = "/proc/loadavg";
+const PRESSURE_DIR: &str = "/proc/pressure";
+
+/// Why a scheduled run should wait
+#[derive(Debug, Clone, PartialEq)]
+pub enum Busy {
+    OnBattery,
+    /// One-minute load average per CPU above the limit
+    Load {
+        per_cpu: f64,
+        limit: f64,
+    },
+    /// Share of the last 10 seconds some tasks stalled on `resource`
+    Pressure {
+        resource: &'static str,
+        percent: f64,
+        limit: f64,
+    },
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Busy::OnBattery => write!(f, "running on battery"),
+            Busy::Load { per_cpu, limit } => {
+                write!(f, "load {:.2} per CPU is above {:.2}", per_cpu, limit)
+            }
+            Busy::Pressure {
+                resource,
+                percent,
+                limit,
+            } => write!(
+                f,
+                "{} pressure {:.1}% is above {:.1}%",
+                resource, percent, limit
+            ),
+        }
+    }
+}
+
+/// The first reason `config` gives to put off a heavy scan right now
+pub fn busy(config: &ScheduleConfig) -> Option<Busy> {
+    if config.defer_on_battery && on_battery(Path::new(POWER_SUPPLY_DIR)) {
+        return Some(Busy::OnBattery);
+    }
+
+    if let Some(limit) = config.max_load_per_cpu {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+        let load = fs::read_to_string(LOADAVG_PATH)
+            .ok()
+            .and_then(|text| parse_loadavg(&text));
+        if let Some(per_cpu) = load.map(|load| load / cpus).filter(|l| *l > limit) {
+            return Some(Busy::Load { per_cpu, limit });
+        }
+    }
+
+    if let Some(limit) = config.max_pressure {
+        for resource in ["cpu", "io"] {
+            let pressure = fs::read_to_string(Path::new(PRESSURE_DIR).join(resource))
+                .ok()
+                .and_then(|text| parse_pressure(&text));
+            if let Some(percent) = pressure.filter(|p| *p > limit) {
+                return Some(Busy::Pressure {
+                    resource,
+                    percent,
+                    limit,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Wait while the machine is busy, checking again every `retry_every`.
+/// Returns why it still is once `give_up_after` has passed.
+pub fn wait_until_idle(config: &ScheduleConfig) -> Result<(), Busy> {
+    let started = Instant::now();
+    loop {
+        let Some(reason) = busy(config) else {
+            return Ok(());
+        };
+        if started.elapsed() + config.retry_every > config.give_up_after {
+            return Err(reason);
+        }
+        println!(
+            "Deferring the run: {}; checking again in {}",
+            reason,
+            format_duration(config.retry_every)
+        );
+        thread::sleep(config.retry_every);
+    }
+}
+
+/// Whether the machine runs on battery: it has a battery and no mains or
+/// USB supply is online. Machines without any power supply class entries
+/// are taken to be on mains.
+fn on_battery(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let supplies: Vec<(String, bool)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let read = |name: &str| fs::read_to_string(entry.path().join(name)).ok();
+            let kind = read("type")?.trim().to_string();
+            let online = read("online").is_some_and(|o| o.trim() == "1");
+            Some((kind, online))
+        })
+        .collect();
+    let has_battery = supplies.iter().any(|(kind, _)| kind == "Battery");
+    let external = supplies
+        .iter()
+        .any(|(kind, online)| kind != "Battery" && *online);
+    has_battery && !external
+}
+
+/// The one-minute load average from `/proc/loadavg`
+fn parse_loadavg(text: &str) -> Option<f64> {
+    text.split_whitespace().next()?.parse().ok()
+}
+
+/// The `some avg10` share from a `/proc/pressure` file, in percent
+fn parse_pressure(text: &str) -> Option<f64> {
+    pressure_field(text, "avg10")?.parse().ok()
+}
+
+/// One field of the `some` line of a `/proc/pressure` file
+pub fn pressure_field<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    text.lines()
+        .find(|line| line.starts_with("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_power_and_load_detection() {
+        let temp_dir = TempDir::new().unwrap();
+        let supply = |name: &str, kind: &str, online: Option<&str>| {
+            let dir = temp_dir.path().join(name);
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+            if let Some(online) = online {
+                fs::write(dir.join("online"), online).unwrap();
+            }
+        };
+        assert!(!on_battery(temp_dir.path()));
+        supply("BAT0", "Battery", None);
+        assert!(on_battery(temp_dir.path()));
+        supply("AC", "Mains", Some("0\n"));
+        assert!(on_battery(temp_dir.path()));
+        fs::write(temp_dir.path().join("AC").join("online"), "1\n").unwrap();
+        assert!(!on_battery(temp_dir.path()));
+
+        assert_eq!(parse_loadavg("3.52 2.10 1.00 2/812 4242\n"), Some(3.52));
+        let pressure = "some avg10=12.50 avg60=3.00 avg300=1.00 total=123\n\
+                        full avg10=1.00 avg60=0.00 avg300=0.00 total=12\n";
+        assert_eq!(parse_pressure(pressure), Some(12.5));
+        assert_eq!(parse_pressure(""), None);
+    }
+}
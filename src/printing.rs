@@ -0,0 +1,153 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::owners::Owner;
+use crate::reclaim;
+use crate::units;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// CUPS keeps a control file `cNNNNN` and data files `dNNNNN-NNN` per job here
+const CUPS_SPOOL: &str = "/var/spool/cups";
+
+/// Find print jobs whose files have all been untouched for
+/// `printing.job_retention`, when the CUPS spool lies below `scan_root`.
+/// Each job is one item, named by its control file and cleaned with
+/// `cancel -x`, so CUPS drops the job along with its files. Data files
+/// without a control file belong to no job CUPS knows and are left alone.
+pub fn detect(scan_root: &Path, config: &Config) -> Vec<CacheItem> {
+    let spool = Path::new(CUPS_SPOOL);
+    let retention = config.printing.job_retention;
+    if retention.is_zero() || !spool.starts_with(scan_root) || config.is_excluded_path(spool) {
+        return Vec::new();
+    }
+    stale_jobs(spool, retention, SystemTime::now())
+}
+
+/// Arguments of the `cancel` command that purges the job whose control file
+/// is `path`
+pub fn cancel_args(path: &Path) -> Option<Vec<OsString>> {
+    let id = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix('c')
+        .and_then(|id| id.parse::<u64>().ok())?;
+    Some(vec!["-x".into(), id.to_string().into()])
+}
+
+/// Purge the job whose control file is `path` through CUPS
+pub fn cancel_job(path: &Path) -> io::Result<()> {
+    let args = cancel_args(path).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "not a CUPS job control file")
+    })?;
+    reclaim::run_tool("cancel", &args)
+}
+
+/// Jobs in `spool` whose files are all older than `retention`
+fn stale_jobs(spool: &Path, retention: Duration, now: SystemTime) -> Vec<CacheItem> {
+    let Ok(entries) = fs::read_dir(spool) else {
+        return Vec::new();
+    };
+
+    let mut jobs: BTreeMap<String, Vec<(PathBuf, fs::Metadata)>> = BTreeMap::new();
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if let Some(job) = entry.file_name().to_str().and_then(job_id)
+            && metadata.is_file()
+        {
+            jobs.entry(job.to_string())
+                .or_default()
+                .push((entry.path(), metadata));
+        }
+    }
+
+    let is_stale = |metadata: &fs::Metadata| {
+        metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age >= retention)
+    };
+    jobs.into_iter()
+        .filter(|(_, files)| files.iter().all(|(_, metadata)| is_stale(metadata)))
+        .filter_map(|(id, files)| {
+            let control = spool.join(format!("c{}", id));
+            let (_, metadata) = files.iter().find(|(path, _)| *path == control)?;
+            Some(CacheItem {
+                size_bytes: Some(files.iter().map(|(_, metadata)| metadata.len()).sum()),
+                file_count: Some(files.len()),
+                last_modified: files
+                    .iter()
+                    .filter_map(|(_, metadata)| metadata.modified().ok())
+                    .max(),
+                owner: Some(Owner::of(metadata)),
+                ..CacheItem::new(
+                    control,
+                    CacheType::PrintJob,
+                    format!(
+                        "print job untouched for {} (printing.job_retention)",
+                        units::format_duration(retention)
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Job number of a control (`c00042`) or data (`d00042-001`) file name
+fn job_id(name: &str) -> Option<&str> {
+    let id = match name.strip_prefix('c') {
+        Some(id) => id,
+        None => {
+            let (id, document) = name.strip_prefix('d')?.split_once('-')?;
+            if document.is_empty() || !document.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            id
+        }
+    };
+    (!id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())).then_some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stale_jobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let spool = temp_dir.path();
+        for name in [
+            "c00041",
+            "d00041-001",
+            "d00041-002",
+            "d00040-001",
+            "cache",
+            "tmp",
+        ] {
+            fs::write(spool.join(name), "job").unwrap();
+        }
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now() + 10 * day;
+
+        // The data file without a control file is no job CUPS knows
+        let stale = stale_jobs(spool, 7 * day, now);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].path, spool.join("c00041"));
+        assert_eq!(
+            (stale[0].size_bytes, stale[0].file_count),
+            (Some(9), Some(3))
+        );
+        assert_eq!(
+            cancel_args(&stale[0].path),
+            Some(vec![OsString::from("-x"), OsString::from("41")])
+        );
+        assert!(stale_jobs(spool, 30 * day, now).is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::owners::Owner;
+use crate::reclaim;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Browser profile roots relative to a home directory
+const PROFILE_ROOTS: &[(&str, &str, Layout)] = &[
+    (".mozilla/firefox", "Firefox", Layout::Firefox),
+    (".config/google-chrome", "Chrome", Layout::Chromium),
+    (".config/chromium", "Chromium", Layout::Chromium),
+    (
+        ".config/BraveSoftware/Brave-Browser",
+        "Brave",
+        Layout::Chromium,
+    ),
+    (".config/microsoft-edge", "Edge", Layout::Chromium),
+    (".config/vivaldi", "Vivaldi", Layout::Chromium),
+];
+
+/// Files a browser rewrites whenever a profile is opened
+const FIREFOX_MARKERS: &[&str] = &["lock", ".parentlock", "times.json", "prefs.js"];
+const CHROMIUM_MARKERS: &[&str] = &["Preferences", "Current Session", "Last Session", "History"];
+
+/// Credentials, cookies, bookmarks and history, which nothing may delete
+const FIREFOX_PROTECTED: &[&str] = &[
+    "cookies.sqlite",
+    "key4.db",
+    "key3.db",
+    "logins.json",
+    "cert9.db",
+    "places.sqlite",
+    "formhistory.sqlite",
+];
+const CHROMIUM_PROTECTED: &[&str] = &[
+    "Cookies",
+    "Login Data",
+    "Login Data For Account",
+    "Web Data",
+    "History",
+    "Bookmarks",
+    "Local State",
+];
+
+/// How far below a profile root protected files are looked for, as in
+/// `Default/Network/Cookies`
+const PROTECTED_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    /// Random-named profile directories holding a `prefs.js`
+    Firefox,
+    /// `Default` and `Profile N` directories holding a `Preferences` file
+    Chromium,
+}
+
+impl Layout {
+    fn is_profile(self, dir: &Path) -> bool {
+        match self {
+            Layout::Firefox => dir.join("prefs.js").is_file() || dir.join("times.json").is_file(),
+            Layout::Chromium => {
+                let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+                (name == "Default" || name.starts_with("Profile "))
+                    && dir.join("Preferences").is_file()
+            }
+        }
+    }
+
+    fn markers(self) -> &'static [&'static str] {
+        match self {
+            Layout::Firefox => FIREFOX_MARKERS,
+            Layout::Chromium => CHROMIUM_MARKERS,
+        }
+    }
+
+    fn protected(self) -> &'static [&'static str] {
+        match self {
+            Layout::Firefox => FIREFOX_PROTECTED,
+            Layout::Chromium => CHROMIUM_PROTECTED,
+        }
+    }
+}
+
+/// Browser files no deletion may touch, whatever pattern matched them: the
+/// built-in list per browser and the names `browser_profiles.protected_files`
+/// adds to it
+#[derive(Debug, Clone, Default)]
+pub struct ProtectionList {
+    /// Extra file names by lowercase browser name
+    extra: BTreeMap<String, Vec<String>>,
+}
+
+impl ProtectionList {
+    pub fn new(config: &Config) -> Self {
+        let extra = config
+            .browser_profiles
+            .protected_files
+            .iter()
+            .map(|(browser, names)| (browser.to_lowercase(), names.clone()))
+            .collect();
+        Self { extra }
+    }
+
+    /// The protected file `path` is or holds, named with its browser.
+    /// Directories above a profile root are searched as well as those
+    /// inside it.
+    pub fn blocking(&self, path: &Path) -> Option<String> {
+        let components: Vec<Component> = path.components().collect();
+        for &(root, browser, layout) in PROFILE_ROOTS {
+            let root: Vec<Component> = Path::new(root).components().collect();
+            let extra = self.extra.get(&browser.to_lowercase());
+            let names: Vec<&str> = (layout.protected().iter().copied())
+                .chain(extra.into_iter().flatten().map(String::as_str))
+                .collect();
+            let found = |path: &Path, depth| {
+                find_protected(path, depth, &names).map(|name| format!("{} {}", browser, name))
+            };
+
+            if let Some(start) = components.windows(root.len()).position(|w| w == root) {
+                let below = components.len() - start - root.len();
+                match PROTECTED_DEPTH
+                    .checked_sub(below)
+                    .map(|depth| found(path, depth))
+                {
+                    Some(Some(file)) => return Some(file),
+                    _ => continue,
+                }
+            }
+            for shared in 0..root.len() {
+                let profiles = root[shared..]
+                    .iter()
+                    .fold(path.to_path_buf(), |p, c| p.join(c));
+                if components.ends_with(&root[..shared])
+                    && let Some(file) = found(&profiles, PROTECTED_DEPTH)
+                {
+                    return Some(file);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The first of `names` at `path` or up to `depth` levels below it
+fn find_protected(path: &Path, depth: usize, names: &[&str]) -> Option<String> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if !metadata.is_dir() {
+        let name = path.file_name()?.to_str()?;
+        return names.contains(&name).then(|| name.to_string());
+    }
+    if depth == 0 {
+        return None;
+    }
+    fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .find_map(|entry| find_protected(&entry.path(), depth - 1, names))
+}
+
+/// A browser profile nobody opened for `browser_profiles.unused_after`. Only
+/// reported unless `--clean-unused-profiles` is given.
+#[derive(Debug, Clone)]
+pub struct UnusedProfile {
+    pub path: PathBuf,
+    pub browser: &'static str,
+    pub size_bytes: u64,
+    pub file_count: usize,
+    pub last_used: SystemTime,
+    pub owner: Option<Owner>,
+}
+
+impl UnusedProfile {
+    pub fn to_cache_item(&self) -> CacheItem {
+        CacheItem {
+            size_bytes: Some(self.size_bytes),
+            file_count: Some(self.file_count),
+            last_modified: Some(self.last_used),
+            owner: self.owner,
+            ..CacheItem::new(
+                self.path.clone(),
+                CacheType::UnusedBrowserProfile,
+                format!(
+                    "{} profile unused for longer than browser_profiles.unused_after",
+                    self.browser
+                ),
+            )
+        }
+    }
+}
+
+/// Find unused Firefox and Chromium-based browser profiles in the home
+/// directories below `scan_root`, largest first
+pub fn detect(scan_root: &Path, config: &Config) -> Vec<UnusedProfile> {
+    let unused_after = config.browser_profiles.unused_after;
+    if unused_after.is_zero() {
+        return Vec::new();
+    }
+
+    let now = SystemTime::now();
+    let mut profiles: Vec<UnusedProfile> = homes(scan_root)
+        .iter()
+        .flat_map(|home| unused_profiles(home, unused_after, now))
+        .filter(|p| !config.is_excluded_path(&p.path))
+        .collect();
+    profiles.sort_by_key(|p| Reverse(p.size_bytes));
+    profiles
+}
+
+/// Directories below `scan_root` that may be home directories: the root
+/// itself, its subdirectories and those of its `home` directory
+pub fn homes(scan_root: &Path) -> Vec<PathBuf> {
+    let mut homes = vec![scan_root.to_path_buf()];
+    homes.extend(reclaim::subdirectories(scan_root));
+    homes.extend(reclaim::subdirectories(&scan_root.join("home")));
+    homes
+}
+
+/// Profiles in `home` last used more than `unused_after` before `now`
+fn unused_profiles(home: &Path, unused_after: Duration, now: SystemTime) -> Vec<UnusedProfile> {
+    let mut profiles = Vec::new();
+    for &(root, browser, layout) in PROFILE_ROOTS {
+        for dir in reclaim::subdirectories(&home.join(root)) {
+            if !layout.is_profile(&dir) {
+                continue;
+            }
+            // A profile whose use can't be dated is left alone
+            let Some(last_used) = last_used(&dir, layout) else {
+                continue;
+            };
+            if now
+                .duration_since(last_used)
+                .is_ok_and(|idle| idle >= unused_after)
+            {
+                let (size_bytes, file_count) = reclaim::size_of(&dir);
+                profiles.push(UnusedProfile {
+                    size_bytes,
+                    file_count,
+                    owner: fs::symlink_metadata(&dir).ok().as_ref().map(Owner::of),
+                    path: dir,
+                    browser,
+                    last_used,
+                });
+            }
+        }
+    }
+    profiles
+}
+
+/// Latest modification of the files the browser touches when it opens the
+/// profile. Firefox's `lock` is a symlink, so its own time is read.
+fn last_used(profile: &Path, layout: Layout) -> Option<SystemTime> {
+    layout
+        .markers()
+        .iter()
+        .filter_map(|marker| fs::symlink_metadata(profile.join(marker)).ok())
+        .filter_map(|metadata| metadata.modified().ok())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unused_profiles() {
+        let temp_dir = TempDir::new().unwrap();
+        let home = temp_dir.path();
+        let firefox = home.join(".mozilla/firefox/x1y2z3.default-release");
+        fs::create_dir_all(&firefox).unwrap();
+        fs::write(firefox.join("prefs.js"), "user_pref();").unwrap();
+        fs::write(firefox.join("places.sqlite"), "12345").unwrap();
+        let chrome = home.join(".config/google-chrome");
+        fs::create_dir_all(chrome.join("Profile 1")).unwrap();
+        fs::write(chrome.join("Profile 1/Preferences"), "{}").unwrap();
+        // Not a profile: no Preferences, and not a profile name
+        fs::create_dir_all(chrome.join("Default")).unwrap();
+        fs::create_dir_all(chrome.join("ShaderCache")).unwrap();
+        fs::write(chrome.join("ShaderCache/Preferences"), "{}").unwrap();
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        let later = SystemTime::now() + 200 * day;
+        let profiles = unused_profiles(home, 180 * day, later);
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].path, firefox);
+        assert_eq!(profiles[0].browser, "Firefox");
+        assert_eq!(profiles[0].size_bytes, 17);
+        assert_eq!(profiles[1].path, chrome.join("Profile 1"));
+
+        assert!(unused_profiles(home, 365 * day, later).is_empty());
+    }
+
+    #[test]
+    fn test_protection_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let home = temp_dir.path();
+        let chrome = home.join(".config/google-chrome");
+        fs::create_dir_all(chrome.join("Default/Network")).unwrap();
+        fs::create_dir_all(chrome.join("Default/Cache/Cache_Data")).unwrap();
+        fs::write(chrome.join("Default/Network/Cookies"), "").unwrap();
+        let firefox = home.join(".mozilla/firefox/x1y2z3.default");
+        fs::create_dir_all(&firefox).unwrap();
+        fs::write(firefox.join("key4.db"), "").unwrap();
+        fs::write(firefox.join("sessionstore.jsonlz4"), "").unwrap();
+
+        let mut config = Config::default();
+        let list = ProtectionList::new(&config);
+        let blocking = |list: &ProtectionList, path: &Path| list.blocking(path);
+        assert_eq!(
+            blocking(&list, &chrome.join("Default/Network/Cookies")).as_deref(),
+            Some("Chrome Cookies")
+        );
+        assert_eq!(
+            blocking(&list, &chrome.join("Default")).as_deref(),
+            Some("Chrome Cookies")
+        );
+        assert_eq!(
+            blocking(&list, &home.join(".mozilla")).as_deref(),
+            Some("Firefox key4.db")
+        );
+        assert_eq!(blocking(&list, &chrome.join("Default/Cache")), None);
+        assert_eq!(blocking(&list, &home.join(".cache")), None);
+        assert_eq!(blocking(&list, &firefox.join("sessionstore.jsonlz4")), None);
+
+        config.browser_profiles.protected_files.insert(
+            "firefox".to_string(),
+            vec!["sessionstore.jsonlz4".to_string()],
+        );
+        let extended = ProtectionList::new(&config);
+        assert_eq!(
+            blocking(&extended, &firefox.join("sessionstore.jsonlz4")).as_deref(),
+            Some("Firefox sessionstore.jsonlz4")
+        );
+    }
+}
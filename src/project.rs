@@ -0,0 +1,620 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::owners::Owner;
+use crate::reclaim;
+use crate::scan_report::ScanReport;
+use crate::walker::ScanWalker;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Levels below a project directory checked for recent changes
+const ACTIVITY_DEPTH: usize = 2;
+
+/// Git files touched by commits, checkouts and staging
+const GIT_ACTIVITY: &[&str] = &[".git/index", ".git/logs/HEAD"];
+
+/// Build output recognized inside a git working tree, by how a directory's
+/// path ends
+const PROJECT_ARTIFACTS: &[&str] = &["target", "node_modules/.cache", "__pycache__"];
+
+/// Language toolchains whose build output `cleaner project` recognizes by
+/// the manifest next to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toolchain {
+    Cargo,
+    Node,
+    Python,
+    Gradle,
+    CMake,
+}
+
+impl Toolchain {
+    const ALL: [Toolchain; 5] = [
+        Toolchain::Cargo,
+        Toolchain::Node,
+        Toolchain::Python,
+        Toolchain::Gradle,
+        Toolchain::CMake,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Toolchain::Cargo => "cargo",
+            Toolchain::Node => "node",
+            Toolchain::Python => "python",
+            Toolchain::Gradle => "gradle",
+            Toolchain::CMake => "cmake",
+        }
+    }
+
+    /// Files marking a directory as a project of this toolchain
+    fn manifests(self) -> &'static [&'static str] {
+        match self {
+            Toolchain::Cargo => &["Cargo.toml"],
+            Toolchain::Node => &["package.json"],
+            Toolchain::Python => &["pyproject.toml", "setup.py", "requirements.txt"],
+            Toolchain::Gradle => &[
+                "build.gradle",
+                "build.gradle.kts",
+                "settings.gradle",
+                "settings.gradle.kts",
+            ],
+            Toolchain::CMake => &["CMakeLists.txt"],
+        }
+    }
+
+    /// Build output of the project in `dir`: fixed directories, plus for
+    /// Python the virtualenvs and for CMake the configured build trees
+    /// among its subdirectories
+    fn outputs(self, dir: &Path) -> Vec<PathBuf> {
+        let fixed: &[&str] = match self {
+            Toolchain::Cargo => &["target"],
+            Toolchain::Node => &["node_modules"],
+            Toolchain::Gradle => &[".gradle", "build"],
+            Toolchain::Python | Toolchain::CMake => &[],
+        };
+        let marker = match self {
+            Toolchain::Python => Some("pyvenv.cfg"),
+            Toolchain::CMake => Some("CMakeCache.txt"),
+            _ => None,
+        };
+        let mut outputs: Vec<PathBuf> = fixed
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|path| path.is_dir() && !path.is_symlink())
+            .collect();
+        if let Some(marker) = marker {
+            outputs.extend(
+                reclaim::subdirectories(dir)
+                    .into_iter()
+                    .filter(|sub| sub.join(marker).is_file()),
+            );
+        }
+        outputs
+    }
+}
+
+/// Find the build output of every project below `scan_root`, keyed off the
+/// manifest files of each toolchain. Projects inside another project's
+/// build output (e.g. packages in `node_modules`) are left out, and inside
+/// a git working tree so is output holding tracked files.
+pub fn detect_build_outputs(scan_root: &Path, config: &Config) -> ScanReport<CacheItem> {
+    let mut report: ScanReport<CacheItem> = ScanReport::new();
+    let root = std::path::absolute(scan_root).unwrap_or_else(|_| scan_root.to_path_buf());
+    let mut manifests: Vec<(PathBuf, Toolchain)> = ScanWalker::new(config)
+        .walk(&root)
+        .entries(&mut report.warnings)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?;
+            let toolchain = Toolchain::ALL
+                .into_iter()
+                .find(|t| t.manifests().contains(&name))?;
+            Some((entry.parent_path().to_path_buf(), toolchain))
+        })
+        .collect();
+    // Outer projects first, so what lies in their output is skipped
+    manifests.sort_by_key(|(dir, _)| dir.components().count());
+    manifests.dedup();
+
+    for (dir, toolchain) in manifests {
+        if report.items.iter().any(|item| dir.starts_with(&item.path)) {
+            continue;
+        }
+        for output in toolchain.outputs(&dir) {
+            if report.items.iter().any(|item| item.path == output)
+                || config.is_excluded_path(&output)
+            {
+                continue;
+            }
+            report.items.push(CacheItem {
+                owner: Owner::of_path(&output),
+                ..CacheItem::new(
+                    output,
+                    CacheType::BuildArtifact,
+                    format!("{} build output of {}", toolchain.name(), dir.display()),
+                )
+            });
+        }
+    }
+
+    // Never touch what a repository tracks
+    if let Some(repo) = repository_root(&root) {
+        let relative: Vec<PathBuf> = report
+            .items
+            .iter()
+            .filter_map(|item| item.path.strip_prefix(&repo).ok().map(Path::to_path_buf))
+            .collect();
+        match tracked_files(&repo, &relative) {
+            Ok(tracked) => {
+                let (kept, clean): (Vec<_>, Vec<_>) = report.items.into_iter().partition(|item| {
+                    item.path
+                        .strip_prefix(&repo)
+                        .is_ok_and(|relative| tracked.iter().any(|file| file.starts_with(relative)))
+                });
+                report.items = clean;
+                report
+                    .warnings
+                    .extend(kept.into_iter().map(|item| CleanerError::Skipped {
+                        path: item.path,
+                        reason: "build output holds files tracked by git".to_string(),
+                    }));
+            }
+            Err(reason) => {
+                report.warnings.push(CleanerError::Skipped {
+                    path: repo,
+                    reason: format!("build output in git working tree not checked: {}", reason),
+                });
+                report.items.clear();
+            }
+        }
+    }
+
+    report.items.sort_by(|a, b| a.path.cmp(&b.path));
+    report
+}
+
+/// A project's build output and how recently the project was worked on
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectUsage {
+    pub dir: PathBuf,
+    pub bytes: u64,
+    pub outputs: usize,
+    /// Newest change to the project outside its build output, including
+    /// git commits and checkouts
+    pub last_activity: Option<SystemTime>,
+}
+
+impl ProjectUsage {
+    /// Whether nobody worked on the project for `stale_after` before `now`.
+    /// Projects whose activity can't be dated are never stale.
+    pub fn is_stale(&self, stale_after: Duration, now: SystemTime) -> bool {
+        self.last_activity
+            .and_then(|activity| now.duration_since(activity).ok())
+            .is_some_and(|idle| idle >= stale_after)
+    }
+}
+
+/// Group the build output found by [`detect_build_outputs`] by project,
+/// biggest output first and the longest idle first among equals
+pub fn rank(items: &[CacheItem]) -> Vec<ProjectUsage> {
+    let mut by_project: BTreeMap<&Path, Vec<&CacheItem>> = BTreeMap::new();
+    for item in items {
+        if let Some(dir) = item.path.parent() {
+            by_project.entry(dir).or_default().push(item);
+        }
+    }
+
+    let mut projects: Vec<ProjectUsage> = by_project
+        .into_iter()
+        .map(|(dir, outputs)| {
+            let paths: Vec<&Path> = outputs.iter().map(|item| item.path.as_path()).collect();
+            ProjectUsage {
+                dir: dir.to_path_buf(),
+                bytes: outputs
+                    .iter()
+                    .map(|item| item.size_bytes.unwrap_or(0))
+                    .sum(),
+                outputs: outputs.len(),
+                last_activity: last_activity(dir, &paths),
+            }
+        })
+        .collect();
+    projects.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.last_activity.cmp(&b.last_activity))
+    });
+    projects
+}
+
+/// Newest modification time in the top levels of the project in `dir`,
+/// leaving its build output out
+fn last_activity(dir: &Path, outputs: &[&Path]) -> Option<SystemTime> {
+    let modified = |path: &Path| fs::symlink_metadata(path).and_then(|m| m.modified()).ok();
+    let mut newest = GIT_ACTIVITY
+        .iter()
+        .filter_map(|file| modified(&dir.join(file)))
+        .max();
+
+    let mut level = vec![dir.to_path_buf()];
+    for _ in 0..ACTIVITY_DEPTH {
+        let mut next = Vec::new();
+        for parent in level {
+            let Ok(entries) = fs::read_dir(&parent) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if outputs.contains(&path.as_path()) || entry.file_name() == ".git" {
+                    continue;
+                }
+                newest = newest.max(modified(&path));
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    next.push(path);
+                }
+            }
+        }
+        level = next;
+    }
+    newest
+}
+
+/// Whether `path` is the `target` directory of a cargo project, which
+/// `cargo clean` removes while honoring cargo's build lock
+pub fn is_cargo_target(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == "target")
+        && path.is_dir()
+        && !path.is_symlink()
+        && path
+            .parent()
+            .is_some_and(|project| project.join("Cargo.toml").is_file())
+}
+
+/// `cargo clean` arguments removing exactly the target directory `target`,
+/// whatever target directory cargo is configured to use
+pub fn cargo_clean_args(target: &Path) -> Vec<PathBuf> {
+    let manifest = target.parent().unwrap_or(Path::new(".")).join("Cargo.toml");
+    vec![
+        PathBuf::from("clean"),
+        PathBuf::from("--manifest-path"),
+        manifest,
+        PathBuf::from("--target-dir"),
+        target.to_path_buf(),
+    ]
+}
+
+/// Remove the cargo target directory `target` with `cargo clean`. Without
+/// cargo installed the directory is removed like any other.
+pub fn cargo_clean(target: &Path, parallel: bool) -> io::Result<()> {
+    let output = match Command::new("cargo")
+        .args(cargo_clean_args(target))
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return crate::file_operations::remove_tree(target, parallel);
+        }
+        Err(e) => return Err(e),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "cargo clean failed ({}): {}",
+            output.status,
+            stderr.lines().last().unwrap_or_default().trim()
+        )));
+    }
+    Ok(())
+}
+
+/// The top of the git working tree containing `path`, if any
+pub fn repository_root(path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// The git working tree a scan of `path` is confined to, if any. The search
+/// stops below `home`: a repository at the home directory or above it, like
+/// a dotfiles repository tracking `~`, holds far more than one project and
+/// leaves the scan a regular one.
+pub fn working_tree(path: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    repository_root(path).filter(|root| home.is_none_or(|home| !home.starts_with(root)))
+}
+
+/// Find the build output below `scan_root` in the working tree at `repo`.
+/// Only directories git ignores and that hold no tracked file are reported;
+/// the rest are skipped with a warning, as is everything when git can't
+/// tell.
+pub fn detect(repo: &Path, scan_root: &Path, config: &Config) -> ScanReport<CacheItem> {
+    let mut report = ScanReport::new();
+    let root = std::path::absolute(scan_root).unwrap_or_else(|_| scan_root.to_path_buf());
+    let mut candidates: Vec<PathBuf> = ScanWalker::new(config)
+        .walk(&root)
+        .entries(&mut report.warnings)
+        .filter(|entry| entry.file_type().is_dir())
+        .map(|entry| entry.path())
+        .filter(|path| PROJECT_ARTIFACTS.iter().any(|a| path.ends_with(a)))
+        .collect();
+    candidates.sort();
+    candidates.dedup_by(|nested, outer| nested.starts_with(outer));
+
+    let relative: Vec<PathBuf> = candidates
+        .iter()
+        .filter_map(|path| path.strip_prefix(repo).ok().map(Path::to_path_buf))
+        .collect();
+    let (ignored, tracked) = match ignored_paths(repo, &relative)
+        .and_then(|ignored| Ok((ignored, tracked_files(repo, &relative)?)))
+    {
+        Ok(found) => found,
+        Err(reason) => {
+            report.warnings.push(CleanerError::Skipped {
+                path: repo.to_path_buf(),
+                reason: format!("build output in git working tree not checked: {}", reason),
+            });
+            return report;
+        }
+    };
+
+    for (path, relative) in candidates.into_iter().zip(relative) {
+        let reason = if !ignored.contains(&relative) {
+            "build output not ignored by git"
+        } else if tracked.iter().any(|file| file.starts_with(&relative)) {
+            "build output holds files tracked by git"
+        } else {
+            report.items.push(CacheItem {
+                owner: Owner::of_path(&path),
+                ..CacheItem::new(
+                    path,
+                    CacheType::BuildArtifact,
+                    "build output ignored by git",
+                )
+            });
+            continue;
+        };
+        report.warnings.push(CleanerError::Skipped {
+            path,
+            reason: reason.to_string(),
+        });
+    }
+    report
+}
+
+/// Which of `paths` (relative to `repo`) git ignores. Tracked paths are
+/// never reported as ignored.
+fn ignored_paths(repo: &Path, paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["check-ignore", "-z", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("git could not be started: {}", e))?;
+    // Fed from another thread: git answers while it reads, and would block
+    // on a full stdout pipe that is only drained once all input is written
+    let input: Vec<u8> = paths
+        .iter()
+        .flat_map(|path| [path.as_os_str().as_encoded_bytes(), b"\0"])
+        .flatten()
+        .copied()
+        .collect();
+    let stdin = child.stdin.take();
+    let writer = thread::spawn(move || stdin.map_or(Ok(()), |mut stdin| stdin.write_all(&input)));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("git failed: {}", e))?;
+    // Exit status 1 means nothing is ignored
+    if !output.status.success() && output.status.code() != Some(1) {
+        return Err(git_error(&output));
+    }
+    writer
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("writer panicked")))
+        .map_err(|e| format!("could not talk to git: {}", e))?;
+    Ok(split_paths(&output.stdout))
+}
+
+/// Files tracked by git below any of `paths` (relative to `repo`)
+fn tracked_files(repo: &Path, paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["ls-files", "-z", "--"])
+        .args(paths)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("git could not be started: {}", e))?;
+    if !output.status.success() {
+        return Err(git_error(&output));
+    }
+    Ok(split_paths(&output.stdout))
+}
+
+fn split_paths(output: &[u8]) -> Vec<PathBuf> {
+    output
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+        .collect()
+}
+
+fn git_error(output: &std::process::Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    format!(
+        "git failed ({}): {}",
+        output.status,
+        stderr.lines().last().unwrap_or_default().trim()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn git(repo: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .output()
+            .is_ok_and(|o| o.status.success())
+    }
+
+    #[test]
+    fn test_build_outputs_keyed_off_manifests() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for dir in [
+            "crate/target/debug",
+            "crate/src",
+            "web/node_modules/left-pad/node_modules",
+            "py/.venv/bin",
+            "py/docs",
+            "native/build-release",
+            "app/.gradle",
+            "app/build/classes",
+            "loose/target",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "crate/Cargo.toml",
+            "web/package.json",
+            "web/node_modules/left-pad/package.json",
+            "py/pyproject.toml",
+            "py/.venv/pyvenv.cfg",
+            "native/CMakeLists.txt",
+            "native/build-release/CMakeCache.txt",
+            "app/build.gradle.kts",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let report = detect_build_outputs(root, &Config::default());
+        let found: Vec<_> = report.items.iter().map(|i| i.path.clone()).collect();
+        assert_eq!(
+            found,
+            vec![
+                root.join("app/.gradle"),
+                root.join("app/build"),
+                root.join("crate/target"),
+                root.join("native/build-release"),
+                root.join("py/.venv"),
+                root.join("web/node_modules"),
+            ]
+        );
+        assert!(is_cargo_target(&root.join("crate/target")));
+        assert!(!is_cargo_target(&root.join("loose/target")));
+    }
+
+    #[test]
+    fn test_rank_projects_by_output_and_activity() {
+        let temp_dir = TempDir::new().unwrap();
+        let output = |path: PathBuf, size: u64| CacheItem {
+            size_bytes: Some(size),
+            ..CacheItem::new(path, CacheType::BuildArtifact, "")
+        };
+        let old = temp_dir.path().join("old");
+        let fresh = temp_dir.path().join("fresh");
+        for dir in [&old, &fresh] {
+            fs::create_dir_all(dir.join("src")).unwrap();
+            fs::create_dir_all(dir.join("target")).unwrap();
+            fs::write(dir.join("src/lib.rs"), "").unwrap();
+        }
+        // Only building touched the old project lately
+        let long_ago = SystemTime::now() - Duration::from_secs(200 * 24 * 60 * 60);
+        for path in ["", "src", "src/lib.rs"] {
+            fs::File::open(old.join(path))
+                .unwrap()
+                .set_modified(long_ago)
+                .unwrap();
+        }
+
+        let items = vec![
+            output(fresh.join("target"), 10),
+            output(old.join("target"), 30),
+        ];
+        let projects = rank(&items);
+        assert_eq!(projects[0].dir, old);
+        assert_eq!(projects[0].bytes, 30);
+        assert_eq!(projects[0].last_activity, Some(long_ago));
+
+        let stale_after = Duration::from_secs(90 * 24 * 60 * 60);
+        let now = SystemTime::now();
+        assert!(projects[0].is_stale(stale_after, now));
+        assert!(!projects[1].is_stale(stale_after, now));
+    }
+
+    #[test]
+    fn test_project_mode_only_cleans_ignored_build_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        if !git(repo, &["init", "-q"]) {
+            return; // git isn't installed
+        }
+        for dir in [
+            "src",
+            "target/debug",
+            "web/node_modules/.cache/babel",
+            "web/node_modules/left-pad",
+            "tools/__pycache__",
+            "vendored/target",
+        ] {
+            fs::create_dir_all(repo.join(dir)).unwrap();
+        }
+        fs::write(repo.join(".gitignore"), "target/\nnode_modules/\n").unwrap();
+        fs::write(repo.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(repo.join("vendored/target/keep.txt"), "checked in").unwrap();
+        assert!(git(repo, &["add", "-f", "vendored/target/keep.txt", "src"]));
+
+        assert_eq!(repository_root(&repo.join("src")).as_deref(), Some(repo));
+        assert_eq!(working_tree(&repo.join("src"), None).as_deref(), Some(repo));
+        assert_eq!(working_tree(&repo.join("src"), Some(repo)), None);
+        assert_eq!(working_tree(repo, Some(&repo.join("src"))), None);
+
+        let report = detect(repo, repo, &Config::default());
+        let found: Vec<_> = report.items.iter().map(|i| i.path.clone()).collect();
+        assert_eq!(
+            found,
+            vec![repo.join("target"), repo.join("web/node_modules/.cache")]
+        );
+        // __pycache__ isn't ignored here, and the vendored target is tracked
+        let skipped: Vec<_> = report
+            .warnings
+            .iter()
+            .filter_map(|w| match w {
+                CleanerError::Skipped { path, .. } => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![repo.join("tools/__pycache__"), repo.join("vendored/target")]
+        );
+
+        // More answers than a pipe holds while the paths are still written
+        let many: Vec<PathBuf> = (0..20_000)
+            .map(|i| PathBuf::from(format!("target/{:08}", i)))
+            .collect();
+        assert_eq!(ignored_paths(repo, &many).unwrap().len(), many.len());
+    }
+}
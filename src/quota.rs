@@ -0,0 +1,125 @@
+use crate::cache_detector::CacheItem;
+use crate::config::{QuotaConfig, QuotaOrder};
+use crate::owners;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// A user whose cache footprint exceeds the per-user quota
+#[derive(Debug, Clone)]
+pub struct QuotaViolation {
+    pub owner: String,
+    pub used_bytes: u64,
+    pub limit_bytes: u64,
+    /// Caches to clean, in order, to bring the user back under the limit
+    pub to_clean: Vec<CacheItem>,
+}
+
+impl QuotaViolation {
+    pub fn freed_bytes(&self) -> u64 {
+        self.to_clean
+            .iter()
+            .map(|i| i.size_bytes.unwrap_or(0))
+            .sum()
+    }
+}
+
+/// Group sized cache items by owner and pick, for every user over the quota,
+/// the caches to clean in the configured order. Items without a known owner
+/// are never attributed to anyone.
+pub fn find_violations(cache_items: &[CacheItem], quota: &QuotaConfig) -> Vec<QuotaViolation> {
+    if quota.per_user == 0 {
+        return Vec::new();
+    }
+
+    let mut by_user: BTreeMap<u32, Vec<&CacheItem>> = BTreeMap::new();
+    for item in cache_items {
+        if let Some(owner) = item.owner {
+            by_user.entry(owner.uid).or_default().push(item);
+        }
+    }
+
+    by_user
+        .into_iter()
+        .filter_map(|(uid, mut items)| {
+            let used_bytes: u64 = items.iter().map(|i| i.size_bytes.unwrap_or(0)).sum();
+            if used_bytes <= quota.per_user {
+                return None;
+            }
+
+            match quota.order {
+                // Unknown modification times sort last so they go only if needed
+                QuotaOrder::Oldest => {
+                    items.sort_by_key(|i| (i.last_modified.is_none(), i.last_modified))
+                }
+                QuotaOrder::Largest => items.sort_by_key(|i| Reverse(i.size_bytes)),
+            }
+
+            let mut remaining = used_bytes;
+            let to_clean = items
+                .into_iter()
+                .take_while(|item| {
+                    let over = remaining > quota.per_user;
+                    remaining = remaining.saturating_sub(item.size_bytes.unwrap_or(0));
+                    over
+                })
+                .cloned()
+                .collect();
+
+            Some(QuotaViolation {
+                owner: owners::user_name(uid),
+                used_bytes,
+                limit_bytes: quota.per_user,
+                to_clean,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::owners::Owner;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_find_violations() {
+        // (uid, size, age in days)
+        let items: Vec<CacheItem> = [
+            (1000, 300, 1),
+            (1000, 200, 30),
+            (1000, 100, 10),
+            (1001, 50, 5),
+        ]
+        .into_iter()
+        .map(|(uid, size, age_days): (u32, u64, u64)| CacheItem {
+            size_bytes: Some(size),
+            file_count: Some(1),
+            last_modified: Some(SystemTime::now() - Duration::from_secs(age_days * 86_400)),
+            owner: Some(Owner { uid, gid: uid }),
+            ..CacheItem::new(
+                format!("/home/u{}/cache-{}", uid, size),
+                CacheType::UserCache,
+                "",
+            )
+        })
+        .collect();
+        let mut quota = QuotaConfig {
+            per_user: 400,
+            ..QuotaConfig::default()
+        };
+
+        // Oldest first: the 200 byte cache alone gets user 1000 under 400
+        let violations = find_violations(&items, &quota);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].used_bytes, 600);
+        assert_eq!(violations[0].freed_bytes(), 200);
+
+        quota.order = QuotaOrder::Largest;
+        let violations = find_violations(&items, &quota);
+        assert_eq!(violations[0].freed_bytes(), 300);
+
+        quota.per_user = 0;
+        assert!(find_violations(&items, &quota).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::{Config, RebuildHint};
+use crate::file_operations::format_bytes;
+
+/// What cleaning some of the items costs later, with the size it concerns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildCost {
+    pub hint: String,
+    pub items: usize,
+    pub bytes: u64,
+}
+
+/// A configured or built-in hint with its path glob compiled
+struct Matcher {
+    hint: RebuildHint,
+    path: Option<glob::Pattern>,
+}
+
+impl Matcher {
+    fn matches(&self, item: &CacheItem) -> bool {
+        (self.hint.cache_types.is_empty() || self.hint.cache_types.contains(&item.cache_type))
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|p| p.matches_path(&item.path))
+    }
+}
+
+/// The rebuild costs of cleaning `cache_items`, one per hint in the order
+/// the hints are listed. Each item counts towards the first hint it matches.
+pub fn costs(cache_items: &[CacheItem], config: &Config) -> Vec<RebuildCost> {
+    let builtin = if config.rebuild_costs.builtin {
+        builtin_hints()
+    } else {
+        Vec::new()
+    };
+    let matchers: Vec<Matcher> = config
+        .rebuild_costs
+        .hints
+        .iter()
+        .chain(&builtin)
+        .map(|hint| Matcher {
+            hint: hint.clone(),
+            path: hint
+                .path
+                .as_ref()
+                .and_then(|p| glob::Pattern::new(&Config::expand_home(p)).ok()),
+        })
+        .collect();
+
+    let mut totals = vec![(0, 0); matchers.len()];
+    for item in cache_items {
+        if let Some(index) = matchers.iter().position(|m| m.matches(item)) {
+            totals[index].0 += 1;
+            totals[index].1 += item.size_bytes.unwrap_or(0);
+        }
+    }
+
+    matchers
+        .iter()
+        .zip(totals)
+        .filter(|(matcher, (items, _))| *items > 0 && !matcher.hint.hint.is_empty())
+        .map(|(matcher, (items, bytes))| RebuildCost {
+            hint: matcher.hint.hint.replace("{size}", &format_bytes(bytes)),
+            items,
+            bytes,
+        })
+        .collect()
+}
+
+/// Hints for caches whose loss is felt, most specific first
+fn builtin_hints() -> Vec<RebuildHint> {
+    let hint = |cache_types: Vec<CacheType>, path: Option<&str>, hint: &str| RebuildHint {
+        cache_types,
+        path: path.map(str::to_string),
+        hint: hint.to_string(),
+    };
+    vec![
+        hint(
+            vec![],
+            Some("*/.cargo/registry*"),
+            "cargo registry: will re-download ~{size} on the next build",
+        ),
+        hint(
+            vec![],
+            Some("*/.gradle/caches*"),
+            "Gradle caches: will re-download ~{size} of dependencies on the next build",
+        ),
+        hint(
+            vec![],
+            Some("*/.m2/repository*"),
+            "Maven repository: will re-download ~{size} on the next build",
+        ),
+        hint(
+            vec![],
+            Some("*/.npm*"),
+            "npm cache: will re-download ~{size} on the next install",
+        ),
+        hint(
+            vec![],
+            Some("*/.cache/pip*"),
+            "pip cache: will re-download ~{size} on the next install",
+        ),
+        hint(
+            vec![],
+            Some("*/go-build*"),
+            "Go build cache: the next builds recompile every package",
+        ),
+        hint(
+            vec![],
+            Some("*shader*"),
+            "shader cache: the first launch of games and GPU apps will stutter",
+        ),
+        hint(
+            vec![],
+            Some("*/nvidia/GLCache*"),
+            "shader cache: the first launch of games and GPU apps will stutter",
+        ),
+        hint(
+            vec![CacheType::BuildArtifact],
+            None,
+            "build output: ~{size} of projects rebuild from scratch on their next build",
+        ),
+        hint(
+            vec![CacheType::PackageManagerCache],
+            None,
+            "package cache: reinstalling or downgrading packages downloads them again",
+        ),
+        hint(
+            vec![CacheType::BrowserCache],
+            None,
+            "browser cache: pages load slower until it fills again",
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebuild_costs() {
+        let items = [
+            (
+                "/home/u/.cargo/registry/cache",
+                CacheType::DevelopmentCache,
+                2048,
+            ),
+            (
+                "/home/u/.cargo/registry/src",
+                CacheType::DevelopmentCache,
+                1024,
+            ),
+            ("/home/u/.cache/mesa_shader_cache", CacheType::UserCache, 10),
+            ("/home/u/.cache/thumbnails", CacheType::UserCache, 10),
+        ]
+        .map(|(path, cache_type, bytes)| CacheItem {
+            size_bytes: Some(bytes),
+            ..CacheItem::new(path, cache_type, "")
+        });
+        let mut config = Config::default();
+        let found = costs(&items, &config);
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[0].hint,
+            "cargo registry: will re-download ~3.00 KB on the next build"
+        );
+        assert_eq!(found[0].items, 2);
+        assert!(found[1].hint.starts_with("shader cache"));
+
+        // Configured hints win, and an empty one hides the built-in hint
+        config.rebuild_costs.hints = vec![
+            RebuildHint {
+                path: Some("*/thumbnails".to_string()),
+                hint: "thumbnails: image folders open slowly once".to_string(),
+                ..RebuildHint::default()
+            },
+            RebuildHint {
+                path: Some("*shader*".to_string()),
+                ..RebuildHint::default()
+            },
+        ];
+        let hints: Vec<String> = costs(&items, &config).into_iter().map(|c| c.hint).collect();
+        assert_eq!(
+            hints,
+            [
+                "thumbnails: image folders open slowly once",
+                "cargo registry: will re-download ~3.00 KB on the next build"
+            ]
+        );
+
+        config.rebuild_costs.builtin = false;
+        assert_eq!(costs(&items, &config).len(), 1);
+    }
+}
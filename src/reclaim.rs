@@ -0,0 +1,330 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::{self, Config};
+use crate::error::CleanerError;
+use crate::ostree;
+use crate::scan_report::ScanReport;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// System flatpak installation's ostree repository
+const FLATPAK_REPO: &str = "/var/lib/flatpak/repo";
+
+/// DKMS module trees, `<module>/<version>/<kernel>/<arch>`
+const DKMS_ROOT: &str = "/var/lib/dkms";
+
+/// One directory per installed kernel
+const MODULES_ROOT: &str = "/lib/modules";
+
+/// Find space that only root can reclaim and no standard tool reports:
+/// unreferenced flatpak ostree objects and stale DKMS builds. Locations
+/// outside `scan_root` are left alone.
+///
+/// The items are reclaimed by their owning tools, `ostree prune` and
+/// `dkms remove`, which keep their own bookkeeping in step; nothing here is
+/// deleted directly.
+///
+/// `exclude_paths` are honored as path prefixes here rather than substrings,
+/// since the default `/lib` exclusion would otherwise hide all of `/var/lib`.
+pub fn detect(scan_root: &Path, config: &Config) -> ScanReport<CacheItem> {
+    let mut report = ScanReport::new();
+    let applies =
+        |location: &Path| location.starts_with(scan_root) && !is_excluded(config, location);
+
+    let repo = Path::new(FLATPAK_REPO);
+    if applies(repo) && repo.is_dir() {
+        if in_transaction(repo) {
+            report.warnings.push(CleanerError::Skipped {
+                path: repo.to_path_buf(),
+                reason: "a flatpak operation is in progress".to_string(),
+            });
+        } else {
+            match ostree::unreferenced_objects(repo) {
+                Ok(objects) if !objects.is_empty() => {
+                    report.items.push(prunable_repo(repo, &objects));
+                }
+                Ok(_) => {}
+                Err(e) => report.warnings.push(e),
+            }
+        }
+    }
+
+    let dkms = Path::new(DKMS_ROOT);
+    if applies(dkms) && dkms.is_dir() {
+        let kernels = installed_kernels(Path::new(MODULES_ROOT));
+        report
+            .items
+            .extend(stale_dkms_builds(dkms, &kernels).into_iter().map(|build| {
+                sized(CacheItem::new(
+                    build,
+                    CacheType::SystemReclaim,
+                    "DKMS build no installed kernel uses",
+                ))
+            }));
+    }
+
+    report.items.retain(|item| !is_excluded(config, &item.path));
+    report
+}
+
+/// Whether a reclaim item is an ostree repository to prune, rather than a
+/// DKMS build to remove
+pub fn is_ostree_repo(path: &Path) -> bool {
+    path.join("objects").is_dir() && path.join("config").is_file()
+}
+
+/// Arguments of the `ostree` command that prunes the objects no ref reaches
+pub fn ostree_prune_args(repo: &Path) -> Vec<OsString> {
+    let mut repo_arg = OsString::from("--repo=");
+    repo_arg.push(repo);
+    vec!["prune".into(), repo_arg, "--refs-only".into()]
+}
+
+/// Arguments of the `dkms` command that removes the build at `path`: one
+/// kernel's build, or a whole module version for every kernel
+pub fn dkms_remove_args(path: &Path) -> Option<Vec<OsString>> {
+    dkms_args(Path::new(DKMS_ROOT), path)
+}
+
+fn dkms_args(dkms_root: &Path, path: &Path) -> Option<Vec<OsString>> {
+    let parts: Vec<&std::ffi::OsStr> = path.strip_prefix(dkms_root).ok()?.iter().collect();
+    let mut args: Vec<OsString> = vec!["remove".into(), "-m".into()];
+    match parts.as_slice() {
+        [module, version] => args.extend([
+            (*module).into(),
+            "-v".into(),
+            (*version).into(),
+            "--all".into(),
+        ]),
+        [module, version, kernel] => args.extend([
+            (*module).into(),
+            "-v".into(),
+            (*version).into(),
+            "-k".into(),
+            (*kernel).into(),
+        ]),
+        _ => return None,
+    }
+    Some(args)
+}
+
+/// Prune the ostree repository at `repo`
+pub fn ostree_prune(repo: &Path) -> io::Result<()> {
+    run_tool("ostree", &ostree_prune_args(repo))
+}
+
+/// Remove the DKMS build at `path` through dkms
+pub fn dkms_remove(path: &Path) -> io::Result<()> {
+    let args = dkms_remove_args(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a DKMS build directory"))?;
+    run_tool("dkms", &args)
+}
+
+/// Run the tool owning some data to remove part of it, failing with the last
+/// line it printed on error. A missing tool is an error too: its data is
+/// never removed behind its back.
+pub fn run_tool(program: &str, args: &[OsString]) -> io::Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                io::Error::new(e.kind(), format!("{} is not installed", program))
+            }
+            _ => e,
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            stderr.lines().last().unwrap_or_default().trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `path` lies below one of the `exclude_paths`, taken as prefixes
+pub fn is_excluded(config: &Config, path: &Path) -> bool {
+    config.safety.exclude_paths.iter().any(|exclude| {
+        let exclude = exclude.strip_prefix(config::ANCHOR).unwrap_or(exclude);
+        path.starts_with(Config::expand_home(exclude))
+    })
+}
+
+/// Whether ostree is writing to the repository right now
+fn in_transaction(repo: &Path) -> bool {
+    fs::read_dir(repo.join("tmp")).is_ok_and(|entries| {
+        entries.flatten().any(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with("staging-"))
+        })
+    })
+}
+
+fn installed_kernels(modules_root: &Path) -> HashSet<String> {
+    subdirectories(modules_root)
+        .iter()
+        .filter_map(|dir| Some(dir.file_name()?.to_str()?.to_string()))
+        .collect()
+}
+
+/// DKMS builds no installed kernel uses: per-kernel build trees of removed
+/// kernels, and whole module versions superseded by another version that is
+/// built for an installed kernel
+fn stale_dkms_builds(dkms_root: &Path, kernels: &HashSet<String>) -> Vec<PathBuf> {
+    let mut stale = Vec::new();
+
+    for module in subdirectories(dkms_root) {
+        let versions: Vec<(PathBuf, Vec<PathBuf>)> = subdirectories(&module)
+            .into_iter()
+            .map(|version| {
+                let builds = subdirectories(&version)
+                    .into_iter()
+                    .filter(|dir| !dir.ends_with("build") && !dir.ends_with("source"))
+                    .collect();
+                (version, builds)
+            })
+            .collect();
+        let is_current = |builds: &[PathBuf]| {
+            builds.iter().any(|build| {
+                build
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|name| kernels.contains(name))
+            })
+        };
+        let any_current = versions.iter().any(|(_, builds)| is_current(builds));
+
+        for (version, builds) in &versions {
+            if any_current && !is_current(builds) {
+                stale.push(version.clone());
+                continue;
+            }
+            stale.extend(
+                builds
+                    .iter()
+                    .filter(|build| !is_current(std::slice::from_ref(build)))
+                    .cloned(),
+            );
+        }
+    }
+
+    stale.sort();
+    stale
+}
+
+/// Real subdirectories of `dir`, not following symlinks
+pub fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Total size of everything at or below `path`, not following symlinks
+pub fn size_of(path: &Path) -> (u64, usize) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    if !metadata.is_dir() {
+        return (metadata.len(), 1);
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| size_of(&entry.path()))
+        .fold((0, 0), |(bytes, files), (b, f)| (bytes + b, files + f))
+}
+
+/// One item for the whole repository, sized by the objects a prune removes
+fn prunable_repo(repo: &Path, objects: &[PathBuf]) -> CacheItem {
+    let (size, files) = objects
+        .iter()
+        .map(|object| size_of(object))
+        .fold((0, 0), |(bytes, files), (b, f)| (bytes + b, files + f));
+    CacheItem {
+        size_bytes: Some(size),
+        file_count: Some(files),
+        ..CacheItem::new(
+            repo,
+            CacheType::SystemReclaim,
+            format!("{} flatpak ostree objects no ref reaches", objects.len()),
+        )
+        .dated()
+    }
+}
+
+/// Sized up front: the regular walker honors the substring exclusions that
+/// would skip these locations
+pub fn sized(item: CacheItem) -> CacheItem {
+    let (size, files) = size_of(&item.path);
+    CacheItem {
+        size_bytes: Some(size),
+        file_count: Some(files),
+        ..item.dated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stale_dkms_builds() {
+        let temp_dir = TempDir::new().unwrap();
+        let dkms = temp_dir.path();
+        for dir in [
+            "nvidia/535.1/6.8.0-40/x86_64/module",
+            "nvidia/550.2/6.8.0-45/x86_64/module",
+            "nvidia/550.2/6.8.0-31/x86_64/module",
+            "nvidia/550.2/build",
+            "zfs/2.2/6.1.0-old/x86_64",
+        ] {
+            fs::create_dir_all(dkms.join(dir)).unwrap();
+        }
+        let kernels = HashSet::from(["6.8.0-45".to_string()]);
+
+        assert_eq!(
+            stale_dkms_builds(dkms, &kernels),
+            vec![
+                dkms.join("nvidia/535.1"),
+                dkms.join("nvidia/550.2/6.8.0-31"),
+                dkms.join("zfs/2.2/6.1.0-old"),
+            ]
+        );
+
+        let args = |path: &str| {
+            dkms_args(dkms, &dkms.join(path)).map(|args| {
+                args.iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+        };
+        assert_eq!(
+            args("nvidia/535.1").as_deref(),
+            Some("remove -m nvidia -v 535.1 --all")
+        );
+        assert_eq!(
+            args("zfs/2.2/6.1.0-old").as_deref(),
+            Some("remove -m zfs -v 2.2 -k 6.1.0-old")
+        );
+        assert_eq!(args("nvidia"), None);
+    }
+}
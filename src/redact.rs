@@ -0,0 +1,219 @@
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::growth::GrowthReport;
+use crate::mounts::MountUsage;
+use crate::owners::OwnerUsage;
+use crate::writers::HeldOpenFile;
+use glob::Pattern;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Top-level and system directory names that say nothing about a user
+const SYSTEM_NAMES: &[&str] = &[
+    "home", "root", "var", "tmp", "usr", "opt", "srv", "etc", "lib", "cache", "log", "mnt",
+    "media", "run", "local", "share", "spool",
+];
+
+/// Redaction of the run, once `--redact-paths` turned it on
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+/// Rewrites paths for reports meant to be shared: home directory names become
+/// `<user>`, names no cache pattern or system directory accounts for become
+/// a short hash, and so does everything inside a cache item. Hashes are
+/// salted per run, so equal names match within one report only.
+pub struct Redactor {
+    homes: HashSet<PathBuf>,
+    known: Vec<Pattern>,
+    salt: RandomState,
+}
+
+impl Redactor {
+    pub fn new(config: &Config, homes: impl IntoIterator<Item = PathBuf>) -> Self {
+        let patterns = &config.cache_patterns;
+        let known = [
+            &patterns.user_cache_dirs,
+            &patterns.system_cache_dirs,
+            &patterns.app_cache_patterns,
+            &patterns.package_manager_caches,
+            &patterns.dev_tool_caches,
+            &patterns.browser_caches,
+            &patterns.temp_patterns,
+            &patterns.build_artifacts,
+            &patterns.regenerating_caches,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|pattern| pattern.split('/'))
+        .filter(|component| component.chars().any(|c| !matches!(c, '*' | '?' | '~')))
+        .filter_map(|component| Pattern::new(component).ok())
+        .collect();
+
+        Self {
+            homes: homes.into_iter().collect(),
+            known,
+            salt: RandomState::new(),
+        }
+    }
+
+    /// `path` with the components of `root` kept where they are known and
+    /// every component below `root` hashed
+    pub fn path_in(&self, path: &Path, root: &Path) -> PathBuf {
+        let inside = path.strip_prefix(root).ok();
+        let outer = inside.map_or(path, |_| root);
+
+        let mut redacted = PathBuf::new();
+        let mut seen = PathBuf::new();
+        for component in outer.components() {
+            seen.push(component);
+            match component {
+                Component::Normal(_) if self.homes.contains(&seen) => redacted.push("<user>"),
+                Component::Normal(name) if !self.is_known(name) => redacted.push(self.hash(name)),
+                component => redacted.push(component),
+            }
+        }
+        for component in inside.into_iter().flat_map(Path::components) {
+            match component {
+                Component::Normal(name) => redacted.push(self.hash(name)),
+                component => redacted.push(component),
+            }
+        }
+        redacted
+    }
+
+    fn is_known(&self, name: &OsStr) -> bool {
+        name.to_str().is_some_and(|name| {
+            SYSTEM_NAMES.contains(&name) || self.known.iter().any(|p| p.matches(name))
+        })
+    }
+
+    /// A short stand-in for `name`, keeping a file extension that tells
+    /// logs and archives apart
+    fn hash(&self, name: &OsStr) -> String {
+        let extension = Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| e.len() <= 4 && e.chars().all(|c| c.is_ascii_alphanumeric()));
+        let hash = self.salt.hash_one(name) as u32;
+        match extension {
+            Some(extension) => format!("<{:08x}>.{}", hash, extension),
+            None => format!("<{:08x}>", hash),
+        }
+    }
+}
+
+/// Redact paths in exported reports from now on
+pub fn enable(config: &Config, homes: impl IntoIterator<Item = PathBuf>) {
+    REDACTOR.get_or_init(|| Redactor::new(config, homes));
+}
+
+/// `path`, redacted if redaction is on. The path is taken as the root of a
+/// cache item, so its own known names are kept.
+pub fn path(path: &Path) -> PathBuf {
+    path_in(path, path)
+}
+
+/// `path` below the cache item at `root`, redacted if redaction is on
+pub fn path_in(path: &Path, root: &Path) -> PathBuf {
+    match REDACTOR.get() {
+        Some(redactor) => redactor.path_in(path, root),
+        None => path.to_path_buf(),
+    }
+}
+
+/// The message of `error` with the path it names redacted
+pub fn error(error: &CleanerError) -> String {
+    let message = error.to_string();
+    match (REDACTOR.get(), error.path()) {
+        (Some(_), Some(path)) => message.replace(
+            &path.display().to_string(),
+            &self::path(path).display().to_string(),
+        ),
+        _ => message,
+    }
+}
+
+/// A user name, as a hash if redaction is on
+pub fn name(name: &str) -> String {
+    match REDACTOR.get() {
+        Some(redactor) => redactor.hash(OsStr::new(name)),
+        None => name.to_string(),
+    }
+}
+
+/// Usage per filesystem with the mount points redacted
+pub fn mounts(mut usage: Vec<MountUsage>) -> Vec<MountUsage> {
+    for mount in &mut usage {
+        mount.mount_point = path(&mount.mount_point);
+    }
+    usage
+}
+
+/// Usage per user with the user names redacted
+pub fn owners(mut usage: Vec<OwnerUsage>) -> Vec<OwnerUsage> {
+    for owner in &mut usage {
+        owner.owner = name(&owner.owner);
+    }
+    usage
+}
+
+/// Growth rates and projections with their paths redacted
+pub fn growth(mut growth: GrowthReport) -> GrowthReport {
+    for rate in &mut growth.growers {
+        rate.path = path(&rate.path);
+    }
+    for projection in &mut growth.projections {
+        projection.mount_point = path(&projection.mount_point);
+    }
+    growth
+}
+
+/// Files held open after cleaning with their paths redacted
+pub fn held_open(mut files: Vec<HeldOpenFile>) -> Vec<HeldOpenFile> {
+    for file in &mut files {
+        file.path = path(&file.path);
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_paths() {
+        let redactor = Redactor::new(&Config::default(), [PathBuf::from("/home/alice")]);
+        let redact = |path: &str, root: &str| {
+            redactor
+                .path_in(Path::new(path), Path::new(root))
+                .display()
+                .to_string()
+        };
+
+        let pip = redact("/home/alice/.cache/pip/wheels/a.whl", "/home/alice/.cache");
+        let parts: Vec<&str> = pip.split('/').collect();
+        assert_eq!(parts[..4], ["", "home", "<user>", ".cache"]);
+        assert!(parts[4].starts_with('<') && parts[4].ends_with('>'));
+        assert!(parts[6].ends_with(">.whl"));
+        assert!(!pip.contains("pip") && !pip.contains("wheels"));
+
+        // Project names are hashed, the cache name they hold is kept
+        let target = redact(
+            "/home/alice/work/secret-project/node_modules",
+            "/home/alice/work/secret-project/node_modules",
+        );
+        assert!(target.starts_with("/home/<user>/<"));
+        assert!(target.ends_with("/node_modules"));
+        assert!(!target.contains("secret") && !target.contains("work"));
+        assert_eq!(redact("/var/cache/apt", "/var/cache/apt"), "/var/cache/apt");
+
+        // The same name hashes alike within a run
+        let component = |path: &str| redact(path, path).split('/').nth(3).unwrap().to_string();
+        assert_eq!(
+            component("/home/alice/work/x"),
+            component("/home/alice/work/y")
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::mounts::MountTable;
+use crate::plan::Plan;
+use crate::trash::{self, Trashed};
+use chrono::{Local, Timelike};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How restoring one entry of a backup manifest went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Restored {
+    pub path: PathBuf,
+    /// How many trashed items were put back, or why none could be
+    pub outcome: Result<usize, String>,
+}
+
+/// The manifest written next to a backup list, which may be given instead
+pub fn manifest_path(backup: &Path) -> PathBuf {
+    if backup.extension().is_some_and(|ext| ext == "txt") {
+        backup.with_extension("json")
+    } else {
+        backup.to_path_buf()
+    }
+}
+
+/// Move the entries of `manifest` back from the trash they went to in its
+/// clean. Entries that were deleted outright can't be recovered.
+pub fn restore(manifest: &Plan, mounts: &MountTable) -> Vec<Restored> {
+    let mut listed: HashMap<PathBuf, Vec<Trashed>> = HashMap::new();
+    manifest
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut trashed = Vec::new();
+            for (dir, top) in trash::trash_dirs_for(&entry.path, mounts) {
+                let items = listed
+                    .entry(dir)
+                    .or_insert_with_key(|dir| trash::list(dir, &top));
+                trashed.extend(items.iter().cloned());
+            }
+            restore_entry(
+                manifest,
+                &entry.path,
+                entry.resolved_path.as_deref(),
+                trashed,
+            )
+        })
+        .collect()
+}
+
+/// Restore `path` from `trashed`: the latest item trashed from there since
+/// the manifest was written, or for a symlinked cache every item trashed
+/// from its `resolved` target
+fn restore_entry(
+    manifest: &Plan,
+    path: &Path,
+    resolved: Option<&Path>,
+    trashed: Vec<Trashed>,
+) -> Restored {
+    // Deletion dates are local and to the second
+    let since = manifest
+        .created_at
+        .with_timezone(&Local)
+        .naive_local()
+        .with_nanosecond(0);
+    let mut matching: Vec<Trashed> = trashed
+        .into_iter()
+        .filter(|t| t.deleted_at >= since)
+        .filter(|t| match resolved {
+            Some(target) => t.original.parent() == Some(target),
+            None => t.original == path,
+        })
+        .collect();
+    matching.sort_by_key(|t| t.deleted_at);
+    if resolved.is_none() {
+        matching.drain(..matching.len().saturating_sub(1));
+    }
+
+    let outcome = if matching.is_empty() {
+        Err("not in the trash; it was deleted outright or the trash was emptied".to_string())
+    } else {
+        matching
+            .iter()
+            .try_for_each(|t| {
+                t.restore()
+                    .map_err(|e| format!("{}: {}", t.file.display(), e))
+            })
+            .map(|()| matching.len())
+    };
+    Restored {
+        path: path.to_path_buf(),
+        outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::{CacheItem, CacheType};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_restore_from_trash() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_trash = temp_dir.path().join("Trash");
+        let [moved, deleted, occupied] = ["moved", "deleted", "occupied"].map(|name| {
+            let path = temp_dir.path().join("data").join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("file"), name).unwrap();
+            path
+        });
+        let items = [&moved, &deleted, &occupied]
+            .map(|path| CacheItem::new(path.clone(), CacheType::UserCache, ""));
+        let manifest = Plan::from_items(temp_dir.path(), &items, &[]);
+
+        fs::create_dir_all(home_trash.join("files")).unwrap();
+        fs::create_dir_all(home_trash.join("info")).unwrap();
+        let trash_item = |path: &Path, name: &str, date: &str| {
+            fs::rename(path, home_trash.join("files").join(name)).unwrap();
+            fs::write(
+                home_trash.join("info").join(format!("{name}.trashinfo")),
+                format!(
+                    "[Trash Info]\nPath={}\nDeletionDate={date}\n",
+                    path.display()
+                ),
+            )
+            .unwrap();
+        };
+        let now = Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+        trash_item(&moved, "moved", &now);
+        fs::remove_dir_all(&deleted).unwrap();
+        trash_item(&occupied, "occupied", &now);
+        fs::create_dir(&occupied).unwrap();
+        // An older item from the same place belongs to an earlier clean
+        fs::create_dir(home_trash.join("files/moved.2")).unwrap();
+        fs::write(
+            home_trash.join("info/moved.2.trashinfo"),
+            format!(
+                "[Trash Info]\nPath={}\nDeletionDate=2020-01-01T00:00:00\n",
+                moved.display()
+            ),
+        )
+        .unwrap();
+
+        let trashed = trash::list(&home_trash, Path::new("/"));
+        let outcomes: Vec<Restored> = manifest
+            .entries
+            .iter()
+            .map(|entry| restore_entry(&manifest, &entry.path, None, trashed.clone()))
+            .collect();
+
+        assert_eq!(outcomes[0].outcome, Ok(1));
+        assert_eq!(fs::read_to_string(moved.join("file")).unwrap(), "moved");
+        assert!(!home_trash.join("info/moved.trashinfo").exists());
+        assert!(home_trash.join("files/moved.2").exists());
+        assert!(
+            outcomes[1]
+                .outcome
+                .as_ref()
+                .unwrap_err()
+                .contains("not in the trash")
+        );
+        assert!(outcomes[2].outcome.is_err());
+        assert!(home_trash.join("files/occupied/file").exists());
+
+        let backup = temp_dir.path().join("cleanup_1.txt");
+        assert_eq!(
+            manifest_path(&backup),
+            temp_dir.path().join("cleanup_1.json")
+        );
+    }
+}
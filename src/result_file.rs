@@ -0,0 +1,98 @@
+use crate::output::{self, RunResult, RunStatus};
+use chrono::Utc;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::{process, thread};
+
+/// Where `--result-file` writes the outcome, once set
+static RESULT_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Outcome of the run so far
+static RESULT: Mutex<Option<RunResult>> = Mutex::new(None);
+
+/// Signals that end the run with an `interrupted` result
+const STOP_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Write the outcome to `path` however the run ends. Must be called before
+/// any other thread starts, so they all inherit the blocked stop signals.
+pub fn init(path: PathBuf) {
+    *lock() = Some(RunResult {
+        schema_version: output::SCHEMA_VERSION,
+        status: RunStatus::Running,
+        exit_code: 0,
+        error: None,
+        started_at: Utc::now(),
+        finished_at: None,
+        scan_root: None,
+        dry_run: false,
+        items_found: 0,
+        bytes_found: 0,
+        items_cleaned: 0,
+        items_failed: 0,
+        bytes_freed: 0,
+        changed: None,
+    });
+    if RESULT_PATH.set(path).is_err() {
+        return;
+    }
+
+    // Stop signals are taken by a dedicated thread rather than a handler, so
+    // writing the file doesn't have to be async-signal-safe. Child processes
+    // get a fresh signal mask from std::process.
+    // SAFETY: plain libc calls on a zeroed, locally owned signal set
+    let signals = unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        for signal in STOP_SIGNALS {
+            libc::sigaddset(&mut signals, signal);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+        signals
+    };
+    thread::spawn(move || {
+        let mut signal = 0;
+        // SAFETY: `signals` is an initialized set blocked in every thread
+        if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+            let code = 128 + signal;
+            finish(RunStatus::Interrupted, code, None);
+            process::exit(code);
+        }
+    });
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        finish(RunStatus::Failed, 101, Some(info.to_string()));
+    }));
+}
+
+/// Update the outcome recorded so far; does nothing without `--result-file`
+pub fn record(update: impl FnOnce(&mut RunResult)) {
+    if let Some(result) = lock().as_mut() {
+        update(result);
+    }
+}
+
+/// Write the final outcome. Failing to write it is reported but doesn't
+/// change the exit code.
+pub fn finish(status: RunStatus, exit_code: i32, error: Option<String>) {
+    let Some(path) = RESULT_PATH.get() else {
+        return;
+    };
+    let mut result = lock();
+    let Some(result) = result.as_mut() else {
+        return;
+    };
+    result.status = status;
+    result.exit_code = exit_code;
+    result.error = error;
+    result.finished_at = Some(Utc::now());
+    if let Err(e) = output::write_json(result, path) {
+        eprintln!("Error writing result file: {}", e);
+    }
+}
+
+/// The recorded outcome, even if a panic poisoned the lock
+fn lock() -> std::sync::MutexGuard<'static, Option<RunResult>> {
+    RESULT.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,215 @@
+use crate::cache_detector::CacheItem;
+use crate::config::Config;
+use crate::log_cleaner::LogFile;
+use crate::walker::ScanWalker;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// `_IOWR('f', 11, struct fiemap)`
+const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+const FIEMAP_EXTENT_LAST: u32 = 0x0001;
+const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+/// Extents fetched per ioctl call
+const FIEMAP_BATCH: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    reserved64: [u64; 2],
+    flags: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+struct Fiemap {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    reserved: u32,
+    extents: [FiemapExtent; FIEMAP_BATCH],
+}
+
+/// Projected savings with and without space that stays allocated elsewhere
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Savings {
+    /// Sum of the sizes of everything that would be deleted
+    pub apparent: u64,
+    /// What deleting it actually frees: files hard-linked from outside the
+    /// candidates and extents shared through reflinks or snapshots don't count
+    pub reclaimable: u64,
+}
+
+/// One hard-linked inode met below the candidates
+#[derive(Debug, Clone, Copy)]
+struct LinkedInode {
+    links: u64,
+    seen: u64,
+    bytes: u64,
+}
+
+/// What the files of one item add up to
+#[derive(Debug, Default)]
+struct Tally {
+    apparent: u64,
+    unshared: u64,
+    linked: HashMap<(u64, u64), LinkedInode>,
+}
+
+impl Tally {
+    fn add_file(&mut self, path: &Path, metadata: &fs::Metadata) {
+        let bytes = metadata.len();
+        self.apparent += bytes;
+        let unshared = bytes.saturating_sub(shared_bytes(path).min(bytes));
+
+        if metadata.nlink() > 1 {
+            let inode = self
+                .linked
+                .entry((metadata.dev(), metadata.ino()))
+                .or_insert(LinkedInode {
+                    links: metadata.nlink(),
+                    seen: 0,
+                    bytes: unshared,
+                });
+            inode.seen += 1;
+        } else {
+            self.unshared += unshared;
+        }
+    }
+
+    fn merge(mut self, other: Tally) -> Tally {
+        self.apparent += other.apparent;
+        self.unshared += other.unshared;
+        for (key, inode) in other.linked {
+            self.linked
+                .entry(key)
+                .and_modify(|existing| existing.seen += inode.seen)
+                .or_insert(inode);
+        }
+        self
+    }
+
+    /// A hard-linked inode is only freed when every one of its links goes
+    fn savings(self) -> Savings {
+        let linked: u64 = self
+            .linked
+            .values()
+            .filter(|inode| inode.seen >= inode.links)
+            .map(|inode| inode.bytes)
+            .sum();
+        Savings {
+            apparent: self.apparent,
+            reclaimable: self.unshared + linked,
+        }
+    }
+}
+
+/// Walk the candidates once more, in parallel, to find out how much space
+/// deleting them really frees
+pub fn estimate(cache_items: &[CacheItem], log_files: &[LogFile], config: &Config) -> Savings {
+    let walker = ScanWalker::new(config).unbounded().serial();
+
+    let caches = cache_items
+        .par_iter()
+        .map(|item| {
+            let mut tally = Tally::default();
+            for entry in walker.walk(item.target_path()).filter_map(Result::ok) {
+                if entry.file_type().is_file()
+                    && let Ok(metadata) = entry.metadata()
+                {
+                    tally.add_file(&entry.path(), &metadata);
+                }
+            }
+            tally
+        })
+        .reduce(Tally::default, Tally::merge);
+
+    let logs = log_files
+        .par_iter()
+        .map(|log| {
+            let mut tally = Tally::default();
+            if let Ok(metadata) = fs::symlink_metadata(&log.path)
+                && metadata.is_file()
+            {
+                tally.add_file(&log.path, &metadata);
+            }
+            tally
+        })
+        .reduce(Tally::default, Tally::merge);
+
+    caches.merge(logs).savings()
+}
+
+/// Bytes of `path` in extents shared with other files, per FIEMAP. Filesystems
+/// without FIEMAP or reflinks report nothing shared.
+fn shared_bytes(path: &Path) -> u64 {
+    let Ok(file) = File::open(path) else {
+        return 0;
+    };
+
+    let mut shared = 0;
+    let mut start = 0;
+    loop {
+        let mut request = Fiemap {
+            start,
+            length: u64::MAX - start,
+            flags: 0,
+            mapped_extents: 0,
+            extent_count: FIEMAP_BATCH as u32,
+            reserved: 0,
+            extents: [FiemapExtent::default(); FIEMAP_BATCH],
+        };
+        // SAFETY: `request` is a correctly laid out `struct fiemap` with room
+        // for `extent_count` extents, and outlives the call
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut request) };
+        if result != 0 || request.mapped_extents == 0 {
+            return shared;
+        }
+
+        let extents = &request.extents[..request.mapped_extents as usize];
+        for extent in extents {
+            if extent.flags & FIEMAP_EXTENT_SHARED != 0 {
+                shared += extent.length;
+            }
+        }
+
+        let last = extents[extents.len() - 1];
+        if last.flags & FIEMAP_EXTENT_LAST != 0 {
+            return shared;
+        }
+        start = last.logical + last.length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hard_links_outside_candidates_are_not_reclaimable() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        fs::create_dir(&cache).unwrap();
+        fs::write(cache.join("plain"), "12345").unwrap();
+        fs::write(cache.join("linked_out"), "1234567890").unwrap();
+        fs::hard_link(cache.join("linked_out"), temp_dir.path().join("kept")).unwrap();
+        fs::write(cache.join("linked_in"), "abc").unwrap();
+        fs::hard_link(cache.join("linked_in"), cache.join("linked_in_2")).unwrap();
+
+        let item = CacheItem::new(cache, CacheType::UserCache, "");
+        let savings = estimate(&[item], &[], &Config::default());
+
+        assert_eq!(savings.apparent, 5 + 10 + 3 + 3);
+        assert_eq!(savings.reclaimable, 5 + 3);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::error::CleanerError;
+use std::collections::HashSet;
+
+/// Items found by a scan together with the non-fatal issues hit along the way
+#[derive(Debug, Clone)]
+pub struct ScanReport<T> {
+    pub items: Vec<T>,
+    pub warnings: Vec<CleanerError>,
+}
+
+impl<T> Default for ScanReport<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Items and warnings in the order a walk yields them
+impl<T> FromIterator<Result<T, CleanerError>> for ScanReport<T> {
+    fn from_iter<I: IntoIterator<Item = Result<T, CleanerError>>>(iter: I) -> Self {
+        let mut report = Self::new();
+        for found in iter {
+            match found {
+                Ok(item) => report.items.push(item),
+                Err(warning) => report.warnings.push(warning),
+            }
+        }
+        report
+    }
+}
+
+impl<T> ScanReport<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold another report into this one
+    pub fn merge(&mut self, other: ScanReport<T>) {
+        self.items.extend(other.items);
+        self.warnings.extend(other.warnings);
+    }
+
+    /// Drop repeated warnings, e.g. the same unreadable directory hit by two walkers
+    pub fn dedup_warnings(&mut self) {
+        let mut seen = HashSet::new();
+        self.warnings.retain(|w| seen.insert(w.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_merge_reports() {
+        let mut report = ScanReport {
+            items: vec![1],
+            warnings: Vec::new(),
+        };
+        report.merge(ScanReport {
+            items: vec![2, 3],
+            warnings: vec![CleanerError::PermissionDenied(PathBuf::from("/root"))],
+        });
+
+        report.merge(ScanReport {
+            items: Vec::new(),
+            warnings: vec![CleanerError::PermissionDenied(PathBuf::from("/root"))],
+        });
+        report.dedup_warnings();
+
+        assert_eq!(report.items, vec![1, 2, 3]);
+        assert_eq!(report.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::cache_detector::CacheItem;
+use crate::log_cleaner::LogFile;
+use std::ops::RangeInclusive;
+
+/// Numbers of listed items to clean, as `--select 1,3,7-12` gives them.
+/// Cache items are numbered from 1 in listing order, old logs after them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    ranges: Vec<RangeInclusive<usize>>,
+}
+
+impl Selection {
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let ranges = list
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let (start, end) = part.split_once('-').unwrap_or((part, part));
+                let number = |n: &str| {
+                    n.trim()
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("'{}' is not an item number", part))
+                };
+                let (start, end) = (number(start)?, number(end)?);
+                if start > end {
+                    return Err(format!("range '{}' runs backwards", part));
+                }
+                Ok(start..=end)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if ranges.is_empty() {
+            return Err("no item numbers given".to_string());
+        }
+        Ok(Self { ranges })
+    }
+
+    fn contains(&self, number: usize) -> bool {
+        self.ranges.iter().any(|range| range.contains(&number))
+    }
+
+    /// Keep the selected items, or say which number is past the end of the
+    /// listing
+    pub fn retain(
+        &self,
+        cache_items: Vec<CacheItem>,
+        log_files: Vec<LogFile>,
+    ) -> Result<(Vec<CacheItem>, Vec<LogFile>), String> {
+        let listed = cache_items.len() + log_files.len();
+        if let Some(last) = self.ranges.iter().map(|range| *range.end()).max()
+            && last > listed
+        {
+            return Err(format!(
+                "there is no item {}; the listing has {}",
+                last, listed
+            ));
+        }
+
+        let offset = cache_items.len();
+        let cache_items = cache_items
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, item)| self.contains(i + 1).then_some(item))
+            .collect();
+        let log_files = log_files
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, log)| self.contains(offset + i + 1).then_some(log))
+            .collect();
+        Ok((cache_items, log_files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::log_cleaner::{Confidence, LogType};
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_select_by_number() {
+        let items: Vec<CacheItem> = (1..=4)
+            .map(|n| {
+                CacheItem::new(
+                    PathBuf::from(format!("/cache/{}", n)),
+                    CacheType::UserCache,
+                    "",
+                )
+            })
+            .collect();
+        let logs: Vec<LogFile> = (5..=6)
+            .map(|n| LogFile {
+                path: PathBuf::from(format!("/var/log/{}.log", n)),
+                size_bytes: 0,
+                last_modified: SystemTime::UNIX_EPOCH,
+                age: Duration::ZERO,
+                log_type: LogType::System,
+                confidence: Confidence::High,
+                owner: None,
+                writer: None,
+                file_id: None,
+                reason: String::new(),
+            })
+            .collect();
+
+        let selection = Selection::parse("1, 3-3,5-6,").unwrap();
+        let (items, logs) = selection.retain(items, logs).unwrap();
+        let paths: Vec<&str> = items
+            .iter()
+            .map(|i| i.path.to_str().unwrap())
+            .chain(logs.iter().map(|l| l.path.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            paths,
+            ["/cache/1", "/cache/3", "/var/log/5.log", "/var/log/6.log"]
+        );
+
+        let past_end = Selection::parse("2,7-9").unwrap().retain(items, logs);
+        assert_eq!(
+            past_end.unwrap_err(),
+            "there is no item 9; the listing has 4"
+        );
+
+        for bad in ["", "0", "3-1", "a", "1-", ","] {
+            assert!(Selection::parse(bad).is_err(), "{bad}");
+        }
+    }
+}
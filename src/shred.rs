@@ -0,0 +1,146 @@
+use crate::unlink::{Dir, c_string};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::Path;
+
+/// Bytes written per call while overwriting
+const CHUNK: usize = 1 << 16;
+
+/// Overwrite the regular file at `path` with `passes` rounds of random data,
+/// syncing after each so they reach the disk one after the other. Symlinks
+/// are not followed, and files with other hard links are left as they are,
+/// as their contents live on under another name.
+///
+/// Copy-on-write filesystems, SSDs and journals may keep old blocks anyway;
+/// this is a best effort for ordinary files on ordinary disks.
+pub fn overwrite(path: &Path, passes: u32) -> io::Result<()> {
+    match OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+    {
+        Ok(file) => overwrite_file(file, passes),
+        Err(e) if is_no_regular_file(&e) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether opening a file for writing failed as it is a symbolic link, a
+/// directory, or a FIFO or socket without a peer
+fn is_no_regular_file(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::ELOOP | libc::EISDIR | libc::ENXIO)
+    )
+}
+
+/// Overwrite every regular file below `dir`, without following symlinks.
+/// Directories are opened relative to their parent, so one swapped for a
+/// symlink meanwhile is skipped rather than followed.
+pub fn overwrite_tree(dir: &Path, passes: u32) -> io::Result<()> {
+    let dir = match Dir::open(libc::AT_FDCWD, &c_string(dir.as_os_str())?, false) {
+        Ok(dir) => dir,
+        Err(e) if matches!(e.raw_os_error(), Some(libc::ELOOP | libc::ENOTDIR)) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    overwrite_dir(&dir, passes)
+}
+
+/// Overwrite every regular file below the opened `dir`
+fn overwrite_dir(dir: &Dir, passes: u32) -> io::Result<()> {
+    for (name, is_dir) in dir.entries()? {
+        if is_dir {
+            if let Some(subdir) = dir.open_subdir(&name)? {
+                overwrite_dir(&subdir, passes)?;
+            }
+            continue;
+        }
+        let flags = libc::O_WRONLY | libc::O_NOFOLLOW | libc::O_NONBLOCK | libc::O_CLOEXEC;
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        if fd < 0 {
+            let error = io::Error::last_os_error();
+            // Also skip files removed since listing
+            if is_no_regular_file(&error) || error.kind() == io::ErrorKind::NotFound {
+                continue;
+            }
+            return Err(error);
+        }
+        overwrite_file(unsafe { File::from_raw_fd(fd) }, passes)?;
+    }
+    Ok(())
+}
+
+/// Overwrite the opened `file` if it is a regular file with no other links
+fn overwrite_file(mut file: File, passes: u32) -> io::Result<()> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.nlink() > 1 || metadata.len() == 0 {
+        return Ok(());
+    }
+
+    let mut buffer = vec![0; CHUNK];
+    for _ in 0..passes {
+        let mut noise = noise()?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut left = metadata.len();
+        while left > 0 {
+            let chunk = &mut buffer[..left.min(CHUNK as u64) as usize];
+            noise.fill(chunk);
+            file.write_all(chunk)?;
+            left -= chunk.len() as u64;
+        }
+        file.sync_data()?;
+    }
+    Ok(())
+}
+
+/// A stream of random bytes: the BLAKE3 output stream under a fresh key from
+/// the kernel, which is far cheaper than reading that much from it
+fn noise() -> io::Result<blake3::OutputReader> {
+    let mut key = [0u8; 32];
+    let read = unsafe { libc::getrandom(key.as_mut_ptr().cast(), key.len(), 0) };
+    if read != key.len() as isize {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(blake3::Hasher::new_keyed(&key).finalize_xof())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_overwrite_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let secret = b"session=0123456789abcdef".repeat(5000);
+        let cookies = temp_dir.path().join("Cache/Cookies");
+        fs::create_dir_all(cookies.parent().unwrap()).unwrap();
+        fs::write(&cookies, &secret).unwrap();
+        // A hard link and a symlink target outside the tree keep their data
+        let linked = temp_dir.path().join("Cache/linked");
+        let kept = temp_dir.path().join("kept");
+        fs::write(&kept, &secret).unwrap();
+        fs::hard_link(&kept, &linked).unwrap();
+        std::os::unix::fs::symlink(&kept, temp_dir.path().join("Cache/link")).unwrap();
+
+        overwrite_tree(&temp_dir.path().join("Cache"), 2).unwrap();
+
+        let shredded = fs::read(&cookies).unwrap();
+        assert_eq!(shredded.len(), secret.len());
+        assert!(!shredded.windows(8).any(|w| w == b"session="));
+        assert_eq!(fs::read(&kept).unwrap(), secret);
+
+        // Nor is a symlinked tree followed
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("data"), &secret).unwrap();
+        let link = temp_dir.path().join("Cache/dir-link");
+        std::os::unix::fs::symlink(&outside, &link).unwrap();
+        overwrite_tree(&link, 1).unwrap();
+        overwrite_tree(&temp_dir.path().join("Cache"), 1).unwrap();
+        assert_eq!(fs::read(outside.join("data")).unwrap(), secret);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::cache_detector::{CacheItem, calculate_sizes};
+use crate::config::Config;
+use crate::error::CleanerError;
+use crate::scan_report::ScanReport;
+use crate::state;
+use crate::walker;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Version written into new cache files; other versions are ignored
+const SIZE_CACHE_VERSION: u32 = 1;
+
+/// What identifies an unchanged item: the mtime and entry count of its
+/// directory (or the mtime of a file item)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Fingerprint {
+    mtime_secs: i64,
+    mtime_nanos: i64,
+    entries: usize,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let entries = if metadata.is_dir() {
+            fs::read_dir(path).ok()?.count()
+        } else {
+            0
+        };
+        Some(Self {
+            mtime_secs: metadata.mtime(),
+            mtime_nanos: metadata.mtime_nsec(),
+            entries,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedSize {
+    fingerprint: Fingerprint,
+    size_bytes: u64,
+    file_count: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SizeCacheFile {
+    version: u32,
+    sizes: HashMap<PathBuf, CachedSize>,
+}
+
+/// Item sizes measured by earlier scans, reused while the item's directory
+/// is unchanged so monitoring runs don't re-walk huge caches
+pub struct SizeCache {
+    path: PathBuf,
+    sizes: HashMap<PathBuf, CachedSize>,
+}
+
+impl SizeCache {
+    /// The cache of earlier runs; missing or unreadable files start empty
+    pub fn load() -> Self {
+        Self::load_from(Self::default_path())
+    }
+
+    fn load_from(path: PathBuf) -> Self {
+        let sizes = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<SizeCacheFile>(&content).ok())
+            .filter(|file| file.version == SIZE_CACHE_VERSION)
+            .map(|file| file.sizes)
+            .unwrap_or_default();
+        Self { path, sizes }
+    }
+
+    /// Like [`calculate_sizes`], taking the sizes of unchanged items from the
+    /// cache and remembering the ones it had to measure
+    pub fn calculate_sizes(
+        &mut self,
+        mut items: Vec<CacheItem>,
+        config: &Config,
+    ) -> Result<ScanReport<CacheItem>, CleanerError> {
+        let mut fingerprints = HashMap::new();
+        let mut stale = Vec::new();
+        for item in &mut items {
+            let fingerprint = Fingerprint::of(item.target_path());
+            match self.sizes.get(item.target_path()) {
+                Some(cached) if fingerprint == Some(cached.fingerprint) => {
+                    item.size_bytes = Some(cached.size_bytes);
+                    item.file_count = Some(cached.file_count);
+                }
+                _ => {
+                    if let Some(fingerprint) = fingerprint {
+                        fingerprints.insert(item.target_path().to_path_buf(), fingerprint);
+                    }
+                    stale.push(item.clone());
+                }
+            }
+        }
+
+        let measured = calculate_sizes(stale, config)?;
+        // Walks cut short by --timeout measured only part of their item
+        if walker::scan_timed_out() {
+            fingerprints.clear();
+        }
+        let mut by_path: HashMap<PathBuf, CacheItem> = HashMap::new();
+        for item in measured.items {
+            if let (Some(fingerprint), Some(size_bytes), Some(file_count)) = (
+                fingerprints.remove(item.target_path()),
+                item.size_bytes,
+                item.file_count,
+            ) {
+                self.sizes.insert(
+                    item.target_path().to_path_buf(),
+                    CachedSize {
+                        fingerprint,
+                        size_bytes,
+                        file_count,
+                    },
+                );
+            }
+            by_path.insert(item.path.clone(), item);
+        }
+
+        for item in &mut items {
+            if let Some(measured) = by_path.remove(&item.path) {
+                *item = measured;
+            }
+        }
+
+        Ok(ScanReport {
+            items,
+            warnings: measured.warnings,
+        })
+    }
+
+    /// Persist the cache, dropping entries whose path no longer exists
+    pub fn save(mut self) -> Result<(), CleanerError> {
+        self.sizes.retain(|path, _| path.exists());
+        let _lock = match self.path.parent() {
+            Some(parent) => Some(state::lock(parent)?),
+            None => None,
+        };
+
+        let file = SizeCacheFile {
+            version: SIZE_CACHE_VERSION,
+            sizes: self.sizes,
+        };
+        let content = serde_json::to_string(&file).map_err(|e| CleanerError::Io {
+            path: self.path.clone(),
+            kind: std::io::ErrorKind::Other,
+            message: e.to_string(),
+        })?;
+        state::write_atomic(&self.path, content.as_bytes())
+    }
+
+    fn default_path() -> PathBuf {
+        state::dir().join("size_cache.json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unchanged_sizes_are_reused() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = temp_dir.path().join("cache");
+        fs::create_dir_all(cache.join("deep")).unwrap();
+        fs::write(cache.join("a"), "12345").unwrap();
+        let item = CacheItem::new(cache.clone(), CacheType::UserCache, "");
+        let cache_file = temp_dir.path().join("size_cache.json");
+        let config = Config::default();
+
+        let mut sizes = SizeCache::load_from(cache_file.clone());
+        let report = sizes.calculate_sizes(vec![item.clone()], &config).unwrap();
+        assert_eq!(report.items[0].size_bytes, Some(5));
+        sizes.save().unwrap();
+
+        // Deep changes leave the directory's fingerprint alone
+        fs::write(cache.join("deep/b"), "1234567890").unwrap();
+        let mut sizes = SizeCache::load_from(cache_file.clone());
+        let report = sizes.calculate_sizes(vec![item.clone()], &config).unwrap();
+        assert_eq!(report.items[0].size_bytes, Some(5));
+
+        fs::write(cache.join("c"), "1").unwrap();
+        let report = sizes.calculate_sizes(vec![item], &config).unwrap();
+        assert_eq!(report.items[0].size_bytes, Some(16));
+    }
+}
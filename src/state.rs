@@ -0,0 +1,224 @@
+use crate::error::CleanerError;
+use crate::reclaim;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+/// Entries earlier versions wrote next to the configuration
+const LEGACY_ENTRIES: &[&str] = &[
+    "history",
+    "audit",
+    "backups",
+    "hooks.log",
+    "size_cache.json",
+];
+
+/// Name of the lock file inside a locked directory
+const LOCK_FILE: &str = ".lock";
+
+/// Where the cleaner keeps what it records between runs: scan history, audit
+/// manifests, backup lists, the hook log and the size cache. This is
+/// `$XDG_STATE_HOME/cleaner`, or `~/.local/state/cleaner`.
+///
+/// Entries left in `~/.config/cleaner` by earlier versions are moved here the
+/// first time the directory is asked for.
+pub fn dir() -> PathBuf {
+    static MIGRATE: Once = Once::new();
+
+    let dir = state_home().join("cleaner");
+    MIGRATE.call_once(|| {
+        if let Err(e) = migrate(&legacy_dir(), &dir) {
+            eprintln!("Warning: Could not move state to {}: {}", dir.display(), e);
+        }
+    });
+    dir
+}
+
+/// Exclusive lock on a state directory, held until dropped
+pub struct StateLock {
+    _file: File,
+}
+
+/// Lock `dir` against other cleaner processes writing to it, waiting for
+/// any current holder
+pub fn lock(dir: &Path) -> Result<StateLock, CleanerError> {
+    fs::create_dir_all(dir).map_err(|e| CleanerError::from_io(dir, &e))?;
+    let path = dir.join(LOCK_FILE);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| CleanerError::from_io(&path, &e))?;
+
+    // SAFETY: flock on a descriptor owned by `file`, which outlives the lock
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(CleanerError::from_io(
+            &path,
+            &std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(StateLock { _file: file })
+}
+
+/// Replace `path` with `contents` so readers see either the old or the new
+/// file, never a partial one
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), CleanerError> {
+    let temp = temp_path(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CleanerError::from_io(parent, &e))?;
+    }
+
+    let mut file = File::create(&temp).map_err(|e| CleanerError::from_io(&temp, &e))?;
+    file.write_all(contents)
+        .and_then(|()| file.sync_all())
+        .map_err(|e| CleanerError::from_io(&temp, &e))?;
+    fs::rename(&temp, path).map_err(|e| {
+        fs::remove_file(&temp).ok();
+        CleanerError::from_io(path, &e)
+    })
+}
+
+/// Private name next to `path` to write its new contents under before they
+/// are renamed into place
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Move the entries of `legacy` that `dir` doesn't have yet
+fn migrate(legacy: &Path, dir: &Path) -> Result<(), CleanerError> {
+    let pending: Vec<&str> = LEGACY_ENTRIES
+        .iter()
+        .copied()
+        .filter(|entry| legacy.join(entry).exists() && !dir.join(entry).exists())
+        .collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let _lock = lock(dir)?;
+    for entry in pending {
+        let (from, to) = (legacy.join(entry), dir.join(entry));
+        if from.exists() && !to.exists() {
+            move_entry(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move `from` to `to`, copying when they lie on different filesystems, as
+/// `~/.config` and `~/.local/state` may. The copy is put in place under its
+/// final name only once it is complete, and `from` is removed after that.
+fn move_entry(from: &Path, to: &Path) -> Result<(), CleanerError> {
+    match fs::rename(from, to) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {}
+        result => return result.map_err(|e| CleanerError::from_io(from, &e)),
+    }
+
+    let temp = temp_path(to);
+    let copied = copy_tree(from, &temp).and_then(|()| {
+        if reclaim::size_of(from) == reclaim::size_of(&temp) {
+            Ok(())
+        } else {
+            Err(std::io::Error::other("copy is incomplete"))
+        }
+    });
+    if let Err(e) = copied.and_then(|()| fs::rename(&temp, to)) {
+        remove(&temp).ok();
+        return Err(CleanerError::from_io(from, &e));
+    }
+    remove(from).map_err(|e| CleanerError::from_io(from, &e))
+}
+
+/// Copy the file or directory tree `from` to `to`, not following symlinks
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else if metadata.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)
+    } else {
+        fs::copy(from, to)?;
+        File::open(to)?.sync_all()
+    }
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+fn state_home() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home().join(".local").join("state"))
+}
+
+fn legacy_dir() -> PathBuf {
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home().join(".config"));
+    config_home.join("cleaner")
+}
+
+fn home() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrate_and_write_atomic() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join("config");
+        let dir = temp_dir.path().join("state");
+        fs::create_dir_all(legacy.join("history")).unwrap();
+        fs::write(legacy.join("history/scan_1.json"), "{}").unwrap();
+        fs::write(legacy.join("hooks.log"), "old").unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("hooks.log"), "new").unwrap();
+
+        migrate(&legacy, &dir).unwrap();
+        assert!(dir.join("history/scan_1.json").is_file());
+        assert!(!legacy.join("history").exists());
+        // Entries already in the state directory win
+        assert_eq!(fs::read_to_string(dir.join("hooks.log")).unwrap(), "new");
+
+        // What a move across filesystems does after rename fails with EXDEV
+        let copy = temp_dir.path().join("copy");
+        copy_tree(&dir.join("history"), &copy).unwrap();
+        assert_eq!(
+            reclaim::size_of(&copy),
+            reclaim::size_of(&dir.join("history"))
+        );
+        remove(&copy).unwrap();
+
+        write_atomic(&dir.join("size_cache.json"), b"[]").unwrap();
+        let _lock = lock(&dir).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, [".lock", "history", "hooks.log", "size_cache.json"]);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+const SYSFS_ROOT: &str = "/sys";
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// Kind of device a filesystem lives on, as far as parallel I/O is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    /// Spinning disk, where concurrent walkers mostly add seeks
+    Rotational,
+    /// SSD or NVMe, which keeps up with many requests in flight
+    SolidState,
+    /// Network, virtual or otherwise undetectable storage
+    Unknown,
+}
+
+impl StorageKind {
+    /// Storage holding `path`, from the `queue/rotational` flag of its block device
+    pub fn of(path: &Path) -> Self {
+        let Ok(metadata) = fs::metadata(path) else {
+            return StorageKind::Unknown;
+        };
+        let (major, minor) = (libc::major(metadata.dev()), libc::minor(metadata.dev()));
+        let sys = Path::new(SYSFS_ROOT);
+
+        match Self::of_device(&sys.join(format!("dev/block/{}:{}", major, minor))) {
+            // Btrfs and similar report an anonymous device; fall back to the
+            // block device the filesystem was mounted from
+            StorageKind::Unknown => mount_source(path)
+                .and_then(|source| Some(sys.join("class/block").join(source.file_name()?)))
+                .map_or(StorageKind::Unknown, |device| Self::of_device(&device)),
+            kind => kind,
+        }
+    }
+
+    /// Kind of the block device behind the sysfs link `device`. Partitions
+    /// have no queue of their own and inherit the flag of their disk.
+    fn of_device(device: &Path) -> Self {
+        let Ok(device) = fs::canonicalize(device) else {
+            return StorageKind::Unknown;
+        };
+        device
+            .ancestors()
+            .take(2)
+            .find_map(|dir| fs::read_to_string(dir.join("queue/rotational")).ok())
+            .map_or(StorageKind::Unknown, |flag| match flag.trim() {
+                "1" => StorageKind::Rotational,
+                "0" => StorageKind::SolidState,
+                _ => StorageKind::Unknown,
+            })
+    }
+
+    /// Default worker count for `cpus` available cores
+    pub fn default_threads(self, cpus: usize) -> usize {
+        match self {
+            StorageKind::Rotational => cpus.min(2),
+            StorageKind::SolidState => cpus.min(16),
+            StorageKind::Unknown => cpus.min(8),
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            StorageKind::Rotational => "rotational storage",
+            StorageKind::SolidState => "solid-state storage",
+            StorageKind::Unknown => "unknown storage",
+        }
+    }
+}
+
+/// Device the filesystem holding `path` was mounted from, e.g. `/dev/nvme0n1p2`
+fn mount_source(path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+    let mountinfo = fs::read_to_string(MOUNTINFO_PATH).ok()?;
+
+    // The longest mount point containing the path wins; later lines shadow
+    // earlier mounts on the same point
+    let (_, source) = mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let mount_point = Path::new(fields.get(4)?);
+            let separator = fields.iter().position(|f| *f == "-")?;
+            let source = fields.get(separator + 2)?;
+            path.starts_with(mount_point)
+                .then(|| (mount_point.components().count(), *source))
+        })
+        .max_by_key(|(depth, _)| *depth)?;
+
+    source
+        .starts_with("/dev/")
+        .then(|| fs::canonicalize(source).ok())
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_partition_inherits_disk_rotational_flag() {
+        let temp_dir = TempDir::new().unwrap();
+        let sys = temp_dir.path();
+        let hdd = sys.join("devices/pci0/ata1/block/sda");
+        let nvme = sys.join("devices/pci0/nvme/nvme0n1");
+        fs::create_dir_all(hdd.join("sda1")).unwrap();
+        fs::create_dir_all(hdd.join("queue")).unwrap();
+        fs::write(hdd.join("queue/rotational"), "1\n").unwrap();
+        fs::create_dir_all(nvme.join("queue")).unwrap();
+        fs::write(nvme.join("queue/rotational"), "0\n").unwrap();
+        fs::create_dir_all(sys.join("dev/block")).unwrap();
+        std::os::unix::fs::symlink(hdd.join("sda1"), sys.join("dev/block/8:1")).unwrap();
+        std::os::unix::fs::symlink(&nvme, sys.join("dev/block/259:0")).unwrap();
+
+        let kind = |device: &str| StorageKind::of_device(&sys.join("dev/block").join(device));
+        assert_eq!(kind("8:1"), StorageKind::Rotational);
+        assert_eq!(kind("259:0"), StorageKind::SolidState);
+        assert_eq!(kind("0:42"), StorageKind::Unknown);
+        assert_eq!(StorageKind::Rotational.default_threads(32), 2);
+    }
+}
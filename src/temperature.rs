@@ -0,0 +1,130 @@
+use crate::cache_detector::CacheItem;
+use crate::history::SnapshotItem;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// Items used this recently are hot
+const HOT_WITHIN: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+
+/// Items unused this long, and unchanged since the previous scan, are cold
+const COLD_AFTER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How actively a cache is used, from its modification and access times and
+/// how it changed since the previous scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Temperature {
+    /// Written or read within the last two days, or changed since the
+    /// previous scan
+    Hot,
+    /// Neither hot nor cold, including items whose use can't be dated
+    Warm,
+    /// Neither written nor read for 30 days and unchanged since the previous
+    /// scan
+    Cold,
+}
+
+impl Temperature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Temperature::Hot => "hot",
+            Temperature::Warm => "warm",
+            Temperature::Cold => "cold",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "hot" => Ok(Temperature::Hot),
+            "warm" => Ok(Temperature::Warm),
+            "cold" => Ok(Temperature::Cold),
+            _ => Err(format!("unknown temperature '{}'", name)),
+        }
+    }
+
+    /// Rate `item` at `now`. `previous` is the item in the previous scan of
+    /// the same root, if it was found then; access times are only used when
+    /// `atime_usable`, i.e. the filesystem isn't mounted noatime.
+    pub fn of(
+        item: &CacheItem,
+        previous: Option<&SnapshotItem>,
+        atime_usable: bool,
+        now: SystemTime,
+    ) -> Self {
+        // Snapshots record a file count only for items they sized
+        let changed = previous
+            .and_then(|previous| Some((previous, previous.file_count?)))
+            .is_some_and(|(previous, files_before)| {
+                item.size_bytes
+                    .is_some_and(|size| size != previous.size_bytes)
+                    || item.file_count.is_some_and(|files| files != files_before)
+            });
+        if changed {
+            return Temperature::Hot;
+        }
+
+        let accessed = item.last_accessed.filter(|_| atime_usable);
+        let Some(last_used) = item.last_modified.max(accessed) else {
+            return Temperature::Warm;
+        };
+        match now.duration_since(last_used) {
+            Err(_) => Temperature::Hot,
+            Ok(idle) if idle < HOT_WITHIN => Temperature::Hot,
+            // A file read without the access time showing it could be in use
+            Ok(idle) if idle >= COLD_AFTER && accessed.is_some() => Temperature::Cold,
+            Ok(_) => Temperature::Warm,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_detector::CacheType;
+    use crate::plan::PlanKind;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_temperature_of_items() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        let item = |modified_days: u32, accessed_days: u32, size: u64| CacheItem {
+            size_bytes: Some(size),
+            file_count: Some(3),
+            last_modified: Some(now - modified_days * day),
+            last_accessed: Some(now - accessed_days * day),
+            ..CacheItem::new("/home/u/.cache/app", CacheType::UserCache, "")
+        };
+        let previous = SnapshotItem {
+            path: PathBuf::from("/home/u/.cache/app"),
+            kind: PlanKind::Cache(CacheType::UserCache),
+            size_bytes: 100,
+            file_count: Some(3),
+        };
+
+        assert_eq!(
+            Temperature::of(&item(90, 60, 100), Some(&previous), true, now),
+            Temperature::Cold
+        );
+        // Written once long ago but still read
+        assert_eq!(
+            Temperature::of(&item(90, 1, 100), Some(&previous), true, now),
+            Temperature::Hot
+        );
+        assert_eq!(
+            Temperature::of(&item(90, 10, 100), None, true, now),
+            Temperature::Warm
+        );
+        // Grown since the previous scan
+        assert_eq!(
+            Temperature::of(&item(90, 60, 120), Some(&previous), true, now),
+            Temperature::Hot
+        );
+        // Without usable access times nothing is called cold
+        assert_eq!(
+            Temperature::of(&item(90, 60, 100), Some(&previous), false, now),
+            Temperature::Warm
+        );
+    }
+}
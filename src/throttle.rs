@@ -0,0 +1,171 @@
+use crate::config::PerformanceConfig;
+use crate::power;
+use std::cell::Cell;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const IO_PRESSURE_PATH: &str = "/proc/pressure/io";
+
+/// How often I/O pressure is sampled while the cleaner works
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Workers allowed to do I/O at once; 0 while nothing is throttled
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Workers doing I/O right now
+static ACTIVE: Mutex<usize> = Mutex::new(0);
+static RELEASED: Condvar = Condvar::new();
+
+/// Whether the run had to slow down at any point
+static THROTTLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether pressure is already being watched, as every scan root calls `start`
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Whether this thread already holds a permit, so nested work doesn't
+    /// wait on itself
+    static HOLDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Leave to do one unit of I/O work, returned when dropped
+pub struct Permit {
+    counted: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.counted {
+            HOLDING.with(|h| h.set(false));
+            let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+            *active -= 1;
+            RELEASED.notify_one();
+        }
+    }
+}
+
+/// Wait until fewer workers than the current limit are doing I/O. Scanning
+/// and deleting take a permit per directory or item they work on.
+pub fn permit() -> Permit {
+    // Unthrottled work isn't counted, so it never contends on the lock
+    if LIMIT.load(Ordering::Relaxed) == 0 || HOLDING.with(Cell::get) {
+        return Permit { counted: false };
+    }
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let limit = LIMIT.load(Ordering::Relaxed);
+        if limit == 0 || *active < limit {
+            break;
+        }
+        // Time out now and then, as the limit changes without the lock held
+        active = RELEASED
+            .wait_timeout(active, SAMPLE_INTERVAL)
+            .map(|(active, _)| active)
+            .unwrap_or_else(|e| e.into_inner().0);
+    }
+    *active += 1;
+    HOLDING.with(|h| h.set(true));
+    Permit { counted: true }
+}
+
+/// Whether workers are held back for I/O pressure right now
+pub fn is_limited() -> bool {
+    LIMIT.load(Ordering::Relaxed) != 0
+}
+
+/// Whether workers were held back for I/O pressure since the last call
+pub fn take_throttled() -> bool {
+    THROTTLED.swap(false, Ordering::Relaxed)
+}
+
+/// Watch I/O pressure for the rest of the run, halving the `workers` allowed
+/// at once while it is above the configured limit and letting them back one
+/// at a time once it fell below half of it. Does nothing on kernels without
+/// pressure stall information.
+pub fn start(config: &PerformanceConfig, workers: usize) {
+    if !config.adaptive_throttle || workers < 2 || WATCHING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let Some(mut last) = read_stall() else {
+        return;
+    };
+    let high = config.throttle_pressure;
+    thread::spawn(move || {
+        let mut sampled = Instant::now();
+        loop {
+            thread::sleep(SAMPLE_INTERVAL);
+            let Some(stall) = read_stall() else {
+                return;
+            };
+            let elapsed = sampled.elapsed();
+            sampled = Instant::now();
+            let percent = stall.saturating_sub(last) as f64 * 100.0 / elapsed.as_micros() as f64;
+            last = stall;
+
+            let current = match LIMIT.load(Ordering::Relaxed) {
+                0 => workers,
+                limit => limit,
+            };
+            let next = next_limit(current, workers, percent, high);
+            if next < current {
+                THROTTLED.store(true, Ordering::Relaxed);
+            }
+            LIMIT.store(if next >= workers { 0 } else { next }, Ordering::Relaxed);
+            RELEASED.notify_all();
+        }
+    });
+}
+
+/// The workers allowed next, given the share of the last interval tasks
+/// stalled on I/O
+fn next_limit(current: usize, workers: usize, percent: f64, high: f64) -> usize {
+    if percent > high {
+        (current / 2).max(1)
+    } else if percent < high / 2.0 {
+        (current + 1).min(workers)
+    } else {
+        current
+    }
+}
+
+/// Microseconds some task stalled on I/O since boot
+fn read_stall() -> Option<u64> {
+    parse_stall_total(&fs::read_to_string(IO_PRESSURE_PATH).ok()?)
+}
+
+/// The `some total` counter of a `/proc/pressure` file
+fn parse_stall_total(text: &str) -> Option<u64> {
+    power::pressure_field(text, "total")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_limits() {
+        assert_eq!(
+            parse_stall_total("some avg10=0.00 avg60=0.00 avg300=0.00 total=8812\n"),
+            Some(8812)
+        );
+
+        // Spikes halve the workers down to one, calm brings them back one by one
+        assert_eq!(next_limit(8, 8, 45.0, 30.0), 4);
+        assert_eq!(next_limit(1, 8, 90.0, 30.0), 1);
+        assert_eq!(next_limit(4, 8, 20.0, 30.0), 4);
+        assert_eq!(next_limit(4, 8, 5.0, 30.0), 5);
+        assert_eq!(next_limit(8, 8, 0.0, 30.0), 8);
+
+        // Nested work on a thread that holds a permit never waits
+        LIMIT.store(1, Ordering::Relaxed);
+        let outer = permit();
+        let inner = permit();
+        assert!(outer.counted && !inner.counted);
+        drop((inner, outer));
+        LIMIT.store(0, Ordering::Relaxed);
+        assert!(!permit().counted);
+    }
+}
@@ -0,0 +1,549 @@
+use crate::cache_detector::{CacheItem, CacheType};
+use crate::config::Config;
+use crate::mounts::MountTable;
+use crate::owners::Owner;
+use chrono::NaiveDateTime;
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Find what was moved to the trash directories file managers keep at the
+/// top of each volume, `.Trash-$UID` and `.Trash/$UID`, on writable volumes
+/// mounted below `scan_root`. Each item in the trash of `uid` that was
+/// trashed `volume_trash.retention` ago is reported on its own, dated by
+/// the deletion date its trashinfo records.
+pub fn detect(scan_root: &Path, config: &Config, mounts: &MountTable, uid: u32) -> Vec<CacheItem> {
+    let mut volumes: Vec<&Path> = mounts
+        .writable_mount_points()
+        .filter(|mount_point| mount_point.starts_with(scan_root))
+        .collect();
+    volumes.sort();
+
+    let retention = config.volume_trash.retention;
+    let now = SystemTime::now();
+    volumes
+        .into_iter()
+        .flat_map(|volume| trash_dirs(volume, uid))
+        .filter(|dir| !config.is_excluded_path(dir))
+        .flat_map(|dir| trashed_items(&dir))
+        .filter(|item| !config.is_excluded_path(&item.path))
+        .filter(|item| {
+            item.last_modified
+                .and_then(|trashed| now.duration_since(trashed).ok())
+                .is_some_and(|age| age >= retention)
+        })
+        .collect()
+}
+
+/// Trash directories at the top of `volume` belonging to `uid`
+fn trash_dirs(volume: &Path, uid: u32) -> Vec<PathBuf> {
+    let belongs = |name: &str| name.parse::<u32>() == Ok(uid);
+
+    // Per-user `.Trash-$UID` next to the shared, sticky `.Trash/$UID`
+    let per_user = real_subdirectories(volume).into_iter().filter(|dir| {
+        dir.file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(".Trash-"))
+            .is_some_and(belongs)
+    });
+    let shared = real_subdirectories(&volume.join(".Trash"))
+        .into_iter()
+        .filter(|dir| {
+            dir.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(belongs)
+        });
+
+    let mut dirs: Vec<PathBuf> = per_user.chain(shared).collect();
+    dirs.sort();
+    dirs
+}
+
+/// Subdirectories of `dir` that aren't symlinks
+fn real_subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// The items in the `files` directory of the trash at `trash`, dated when
+/// they were trashed. Those without a readable trashinfo are dated by their
+/// own modification time.
+fn trashed_items(trash: &Path) -> Vec<CacheItem> {
+    let Ok(entries) = fs::read_dir(trash.join("files")) else {
+        return Vec::new();
+    };
+    let volume = volume_of(trash);
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let metadata = fs::symlink_metadata(&path).ok()?;
+            let deleted_at = deletion_date(&info_file(&path)?);
+            let trashed = deleted_at
+                .and_then(|date| date.and_local_timezone(chrono::Local).single())
+                .map(SystemTime::from)
+                .or_else(|| metadata.modified().ok());
+            Some(CacheItem {
+                last_modified: trashed,
+                owner: Some(Owner::of(&metadata)),
+                ..CacheItem::new(
+                    path,
+                    CacheType::VolumeTrash,
+                    match deleted_at {
+                        Some(date) => format!(
+                            "trashed {} on volume {}",
+                            date.format("%Y-%m-%d"),
+                            volume.display()
+                        ),
+                        None => format!("in the trash of volume {}", volume.display()),
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+/// The trashinfo file recording the item `file` in a trash's `files`
+fn info_file(file: &Path) -> Option<PathBuf> {
+    let mut name = file.file_name()?.to_os_string();
+    name.push(".trashinfo");
+    Some(file.parent()?.parent()?.join("info").join(name))
+}
+
+/// The `DeletionDate` a trashinfo file records
+fn deletion_date(info: &Path) -> Option<NaiveDateTime> {
+    let contents = fs::read_to_string(info).ok()?;
+    info_field(&contents, "DeletionDate")
+        .and_then(|date| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S").ok())
+}
+
+/// The value of `key` in the trashinfo `contents`
+fn info_field<'a>(contents: &'a str, key: &str) -> Option<&'a str> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Drop the trashinfo of the item `file` once it is deleted from the trash,
+/// so file managers don't list it anymore
+pub fn forget(file: &Path) {
+    if let Some(info) = info_file(file) {
+        fs::remove_file(info).ok();
+    }
+}
+
+/// Volume a `.Trash-$UID` or `.Trash/$UID` directory sits at the top of
+fn volume_of(trash_dir: &Path) -> &Path {
+    let parent = trash_dir.parent().unwrap_or(trash_dir);
+    if parent.ends_with(".Trash") {
+        parent.parent().unwrap_or(parent)
+    } else {
+        parent
+    }
+}
+
+/// The trash of the user's home, `$XDG_DATA_HOME/Trash`
+pub fn home_trash() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|data| data.join("Trash"))
+}
+
+/// Whether `path` is a trash directory or lies inside one, so moving it to
+/// the trash would recover nothing
+pub fn holds(path: &Path) -> bool {
+    let in_home_trash = home_trash().is_some_and(|trash| path.starts_with(&trash));
+    in_home_trash
+        || path.ancestors().any(|ancestor| {
+            ancestor
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| {
+                    n == ".Trash"
+                        || n.strip_prefix(".Trash-")
+                            .is_some_and(|uid| uid.parse::<u32>().is_ok())
+                })
+        })
+}
+
+/// Move `path` to the trash following the freedesktop.org trash spec, so a
+/// file manager can restore it: the home trash when `path` is on the same
+/// filesystem, else the trash at the top of its volume. Nothing is copied
+/// across filesystems. Returns where the item went.
+pub fn move_to_trash(path: &Path, mounts: &MountTable) -> io::Result<PathBuf> {
+    let device = fs::symlink_metadata(path)?.dev();
+    if let Some(home) = home_trash()
+        && device_of(&home) == Some(device)
+    {
+        return move_into(path, &home, path);
+    }
+
+    let top = mounts.mount_point_of(path);
+    let uid = unsafe { libc::getuid() };
+    let trash = volume_trash(top, uid)?;
+    let relative = path.strip_prefix(top).unwrap_or(path);
+    move_into(path, &trash, relative)
+}
+
+/// The shared `.Trash/$UID` if the volume has a sticky `.Trash`, else the
+/// per-user `.Trash-$UID`, created if missing. As the trash spec asks, a
+/// trash directory that is a symlink, or isn't owned by `uid` and private
+/// to it, is never used, so nobody can plant one to catch another user's
+/// files.
+fn volume_trash(top: &Path, uid: u32) -> io::Result<PathBuf> {
+    let shared = top.join(".Trash");
+    let usable =
+        fs::symlink_metadata(&shared).is_ok_and(|m| m.is_dir() && m.mode() & libc::S_ISVTX != 0);
+    if usable && let Ok(trash) = private_dir(&shared.join(uid.to_string()), uid) {
+        return Ok(trash);
+    }
+    match private_dir(&top.join(format!(".Trash-{}", uid)), uid) {
+        Err(e)
+            if e.kind() == io::ErrorKind::NotFound
+                || e.kind() == io::ErrorKind::PermissionDenied
+                || e.kind() == io::ErrorKind::ReadOnlyFilesystem =>
+        {
+            Err(io::Error::new(
+                io::ErrorKind::CrossesDevices,
+                format!("no trash on the volume at {}", top.display()),
+            ))
+        }
+        result => result,
+    }
+}
+
+/// The directory `dir`, created with mode 0700 unless it exists, if it is a
+/// real directory owned by `uid` that only `uid` can access
+fn private_dir(dir: &Path, uid: u32) -> io::Result<PathBuf> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        result => result?,
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(io::Error::other(format!(
+            "refusing to use {} as trash: not a directory of yours with mode 0700",
+            dir.display()
+        )));
+    }
+    Ok(dir.to_path_buf())
+}
+
+/// Device of `path`, or of its closest existing ancestor
+fn device_of(path: &Path) -> Option<u64> {
+    path.ancestors()
+        .find_map(|ancestor| fs::metadata(ancestor).ok())
+        .map(|m| m.dev())
+}
+
+/// Move `path` into the `files` directory of `trash`, recording it as
+/// `original` in the matching `info/*.trashinfo`. The info file is created
+/// first and exclusively, which reserves the name.
+fn move_into(path: &Path, trash: &Path, original: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    let mut builder = DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(&files)?;
+    builder.create(&info)?;
+
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original.as_os_str().as_bytes()),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    for n in 1.. {
+        let mut unique = name.to_os_string();
+        if n > 1 {
+            unique.push(format!(".{}", n));
+        }
+        let mut info_name = unique.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(&info_name);
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&info_path)
+        {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => result?,
+        };
+        // A file left without its info keeps its name too
+        let destination = files.join(&unique);
+        if fs::symlink_metadata(&destination).is_ok() {
+            drop(file);
+            fs::remove_file(&info_path).ok();
+            continue;
+        }
+        let moved = file
+            .write_all(contents.as_bytes())
+            .and_then(|()| fs::rename(path, &destination));
+        return match moved {
+            Ok(()) => Ok(destination),
+            Err(e) => {
+                fs::remove_file(&info_path).ok();
+                Err(e)
+            }
+        };
+    }
+    unreachable!()
+}
+
+/// An item in a trash directory, as its trashinfo file records it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trashed {
+    /// Where the item was before it was trashed
+    pub original: PathBuf,
+    /// The item in the trash
+    pub file: PathBuf,
+    pub deleted_at: Option<NaiveDateTime>,
+    info: PathBuf,
+}
+
+impl Trashed {
+    /// Move the item back where it was and drop its trashinfo, unless
+    /// something took its place meanwhile
+    pub fn restore(&self) -> io::Result<()> {
+        if fs::symlink_metadata(&self.original).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "something is in its place again",
+            ));
+        }
+        if let Some(parent) = self.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&self.file, &self.original)?;
+        fs::remove_file(&self.info).ok();
+        Ok(())
+    }
+}
+
+/// Trash directories items from `path` may have been moved to: the home
+/// trash and those at the top of its volume, with the directory their
+/// relative paths start from
+pub fn trash_dirs_for(path: &Path, mounts: &MountTable) -> Vec<(PathBuf, PathBuf)> {
+    let top = mounts.mount_point_of(path);
+    let uid = unsafe { libc::getuid() };
+    home_trash()
+        .map(|home| (home, PathBuf::from("/")))
+        .into_iter()
+        .chain([
+            (top.join(".Trash").join(uid.to_string()), top.to_path_buf()),
+            (top.join(format!(".Trash-{}", uid)), top.to_path_buf()),
+        ])
+        .collect()
+}
+
+/// The items in the trash directory `trash`, with relative paths taken
+/// from `top`. Info files without their item are left out.
+pub fn list(trash: &Path, top: &Path) -> Vec<Trashed> {
+    let Ok(entries) = fs::read_dir(trash.join("info")) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let info = entry.path();
+            let name = info.file_name()?.as_bytes().strip_suffix(b".trashinfo")?;
+            let file = trash.join("files").join(OsString::from_vec(name.to_vec()));
+            fs::symlink_metadata(&file).ok()?;
+            let contents = fs::read_to_string(&info).ok()?;
+            let field = |key: &str| info_field(&contents, key);
+            let original = PathBuf::from(OsString::from_vec(percent_decode(field("Path")?)));
+            Some(Trashed {
+                original: top.join(original),
+                file,
+                deleted_at: field("DeletionDate")
+                    .and_then(|date| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S").ok()),
+                info,
+            })
+        })
+        .collect()
+}
+
+/// Escape `bytes` as a URI path, as trashinfo files hold them
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"/-_.~!*'()".contains(&byte) {
+            encoded.push(byte);
+        } else {
+            encoded.extend(format!("%{:02X}", byte).bytes());
+        }
+    }
+    OsString::from_vec(encoded).to_string_lossy().into_owned()
+}
+
+/// The bytes of a path escaped by [`percent_encode`] or another
+/// implementation of the trash spec
+fn percent_decode(encoded: &str) -> Vec<u8> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_trash_dirs_of_user() {
+        let temp_dir = TempDir::new().unwrap();
+        let volume = temp_dir.path();
+        for dir in [
+            ".Trash-1000",
+            ".Trash-1001",
+            ".Trash/1000",
+            ".Trash-x",
+            "data",
+        ] {
+            fs::create_dir_all(volume.join(dir)).unwrap();
+        }
+        std::os::unix::fs::symlink(volume.join("data"), volume.join(".Trash-1002")).unwrap();
+
+        assert_eq!(
+            trash_dirs(volume, 1000),
+            vec![volume.join(".Trash/1000"), volume.join(".Trash-1000")]
+        );
+        assert!(trash_dirs(volume, 1002).is_empty());
+    }
+
+    #[test]
+    fn test_detect_trashed_items() {
+        let temp_dir = TempDir::new().unwrap();
+        let volume = temp_dir.path();
+        let trash = volume.join(".Trash-1000");
+        fs::create_dir_all(trash.join("files")).unwrap();
+        fs::create_dir_all(trash.join("info")).unwrap();
+        for (name, date) in [("old.iso", "2020-01-02T03:04:05"), ("new.iso", "")] {
+            fs::write(trash.join("files").join(name), "x").unwrap();
+            let date = match date {
+                "" => chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+                date => date.to_string(),
+            };
+            fs::write(
+                trash.join(format!("info/{}.trashinfo", name)),
+                format!("[Trash Info]\nPath=/{}\nDeletionDate={}\n", name, date),
+            )
+            .unwrap();
+        }
+        let mounts = MountTable::parse(&format!(
+            "1 0 8:1 / {} rw - ext4 /dev/sdb1 rw\n",
+            volume.display()
+        ));
+
+        // Only what was trashed before the retention, and only for its user
+        let items = detect(volume, &Config::default(), &mounts, 1000);
+        let paths: Vec<&Path> = items.iter().map(|item| item.path.as_path()).collect();
+        assert_eq!(paths, vec![trash.join("files/old.iso")]);
+        assert!(items[0].reason.starts_with("trashed 2020-01-02"));
+        assert!(detect(volume, &Config::default(), &mounts, 1001).is_empty());
+
+        fs::remove_file(&items[0].path).unwrap();
+        forget(&items[0].path);
+        assert!(!trash.join("info/old.iso.trashinfo").exists());
+        assert!(trash.join("info/new.iso.trashinfo").exists());
+    }
+
+    #[test]
+    fn test_move_into_trash() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("Trash");
+        let cache = temp_dir.path().join("my cache");
+        fs::create_dir(&cache).unwrap();
+        fs::write(cache.join("entry"), "x").unwrap();
+
+        let moved = move_into(&cache, &trash, &cache).unwrap();
+        assert_eq!(moved, trash.join("files/my cache"));
+        assert!(moved.join("entry").is_file());
+        assert!(!cache.exists());
+        let info = fs::read_to_string(trash.join("info/my cache.trashinfo")).unwrap();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("Path={}/my%20cache", temp_dir.path().display())
+        );
+        assert!(lines.next().unwrap().starts_with("DeletionDate="));
+
+        // A second item of the same name gets a name of its own
+        fs::write(&cache, "again").unwrap();
+        let moved = move_into(&cache, &trash, Path::new("my cache")).unwrap();
+        assert_eq!(moved, trash.join("files/my cache.2"));
+        let info = fs::read_to_string(trash.join("info/my cache.2.trashinfo")).unwrap();
+        assert!(info.contains("\nPath=my%20cache\n"));
+
+        // A failed move leaves no info file behind
+        assert!(move_into(&temp_dir.path().join("gone"), &trash, &cache).is_err());
+        assert_eq!(fs::read_dir(trash.join("info")).unwrap().count(), 2);
+
+        assert!(holds(&temp_dir.path().join(".Trash-1000/files/x")));
+        assert!(!holds(&cache));
+    }
+
+    #[test]
+    fn test_volume_trash_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let top = temp_dir.path();
+        let uid = unsafe { libc::getuid() };
+        let per_user = top.join(format!(".Trash-{}", uid));
+
+        assert_eq!(volume_trash(top, uid).unwrap(), per_user);
+        let mode = fs::metadata(&per_user).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // Another user's or a shared directory in its place is refused
+        fs::set_permissions(&per_user, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(volume_trash(top, uid).is_err());
+        assert!(volume_trash(top, uid + 1).is_err());
+
+        // As is a planted symlink, without creating anything at its target
+        fs::remove_dir(&per_user).unwrap();
+        let planted = top.join("planted");
+        fs::create_dir(&planted).unwrap();
+        fs::set_permissions(&planted, fs::Permissions::from_mode(0o700)).unwrap();
+        std::os::unix::fs::symlink(&planted, &per_user).unwrap();
+        let cache = top.join("cache");
+        fs::write(&cache, "x").unwrap();
+        assert!(volume_trash(top, uid).is_err());
+        assert!(cache.exists());
+        assert_eq!(fs::read_dir(&planted).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,322 @@
+use serde::Serializer;
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Size suffixes, longest first so `KiB` wins over `B`
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("tib", 1 << 40),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("tb", 1_000_000_000_000),
+    ("k", 1 << 10),
+    ("m", 1 << 20),
+    ("g", 1 << 30),
+    ("t", 1 << 40),
+    ("b", 1),
+];
+
+/// Parse a size such as `250MiB`, `1GB` or `4096` (bytes)
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let unit = unit.trim().to_lowercase();
+
+    let multiplier = if unit.is_empty() {
+        1
+    } else {
+        SIZE_UNITS
+            .iter()
+            .find(|(suffix, _)| *suffix == unit)
+            .map(|(_, multiplier)| *multiplier)
+            .ok_or_else(|| {
+                format!(
+                    "invalid size '{}': unknown unit '{}' (use B, KB, MB, GB, TB, KiB, MiB, GiB or TiB)",
+                    value,
+                    unit
+                )
+            })?
+    };
+    let number: f64 = number.parse().map_err(|_| {
+        format!(
+            "invalid size '{}': expected a number followed by a unit",
+            value
+        )
+    })?;
+
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Parse a duration such as `36h`, `2w` or `1day 12h`; a bare number counts
+/// in `bare_unit_secs` for compatibility with the old integer-only values
+pub fn parse_duration(value: &str, bare_unit_secs: u64) -> Result<Duration, String> {
+    let trimmed = value.trim();
+    if let Ok(count) = trimmed.parse::<u64>() {
+        return bare_duration(count, bare_unit_secs)
+            .ok_or_else(|| format!("invalid duration '{}': too large", value));
+    }
+    humantime::parse_duration(trimmed)
+        .map_err(|e| format!("invalid duration '{}': {} (use e.g. 36h, 7d, 2w)", value, e))
+}
+
+/// `count` units of `bare_unit_secs` each, unless that overflows
+fn bare_duration(count: u64, bare_unit_secs: u64) -> Option<Duration> {
+    count.checked_mul(bare_unit_secs).map(Duration::from_secs)
+}
+
+/// Parse an age given in days by default, e.g. `--log-age 7` or `--log-age 36h`
+pub fn parse_age_arg(value: &str) -> Result<Duration, String> {
+    parse_duration(value, SECS_PER_DAY)
+}
+
+/// Parse a timeout given in seconds by default, e.g. `--timeout 600` or `--timeout 10m`
+pub fn parse_timeout_arg(value: &str) -> Result<Duration, String> {
+    parse_duration(value, 1)
+}
+
+/// Format a duration the way it is written in the config file
+pub fn format_duration(duration: Duration) -> String {
+    humantime::format_duration(duration).to_string()
+}
+
+/// Languages writing a comma as the decimal mark, where a comma between
+/// thousands would read as one
+const DECIMAL_COMMA_LANGUAGES: &[&str] = &[
+    "az", "be", "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu",
+    "id", "is", "it", "kk", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl",
+    "sr", "sv", "tr", "uk", "vi",
+];
+
+/// A count with its thousands grouped, e.g. `1,382,190`. Sizes keep a dot
+/// as their decimal mark, so locales with a decimal comma group with a
+/// narrow no-break space instead of a dot.
+pub fn group_digits(count: u64) -> String {
+    static SEPARATOR: OnceLock<&str> = OnceLock::new();
+    let separator = SEPARATOR.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+            .unwrap_or_default();
+        thousands_separator(&locale)
+    });
+    group_digits_with(count, separator)
+}
+
+/// Separator for a locale such as `de_DE.UTF-8`
+fn thousands_separator(locale: &str) -> &'static str {
+    let language = locale.split(['_', '.', '@']).next().unwrap_or_default();
+    if DECIMAL_COMMA_LANGUAGES.contains(&language) {
+        "\u{202f}"
+    } else {
+        ","
+    }
+}
+
+fn group_digits_with(count: u64, separator: &str) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 * separator.len());
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Accepts either an integer in the field's legacy unit or a human-readable string
+struct HumanValue<F>(F, &'static str);
+
+impl<'de, T, F: Fn(HumanInput) -> Result<T, String>> Visitor<'de> for HumanValue<F> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.1)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        (self.0)(HumanInput::Number(v)).map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        let v = u64::try_from(v).map_err(|_| E::custom("value cannot be negative"))?;
+        self.visit_u64(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        (self.0)(HumanInput::Text(v.to_string())).map_err(E::custom)
+    }
+}
+
+enum HumanInput {
+    Number(u64),
+    Text(String),
+}
+
+/// Serde adapter for sizes: integers are bytes, strings are parsed with units
+pub mod size {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_size(*bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_any(HumanValue(
+            |input| match input {
+                HumanInput::Number(bytes) => Ok(bytes),
+                HumanInput::Text(text) => parse_size(&text),
+            },
+            "a size such as \"250MiB\" or a number of bytes",
+        ))
+    }
+
+    /// Exact binary-unit rendering so values round-trip unchanged
+    fn format_size(bytes: u64) -> String {
+        for (unit, multiplier) in [
+            ("TiB", 1u64 << 40),
+            ("GiB", 1 << 30),
+            ("MiB", 1 << 20),
+            ("KiB", 1 << 10),
+        ] {
+            if bytes >= multiplier && bytes.is_multiple_of(multiplier) {
+                return format!("{}{}", bytes / multiplier, unit);
+            }
+        }
+        format!("{}B", bytes)
+    }
+}
+
+/// Serde adapter for durations whose bare integers count days
+pub mod days {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_duration(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserialize_with_unit(deserializer, SECS_PER_DAY)
+    }
+}
+
+/// Serde adapter for durations whose bare integers count seconds
+pub mod secs {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_duration(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserialize_with_unit(deserializer, 1)
+    }
+}
+
+/// Serde adapter for maps of durations whose bare integers count days
+pub mod days_map {
+    use super::*;
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize)]
+    struct Days(#[serde(with = "super::days")] Duration);
+
+    pub fn serialize<K: Serialize, S: Serializer>(
+        map: &BTreeMap<K, Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_map(Some(map.len()))?;
+        for (key, duration) in map {
+            out.serialize_entry(key, &format_duration(*duration))?;
+        }
+        out.end()
+    }
+
+    pub fn deserialize<'de, K, D>(deserializer: D) -> Result<BTreeMap<K, Duration>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        D: Deserializer<'de>,
+    {
+        let map = BTreeMap::<K, Days>::deserialize(deserializer)?;
+        Ok(map.into_iter().map(|(key, Days(d))| (key, d)).collect())
+    }
+}
+
+fn deserialize_with_unit<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    bare_unit_secs: u64,
+) -> Result<Duration, D::Error> {
+    deserializer.deserialize_any(HumanValue(
+        |input| match input {
+            HumanInput::Number(count) => bare_duration(count, bare_unit_secs)
+                .ok_or_else(|| format!("invalid duration {}: too large", count)),
+            HumanInput::Text(text) => parse_duration(&text, bare_unit_secs),
+        },
+        "a duration such as \"36h\" or a number",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("250MiB"), Ok(250 * 1024 * 1024));
+        assert_eq!(parse_size("1GB"), Ok(1_000_000_000));
+        assert_eq!(parse_size("1.5 KiB"), Ok(1536));
+        assert!(
+            parse_size("10XB")
+                .unwrap_err()
+                .contains("unknown unit 'xb'")
+        );
+        assert!(parse_size("MiB").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(
+            parse_age_arg("7"),
+            Ok(Duration::from_secs(7 * SECS_PER_DAY))
+        );
+        assert_eq!(parse_age_arg("36h"), Ok(Duration::from_secs(36 * 60 * 60)));
+        assert_eq!(
+            parse_age_arg("2w"),
+            Ok(Duration::from_secs(14 * SECS_PER_DAY))
+        );
+        assert!(
+            parse_age_arg("soon")
+                .unwrap_err()
+                .contains("invalid duration 'soon'")
+        );
+        assert_eq!(
+            parse_age_arg("18446744073709551615"),
+            Err("invalid duration '18446744073709551615': too large".to_string())
+        );
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits_with(1_382_190, ","), "1,382,190");
+        assert_eq!(group_digits_with(999, ","), "999");
+        assert_eq!(group_digits_with(1000, ","), "1,000");
+        assert_eq!(group_digits_with(0, ","), "0");
+        assert_eq!(thousands_separator("en_US.UTF-8"), ",");
+        assert_eq!(thousands_separator("C"), ",");
+        assert_eq!(thousands_separator("de_DE.UTF-8"), "\u{202f}");
+        assert_eq!(thousands_separator("fr_FR@euro"), "\u{202f}");
+    }
+}
@@ -0,0 +1,332 @@
+use rayon::prelude::*;
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long removing one batch of files should take, so progress moves at
+/// least this often however slow the filesystem is
+const BATCH_TARGET: Duration = Duration::from_millis(100);
+
+const MIN_BATCH: usize = 8;
+const MAX_BATCH: usize = 65_536;
+const INITIAL_BATCH: usize = 256;
+
+/// Files removed by tree deletions so far, published once per batch
+static FILES_REMOVED: AtomicU64 = AtomicU64::new(0);
+
+/// Batch size shared by every deletion, adapted to the latest batches
+static SIZER: BatchSizer = BatchSizer::new();
+
+/// Files removed by tree deletions since the process started
+pub fn files_removed() -> u64 {
+    FILES_REMOVED.load(Ordering::Relaxed)
+}
+
+/// Sizes batches of unlinks from how long the last ones took per file: a few
+/// files each on NFS or SMR drives, thousands on local SSDs
+struct BatchSizer {
+    size: AtomicUsize,
+}
+
+impl BatchSizer {
+    const fn new() -> Self {
+        Self {
+            size: AtomicUsize::new(INITIAL_BATCH),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Move towards the size that would have taken `BATCH_TARGET`, at most
+    /// fourfold per batch so a single stall doesn't swing it to the bounds
+    fn record(&self, files: usize, elapsed: Duration) {
+        if files == 0 {
+            return;
+        }
+        let current = self.size();
+        let ideal = match elapsed.as_nanos() / files as u128 {
+            0 => MAX_BATCH,
+            per_file => (BATCH_TARGET.as_nanos() / per_file).min(MAX_BATCH as u128) as usize,
+        };
+        let next = ideal
+            .clamp(current / 4, current.saturating_mul(4))
+            .clamp(MIN_BATCH, MAX_BATCH);
+        self.size.store(next, Ordering::Relaxed);
+    }
+}
+
+/// Remove the tree at `dir` like `fs::remove_dir_all`, unlinking files in
+/// batches that keep `files_removed` moving. Every directory is opened
+/// relative to its parent without following symbolic links, so a link at
+/// `dir` or swapped in for a subdirectory meanwhile is removed itself and
+/// what it points to is left alone.
+pub fn remove_dir_all(dir: &Path) -> io::Result<()> {
+    remove_root(dir, remove_subdir)
+}
+
+/// Remove the tree at `dir` with the entries of every directory spread over
+/// the rayon pool, subdirectories and batches of files alike, as safely as
+/// `remove_dir_all`
+pub fn remove_dir_all_parallel(dir: &Path) -> io::Result<()> {
+    remove_root(dir, remove_subdir_parallel)
+}
+
+/// Open the parent of `path` and hand its last component to `remove`
+fn remove_root(path: &Path, remove: fn(&Dir, &CStr) -> io::Result<()>) -> io::Result<()> {
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no final component to remove", path.display()),
+        ));
+    };
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent = Dir::open(libc::AT_FDCWD, &c_string(parent.as_os_str())?, true)?;
+    remove(&parent, &c_string(name)?)
+}
+
+/// Remove the directory `name` in `parent` and everything below it
+fn remove_subdir(parent: &Dir, name: &CStr) -> io::Result<()> {
+    let Some(dir) = parent.open_subdir(name)? else {
+        return parent.unlink(name, false);
+    };
+    let mut files = Vec::new();
+    for (entry, is_dir) in dir.entries()? {
+        if is_dir {
+            ignore_missing(remove_subdir(&dir, &entry))?;
+        } else {
+            files.push(entry);
+            if files.len() >= SIZER.size() {
+                remove_batch(&dir, &files)?;
+                files.clear();
+            }
+        }
+    }
+    remove_batch(&dir, &files)?;
+    parent.unlink(name, true)
+}
+
+/// Remove the directory `name` in `parent` like `remove_subdir`, its
+/// subdirectories and batches of files on the rayon pool
+fn remove_subdir_parallel(parent: &Dir, name: &CStr) -> io::Result<()> {
+    let Some(dir) = parent.open_subdir(name)? else {
+        return parent.unlink(name, false);
+    };
+    let (subdirs, files): (Vec<_>, Vec<_>) =
+        dir.entries()?.into_iter().partition(|(_, is_dir)| *is_dir);
+    let files: Vec<CString> = files.into_iter().map(|(file, _)| file).collect();
+    let (subdirs, files) = rayon::join(
+        || {
+            subdirs
+                .par_iter()
+                .try_for_each(|(subdir, _)| ignore_missing(remove_subdir_parallel(&dir, subdir)))
+        },
+        || {
+            files
+                .par_chunks(SIZER.size().max(1))
+                .try_for_each(|batch| remove_batch(&dir, batch))
+        },
+    );
+    subdirs.and(files)?;
+    parent.unlink(name, true)
+}
+
+/// Unlink `files` in `dir`, none of which is a directory, so symbolic links
+/// are removed and never followed
+fn remove_batch(dir: &Dir, files: &[CString]) -> io::Result<()> {
+    let started = Instant::now();
+    for file in files {
+        ignore_missing(dir.unlink(file, false))?;
+    }
+    SIZER.record(files.len(), started.elapsed());
+    FILES_REMOVED.fetch_add(files.len() as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+/// An open directory whose entries are looked up and removed relative to
+/// it, never through a path a symbolic link could redirect
+pub(crate) struct Dir {
+    fd: OwnedFd,
+}
+
+impl Dir {
+    /// Open the directory `name` relative to `at`, following a symbolic
+    /// link in its place only when asked to
+    pub(crate) fn open(at: RawFd, name: &CStr, follow: bool) -> io::Result<Dir> {
+        let mut flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        if !follow {
+            flags |= libc::O_NOFOLLOW;
+        }
+        let fd = unsafe { libc::openat(at, name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Dir {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Open the subdirectory `name`, or `None` when it is no directory
+    /// (anymore), e.g. as it was swapped for a symbolic link
+    pub(crate) fn open_subdir(&self, name: &CStr) -> io::Result<Option<Dir>> {
+        match Dir::open(self.fd.as_raw_fd(), name, false) {
+            Ok(dir) => Ok(Some(dir)),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ELOOP | libc::ENOTDIR)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The names in the directory, each with whether it is a directory
+    /// itself; symbolic links never are
+    pub(crate) fn entries(&self) -> io::Result<Vec<(CString, bool)>> {
+        // `closedir` closes the descriptor it reads, so it gets a copy
+        let fd = unsafe { libc::fcntl(self.fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let error = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(error);
+        }
+
+        let mut entries = Vec::new();
+        let result = loop {
+            unsafe { *libc::__errno_location() = 0 };
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                break match io::Error::last_os_error() {
+                    error if error.raw_os_error() == Some(0) => Ok(()),
+                    error => Err(error),
+                };
+            }
+            let (name, file_type) =
+                unsafe { (CStr::from_ptr((*entry).d_name.as_ptr()), (*entry).d_type) };
+            if matches!(name.to_bytes(), b"." | b"..") {
+                continue;
+            }
+            let is_dir = match file_type {
+                libc::DT_DIR => true,
+                libc::DT_UNKNOWN => self.is_dir(name),
+                _ => false,
+            };
+            entries.push((name.to_owned(), is_dir));
+        };
+        unsafe { libc::closedir(stream) };
+        result.map(|()| entries)
+    }
+
+    /// Whether `name` is a directory, for filesystems not telling while
+    /// listing
+    fn is_dir(&self, name: &CStr) -> bool {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            libc::fstatat(
+                self.fd.as_raw_fd(),
+                name.as_ptr(),
+                &mut stat,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        result == 0 && stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    /// Remove the entry `name`, an empty directory when `dir` is set
+    fn unlink(&self, name: &CStr, dir: bool) -> io::Result<()> {
+        let flags = if dir { libc::AT_REMOVEDIR } else { 0 };
+        if unsafe { libc::unlinkat(self.fd.as_raw_fd(), name.as_ptr(), flags) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Dir {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+pub(crate) fn c_string(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Entries something else removed meanwhile are no error
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_batch_sizes() {
+        let sizer = BatchSizer::new();
+
+        // 10ms per file on a slow mount shrinks towards 10 files a batch
+        sizer.record(256, Duration::from_millis(2560));
+        assert_eq!(sizer.size(), 64);
+        sizer.record(64, Duration::from_millis(640));
+        sizer.record(16, Duration::from_millis(160));
+        assert_eq!(sizer.size(), 10);
+
+        // Fast unlinks grow it again, fourfold at a time, up to the bound
+        sizer.record(10, Duration::from_micros(10));
+        assert_eq!(sizer.size(), 40);
+        for _ in 0..10 {
+            sizer.record(1000, Duration::ZERO);
+        }
+        assert_eq!(sizer.size(), MAX_BATCH);
+        sizer.record(0, Duration::from_secs(1));
+        assert_eq!(sizer.size(), MAX_BATCH);
+    }
+
+    #[test]
+    fn test_never_follows_symlinks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let outside = root.join("outside");
+        fs::create_dir_all(outside.join("sub")).unwrap();
+        fs::write(outside.join("sub/keep"), "data").unwrap();
+
+        // A symbolic link given as the tree is removed, not its target
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        remove_dir_all(&root.join("link")).unwrap();
+        assert!(fs::symlink_metadata(root.join("link")).is_err());
+        assert!(outside.join("sub/keep").exists());
+
+        // Nor is a link inside the tree followed
+        let tree = root.join("tree");
+        fs::create_dir_all(tree.join("a/b")).unwrap();
+        fs::write(tree.join("a/b/file"), "data").unwrap();
+        std::os::unix::fs::symlink(&outside, tree.join("a/link")).unwrap();
+        remove_dir_all(&tree).unwrap();
+        assert!(!tree.exists());
+        fs::create_dir_all(tree.join("a/b")).unwrap();
+        std::os::unix::fs::symlink(&outside, tree.join("a/link")).unwrap();
+        remove_dir_all_parallel(&tree).unwrap();
+        assert!(!tree.exists());
+        assert!(outside.join("sub/keep").exists());
+
+        // A subdirectory swapped for a link after listing is unlinked as is
+        std::os::unix::fs::symlink(&outside, root.join("swapped")).unwrap();
+        let parent = Dir::open(libc::AT_FDCWD, &c_string(root.as_os_str()).unwrap(), true).unwrap();
+        remove_subdir(&parent, c"swapped").unwrap();
+        assert!(fs::symlink_metadata(root.join("swapped")).is_err());
+        assert!(outside.join("sub/keep").exists());
+    }
+}
@@ -0,0 +1,307 @@
+use crate::error::CleanerError;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Latest release of the project, where install.sh looks too
+const RELEASE_FEED: &str = "https://api.github.com/repos/Brean-dev/cleaner/releases/latest";
+
+/// Minisign public key the release binaries are signed with, built into
+/// release builds from the environment
+const PUBLIC_KEY: Option<&str> = option_env!("CLEANER_RELEASE_PUBLIC_KEY");
+
+/// How long one download may take
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Largest download accepted, well above any release binary
+const MAX_DOWNLOAD_BYTES: u64 = 256 << 20;
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// A release newer than the running binary
+#[derive(Debug, PartialEq, Eq)]
+pub struct Update {
+    pub version: String,
+    binary_url: String,
+    signature_url: String,
+}
+
+/// The newest release, when it is newer than this binary
+pub fn check() -> Result<Option<Update>, CleanerError> {
+    let feed = fetch(RELEASE_FEED)?;
+    let release: Release = serde_json::from_slice(&feed)
+        .map_err(|e| CleanerError::Update(format!("unreadable release feed: {}", e)))?;
+    newer(&release, env!("CARGO_PKG_VERSION"), &asset_name())
+}
+
+/// The static binary for this machine, as install.sh names it
+fn asset_name() -> String {
+    format!("cleaner-{}-linux", std::env::consts::ARCH)
+}
+
+fn newer(release: &Release, current: &str, asset: &str) -> Result<Option<Update>, CleanerError> {
+    let version = release.tag_name.trim_start_matches('v');
+    let (Some(latest), Some(running)) = (parse_version(version), parse_version(current)) else {
+        return Err(CleanerError::Update(format!(
+            "cannot compare release {} with {}",
+            release.tag_name, current
+        )));
+    };
+    if latest <= running {
+        return Ok(None);
+    }
+
+    let url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.clone())
+            .ok_or_else(|| {
+                CleanerError::Update(format!("release {} has no {}", release.tag_name, name))
+            })
+    };
+    Ok(Some(Update {
+        version: version.to_string(),
+        binary_url: url(asset)?,
+        signature_url: url(&format!("{}.minisig", asset))?,
+    }))
+}
+
+/// `major.minor.patch`, ignoring pre-release and build suffixes
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// Download `update`, check its signature, and put it in place of the binary
+/// at `target`
+pub fn install(update: &Update, target: &Path) -> Result<(), CleanerError> {
+    let key = PUBLIC_KEY.ok_or_else(|| {
+        CleanerError::Update(
+            "this build has no release key to verify updates with; install the release \
+             binary instead"
+                .to_string(),
+        )
+    })?;
+    let binary = fetch(&update.binary_url)?;
+    let signature = String::from_utf8(fetch(&update.signature_url)?)
+        .map_err(|_| CleanerError::Update("the signature is not text".to_string()))?;
+    verify(
+        key,
+        &binary,
+        &signature,
+        &asset_name(),
+        env!("CARGO_PKG_VERSION"),
+    )?;
+    replace(target, &binary)
+}
+
+/// Check the signature of `binary`, then that its trusted comment names
+/// `asset` and a release newer than `running`: a validly signed older or
+/// different binary is not taken as the update. Release signatures carry
+/// tab-separated `file:<asset>` and `version:<x.y.z>` fields there.
+fn verify(
+    key: &str,
+    binary: &[u8],
+    signature: &str,
+    asset: &str,
+    running: &str,
+) -> Result<(), CleanerError> {
+    let key = PublicKey::from_base64(key)
+        .map_err(|e| CleanerError::Update(format!("invalid release key: {}", e)))?;
+    let signature = Signature::decode(signature)
+        .map_err(|e| CleanerError::Update(format!("invalid signature: {}", e)))?;
+    key.verify(binary, &signature, false)
+        .map_err(|e| CleanerError::Update(format!("signature check failed: {}", e)))?;
+
+    let comment = signature.trusted_comment();
+    let field = |key: &str| {
+        comment
+            .split('\t')
+            .find_map(|field| field.strip_prefix(key)?.strip_prefix(':'))
+    };
+    if field("file") != Some(asset) {
+        return Err(CleanerError::Update(format!(
+            "the signature is for {}, not {}",
+            field("file").unwrap_or("an unnamed file"),
+            asset
+        )));
+    }
+    let signed = field("version").unwrap_or_default();
+    match (parse_version(signed), parse_version(running)) {
+        (Some(signed), Some(running)) if signed > running => Ok(()),
+        _ => Err(CleanerError::Update(format!(
+            "the signed release '{}' is not newer than {}",
+            signed, running
+        ))),
+    }
+}
+
+/// Write `binary` next to `target` with its permissions, make sure it runs,
+/// then rename it over `target`. A running process keeps the old file.
+fn replace(target: &Path, binary: &[u8]) -> Result<(), CleanerError> {
+    let (Some(dir), Some(name)) = (target.parent(), target.file_name()) else {
+        return Err(CleanerError::Update(format!(
+            "cannot replace {}",
+            target.display()
+        )));
+    };
+    let staged = dir.join(format!(
+        ".{}.update-{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let mode = fs::metadata(target)
+        .map(|m| m.permissions().mode())
+        .unwrap_or(0o755);
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&staged)?;
+        file.write_all(binary)?;
+        // The umask may have taken bits away
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.sync_all()
+    })()
+    .map_err(|e| CleanerError::from_io(&staged, &e))
+    .and_then(|()| {
+        let runs = Command::new(&staged)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if runs {
+            fs::rename(&staged, target).map_err(|e| CleanerError::from_io(target, &e))
+        } else {
+            Err(CleanerError::Update(
+                "the downloaded binary does not run on this machine".to_string(),
+            ))
+        }
+    });
+    if result.is_err() {
+        fs::remove_file(&staged).ok();
+    }
+    result
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, CleanerError> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    agent
+        .get(url)
+        .header("User-Agent", concat!("cleaner/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .and_then(|mut response| {
+            response
+                .body_mut()
+                .with_config()
+                .limit(MAX_DOWNLOAD_BYTES)
+                .read_to_vec()
+        })
+        .map_err(|e| CleanerError::Update(format!("{}: {}", url, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_newer_release() {
+        let release: Release = serde_json::from_str(
+            r#"{"tag_name": "v1.2.0", "assets": [
+                {"name": "cleaner-x86_64-linux", "browser_download_url": "https://dl/bin"},
+                {"name": "cleaner-x86_64-linux.minisig", "browser_download_url": "https://dl/sig"},
+                {"name": "cleaner_1.2.0_amd64.deb", "browser_download_url": "https://dl/deb"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let update = newer(&release, "1.1.9", "cleaner-x86_64-linux").unwrap();
+        assert_eq!(
+            update,
+            Some(Update {
+                version: "1.2.0".to_string(),
+                binary_url: "https://dl/bin".to_string(),
+                signature_url: "https://dl/sig".to_string(),
+            })
+        );
+        assert_eq!(
+            newer(&release, "1.2.0", "cleaner-x86_64-linux").unwrap(),
+            None
+        );
+        assert_eq!(
+            newer(&release, "1.10.0", "cleaner-x86_64-linux").unwrap(),
+            None
+        );
+        assert!(newer(&release, "1.0.0", "cleaner-aarch64-linux").is_err());
+        assert_eq!(parse_version("0.6.0-rc.1"), Some((0, 6, 0)));
+        assert_eq!(parse_version("0.6"), None);
+    }
+
+    #[test]
+    fn test_verify_and_replace() {
+        // Test vector of minisign-verify: "test" signed with its key, without
+        // the release fields
+        let key = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+        let signature = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+        assert!(verify(key, b"test", signature, "test", "0.1.0").is_err());
+
+        // A release signature for cleaner-x86_64-linux 1.2.0
+        let key = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+        let signature = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCJApv0Vj3Dt/UpRRwhTB+Guh0TELwGjSPA1R9DC/L7mx2nBM3ssIFpS+f2/HaWlOUCop8POeLZYyMInENbhjKAE=
+trusted comment: timestamp:1760000000\tfile:cleaner-x86_64-linux\tversion:1.2.0
+G7M+v210S5jjNIzf2mqWoprmRRrDmWTRs6gI4WW4xMb7J5a11LbowjccAOqH0v+kruBml33dbaISepTyK03TCA==";
+        let binary = b"#!/bin/sh\necho cleaner 1.2.0\n";
+        let asset = "cleaner-x86_64-linux";
+        assert!(verify(key, binary, signature, asset, "1.1.9").is_ok());
+        assert!(verify(key, b"tampered", signature, asset, "1.1.9").is_err());
+        assert!(verify(key, binary, signature, "cleaner-aarch64-linux", "1.1.9").is_err());
+        // Replaying an older release is refused
+        assert!(verify(key, binary, signature, asset, "1.2.0").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("cleaner");
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o750)).unwrap();
+
+        // A binary that doesn't run leaves the old one in place
+        assert!(replace(&target, b"#!/bin/sh\nexit 1\n").is_err());
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+
+        let binary = b"#!/bin/sh\necho cleaner 9.9.9\n";
+        replace(&target, binary).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), binary);
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,72 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// Metadata directories kept by version control systems inside a working tree
+const METADATA_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Whether `name` is the name of a working tree's metadata directory
+pub fn is_metadata_name(name: &OsStr) -> bool {
+    METADATA_DIRS.iter().any(|dir| name == *dir)
+}
+
+/// Whether `dir` is a bare git repository: a `HEAD` file next to `objects`
+/// and `refs` directories, whatever the directory is called
+pub fn is_bare_repository(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// Whether a directory listing holds the layout of a bare git repository,
+/// checked from the names alone so walkers can tell without extra stats
+pub fn lists_bare_repository<'a>(entries: impl IntoIterator<Item = (&'a OsStr, bool)>) -> bool {
+    let (mut head, mut objects, mut refs) = (false, false, false);
+    for (name, is_dir) in entries {
+        match name.to_str() {
+            Some("HEAD") => head = !is_dir,
+            Some("objects") => objects = is_dir,
+            Some("refs") => refs = is_dir,
+            _ => {}
+        }
+    }
+    head && objects && refs
+}
+
+/// Whether `path` is, or lies inside, version control metadata: a `.git`,
+/// `.hg` or `.svn` component at any depth, or a bare git repository above it
+pub fn is_in_metadata(path: &Path) -> bool {
+    path.components()
+        .any(|component| is_metadata_name(component.as_os_str()))
+        || path.ancestors().any(is_bare_repository)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_vcs_metadata_detection() {
+        let temp_dir = TempDir::new().unwrap();
+        let bare = temp_dir.path().join("mirror");
+        fs::create_dir_all(bare.join("objects").join("tmp")).unwrap();
+        fs::create_dir(bare.join("refs")).unwrap();
+        fs::write(bare.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        assert!(is_bare_repository(&bare));
+        assert!(is_in_metadata(&bare.join("objects").join("tmp")));
+        assert!(is_in_metadata(Path::new(
+            "/src/app/vendor/lib/.git/objects/tmp"
+        )));
+        assert!(is_in_metadata(Path::new("/src/app/.hg/cache")));
+        assert!(!is_in_metadata(Path::new("/src/app/.github/cache")));
+        assert!(!is_in_metadata(&temp_dir.path().join("tmp")));
+
+        let listing = [
+            (OsStr::new("HEAD"), false),
+            (OsStr::new("objects"), true),
+            (OsStr::new("refs"), true),
+        ];
+        assert!(lists_bare_repository(listing));
+        assert!(!lists_bare_repository(listing.into_iter().take(2)));
+    }
+}
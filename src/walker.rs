@@ -0,0 +1,659 @@
+use crate::config::{Config, PatternOptions, PerformanceConfig, ScanHidden};
+use crate::error::CleanerError;
+use crate::throttle;
+use crate::vcs;
+use glob::{MatchOptions, Pattern};
+use jwalk::{Parallelism, WalkDir};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How often the probe checks the clock while counting entries
+const PROBE_CLOCK_INTERVAL: usize = 1024;
+
+/// When every scan walker has to stop, set once by `--timeout`
+static SCAN_DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// Whether a walker stopped at the deadline, leaving the scan incomplete
+static SCAN_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Entries yielded by all walkers so far, for progress reporting
+static WALKED_ENTRIES: AtomicU64 = AtomicU64::new(0);
+
+/// Stop all walking once `timeout` has passed from now
+pub fn set_scan_timeout(timeout: Duration) {
+    SCAN_DEADLINE.set(Instant::now() + timeout).ok();
+}
+
+/// Whether the scan deadline cut any walk short
+pub fn scan_timed_out() -> bool {
+    SCAN_TIMED_OUT.load(Ordering::Relaxed)
+}
+
+/// How many entries all walkers have yielded so far
+pub fn walked_entries() -> u64 {
+    WALKED_ENTRIES.load(Ordering::Relaxed)
+}
+
+/// Entry yielded by the shared walker
+pub type WalkEntry = jwalk::DirEntry<((), ())>;
+
+/// Per-directory limits on what a walker lists. The directory a walk starts
+/// from is probed before it is walked; below it, directories are judged by
+/// the entries the walk already read, and their contents are left out.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectoryGuard {
+    timeout: Option<Duration>,
+    max_entries: Option<usize>,
+}
+
+impl DirectoryGuard {
+    pub fn from_config(performance: &PerformanceConfig) -> Self {
+        Self {
+            timeout: (!performance.access_timeout.is_zero()).then_some(performance.access_timeout),
+            max_entries: performance.max_entries_per_dir,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.timeout.is_some() || self.max_entries.is_some()
+    }
+
+    /// Probe the directory a walk starts from by counting its entries,
+    /// giving up once a limit is exceeded. Returns the reason the walk
+    /// should be skipped, if any.
+    pub fn probe(&self, path: &Path) -> Option<CleanerError> {
+        // Read errors are left for the walker itself to report
+        let entries = fs::read_dir(path).ok()?;
+        let start = Instant::now();
+
+        for (count, _) in entries.enumerate() {
+            if let Some(max_entries) = self.max_entries
+                && count >= max_entries
+            {
+                return Some(CleanerError::Skipped {
+                    path: path.to_path_buf(),
+                    reason: format!("more than {} entries", max_entries),
+                });
+            }
+
+            if count % PROBE_CLOCK_INTERVAL == 0
+                && let Some(reason) = self.timed_out(start)
+            {
+                return Some(CleanerError::Skipped {
+                    path: path.to_path_buf(),
+                    reason,
+                });
+            }
+        }
+
+        self.timed_out(start).map(|reason| CleanerError::Skipped {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    /// Whether the `entries` a walk read from `path` exceed the limit
+    fn check_listed(&self, path: &Path, entries: usize) -> Option<CleanerError> {
+        let max_entries = self.max_entries?;
+        (entries > max_entries).then(|| CleanerError::Skipped {
+            path: path.to_path_buf(),
+            reason: format!("more than {} entries", max_entries),
+        })
+    }
+
+    fn timed_out(&self, start: Instant) -> Option<String> {
+        let timeout = self.timeout?;
+        (start.elapsed() > timeout)
+            .then(|| format!("listing took longer than {}s", timeout.as_secs()))
+    }
+}
+
+/// Decides which hidden directories a scan descends into, per `scan_hidden`
+#[derive(Debug, Clone)]
+pub struct HiddenDirs {
+    mode: ScanHidden,
+    /// Hidden components of the cache patterns, for `conservative`
+    known: Vec<Pattern>,
+}
+
+impl HiddenDirs {
+    pub fn new(config: &Config) -> Self {
+        let patterns = &config.cache_patterns;
+        let known = [
+            &patterns.user_cache_dirs,
+            &patterns.package_manager_caches,
+            &patterns.dev_tool_caches,
+            &patterns.browser_caches,
+            &patterns.app_cache_patterns,
+            &patterns.regenerating_caches,
+            &patterns.temp_patterns,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|pattern| pattern.split('/'))
+        .filter(|component| component.starts_with('.'))
+        .filter_map(|component| Pattern::new(component).ok())
+        .collect();
+
+        Self {
+            mode: config.performance.scan_hidden,
+            known,
+        }
+    }
+
+    /// Whether a directory called `name` may be descended into
+    pub fn descends(&self, name: &OsStr) -> bool {
+        let Some(name) = name.to_str().filter(|name| name.starts_with('.')) else {
+            return true;
+        };
+        match self.mode {
+            ScanHidden::All => true,
+            ScanHidden::None => false,
+            ScanHidden::Conservative => self.known.iter().any(|pattern| pattern.matches(name)),
+        }
+    }
+}
+
+/// Traversal settings resolved for one location
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    descend: bool,
+    follow_symlinks: bool,
+    one_file_system: bool,
+}
+
+/// Global traversal settings plus the per-pattern overrides from the config
+#[derive(Debug, Clone)]
+struct TraversalRules {
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    one_file_system: bool,
+    overrides: Vec<(Pattern, PatternOptions)>,
+}
+
+impl TraversalRules {
+    fn new(config: &Config, max_depth: Option<usize>) -> Self {
+        let overrides = config
+            .cache_patterns
+            .pattern_options
+            .iter()
+            .filter_map(|options| {
+                let expanded = Config::expand_home(&options.pattern);
+                let anchored = if expanded.starts_with('/') {
+                    expanded
+                } else {
+                    format!("/**/{}", expanded)
+                };
+                Pattern::new(anchored.trim_end_matches('/'))
+                    .ok()
+                    .map(|pattern| (pattern, options.clone()))
+            })
+            .collect();
+
+        Self {
+            max_depth,
+            follow_symlinks: !config.performance.skip_symlinks,
+            one_file_system: config.performance.one_file_system,
+            overrides,
+        }
+    }
+
+    /// Whether any location may have its symlinks followed
+    fn follows_any_symlinks(&self) -> bool {
+        self.follow_symlinks
+            || self
+                .overrides
+                .iter()
+                .any(|(_, options)| options.follow_symlinks == Some(true))
+    }
+
+    /// Closest ancestor of `path` (or `path` itself) matched by an override
+    fn matching_override<'a>(&self, path: &'a Path) -> Option<(&'a Path, &PatternOptions)> {
+        let match_options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+
+        path.ancestors().find_map(|ancestor| {
+            self.overrides
+                .iter()
+                .find(|(pattern, _)| pattern.matches_path_with(ancestor, match_options))
+                .map(|(_, options)| (ancestor, options))
+        })
+    }
+
+    /// Rule for an entry at `path`, `depth` levels below the walk root
+    fn resolve(&self, path: &Path, depth: usize) -> Rule {
+        let mut rule = Rule {
+            descend: self.max_depth.is_none_or(|max_depth| depth < max_depth),
+            follow_symlinks: self.follow_symlinks,
+            one_file_system: self.one_file_system,
+        };
+
+        if self.overrides.is_empty() {
+            return rule;
+        }
+
+        if let Some((anchor, options)) = self.matching_override(path) {
+            // Unbounded walks (sizing) ignore depth overrides as well
+            if let Some(max_depth) = options.max_depth
+                && self.max_depth.is_some()
+            {
+                let below_anchor = path.components().count() - anchor.components().count();
+                rule.descend = below_anchor < max_depth;
+            }
+            if let Some(follow_symlinks) = options.follow_symlinks {
+                rule.follow_symlinks = follow_symlinks;
+            }
+            if let Some(one_file_system) = options.one_file_system {
+                rule.one_file_system = one_file_system;
+            }
+        }
+
+        rule
+    }
+}
+
+/// Builds directory walkers that apply the performance and safety settings
+/// (symlinks, depth, exclusions, parallelism, per-directory limits) the same
+/// way for cache detection, log scanning and sizing.
+#[derive(Debug, Clone)]
+pub struct ScanWalker {
+    config: Config,
+    max_depth: Option<usize>,
+    serial: bool,
+    deadline: Option<Instant>,
+}
+
+impl ScanWalker {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            max_depth: Some(config.performance.max_depth.unwrap_or(10)),
+            serial: false,
+            deadline: SCAN_DEADLINE.get().copied(),
+        }
+    }
+
+    /// Walk to the end even past the scan deadline, e.g. when recording what
+    /// is about to be deleted
+    pub fn without_deadline(mut self) -> Self {
+        self.deadline = None;
+        self
+    }
+
+    /// Walk the whole tree regardless of `max_depth`, e.g. when sizing an
+    /// item that will be removed as a whole
+    pub fn unbounded(mut self) -> Self {
+        self.max_depth = None;
+        self
+    }
+
+    /// Walk on the calling thread, for callers that already run in parallel
+    pub fn serial(mut self) -> Self {
+        self.serial = true;
+        self
+    }
+
+    /// Walk on the shared global pool so `--threads` bounds the walkers too
+    fn parallelism(&self) -> Parallelism {
+        if self.serial || rayon::current_num_threads() == 1 {
+            Parallelism::Serial
+        } else {
+            Parallelism::RayonDefaultPool {
+                busy_timeout: Duration::from_secs(1),
+            }
+        }
+    }
+
+    /// Walk `root`, yielding every entry that passed the filters as it is
+    /// read. Unreadable and skipped directories come as errors, to be kept
+    /// as warnings rather than abort the walk. Past the scan deadline the
+    /// walk stops with a `TimedOut` error for `root`.
+    pub fn walk(&self, root: &Path) -> Walk {
+        let mut walk = Walk {
+            entries: None,
+            root: root.to_path_buf(),
+            deadline: self.deadline,
+            skipped: Arc::default(),
+            pending: Vec::new().into_iter(),
+        };
+        if self.config.is_excluded_path(root) {
+            return walk;
+        }
+        let guard = DirectoryGuard::from_config(&self.config.performance);
+        if guard.is_enabled()
+            && let Some(skip) = guard.probe(root)
+        {
+            walk.pending = vec![skip].into_iter();
+            return walk;
+        }
+
+        let rules = TraversalRules::new(&self.config, self.max_depth);
+        let keep_symlinks = self.config.safety.resolve_symlink_targets;
+        // Version control internals are never caches, but an item removed as
+        // a whole is sized with everything inside it
+        let skip_vcs_metadata = self.max_depth.is_some();
+        let hidden = self.max_depth.map(|_| HiddenDirs::new(&self.config));
+        let config = self.config.clone();
+        let sink = Arc::clone(&walk.skipped);
+
+        // jwalk skips dotfiles by default, which would hide `.cache` and friends.
+        // Depth and symlink handling are resolved per entry in the callback so
+        // pattern overrides can loosen or tighten them.
+        let walker = WalkDir::new(root)
+            .skip_hidden(false)
+            .parallelism(self.parallelism())
+            .follow_links(rules.follows_any_symlinks())
+            .process_read_dir(move |depth, dir, _, children| {
+                // The root itself is always walked
+                if depth.is_none() {
+                    return;
+                }
+                let _permit = throttle::permit();
+
+                if let Some(skip) = guard.check_listed(dir, children.len()) {
+                    children.clear();
+                    if let Ok(mut sink) = sink.lock() {
+                        sink.push(skip);
+                    }
+                    return;
+                }
+
+                if skip_vcs_metadata
+                    && vcs::lists_bare_repository(
+                        children
+                            .iter()
+                            .flatten()
+                            .map(|child| (child.file_name.as_os_str(), child.file_type.is_dir())),
+                    )
+                {
+                    children.clear();
+                    return;
+                }
+
+                let mut dir_device = None;
+                children.retain_mut(|child| {
+                    let Ok(child) = child else {
+                        return true;
+                    };
+
+                    if skip_vcs_metadata && vcs::is_metadata_name(&child.file_name) {
+                        return false;
+                    }
+
+                    let path = child.path();
+                    if config.is_excluded_path(&path) {
+                        return false;
+                    }
+
+                    let rule = rules.resolve(&path, child.depth);
+                    if child.path_is_symlink() && !rule.follow_symlinks {
+                        // Unfollowed links are only kept so their targets can be resolved
+                        if !keep_symlinks {
+                            return false;
+                        }
+                        child.read_children_path = None;
+                        return true;
+                    }
+
+                    if child.read_children_path.is_none() {
+                        return true;
+                    }
+
+                    if !rule.descend
+                        || hidden
+                            .as_ref()
+                            .is_some_and(|hidden| !hidden.descends(&child.file_name))
+                        || (rule.one_file_system && crosses_device(dir, &path, &mut dir_device))
+                    {
+                        child.read_children_path = None;
+                    }
+
+                    true
+                });
+            });
+
+        walk.entries = Some(walker.into_iter());
+        walk
+    }
+}
+
+/// The entries of one [`ScanWalker::walk`] as they are read, followed by the
+/// directories it skipped
+pub struct Walk {
+    entries: Option<jwalk::DirEntryIter<((), ())>>,
+    root: PathBuf,
+    deadline: Option<Instant>,
+    skipped: Arc<Mutex<Vec<CleanerError>>>,
+    pending: std::vec::IntoIter<CleanerError>,
+}
+
+impl Walk {
+    /// The entries alone, with the errors put into `warnings` as they come
+    pub fn entries(self, warnings: &mut Vec<CleanerError>) -> impl Iterator<Item = WalkEntry> {
+        self.filter_map(|entry| entry.map_err(|e| warnings.push(e)).ok())
+    }
+
+    /// Stop reading, leaving only the skipped directories to report
+    fn finish(&mut self) {
+        self.entries = None;
+        if let Ok(mut skipped) = self.skipped.lock() {
+            self.pending = std::mem::take(&mut *skipped).into_iter();
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<WalkEntry, CleanerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entries) = &mut self.entries {
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                // Only the --timeout deadline leaves the whole scan incomplete
+                if SCAN_DEADLINE.get() == self.deadline.as_ref() {
+                    SCAN_TIMED_OUT.store(true, Ordering::Relaxed);
+                }
+                self.finish();
+                return Some(Err(CleanerError::TimedOut(self.root.clone())));
+            }
+            match entries.next() {
+                Some(entry) => {
+                    WALKED_ENTRIES.fetch_add(1, Ordering::Relaxed);
+                    return Some(entry.map_err(CleanerError::from));
+                }
+                None => self.finish(),
+            }
+        }
+        self.pending.next().map(Err)
+    }
+}
+
+/// Whether `child` lives on a different filesystem than its parent `dir`
+fn crosses_device(dir: &Path, child: &Path, dir_device: &mut Option<u64>) -> bool {
+    let parent = match dir_device {
+        Some(device) => *device,
+        None => match fs::metadata(dir) {
+            Ok(metadata) => *dir_device.insert(metadata.dev()),
+            Err(_) => return false,
+        },
+    };
+
+    fs::metadata(child).is_ok_and(|metadata| metadata.dev() != parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan_report::ScanReport;
+    use tempfile::TempDir;
+
+    fn walked_paths(walk: Walk) -> Vec<PathBuf> {
+        walk.filter_map(Result::ok).map(|e| e.path()).collect()
+    }
+
+    #[test]
+    fn test_guard_skips_large_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let big = temp_dir.path().join("big");
+        std::fs::create_dir(&big).unwrap();
+        for i in 0..5 {
+            std::fs::write(big.join(format!("file{}", i)), "x").unwrap();
+        }
+        std::fs::create_dir(temp_dir.path().join("small")).unwrap();
+
+        let mut config = Config::default();
+        config.performance.max_entries_per_dir = Some(3);
+        let report: ScanReport<WalkEntry> =
+            ScanWalker::new(&config).walk(temp_dir.path()).collect();
+        let paths: Vec<PathBuf> = report.items.iter().map(|e| e.path()).collect();
+
+        assert!(paths.contains(&big));
+        assert!(!paths.contains(&big.join("file0")));
+        assert_eq!(report.warnings.len(), 1);
+        assert!(matches!(&report.warnings[0], CleanerError::Skipped { path, .. } if *path == big));
+
+        // A walk starting there is probed before anything is read
+        let report: ScanReport<WalkEntry> = ScanWalker::new(&config).walk(&big).collect();
+        assert!(report.items.is_empty());
+        assert!(
+            matches!(&report.warnings[..], [CleanerError::Skipped { path, .. }] if *path == big)
+        );
+    }
+
+    #[test]
+    fn test_walk_stops_at_deadline() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("a")).unwrap();
+
+        let config = Config::default();
+        let mut walker = ScanWalker::new(&config);
+        walker.deadline = Some(Instant::now());
+        let report: ScanReport<WalkEntry> = walker.walk(temp_dir.path()).collect();
+        assert!(report.items.is_empty());
+        assert_eq!(
+            report.warnings,
+            vec![CleanerError::TimedOut(temp_dir.path().to_path_buf())]
+        );
+        // A deadline of its own leaves the process-wide flag alone
+        assert!(!scan_timed_out());
+
+        assert_eq!(
+            walked_paths(walker.without_deadline().walk(temp_dir.path())).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_walker_applies_depth_symlinks_and_exclusions() {
+        let temp_dir = TempDir::new().unwrap();
+        let deep = temp_dir.path().join("a").join("b").join("c");
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::create_dir(temp_dir.path().join("excluded_dir")).unwrap();
+        std::fs::create_dir(temp_dir.path().join(".hidden")).unwrap();
+        std::os::unix::fs::symlink(&deep, temp_dir.path().join("link")).unwrap();
+
+        let mut config = Config::default();
+        config.performance.max_depth = Some(2);
+        config.safety.exclude_paths = vec!["excluded_dir".to_string()];
+        let paths = walked_paths(ScanWalker::new(&config).walk(temp_dir.path()));
+
+        assert!(paths.contains(&temp_dir.path().join("a").join("b")));
+        assert!(paths.contains(&temp_dir.path().join(".hidden")));
+        assert!(!paths.contains(&deep));
+        assert!(!paths.contains(&temp_dir.path().join("excluded_dir")));
+        assert!(!paths.contains(&temp_dir.path().join("link")));
+
+        let paths = walked_paths(ScanWalker::new(&config).unbounded().walk(temp_dir.path()));
+        assert!(paths.contains(&deep));
+    }
+
+    #[test]
+    fn test_scan_hidden_limits_dotfile_trees() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".kube");
+        let cache_dir = temp_dir.path().join(".cache");
+        std::fs::create_dir_all(config_dir.join("app")).unwrap();
+        std::fs::create_dir_all(cache_dir.join("pip")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("src").join("app")).unwrap();
+
+        let mut config = Config::default();
+        let paths = walked_paths(ScanWalker::new(&config).walk(temp_dir.path()));
+        assert!(paths.contains(&config_dir.join("app")));
+
+        config.performance.scan_hidden = ScanHidden::Conservative;
+        let paths = walked_paths(ScanWalker::new(&config).walk(temp_dir.path()));
+        assert!(paths.contains(&config_dir));
+        assert!(!paths.contains(&config_dir.join("app")));
+        assert!(paths.contains(&cache_dir.join("pip")));
+        assert!(paths.contains(&temp_dir.path().join("src").join("app")));
+
+        config.performance.scan_hidden = ScanHidden::None;
+        let paths = walked_paths(ScanWalker::new(&config).walk(temp_dir.path()));
+        assert!(paths.contains(&cache_dir));
+        assert!(!paths.contains(&cache_dir.join("pip")));
+
+        // Sizing still counts everything
+        let paths = walked_paths(ScanWalker::new(&config).unbounded().walk(temp_dir.path()));
+        assert!(paths.contains(&cache_dir.join("pip")));
+    }
+
+    #[test]
+    fn test_pattern_options_override_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let deep_cache = temp_dir.path().join("cache").join("a").join("b").join("c");
+        let shallow = temp_dir.path().join("var").join("a").join("b");
+        std::fs::create_dir_all(&deep_cache).unwrap();
+        std::fs::create_dir_all(&shallow).unwrap();
+
+        let mut config = Config::default();
+        config.performance.max_depth = Some(2);
+        config.cache_patterns.pattern_options = vec![
+            PatternOptions {
+                pattern: "cache".to_string(),
+                max_depth: Some(5),
+                ..PatternOptions::default()
+            },
+            PatternOptions {
+                pattern: temp_dir.path().join("var").to_string_lossy().into_owned(),
+                max_depth: Some(1),
+                ..PatternOptions::default()
+            },
+        ];
+        let paths = walked_paths(ScanWalker::new(&config).walk(temp_dir.path()));
+
+        assert!(paths.contains(&deep_cache));
+        assert!(paths.contains(&temp_dir.path().join("var").join("a")));
+        assert!(!paths.contains(&shallow));
+    }
+
+    #[test]
+    fn test_pattern_options_follow_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        std::fs::create_dir_all(target.join("inner")).unwrap();
+        std::fs::create_dir(temp_dir.path().join("followed")).unwrap();
+        std::os::unix::fs::symlink(&target, temp_dir.path().join("followed").join("link")).unwrap();
+        std::os::unix::fs::symlink(&target, temp_dir.path().join("ignored")).unwrap();
+
+        let mut config = Config::default();
+        config.cache_patterns.pattern_options = vec![PatternOptions {
+            pattern: "followed".to_string(),
+            follow_symlinks: Some(true),
+            ..PatternOptions::default()
+        }];
+        let paths = walked_paths(ScanWalker::new(&config).walk(temp_dir.path()));
+
+        let followed = temp_dir.path().join("followed").join("link");
+        assert!(paths.contains(&followed.join("inner")));
+        assert!(!paths.contains(&temp_dir.path().join("ignored")));
+    }
+}
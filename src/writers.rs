@@ -0,0 +1,243 @@
+use crate::file_operations::DELETING_PREFIX;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+const PROC_DIR: &str = "/proc";
+
+/// A running process holding a file open, usually for writing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Writer {
+    pub pid: u32,
+    /// Command name from `/proc/<pid>/comm`
+    pub command: String,
+}
+
+impl Writer {
+    /// The process whose `/proc` directory is `process`
+    fn of(process: &Path) -> Option<Self> {
+        let pid = process.file_name()?.to_str()?.parse().ok()?;
+        let command = fs::read_to_string(process.join("comm"))
+            .map(|comm| comm.trim().to_string())
+            .unwrap_or_default();
+        Some(Self { pid, command })
+    }
+}
+
+impl fmt::Display for Writer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (pid {})", self.command, self.pid)
+    }
+}
+
+/// Regular files running processes hold open for writing, by device and
+/// inode. Without root only the caller's own processes can be inspected.
+#[derive(Debug, Default)]
+pub struct WriterTable {
+    files: HashMap<(u64, u64), Writer>,
+}
+
+impl WriterTable {
+    pub fn load() -> Self {
+        Self::load_from(Path::new(PROC_DIR))
+    }
+
+    /// Read the open file descriptors of every process below `proc`
+    fn load_from(proc: &Path) -> Self {
+        let mut files = HashMap::new();
+        for_each_open_file(proc, |process, fd, metadata| {
+            let fdinfo = process
+                .join("fdinfo")
+                .join(fd.file_name().unwrap_or_default());
+            let writing = fs::read_to_string(fdinfo)
+                .ok()
+                .and_then(|info| parse_flags(&info))
+                .is_some_and(|flags| flags & libc::O_ACCMODE as u32 != libc::O_RDONLY as u32);
+            if writing && let Some(writer) = Writer::of(process) {
+                files
+                    .entry((metadata.dev(), metadata.ino()))
+                    .or_insert(writer);
+            }
+        });
+        Self { files }
+    }
+
+    /// A process writing to `path`, if any
+    pub fn writer_of(&self, path: &Path) -> Option<Writer> {
+        let metadata = fs::metadata(path).ok()?;
+        self.files.get(&(metadata.dev(), metadata.ino())).cloned()
+    }
+}
+
+/// A deleted file a process still holds open, so its space stays in use
+/// until the process closes it or exits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct HeldOpenFile {
+    /// Where the file was when it was deleted
+    pub path: PathBuf,
+    /// Space the file still takes on disk
+    pub disk_bytes: u64,
+    pub holder: Writer,
+}
+
+/// Deleted files below `cleaned` that processes still hold open, each once,
+/// largest first. Directories cleaned by renaming them aside first leave
+/// their files under a `.cleaner-deleting-*` sibling.
+pub fn held_open_files(cleaned: &[PathBuf]) -> Vec<HeldOpenFile> {
+    held_open_files_in(Path::new(PROC_DIR), cleaned)
+}
+
+fn held_open_files_in(proc: &Path, cleaned: &[PathBuf]) -> Vec<HeldOpenFile> {
+    let is_cleaned = |path: &Path| {
+        cleaned.iter().any(|root| {
+            path.starts_with(root)
+                || root
+                    .parent()
+                    .and_then(|parent| path.strip_prefix(parent).ok())
+                    .and_then(|rest| rest.components().next())
+                    .is_some_and(|first| {
+                        first
+                            .as_os_str()
+                            .to_string_lossy()
+                            .starts_with(DELETING_PREFIX)
+                    })
+        })
+    };
+
+    let mut seen = HashSet::new();
+    let mut held = Vec::new();
+    for_each_open_file(proc, |process, fd, metadata| {
+        let Ok(target) = fs::read_link(fd) else {
+            return;
+        };
+        let target = target.to_string_lossy();
+        let Some(path) = target.strip_suffix(" (deleted)").map(PathBuf::from) else {
+            return;
+        };
+        let disk_bytes = metadata.blocks() * 512;
+        if disk_bytes == 0 || !is_cleaned(&path) || !seen.insert((metadata.dev(), metadata.ino())) {
+            return;
+        }
+        if let Some(holder) = Writer::of(process) {
+            held.push(HeldOpenFile {
+                path,
+                disk_bytes,
+                holder,
+            });
+        }
+    });
+    held.sort_by_key(|file| Reverse(file.disk_bytes));
+    held
+}
+
+/// Call `visit` with the `/proc` directory, descriptor link and metadata of
+/// every regular file a process below `proc` has open. Other users'
+/// descriptors can't be listed without privileges and are left out.
+fn for_each_open_file(proc: &Path, mut visit: impl FnMut(&Path, &Path, &Metadata)) {
+    let Ok(processes) = fs::read_dir(proc) else {
+        return;
+    };
+    for process in processes.flatten() {
+        let is_pid = process
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.parse::<u32>().is_ok());
+        if !is_pid {
+            continue;
+        }
+        let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            // Following the link stats the open file itself, deleted or not
+            if let Ok(metadata) = fs::metadata(fd.path())
+                && metadata.is_file()
+            {
+                visit(&process.path(), &fd.path(), &metadata);
+            }
+        }
+    }
+}
+
+/// The open flags in a `/proc/<pid>/fdinfo/<fd>` file, given in octal
+fn parse_flags(info: &str) -> Option<u32> {
+    let flags = info
+        .lines()
+        .find_map(|line| line.strip_prefix("flags:"))?
+        .trim();
+    u32::from_str_radix(flags, 8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_log_writers() {
+        assert_eq!(parse_flags("pos:\t0\nflags:\t0102001\n"), Some(0o102001));
+        assert_eq!(parse_flags("pos:\t0\n"), None);
+
+        let temp_dir = TempDir::new().unwrap();
+        let written = temp_dir.path().join("app.log");
+        let read = temp_dir.path().join("old.log");
+        fs::write(&written, "live").unwrap();
+        fs::write(&read, "idle").unwrap();
+
+        let process = temp_dir.path().join("proc").join("812");
+        fs::create_dir_all(process.join("fd")).unwrap();
+        fs::create_dir_all(process.join("fdinfo")).unwrap();
+        fs::write(process.join("comm"), "nginx\n").unwrap();
+        symlink(&written, process.join("fd").join("3")).unwrap();
+        fs::write(process.join("fdinfo").join("3"), "flags:\t0102001\n").unwrap();
+        symlink(&read, process.join("fd").join("4")).unwrap();
+        fs::write(process.join("fdinfo").join("4"), "flags:\t0100000\n").unwrap();
+
+        let table = WriterTable::load_from(&temp_dir.path().join("proc"));
+        assert_eq!(
+            table.writer_of(&written),
+            Some(Writer {
+                pid: 812,
+                command: "nginx".to_string()
+            })
+        );
+        assert_eq!(table.writer_of(&read), None);
+
+        // This process writing shows up in the real table
+        let _open = fs::OpenOptions::new().append(true).open(&read).unwrap();
+        let writer = WriterTable::load().writer_of(&read).unwrap();
+        assert_eq!(writer.pid, std::process::id());
+    }
+
+    #[test]
+    fn test_held_open_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let cleaned = temp_dir.path().join("cache");
+        let aside = temp_dir.path().join(format!("{}1-0", DELETING_PREFIX));
+        let kept = temp_dir.path().join("kept");
+        for dir in [&cleaned, &aside, &kept] {
+            fs::create_dir(dir).unwrap();
+        }
+        let mut open = Vec::new();
+        for (dir, bytes) in [(&cleaned, 8192), (&aside, 65536), (&kept, 8192)] {
+            let path = dir.join("data");
+            fs::write(&path, vec![1u8; bytes]).unwrap();
+            open.push(fs::File::open(&path).unwrap());
+            fs::remove_file(&path).unwrap();
+        }
+
+        let held = held_open_files(std::slice::from_ref(&cleaned));
+        let paths: Vec<&Path> = held.iter().map(|h| h.path.as_path()).collect();
+        assert_eq!(paths, [aside.join("data"), cleaned.join("data")]);
+        assert!(held[0].disk_bytes >= 65536);
+        assert_eq!(held[1].holder.pid, std::process::id());
+        drop(open);
+        assert!(held_open_files(&[cleaned]).is_empty());
+    }
+}